@@ -0,0 +1 @@
+ALTER TABLE users ALTER COLUMN is_active SET DEFAULT 'f';
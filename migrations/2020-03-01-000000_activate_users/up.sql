@@ -0,0 +1,4 @@
+-- login requires an active account from now on, keep the existing accounts usable.
+-- nothing has set is_active before, unless some account is active already, then it's left alone.
+UPDATE users SET is_active = 't' WHERE NOT EXISTS (SELECT 1 FROM users WHERE is_active);
+ALTER TABLE users ALTER COLUMN is_active SET DEFAULT 't';
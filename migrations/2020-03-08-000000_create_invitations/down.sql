@@ -0,0 +1,2 @@
+ALTER TABLE users DROP COLUMN verification_token;
+DROP TABLE invitations;
@@ -0,0 +1,14 @@
+-- registration is invitation only, a code can be used once before it expires
+CREATE TABLE invitations (
+    id SERIAL PRIMARY KEY,
+    code VARCHAR NOT NULL UNIQUE,
+    role VARCHAR NOT NULL DEFAULT 'author',
+    created_by INTEGER NOT NULL REFERENCES users(id),
+    created TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires TIMESTAMP NOT NULL,
+    used_by INTEGER REFERENCES users(id),
+    used TIMESTAMP
+);
+
+-- set on registration, cleared once the email is verified
+ALTER TABLE users ADD COLUMN verification_token VARCHAR;
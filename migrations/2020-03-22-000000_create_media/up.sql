@@ -0,0 +1,13 @@
+-- uploaded images, the files live in media_dir and are named by the hash of their content
+CREATE TABLE media (
+    id SERIAL PRIMARY KEY,
+    file_name VARCHAR NOT NULL UNIQUE, -- <sha256>.<ext>, the resized ones are <sha256>-<width>.<ext>
+    original_name VARCHAR NOT NULL DEFAULT '',
+    content_type VARCHAR NOT NULL,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    size INTEGER NOT NULL, -- bytes of the stored file, after stripping the metadata
+    variants VARCHAR NOT NULL DEFAULT '', -- the widths resized to, comma separated
+    uploaded_by INTEGER NOT NULL REFERENCES users(id),
+    created TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
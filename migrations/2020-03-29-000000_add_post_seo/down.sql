@@ -0,0 +1,4 @@
+ALTER TABLE posts DROP COLUMN excerpt;
+ALTER TABLE posts DROP COLUMN featured_image;
+ALTER TABLE posts DROP COLUMN meta_description;
+ALTER TABLE posts DROP COLUMN canonical_url;
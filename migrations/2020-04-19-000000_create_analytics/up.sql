@@ -0,0 +1,36 @@
+-- the views of articles since the last rollup, no address is stored
+CREATE TABLE page_views (
+    id SERIAL PRIMARY KEY,
+    post_id INTEGER NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+    visitor VARCHAR NOT NULL, -- sha256 of the address and browser with a salt of the day, which is never stored
+    referrer VARCHAR NOT NULL DEFAULT '', -- the host of another site, empty for direct visits
+    viewed TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX page_views_viewed ON page_views (viewed);
+
+-- page_views of the past days, rolled up
+CREATE TABLE daily_views (
+    id SERIAL PRIMARY KEY,
+    day DATE NOT NULL,
+    post_id INTEGER NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+    views INTEGER NOT NULL,
+    visitors INTEGER NOT NULL,
+    UNIQUE (day, post_id)
+);
+
+CREATE TABLE daily_referrers (
+    id SERIAL PRIMARY KEY,
+    day DATE NOT NULL,
+    referrer VARCHAR NOT NULL,
+    views INTEGER NOT NULL,
+    UNIQUE (day, referrer)
+);
+
+-- what visitors look for on /search/
+CREATE TABLE search_terms (
+    id SERIAL PRIMARY KEY,
+    day DATE NOT NULL,
+    term VARCHAR NOT NULL, -- lowercase, single spaced
+    searches INTEGER NOT NULL,
+    UNIQUE (day, term)
+);
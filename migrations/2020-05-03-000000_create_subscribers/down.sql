@@ -0,0 +1,2 @@
+DROP TABLE newsletter_issues;
+DROP TABLE subscribers;
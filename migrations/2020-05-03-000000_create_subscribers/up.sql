@@ -0,0 +1,21 @@
+-- readers of the newsletter, a row is confirmed by the token mailed to its address
+CREATE TABLE subscribers (
+    id SERIAL PRIMARY KEY,
+    email VARCHAR NOT NULL UNIQUE,
+    token VARCHAR NOT NULL UNIQUE, -- in the confirmation and unsubscribe links
+    frequency VARCHAR NOT NULL DEFAULT 'post', -- post: a mail for every new post, weekly: a digest
+    status VARCHAR NOT NULL DEFAULT 'pending', -- pending/active/unsubscribed/bounced
+    bounces INTEGER NOT NULL DEFAULT 0,
+    created TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    confirmed TIMESTAMP
+);
+
+-- what was sent: the announcement of a post, once for each post, or a weekly digest
+CREATE TABLE newsletter_issues (
+    id SERIAL PRIMARY KEY,
+    kind VARCHAR NOT NULL, -- post/digest
+    post_id INTEGER UNIQUE REFERENCES posts(id) ON DELETE SET NULL,
+    created TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    sent TIMESTAMP,
+    recipients INTEGER NOT NULL DEFAULT 0
+);
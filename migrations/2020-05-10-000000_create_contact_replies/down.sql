@@ -0,0 +1,4 @@
+DROP TABLE contact_replies;
+ALTER TABLE contacts DROP COLUMN is_read;
+ALTER TABLE contacts DROP COLUMN starred;
+ALTER TABLE contacts DROP COLUMN archived;
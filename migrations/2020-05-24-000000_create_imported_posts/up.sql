@@ -0,0 +1,7 @@
+-- the posts imported from another blog, by where they were there, so that importing again updates them
+CREATE TABLE imported_posts (
+    id SERIAL PRIMARY KEY,
+    source VARCHAR NOT NULL UNIQUE, -- the old permalink like https://example.com/2019/05/hello-world/, or the file of a draft without one
+    post_id INTEGER NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+    created TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
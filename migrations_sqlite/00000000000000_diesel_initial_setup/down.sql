@@ -0,0 +1,3 @@
+-- the helper functions of the postgres migration are plpgsql, sqlite has nothing to set up.
+-- the migration is kept so both backends have the same versions.
+SELECT 1;
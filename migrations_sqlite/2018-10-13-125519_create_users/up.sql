@@ -0,0 +1,13 @@
+CREATE TABLE users (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    password VARCHAR NOT NULL,
+    username VARCHAR NOT NULL,
+    first_name VARCHAR NOT NULL,
+    last_name VARCHAR NOT NULL,
+    email VARCHAR NOT NULL,
+    is_superuser BOOLEAN NOT NULL DEFAULT 0,
+    is_staff BOOLEAN NOT NULL DEFAULT 0,
+    is_active BOOLEAN NOT NULL DEFAULT 0,
+    last_login TIMESTAMP,
+    date_joined TIMESTAMP
+);
@@ -0,0 +1,12 @@
+CREATE TABLE posts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title VARCHAR(250) NOT NULL,
+    slug VARCHAR(250) NOT NULL,
+    body TEXT NOT NULL,
+    publish TIMESTAMP,
+    created TIMESTAMP,
+    updated TIMESTAMP,
+    status VARCHAR(10) NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES users(id),
+    likes INTEGER NOT NULL
+);
@@ -0,0 +1,8 @@
+CREATE TABLE comments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    username VARCHAR(250) NOT NULL,
+    email VARCHAR(250) NOT NULL,
+    comment TEXT NOT NULL,
+    committed_time TIMESTAMP,
+    post_id INTEGER NOT NULL REFERENCES posts(id)
+);
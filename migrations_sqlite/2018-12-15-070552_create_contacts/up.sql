@@ -0,0 +1,7 @@
+CREATE TABLE contacts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    tourist_name VARCHAR NOT NULL,
+    email VARCHAR NOT NULL,
+    message VARCHAR NOT NULL,
+    committed_time TIMESTAMP
+);
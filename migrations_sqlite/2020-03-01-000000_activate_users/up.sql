@@ -0,0 +1,3 @@
+-- sqlite cannot change a column default, it doesn't matter since every insert sets is_active.
+-- nothing has set is_active before, unless some account is active already, then it's left alone.
+UPDATE users SET is_active = 1 WHERE NOT EXISTS (SELECT 1 FROM users WHERE is_active = 1);
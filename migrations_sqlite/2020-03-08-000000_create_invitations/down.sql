@@ -0,0 +1,20 @@
+DROP TABLE invitations;
+
+-- sqlite cannot drop a column, the table is rebuilt without it
+CREATE TABLE users_without_token (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    password VARCHAR NOT NULL,
+    username VARCHAR NOT NULL,
+    first_name VARCHAR NOT NULL,
+    last_name VARCHAR NOT NULL,
+    email VARCHAR NOT NULL,
+    is_superuser BOOLEAN NOT NULL DEFAULT 0,
+    is_staff BOOLEAN NOT NULL DEFAULT 0,
+    is_active BOOLEAN NOT NULL DEFAULT 0,
+    last_login TIMESTAMP,
+    date_joined TIMESTAMP
+);
+INSERT INTO users_without_token
+    SELECT id, password, username, first_name, last_name, email, is_superuser, is_staff, is_active, last_login, date_joined FROM users;
+DROP TABLE users;
+ALTER TABLE users_without_token RENAME TO users;
@@ -0,0 +1,16 @@
+-- one-shot background jobs, retried with backoff until max_attempts
+CREATE TABLE jobs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind VARCHAR NOT NULL,
+    payload TEXT NOT NULL DEFAULT '',
+    status VARCHAR NOT NULL DEFAULT 'pending', -- pending/running/done/failed
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL DEFAULT 5,
+    run_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    locked_until TIMESTAMP, -- a running job past this was lost by a crashed runner
+    last_error TEXT,
+    created TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    finished TIMESTAMP
+);
+
+CREATE INDEX jobs_status_run_at ON jobs (status, run_at);
@@ -0,0 +1,22 @@
+-- sqlite cannot drop a column, the table is rebuilt without them.
+-- the comments are put aside meanwhile, or dropping posts would break their foreign key.
+CREATE TEMPORARY TABLE comments_backup AS SELECT * FROM comments;
+DELETE FROM comments;
+CREATE TABLE posts_without_seo (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title VARCHAR(250) NOT NULL,
+    slug VARCHAR(250) NOT NULL,
+    body TEXT NOT NULL,
+    publish TIMESTAMP,
+    created TIMESTAMP,
+    updated TIMESTAMP,
+    status VARCHAR(10) NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES users(id),
+    likes INTEGER NOT NULL
+);
+INSERT INTO posts_without_seo
+    SELECT id, title, slug, body, publish, created, updated, status, user_id, likes FROM posts;
+DROP TABLE posts;
+ALTER TABLE posts_without_seo RENAME TO posts;
+INSERT INTO comments SELECT * FROM comments_backup;
+DROP TABLE comments_backup;
@@ -0,0 +1,5 @@
+-- shown on the index cards and in link previews, an empty excerpt is generated from the body
+ALTER TABLE posts ADD COLUMN excerpt TEXT NOT NULL DEFAULT '';
+ALTER TABLE posts ADD COLUMN featured_image VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE posts ADD COLUMN meta_description VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE posts ADD COLUMN canonical_url VARCHAR NOT NULL DEFAULT '';
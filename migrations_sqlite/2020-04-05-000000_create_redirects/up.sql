@@ -0,0 +1,7 @@
+-- permanent redirects from the permalinks of an imported blog to the articles here
+CREATE TABLE redirects (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    old_path VARCHAR NOT NULL UNIQUE, -- without the trailing slash, like /2019/05/hello-world
+    new_path VARCHAR NOT NULL,
+    created TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,8 @@
+-- a like of a post by an anonymous visitor, posts.likes is kept in step with these rows
+CREATE TABLE post_likes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    post_id INTEGER NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+    visitor VARCHAR NOT NULL, -- the sha256 of the visitor's address and browser
+    created TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (post_id, visitor)
+);
@@ -0,0 +1,4 @@
+DROP TABLE search_terms;
+DROP TABLE daily_referrers;
+DROP TABLE daily_views;
+DROP TABLE page_views;
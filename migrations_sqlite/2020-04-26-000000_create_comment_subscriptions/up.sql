@@ -0,0 +1,8 @@
+-- a commenter who wants to hear about the next comments on a post
+CREATE TABLE comment_subscriptions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    post_id INTEGER NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+    email VARCHAR NOT NULL,
+    created TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (post_id, email)
+);
@@ -0,0 +1,13 @@
+DROP TABLE contact_replies;
+-- sqlite cannot drop a column, the table is rebuilt without them
+CREATE TABLE contacts_without_state (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    tourist_name VARCHAR NOT NULL,
+    email VARCHAR NOT NULL,
+    message VARCHAR NOT NULL,
+    committed_time TIMESTAMP
+);
+INSERT INTO contacts_without_state
+    SELECT id, tourist_name, email, message, committed_time FROM contacts;
+DROP TABLE contacts;
+ALTER TABLE contacts_without_state RENAME TO contacts;
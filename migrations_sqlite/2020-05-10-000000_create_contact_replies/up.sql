@@ -0,0 +1,15 @@
+-- the state of a message in the admin's inbox, the messages so far count as read
+ALTER TABLE contacts ADD COLUMN is_read BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE contacts ADD COLUMN starred BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE contacts ADD COLUMN archived BOOLEAN NOT NULL DEFAULT 0;
+UPDATE contacts SET is_read = 1;
+
+-- the mails an admin sent in reply to a message
+CREATE TABLE contact_replies (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    contact_id INTEGER NOT NULL REFERENCES contacts(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id),
+    body TEXT NOT NULL,
+    sent TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX contact_replies_contact_id ON contact_replies (contact_id);
@@ -0,0 +1,9 @@
+-- who can't comment or send messages: an email or @domain, a name pattern with * and ?, or an ip address or range
+CREATE TABLE blocklist (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind VARCHAR NOT NULL, -- email/name/ip
+    value VARCHAR NOT NULL,
+    reason VARCHAR NOT NULL DEFAULT '',
+    created TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (kind, value)
+);
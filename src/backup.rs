@@ -0,0 +1,329 @@
+use actix_web::web::Data;
+use chrono::{ NaiveDateTime, Utc };
+use diesel::prelude::*;
+use serde::de::DeserializeOwned;
+use serde_derive::{ Deserialize, Serialize };
+use std::collections::BTreeMap;
+use std::fs::{ self, File };
+use std::io::{ BufRead, BufReader, Read, Seek, Write };
+use std::path::{ Component, Path, PathBuf };
+use zip::{ write::FileOptions, CompressionMethod, ZipArchive, ZipWriter };
+
+use crate::models::{ migrations, schema };
+use crate::models::analytics::{ DailyReferrer, DailyView, PageView, SearchTerm };
+use crate::models::blocklist::BlockedEntry;
+use crate::models::comment::Comment;
+use crate::models::contact::{ Contact, ContactReply };
+use crate::models::invitation::Invitation;
+use crate::models::like::PostLike;
+use crate::models::media::Media;
+use crate::models::post::{ ImportedPost, Post };
+use crate::models::redirect::Redirect;
+use crate::models::subscriber::{ NewsletterIssue, Subscriber };
+use crate::models::subscription::CommentSubscription;
+use crate::models::user::User;
+use crate::utils::utils::{ DbConnection, DbPool };
+
+// a zip of manifest.json, <table>.ndjson with a json object per row, and media/ with the uploaded files.
+// the rows are json rather than sql, so that a backup of postgres can be restored into sqlite and the other way round.
+pub(crate) const FORMAT: &str = "actix-blog-backup";
+pub(crate) const FORMAT_VERSION: u32 = 1;
+pub(crate) const MANIFEST: &str = "manifest.json";
+const MEDIA_FOLDER: &str = "media/";
+
+// the tables in a backup, the referenced ones first.
+// jobs are left out, the queue of a server isn't worth moving.
+pub(crate) const TABLES: [&str; 18] = [
+    "users", "posts", "comments", "post_likes", "comment_subscriptions", "contacts", "contact_replies", "invitations", "media",
+    "redirects", "imported_posts", "page_views", "daily_views", "daily_referrers", "search_terms", "subscribers", "newsletter_issues",
+    "blocklist",
+];
+
+// sqlite allows 999 variables in a statement, a row of posts has 14
+const INSERT_BATCH: usize = 50;
+
+#[cfg(feature = "postgres")]
+const BACKEND: &str = "postgres";
+#[cfg(feature = "sqlite")]
+const BACKEND: &str = "sqlite";
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub(crate) struct BackupManifest {
+    pub(crate) format: String,
+    pub(crate) format_version: u32,
+    pub(crate) schema_version: String, // the latest migration
+    pub(crate) migrations: Vec<String>,
+    pub(crate) backend: String, // where it was made, any backend can restore it
+    pub(crate) created: NaiveDateTime,
+    pub(crate) tables: BTreeMap<String, usize>, // the rows of every table
+    pub(crate) media_files: usize,
+}
+
+fn migration_names() -> Vec<String> {
+    migrations::embedded_migrations().iter().map(|migration| migration.name.to_owned()).collect()
+}
+
+impl BackupManifest {
+    // a backup is restored by the version of the blog which made it, a newer one migrates it afterwards
+    pub(crate) fn validate(&self) -> Result<(), failure::Error> {
+        if self.format.ne(FORMAT) {
+            return Err(failure::format_err!("not a backup of this blog: the format is {:?}", self.format));
+        }
+        if self.format_version.ne(&FORMAT_VERSION) {
+            return Err(failure::format_err!("backup format {} isn't supported, this version reads format {}", self.format_version, FORMAT_VERSION));
+        }
+        if let Some(table) = TABLES.iter().find(|table| !self.tables.contains_key(**table)) {
+            return Err(failure::format_err!("the manifest doesn't count the rows of {}", table));
+        }
+        let expected = migration_names();
+        if self.migrations.ne(&expected) {
+            return Err(failure::format_err!(
+                "the backup is of schema {}, this version is of schema {}. restore it with the version which made it, then migrate",
+                self.schema_version, expected.last().map(String::as_str).unwrap_or_default()
+            ));
+        }
+        Ok(())
+    }
+}
+
+// a file of the backup cannot be written out of the media folder
+fn safe_relative_path(name: &str) -> Option<PathBuf> {
+    let path = Path::new(name);
+    let normal = path.components().all(|component| if let Component::Normal(_) = component { true } else { false });
+    if name.is_empty() || name.contains('\\') || !normal {
+        return None;
+    }
+    Some(path.to_path_buf())
+}
+
+// the files under the media folder, with their paths relative to it
+fn media_files(dir: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) -> Result<(), failure::Error> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            media_files(&entry.path(), &format!("{}/", name), files)?;
+        } else {
+            files.push((name, entry.path()));
+        }
+    }
+    Ok(())
+}
+
+fn write_rows<T: serde::Serialize, W: Write + Seek>(zip: &mut ZipWriter<W>, table: &str, rows: &[T]) -> Result<usize, failure::Error> {
+    zip.start_file(format!("{}.ndjson", table), FileOptions::default().compression_method(CompressionMethod::Deflated))?;
+    for row in rows {
+        serde_json::to_writer(&mut *zip, row)?;
+        zip.write_all(b"\n")?;
+    }
+    Ok(rows.len())
+}
+
+fn read_rows<T: DeserializeOwned, R: Read + Seek>(archive: &mut ZipArchive<R>, table: &str, expected: usize) -> Result<Vec<T>, failure::Error> {
+    let file_name = format!("{}.ndjson", table);
+    let file = archive.by_name(&file_name).map_err(|_| failure::format_err!("{} is missing in the backup", file_name))?;
+    let mut rows = Vec::with_capacity(expected);
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        rows.push(serde_json::from_str(&line).map_err(|e| failure::format_err!("line {} of {}: {}", i + 1, file_name, e))?);
+    }
+    // a truncated archive isn't restored by half
+    if rows.len().ne(&expected) {
+        return Err(failure::format_err!("{} has {} rows, the manifest says {}", file_name, rows.len(), expected));
+    }
+    Ok(rows)
+}
+
+macro_rules! dump_table {
+    ($conn: expr, $zip: expr, $tables: expr, $table: ident, $row: ty) => {
+        let rows = schema::$table::table.order(schema::$table::id.asc()).load::<$row>($conn)?;
+        $tables.insert(stringify!($table).to_owned(), write_rows($zip, stringify!($table), &rows)?);
+    };
+}
+
+macro_rules! restore_table {
+    ($conn: expr, $archive: expr, $manifest: expr, $table: ident, $row: ty) => {
+        let expected = $manifest.tables[stringify!($table)];
+        let rows = read_rows::<$row, _>($archive, stringify!($table), expected)?;
+        for batch in rows.chunks(INSERT_BATCH) {
+            diesel::insert_into(schema::$table::table).values(batch).execute($conn)?;
+        }
+    };
+}
+
+fn dump_tables<W: Write + Seek>(conn: &DbConnection, zip: &mut ZipWriter<W>) -> Result<BTreeMap<String, usize>, failure::Error> {
+    let mut tables = BTreeMap::new();
+    dump_table!(conn, zip, tables, users, User);
+    dump_table!(conn, zip, tables, posts, Post);
+    dump_table!(conn, zip, tables, comments, Comment);
+    dump_table!(conn, zip, tables, post_likes, PostLike);
+    dump_table!(conn, zip, tables, comment_subscriptions, CommentSubscription);
+    dump_table!(conn, zip, tables, contacts, Contact);
+    dump_table!(conn, zip, tables, contact_replies, ContactReply);
+    dump_table!(conn, zip, tables, invitations, Invitation);
+    dump_table!(conn, zip, tables, media, Media);
+    dump_table!(conn, zip, tables, redirects, Redirect);
+    dump_table!(conn, zip, tables, imported_posts, ImportedPost);
+    dump_table!(conn, zip, tables, page_views, PageView);
+    dump_table!(conn, zip, tables, daily_views, DailyView);
+    dump_table!(conn, zip, tables, daily_referrers, DailyReferrer);
+    dump_table!(conn, zip, tables, search_terms, SearchTerm);
+    dump_table!(conn, zip, tables, subscribers, Subscriber);
+    dump_table!(conn, zip, tables, newsletter_issues, NewsletterIssue);
+    dump_table!(conn, zip, tables, blocklist, BlockedEntry);
+    Ok(tables)
+}
+
+fn count_rows(conn: &DbConnection) -> Result<BTreeMap<&'static str, i64>, failure::Error> {
+    let mut counts = BTreeMap::new();
+    counts.insert("users", schema::users::table.count().get_result::<i64>(conn)?);
+    counts.insert("posts", schema::posts::table.count().get_result::<i64>(conn)?);
+    counts.insert("comments", schema::comments::table.count().get_result::<i64>(conn)?);
+    counts.insert("post_likes", schema::post_likes::table.count().get_result::<i64>(conn)?);
+    counts.insert("comment_subscriptions", schema::comment_subscriptions::table.count().get_result::<i64>(conn)?);
+    counts.insert("contacts", schema::contacts::table.count().get_result::<i64>(conn)?);
+    counts.insert("contact_replies", schema::contact_replies::table.count().get_result::<i64>(conn)?);
+    counts.insert("invitations", schema::invitations::table.count().get_result::<i64>(conn)?);
+    counts.insert("media", schema::media::table.count().get_result::<i64>(conn)?);
+    counts.insert("redirects", schema::redirects::table.count().get_result::<i64>(conn)?);
+    counts.insert("imported_posts", schema::imported_posts::table.count().get_result::<i64>(conn)?);
+    counts.insert("page_views", schema::page_views::table.count().get_result::<i64>(conn)?);
+    counts.insert("daily_views", schema::daily_views::table.count().get_result::<i64>(conn)?);
+    counts.insert("daily_referrers", schema::daily_referrers::table.count().get_result::<i64>(conn)?);
+    counts.insert("search_terms", schema::search_terms::table.count().get_result::<i64>(conn)?);
+    counts.insert("subscribers", schema::subscribers::table.count().get_result::<i64>(conn)?);
+    counts.insert("newsletter_issues", schema::newsletter_issues::table.count().get_result::<i64>(conn)?);
+    counts.insert("blocklist", schema::blocklist::table.count().get_result::<i64>(conn)?);
+    Ok(counts)
+}
+
+// the rows keep their ids, the next id of a new row has to come after them
+#[allow(unused_variables)]
+fn reset_sequences(conn: &DbConnection) -> Result<(), failure::Error> {
+    // sqlite takes the largest rowid by itself
+    #[cfg(feature = "postgres")]
+    {
+        use diesel::connection::SimpleConnection;
+        for table in TABLES.iter() {
+            conn.batch_execute(&format!(
+                "SELECT setval(pg_get_serial_sequence('{0}', 'id'), COALESCE((SELECT MAX(id) FROM {0}), 0) + 1, false);", table
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+// write a backup of the database and the media folder to out.
+// the tables are read in one transaction, so they agree with each other while the blog keeps running.
+pub(crate) fn backup(out: &Path, media_dir: &Path, pool: &Data<DbPool>) -> Result<BackupManifest, failure::Error> {
+    let conn = &*pool.get()?;
+    let status = migrations::migration_status(conn)?;
+    if !status.is_current() {
+        return Err(failure::err_msg("the database isn't at the schema of this version, migrate it before a backup"));
+    }
+
+    let part = out.with_extension("part");
+    let mut zip = ZipWriter::new(File::create(&part)?);
+    #[cfg(feature = "postgres")]
+    let tables = conn.build_transaction().read_only().repeatable_read().run::<_, failure::Error, _>(|| dump_tables(conn, &mut zip))?;
+    #[cfg(feature = "sqlite")]
+    let tables = conn.transaction::<_, failure::Error, _>(|| dump_tables(conn, &mut zip))?;
+
+    let mut files = Vec::new();
+    media_files(media_dir, "", &mut files)?;
+    for (name, path) in files.iter() {
+        // images are compressed already
+        zip.start_file(format!("{}{}", MEDIA_FOLDER, name), FileOptions::default().compression_method(CompressionMethod::Stored))?;
+        std::io::copy(&mut File::open(path)?, &mut zip)?;
+    }
+
+    let migrations = migration_names();
+    let manifest = BackupManifest {
+        format: FORMAT.to_owned(),
+        format_version: FORMAT_VERSION,
+        schema_version: migrations.last().cloned().unwrap_or_default(),
+        migrations,
+        backend: BACKEND.to_owned(),
+        created: Utc::now().naive_utc(),
+        tables,
+        media_files: files.len(),
+    };
+    zip.start_file(MANIFEST, FileOptions::default().compression_method(CompressionMethod::Deflated))?;
+    serde_json::to_writer_pretty(&mut zip, &manifest)?;
+    zip.finish()?;
+    fs::rename(&part, out)?;
+    Ok(manifest)
+}
+
+pub(crate) fn read_manifest<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<BackupManifest, failure::Error> {
+    let file = archive.by_name(MANIFEST).map_err(|_| failure::format_err!("{} is missing, it's not a backup", MANIFEST))?;
+    let manifest: BackupManifest = serde_json::from_reader(file)?;
+    manifest.validate()?;
+    Ok(manifest)
+}
+
+// load a backup into an empty database, the missing tables are created by the migrations first.
+// all the rows go in one transaction, a backup which fails halfway leaves the database empty.
+pub(crate) fn restore(path: &Path, media_dir: &Path, pool: &Data<DbPool>) -> Result<BackupManifest, failure::Error> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let manifest = read_manifest(&mut archive)?;
+
+    let conn = &*pool.get()?;
+    migrations::run_pending_migrations(conn)?;
+    conn.transaction::<_, failure::Error, _>(|| {
+        let not_empty: Vec<String> = count_rows(conn)?.into_iter()
+                                                        .filter(|(_, count)| count.gt(&0))
+                                                        .map(|(table, count)| format!("{} {}", count, table))
+                                                        .collect();
+        if !not_empty.is_empty() {
+            return Err(failure::format_err!("the database isn't empty: {}", not_empty.join(", ")));
+        }
+        restore_table!(conn, &mut archive, manifest, users, User);
+        restore_table!(conn, &mut archive, manifest, posts, Post);
+        restore_table!(conn, &mut archive, manifest, comments, Comment);
+        restore_table!(conn, &mut archive, manifest, post_likes, PostLike);
+        restore_table!(conn, &mut archive, manifest, comment_subscriptions, CommentSubscription);
+        restore_table!(conn, &mut archive, manifest, contacts, Contact);
+        restore_table!(conn, &mut archive, manifest, contact_replies, ContactReply);
+        restore_table!(conn, &mut archive, manifest, invitations, Invitation);
+        restore_table!(conn, &mut archive, manifest, media, Media);
+        restore_table!(conn, &mut archive, manifest, redirects, Redirect);
+        restore_table!(conn, &mut archive, manifest, imported_posts, ImportedPost);
+        restore_table!(conn, &mut archive, manifest, page_views, PageView);
+        restore_table!(conn, &mut archive, manifest, daily_views, DailyView);
+        restore_table!(conn, &mut archive, manifest, daily_referrers, DailyReferrer);
+        restore_table!(conn, &mut archive, manifest, search_terms, SearchTerm);
+        restore_table!(conn, &mut archive, manifest, subscribers, Subscriber);
+        restore_table!(conn, &mut archive, manifest, newsletter_issues, NewsletterIssue);
+        restore_table!(conn, &mut archive, manifest, blocklist, BlockedEntry);
+        reset_sequences(conn)
+    })?;
+
+    // the files after the rows, a failure here is fixed by restoring the files again
+    let mut restored = 0;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let name = file.name().to_owned();
+        if file.is_dir() || !name.starts_with(MEDIA_FOLDER) {
+            continue;
+        }
+        let relative = safe_relative_path(&name[MEDIA_FOLDER.len()..])
+            .ok_or_else(|| failure::format_err!("{} would be written out of the media folder", name))?;
+        let target = media_dir.join(relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        std::io::copy(&mut file, &mut File::create(&target)?)?;
+        restored += 1;
+    }
+    if restored.ne(&manifest.media_files) {
+        return Err(failure::format_err!("{} media files restored, the manifest says {}", restored, manifest.media_files));
+    }
+    Ok(manifest)
+}
@@ -0,0 +1,316 @@
+use actix_web::web::Data;
+use chrono::Utc;
+use std::collections::HashMap;
+use std::io::{ self, BufRead, Write };
+use std::path::Path;
+
+use crate::models::{ migrations, post::{ PostOperation, PostStatus }, user::{ NewUser, UserOperation } };
+use crate::{ backup, site_export };
+use crate::transfer::{ self, ExportOptions, ImportSource };
+use crate::utils::{ config::BlogConfig, newsletter, utils::{ db_pool, DbPool, Status } };
+
+pub(crate) const USAGE: &str = "usage: main [--config <file>] [command]
+
+commands:
+    serve                                        start the blog server (default)
+    createsuperuser <username> <email> [--password <password>]
+    set-password <username> [--password <password>]
+    deactivate-user <username>
+    migrate                                      apply pending migrations
+    list-posts
+    publish <id|title>                           publish a draft, list-posts shows the ids
+    check-config                                 validate the config file and database connection
+    export-posts --out <file.zip> [--status <publish|draft>] [--author <username>] [--since <yyyy-mm-dd>] [--format <toml|yaml>]
+    import-posts <file|dir> [--from <markdown|wordpress|hugo|jekyll>] [--author <username>] [--dry-run]
+                                                 upsert markdown posts by title and posts of another blog by their old permalink,
+                                                 --author owns the posts whose author is unknown
+    export-site --out <dir> [--full]             render the published posts as a static site, only what changed unless --full
+    backup --out <file.zip>                      the tables and media files in a portable archive
+    restore <file.zip>                           load a backup into an empty database
+    help";
+
+// the options which take a value, as --name value or --name=value
+const VALUE_OPTIONS: [&str; 7] = ["--password", "--out", "--status", "--author", "--since", "--format", "--from"];
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Command {
+    Serve,
+    CreateSuperuser { username: String, email: String, password: Option<String> },
+    SetPassword { username: String, password: Option<String> },
+    DeactivateUser { username: String },
+    Migrate,
+    ListPosts,
+    Publish { post: String },
+    CheckConfig,
+    ExportPosts { out: String, options: ExportOptions },
+    ImportPosts { path: String, source: ImportSource, author: Option<String>, dry_run: bool },
+    ExportSite { out: String, full: bool },
+    Backup { out: String },
+    Restore { path: String },
+    Help,
+}
+
+impl Command {
+    // the first item is the program name, --config is handled by the config loader
+    pub(crate) fn from_args(args: &[String]) -> Result<Self, failure::Error> {
+        let mut positional = Vec::new();
+        let mut options: HashMap<&str, String> = HashMap::new();
+        let (mut dry_run, mut full) = (false, false);
+        let mut iter = args.iter().skip(1);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--config" => { iter.next(); }
+                _ if arg.starts_with("--config=") => (),
+                "--dry-run" => dry_run = true,
+                "--full" => full = true,
+                _ => match VALUE_OPTIONS.iter().find(|option| arg.eq(*option) || arg.starts_with(&format!("{}=", option))) {
+                    Some(option) if arg.eq(option) => {
+                        let value = iter.next().cloned().ok_or_else(|| failure::format_err!("{} requires a value", option))?;
+                        options.insert(*option, value);
+                    }
+                    Some(option) => { options.insert(*option, arg[option.len() + 1..].to_owned()); }
+                    None => positional.push(arg.as_str()),
+                }
+            }
+        }
+        let password = options.remove("--password");
+
+        let command = match positional.as_slice() {
+            [] | ["serve"] => Command::Serve,
+            ["createsuperuser", username, email] => {
+                Command::CreateSuperuser { username: (*username).to_owned(), email: (*email).to_owned(), password }
+            }
+            ["set-password", username] => Command::SetPassword { username: (*username).to_owned(), password },
+            ["deactivate-user", username] => Command::DeactivateUser { username: (*username).to_owned() },
+            ["migrate"] => Command::Migrate,
+            ["list-posts"] => Command::ListPosts,
+            ["publish", post] => Command::Publish { post: (*post).to_owned() },
+            ["check-config"] => Command::CheckConfig,
+            ["export-posts"] => {
+                let out = options.remove("--out").ok_or_else(|| failure::err_msg("export-posts requires --out <file.zip>"))?;
+                let export_options = ExportOptions {
+                    status: options.remove("--status").unwrap_or_default(),
+                    author: options.remove("--author").unwrap_or_default(),
+                    since: options.remove("--since").unwrap_or_default(),
+                    format: options.remove("--format").unwrap_or_default(),
+                };
+                export_options.parse()?;
+                Command::ExportPosts { out, options: export_options }
+            }
+            ["import-posts", path] => {
+                let source = options.remove("--from").unwrap_or_default().parse::<ImportSource>()?;
+                Command::ImportPosts { path: (*path).to_owned(), source, author: options.remove("--author"), dry_run }
+            }
+            ["export-site"] => {
+                let out = options.remove("--out").ok_or_else(|| failure::err_msg("export-site requires --out <dir>"))?;
+                Command::ExportSite { out, full }
+            }
+            ["backup"] => {
+                let out = options.remove("--out").ok_or_else(|| failure::err_msg("backup requires --out <file.zip>"))?;
+                Command::Backup { out }
+            }
+            ["restore", path] => Command::Restore { path: (*path).to_owned() },
+            ["help"] | ["--help"] | ["-h"] => Command::Help,
+            _ => return Err(failure::format_err!("invalid command: {}\n\n{}", positional.join(" "), USAGE)),
+        };
+        Ok(command)
+    }
+}
+
+// the typed password isn't echoed, unless stdin isn't a terminal(piped)
+#[cfg(unix)]
+fn read_hidden_line() -> io::Result<String> {
+    let fd = libc::STDIN_FILENO;
+    let mut terminal = unsafe { std::mem::zeroed::<libc::termios>() };
+    let is_terminal = unsafe { libc::isatty(fd).eq(&1) && libc::tcgetattr(fd, &mut terminal).eq(&0) };
+    if is_terminal {
+        let mut hidden = terminal;
+        hidden.c_lflag &= !libc::ECHO;
+        hidden.c_lflag |= libc::ECHONL;
+        unsafe { libc::tcsetattr(fd, libc::TCSANOW, &hidden) };
+    }
+    let mut line = String::new();
+    let read = io::stdin().lock().read_line(&mut line);
+    if is_terminal {
+        unsafe { libc::tcsetattr(fd, libc::TCSANOW, &terminal) };
+    }
+    read.map(|_| line)
+}
+
+#[cfg(not(unix))]
+fn read_hidden_line() -> io::Result<String> {
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line)
+}
+
+// the password isn't required on the command line, so that it won't stay in the shell history
+fn read_password(password: Option<String>) -> Result<String, failure::Error> {
+    let password = match password {
+        Some(password) => password,
+        None => {
+            print!("Password: ");
+            io::stdout().flush()?;
+            read_hidden_line()?.trim_end_matches(&['\r', '\n'][..]).to_owned()
+        }
+    };
+    if password.is_empty() {
+        return Err(failure::err_msg("the password cannot be empty"));
+    }
+    Ok(password)
+}
+
+fn open_pool(blog_config: &BlogConfig) -> Result<Data<DbPool>, failure::Error> {
+    Ok(Data::new(db_pool(&blog_config.database_url)?))
+}
+
+// run an admin command, Command::Serve is handled by main
+pub(crate) fn run(command: Command, blog_config: &BlogConfig) -> Result<(), failure::Error> {
+    match command {
+        Command::Serve => Ok(()),
+        Command::Help => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Command::CreateSuperuser { username, email, password } => {
+            let pool = open_pool(blog_config)?;
+            let password = read_password(password)?;
+            let new_user = NewUser {
+                username: username.clone(),
+                password: bcrypt::hash(&password, bcrypt::DEFAULT_COST)?,
+                first_name: String::new(),
+                last_name: String::new(),
+                email,
+                is_superuser: true,
+                is_staff: true,
+                is_active: true,
+                last_login: None,
+                date_joined: Some(Utc::now().naive_utc()),
+                verification_token: None,
+            };
+            match UserOperation::insert_user(&new_user, &pool)? {
+                Status::Success => {
+                    println!("superuser {} created", username);
+                    Ok(())
+                }
+                Status::Failure => Err(failure::format_err!("the username or email of {} already exists", username)),
+            }
+        }
+        Command::SetPassword { username, password } => {
+            let pool = open_pool(blog_config)?;
+            if UserOperation::get_user_by_name(&username, &pool)?.is_none() {
+                return Err(failure::format_err!("didn't find user {}", username));
+            }
+            let password = read_password(password)?;
+            match UserOperation::modify_password(&bcrypt::hash(&password, bcrypt::DEFAULT_COST)?, &username, &pool)? {
+                Status::Success => {
+                    println!("password of {} changed", username);
+                    Ok(())
+                }
+                Status::Failure => Err(failure::format_err!("didn't find user {}", username)),
+            }
+        }
+        Command::DeactivateUser { username } => {
+            let pool = open_pool(blog_config)?;
+            match UserOperation::set_active(&username, false, &pool)? {
+                Status::Success => {
+                    println!("{} deactivated", username);
+                    Ok(())
+                }
+                Status::Failure => Err(failure::format_err!("didn't find user {}", username)),
+            }
+        }
+        Command::Migrate => {
+            let pool = open_pool(blog_config)?;
+            let applied = migrations::run_pending_migrations(&*pool.get()?)?;
+            if applied.is_empty() {
+                println!("the database is up to date");
+            }
+            for name in applied {
+                println!("applied {}", name);
+            }
+            Ok(())
+        }
+        Command::ListPosts => {
+            let pool = open_pool(blog_config)?;
+            let mut posts = PostOperation::get_all_posts(PostStatus::All, &pool)?;
+            posts.reverse();
+            for post in posts {
+                let publish = post.publish.map_or_else(String::new, |time| time.format("%Y-%m-%d %H:%M").to_string());
+                println!("{:>5}  {:<8} {:<17} {:<30} {}", post.id, post.status, publish, post.slug, post.title);
+            }
+            Ok(())
+        }
+        Command::Publish { post } => {
+            let pool = open_pool(blog_config)?;
+            // the slug is a list of tags shared by many posts, so a post is picked by its id or title
+            let found = match post.parse::<i32>() {
+                Ok(post_id) => PostOperation::get_post_by_id(post_id, &pool)?,
+                Err(_) => None,
+            };
+            let found = match found {
+                Some(found) => found,
+                None => PostOperation::get_post_by_title(&post, &pool)?.ok_or_else(|| failure::format_err!("didn't find post {}", post))?,
+            };
+            if found.status.eq("publish") {
+                println!("{} is published already", found.title);
+                return Ok(());
+            }
+            match PostOperation::publish_post(found.id, &pool)? {
+                Status::Success => {
+                    println!("{} published", found.title);
+                    if newsletter::announce_post(found.id, &pool)? {
+                        println!("the subscribers will get a mail about it");
+                    }
+                    Ok(())
+                }
+                Status::Failure => Err(failure::format_err!("didn't find post {}", post)),
+            }
+        }
+        Command::ExportPosts { out, options } => {
+            let pool = open_pool(blog_config)?;
+            let (zip, exported) = transfer::export_posts(&options, &pool)?;
+            std::fs::write(&out, zip)?;
+            println!("{} post(s) exported to {}", exported, out);
+            Ok(())
+        }
+        Command::ImportPosts { path, source, author, dry_run } => {
+            let pool = open_pool(blog_config)?;
+            let import = transfer::read_import_path(source, Path::new(&path))?;
+            let report = transfer::import_posts(import, author.as_deref(), dry_run, &pool)?;
+            println!("{}", report);
+            Ok(())
+        }
+        Command::ExportSite { out, full } => {
+            let pool = open_pool(blog_config)?;
+            let report = site_export::export_site(Path::new(&out), full, &pool)?;
+            println!("{}", report);
+            Ok(())
+        }
+        Command::Backup { out } => {
+            let pool = open_pool(blog_config)?;
+            let manifest = backup::backup(Path::new(&out), Path::new(&blog_config.media.dir), &pool)?;
+            let rows: Vec<String> = manifest.tables.iter().map(|(table, rows)| format!("{} {}", rows, table)).collect();
+            println!("backup of schema {} written to {}: {}, {} media file(s)", manifest.schema_version, out, rows.join(", "), manifest.media_files);
+            Ok(())
+        }
+        Command::Restore { path } => {
+            let pool = open_pool(blog_config)?;
+            let manifest = backup::restore(Path::new(&path), Path::new(&blog_config.media.dir), &pool)?;
+            let rows: Vec<String> = manifest.tables.iter().map(|(table, rows)| format!("{} {}", rows, table)).collect();
+            println!("restored the backup of {}: {}, {} media file(s)", manifest.created.format("%Y-%m-%d %H:%M:%S"), rows.join(", "), manifest.media_files);
+            Ok(())
+        }
+        Command::CheckConfig => {
+            // the config itself has been validated before any command runs
+            println!("profile {}: configuration is valid", blog_config.profile.name());
+            let pool = open_pool(blog_config)?;
+            let status = migrations::migration_status(&*pool.get()?)?;
+            println!("database is reachable, {} pending migration(s)", status.pending.len());
+            if !status.unknown.is_empty() {
+                return Err(failure::format_err!("the database has migrations this binary doesn't know: {}", status.unknown.join(", ")));
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use actix_web::web::Data;
+use std::sync::{ atomic::{ AtomicBool, AtomicUsize, Ordering }, Arc };
+use std::time::{ Duration, Instant };
+
+use crate::models::{ analytics::AnalyticsOperation, job::{ Job, JobOperation }, subscriber::SubscriberOperation };
+use crate::utils::{ cache::{ PAGE_CACHE, QUERY_CACHE }, mailer::{ self, Mail, MailError }, newsletter::{ self, IssueJob }, utils::{ run_blocking, DbPool } };
+
+// kinds of the persisted one-shot jobs
+pub(crate) const SEND_MAIL: &str = "send_mail";
+pub(crate) const SEND_NEWSLETTER: &str = "send_newsletter";
+// what run_job handles, a runner leaves the other kinds to the runners which know them
+const JOB_KINDS: [&str; 2] = [SEND_MAIL, SEND_NEWSLETTER];
+
+const BATCH_SIZE: i64 = 10;
+const KEEP_DONE_JOBS_DAYS: i64 = 7;
+
+#[derive(Debug, Clone)]
+pub(crate) struct JobsConfig {
+    pub(crate) enabled: bool,
+    pub(crate) poll_interval: u64, // seconds between two checks for due jobs
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        JobsConfig { enabled: true, poll_interval: 5 }
+    }
+}
+
+type Task = fn(&Data<DbPool>) -> Result<(), failure::Error>;
+
+// periodic work, the schedule lives in memory only
+struct IntervalJob {
+    name: &'static str,
+    every: Duration,
+    task: Task,
+}
+
+fn prune_caches(_pool: &Data<DbPool>) -> Result<(), failure::Error> {
+    let pruned = PAGE_CACHE.prune() + QUERY_CACHE.prune();
+    log::debug!("pruned {} expired cache entries", pruned);
+    Ok(())
+}
+
+fn purge_done_jobs(pool: &Data<DbPool>) -> Result<(), failure::Error> {
+    let purged = JobOperation::purge_done_jobs(chrono::Duration::days(KEEP_DONE_JOBS_DAYS), pool)?;
+    log::debug!("purged {} finished jobs", purged);
+    Ok(())
+}
+
+// the raw views of past days become daily counts
+fn rollup_views(pool: &Data<DbPool>) -> Result<(), failure::Error> {
+    let rolled_up = AnalyticsOperation::rollup(chrono::Utc::now().naive_utc().date(), pool)?;
+    log::debug!("rolled up {} page views", rolled_up);
+    Ok(())
+}
+
+// the weekly digest is due once a week after the last one, whenever the server was restarted
+fn newsletter_digest(pool: &Data<DbPool>) -> Result<(), failure::Error> {
+    if newsletter::queue_digest(pool)? {
+        log::info!("queued the newsletter digest");
+    }
+    let purged = SubscriberOperation::purge_pending(chrono::Duration::days(newsletter::CONFIRM_DAYS), pool)?;
+    log::debug!("purged {} unconfirmed subscribers", purged);
+    Ok(())
+}
+
+fn interval_jobs() -> Vec<IntervalJob> {
+    vec![
+        IntervalJob { name: "prune_caches", every: Duration::from_secs(60), task: prune_caches },
+        IntervalJob { name: "purge_done_jobs", every: Duration::from_secs(60 * 60), task: purge_done_jobs },
+        IntervalJob { name: "rollup_views", every: Duration::from_secs(60 * 60), task: rollup_views },
+        IntervalJob { name: "newsletter_digest", every: Duration::from_secs(60 * 60), task: newsletter_digest },
+    ]
+}
+
+// retrying a rejected mail won't help, a subscriber of the newsletter is dropped after a few
+fn send_mail(mail: &Mail, pool: &Data<DbPool>) -> Result<(), failure::Error> {
+    match mailer::send_mail(mail) {
+        Err(e) if e.downcast_ref::<MailError>().is_some() => {
+            log::warn!("{}", e);
+            SubscriberOperation::record_bounce(&mail.to, pool).map(|_| ())
+        }
+        sent => sent,
+    }
+}
+
+// the handler of every kind of one-shot job, the payload is json
+fn run_job(job: &Job, pool: &Data<DbPool>) -> Result<(), failure::Error> {
+    match job.kind.as_str() {
+        SEND_MAIL => send_mail(&serde_json::from_str(&job.payload)?, pool),
+        SEND_NEWSLETTER => newsletter::send_issue(serde_json::from_str::<IssueJob>(&job.payload)?.issue, pool).map(|_| ()),
+        kind => Err(failure::format_err!("unknown job kind {}", kind)),
+    }
+}
+
+#[derive(Default)]
+struct RunnerState {
+    stopping: AtomicBool,
+    running: AtomicUsize,
+}
+
+// the handle of the runner spawned on the actix system
+pub(crate) struct JobRunner {
+    state: Arc<RunnerState>,
+}
+
+impl JobRunner {
+    pub(crate) fn start(pool: Data<DbPool>, jobs_config: &JobsConfig) -> Self {
+        let state = Arc::new(RunnerState::default());
+        if !jobs_config.enabled {
+            return JobRunner { state };
+        }
+
+        let runner_state = state.clone();
+        let poll_interval = Duration::from_secs(jobs_config.poll_interval);
+        actix_rt::spawn(async move {
+            let interval_jobs = interval_jobs();
+            // every interval job is due at start, a process restarted more often than its interval would never run it
+            let mut last_runs: Vec<Option<Instant>> = vec![None; interval_jobs.len()];
+            let mut ticker = actix_rt::time::interval(poll_interval);
+            loop {
+                ticker.tick().await;
+                for (job, last_run) in interval_jobs.iter().zip(last_runs.iter_mut()) {
+                    if matches!(last_run, Some(last_run) if last_run.elapsed().lt(&job.every)) {
+                        continue;
+                    }
+                    *last_run = Some(Instant::now());
+                    let (task, pool) = (job.task, pool.clone());
+                    match Self::track(&runner_state, run_blocking(move || task(&pool))).await {
+                        Some(Err(e)) => log::error!("interval job {} failed: {}", job.name, e),
+                        Some(Ok(())) => (),
+                        None => return,
+                    }
+                }
+
+                // the whole batch counts as running, from the claim to the last job
+                if Self::track(&runner_state, Self::run_due_jobs(&runner_state, &pool)).await.is_none() {
+                    return;
+                }
+            }
+        });
+        JobRunner { state }
+    }
+
+    // the work counts as running before stopping is checked, so shutdown either waits for it or it never starts
+    async fn track<T>(state: &RunnerState, work: impl std::future::Future<Output = T>) -> Option<T> {
+        state.running.fetch_add(1, Ordering::SeqCst);
+        let result = if state.stopping.load(Ordering::SeqCst) { None } else { Some(work.await) };
+        state.running.fetch_sub(1, Ordering::SeqCst);
+        result
+    }
+
+    async fn run_due_jobs(state: &RunnerState, pool: &Data<DbPool>) {
+        let claim_pool = pool.clone();
+        let due = match run_blocking(move || JobOperation::claim_due_jobs(BATCH_SIZE, &JOB_KINDS, &claim_pool)).await {
+            Ok(due) => due,
+            Err(e) => {
+                log::error!("failed to claim due jobs: {}", e);
+                return;
+            }
+        };
+
+        for (i, job) in due.iter().cloned().enumerate() {
+            // the rest of the batch goes back to the queue instead of waiting out the lease
+            if state.stopping.load(Ordering::SeqCst) {
+                let unstarted: Vec<i32> = due[i..].iter().map(|job| job.id).collect();
+                let release_pool = pool.clone();
+                if let Err(e) = run_blocking(move || JobOperation::release(&unstarted, &release_pool)).await {
+                    log::error!("failed to release the claimed jobs: {}", e);
+                }
+                return;
+            }
+            let job_pool = pool.clone();
+            let finished = run_blocking(move || {
+                match run_job(&job, &job_pool) {
+                    Ok(()) => JobOperation::complete(job.id, &job_pool),
+                    Err(e) => {
+                        log::warn!("job {} ({}) failed on attempt {}: {}", job.id, job.kind, job.attempts, e);
+                        JobOperation::fail(&job, &e.to_string(), &job_pool)
+                    }
+                }
+            }).await;
+            if let Err(e) = finished {
+                log::error!("failed to update the job state: {}", e);
+            }
+        }
+    }
+
+    // stop taking new work, and wait for the running jobs at most timeout.
+    // the claimed jobs not started yet are released, the ones not finished in time are picked up again once their lease expires.
+    pub(crate) async fn shutdown(&self, timeout: Duration) {
+        self.state.stopping.store(true, Ordering::SeqCst);
+        let deadline = Instant::now() + timeout;
+        while self.state.running.load(Ordering::SeqCst).gt(&0) {
+            if Instant::now().ge(&deadline) {
+                log::warn!("{} job(s) still running after {:?}, shutting down anyway", self.state.running.load(Ordering::SeqCst), timeout);
+                return;
+            }
+            actix_rt::time::delay_for(Duration::from_millis(100)).await;
+        }
+    }
+}
@@ -45,6 +45,16 @@ pub fn login_required(_: TokenStream, func: TokenStream) -> TokenStream {
     }).collect();
     let (identity_param, identity_type) = params.get(0).unwrap();
     
+    // the pool to check the account is still active, a deactivated user keeps the identity cookie
+    let db_param = func_inputs.iter().find_map(|i| {
+        match i {
+            FnArg::Typed(ref pat_type) if pat_type.ty.clone().into_token_stream().to_string().ends_with("Data < DbPool >") => {
+                Some(&pat_type.pat)
+            }
+            _ => None,
+        }
+    }).expect("login_required needs a web::Data<DbPool> parameter to check the user is still active");
+    
     let caller = quote!{
         // rebuild the function, add a func named is_expired to check user login session expire or not.
         #func_vis #asyncness fn #func_name #func_generics(#func_inputs) #func_output {
@@ -58,6 +68,9 @@ pub fn login_required(_: TokenStream, func: TokenStream) -> TokenStream {
             
             if is_expired(&#identity_param) {
                 Err(ErrorKind::IdentityExpiredError)
+            } else if !crate::views::auth::is_active_user(&#identity_param, &#db_param).await {
+                #identity_param.forget();
+                Err(ErrorKind::IdentityExpiredError)
             } else {
                 #func_block
             }
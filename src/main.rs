@@ -1,37 +1,126 @@
 #[macro_use]
 extern crate diesel;
+extern crate diesel_migrations;
+
+#[cfg(all(feature = "postgres", feature = "sqlite"))]
+compile_error!("postgres and sqlite are exclusive, build sqlite with --no-default-features --features sqlite");
+#[cfg(not(any(feature = "postgres", feature = "sqlite")))]
+compile_error!("either postgres or sqlite feature is required");
 
 use actix_files as fs;
 use actix_session::CookieSession;
 use actix_identity::{ CookieIdentityPolicy, IdentityService };
-use actix_web::{ web, App, HttpServer, middleware };
+use actix_web::{ dev::Service, http::{ header, ContentEncoding }, web, App, HttpServer, middleware };
 
 #[macro_use]
 mod utils;
+mod backup;
+mod cli;
+mod jobs;
+mod site_export;
+mod transfer;
 mod views;
 mod models;
 mod error_types;
 #[cfg(test)]
 mod test;
 
-use crate::utils::utils::{ db_pool, blog_config };
+use crate::utils::{ cache, http_cache, logging, metrics, tls::{ self, PlainHttp }, utils::db_pool };
+use crate::utils::config::{ self, BlogConfig };
+use crate::utils::utils::DbPool;
+use crate::models::migrations;
+
+// refuse to start if the database doesn't match the embedded migrations, unless they can be applied.
+fn check_migrations(pool: &DbPool, auto_migrate: bool) -> Result<(), failure::Error> {
+    let conn = pool.get()?;
+    let status = migrations::migration_status(&conn)?;
+    if status.is_current() {
+        return Ok(());
+    }
+    
+    if !status.unknown.is_empty() {
+        return Err(failure::format_err!("the database has migrations this binary doesn't know: {}", status.unknown.join(", ")));
+    }
+    if !auto_migrate {
+        return Err(failure::format_err!("pending migrations: {}, set auto_migrate = true to apply them at startup", status.pending.join(", ")));
+    }
+    
+    let applied = migrations::run_pending_migrations(&conn)?;
+    log::info!("applied migrations: {}", applied.join(", "));
+    Ok(())
+}
 
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
-    let config = &blog_config().unwrap()["production"];
-    let address = config["address"].as_str().ok_or(failure::err_msg("no address in the section")).unwrap();
-    let port = config["port"].as_integer().ok_or(failure::err_msg("no port in the section")).unwrap();
-    let workers = config["workers"].as_integer().ok_or(failure::err_msg("no workers in the section")).unwrap() as usize;
-    let log_level = config["log"].as_str().ok_or(failure::err_msg("no specified log level in the section")).unwrap();
+    let args: Vec<String> = std::env::args().collect();
+    let command = match cli::Command::from_args(&args) {
+        Ok(cli::Command::Help) => {
+            println!("{}", cli::USAGE);
+            return Ok(());
+        }
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    let blog_config = match BlogConfig::load(config::config_path_from_args(&args).as_deref(), None) {
+        Ok(blog_config) => blog_config,
+        Err(errors) => {
+            eprintln!("{}", errors);
+            std::process::exit(1);
+        }
+    };
+    config::install(blog_config.clone());
+    
+    // admin commands run and exit, only serve starts the server
+    if command.ne(&cli::Command::Serve) {
+        if let Err(e) = cli::run(command, &blog_config) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    
+    cache::configure(blog_config.cache_capacity, std::time::Duration::from_secs(blog_config.cache_ttl));
+    http_cache::set_policies(blog_config.cache_control.clone());
+    
+    logging::init(&blog_config.log, blog_config.log_format);
     
-    std::env::set_var("RUST_LOG", format!("actix_server={},actix_web={}", log_level, log_level)); // log level
-    env_logger::init(); // init a log
+    let pool = db_pool(&blog_config.database_url).expect("failed to open db connection");
+    if let Err(e) = check_migrations(&pool, blog_config.auto_migrate) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
     
-    let pool = db_pool().expect("failed to open db connection");
+    let job_runner = jobs::JobRunner::start(web::Data::new(pool.clone()), &blog_config.jobs);
+    let shutdown_timeout = blog_config.shutdown_timeout;
     
-    let blog_server = HttpServer::new( move || 
+    let (address, port, workers) = (&blog_config.address, blog_config.port, blog_config.workers);
+    let tls_config = blog_config.tls.clone();
+    let compress = blog_config.compression;
+    let static_dir = blog_config.static_dir.clone();
+    let media_dir = blog_config.media.dir.clone();
+    std::fs::create_dir_all(&media_dir)?;
+    let server_tls_config = tls_config.clone();
+    let blog_server = HttpServer::new( move || {
+        let tls_config = server_tls_config.clone();
+        let static_files_policy = http_cache::static_files_policy();
         App::new().data(pool.clone())
-            .wrap(middleware::Logger::default())
+            // gzip/brotli/deflate, negotiated by Accept-Encoding
+            // Compress changes the type of the body, so it's always there, identity leaves the body as it is
+            .wrap(middleware::Compress::new(if compress { ContentEncoding::Auto } else { ContentEncoding::Identity }))
+            .wrap_fn(move |req, srv| {
+                let hsts = tls_config.hsts_for(req.app_config().secure());
+                let res = srv.call(req);
+                async move {
+                    let mut res = res.await?;
+                    if let Some(hsts) = hsts {
+                        res.headers_mut().insert(header::STRICT_TRANSPORT_SECURITY, hsts);
+                    }
+                    Ok(res)
+                }
+            })
             .wrap(middleware::NormalizePath::default())
             .wrap(
                 CookieSession::signed(&[0; 32])
@@ -40,6 +129,17 @@ async fn main() -> std::io::Result<()> {
                     .secure(false)
                     .max_age(60 * 60i64)
             )
+            // inside the identity service, so that the access log knows the user
+            .wrap_fn(|req, srv| {
+                let start = std::time::Instant::now();
+                let request_id = logging::assign_request_id(&req);
+                let res = srv.call(req);
+                async move {
+                    let mut res = res.await?;
+                    logging::log_response(&mut res, &request_id, start.elapsed());
+                    Ok(res)
+                }
+            })
             .wrap(
                 IdentityService::new(
                     CookieIdentityPolicy::new(&[0;32])
@@ -49,8 +149,44 @@ async fn main() -> std::io::Result<()> {
                         .secure(false)
                 )
             )
+            // static files are referenced by fingerprinted urls, and uploads are named by their content,
+            // so both can be cached forever
+            .wrap_fn(move |req, srv| {
+                let is_static = req.path().starts_with("/static/") || req.path().starts_with("/media/");
+                let policy = static_files_policy.clone();
+                let res = srv.call(req);
+                async move {
+                    let mut res = res.await?;
+                    if is_static && res.status().is_success() {
+                        res.headers_mut().insert(header::CACHE_CONTROL, policy);
+                    }
+                    Ok(res)
+                }
+            })
+            // request count and latency per route for /metrics
+            .wrap_fn(|req, srv| {
+                let start = std::time::Instant::now();
+                let method = req.method().to_string();
+                let res = srv.call(req);
+                async move {
+                    let res = res.await?;
+                    let status = res.status().as_u16();
+                    metrics::record_request(&method, metrics::route_label(res.request(), status), status, start.elapsed());
+                    Ok(res)
+                }
+            })
+            // probes for the orchestrator
+            .service(web::resource("/healthz").route(web::get().to(views::health::healthz)))
+            .service(web::resource("/readyz").route(web::get().to(views::health::readyz)))
+            .service(web::resource("/metrics").route(web::get().to(views::health::metrics)))
+            // for search engines
+            .service(web::resource("/robots.txt").route(web::get().to(views::sitemap::robots)))
+            .service(web::resource("/sitemap.xml").route(web::get().to(views::sitemap::sitemap)))
+            .service(web::resource("/sitemap-{page}.xml").route(web::get().to(views::sitemap::sitemap_page)))
             // css, js files loading
-            .service(fs::Files::new("/static", "static/").show_files_listing())
+            .service(fs::Files::new("/static", &static_dir).show_files_listing())
+            // uploaded images, no listing
+            .service(fs::Files::new(utils::media::MEDIA_URL, &media_dir))
             .service(
                 web::scope("/admin")
                     .service(web::resource("/").route(web::get().to(views::auth::redirect_admin)))
@@ -63,15 +199,44 @@ async fn main() -> std::io::Result<()> {
                     )
                     .service(web::resource("/all_posts/").route(web::get().to(views::auth::show_all_posts_by_author)))
                     .service(web::resource("/today_comments/").route(web::get().to(views::auth::today_comments)))
+                    .service(web::resource("/comments/").route(web::get().to(views::comments::commenter_comments)))
+                    .service(web::resource("/comments/{id}/").route(web::get().to(views::comments::edit_comment))
+                                                             .route(web::post().to(views::comments::save_comment))
+                    )
+                    .service(web::resource("/comments/{id}/delete/").route(web::post().to(views::comments::delete_comment)))
+                    .service(web::resource("/blocklist/").route(web::get().to(views::comments::show_blocklist))
+                                                         .route(web::post().to(views::comments::add_blocked))
+                    )
+                    .service(web::resource("/blocklist/{id}/delete/").route(web::post().to(views::comments::remove_blocked)))
                     .service(web::resource("/all_guests_messages/").route(web::get().to(views::auth::all_guests_messages)))
+                    .service(web::resource("/all_guests_messages/{id}/").route(web::get().to(views::auth::guest_message))
+                                                                        .route(web::post().to(views::auth::update_guest_message))
+                    )
+                    .service(web::resource("/all_guests_messages/{id}/reply/").route(web::post().to(views::auth::reply_guest_message)))
                     .service(web::resource("/about_self/").route(web::get().to(views::auth::about_self)))
                     .service(web::resource("/logout/").route(web::get().to(views::auth::logout)))
+                    .service(web::resource("/cache_stats/").route(web::get().to(views::auth::cache_stats)))
                     .service(web::resource("/write_post/").route(web::get().to(views::auth::write_post))
                                                           .route(web::post().to(views::auth::submit_post))
                     )
                     .service(web::resource("/register/").route(web::get().to(views::auth::register))
                                                         .route(web::post().to(views::auth::handle_registration))
                     )
+                    .service(web::resource("/verify_email/{token}/").route(web::get().to(views::auth::verify_email)))
+                    .service(web::resource("/invitations/").route(web::get().to(views::auth::invitations))
+                                                           .route(web::post().to(views::auth::create_invitation))
+                    )
+                    .service(web::resource("/media/").route(web::get().to(views::media::gallery))
+                                                     .route(web::post().to(views::media::upload_media))
+                    )
+                    .service(web::resource("/analytics/").route(web::get().to(views::analytics::analytics)))
+                    .service(web::resource("/subscribers/").route(web::get().to(views::newsletter::subscribers))
+                                                           .route(web::post().to(views::newsletter::update_subscriber))
+                    )
+                    .service(web::resource("/subscribers/export/").route(web::get().to(views::newsletter::export_subscribers)))
+                    .service(web::resource("/transfer/").route(web::get().to(views::transfer::transfer)))
+                    .service(web::resource("/transfer/export/").route(web::get().to(views::transfer::export_posts)))
+                    .service(web::resource("/transfer/import/").route(web::post().to(views::transfer::import_posts)))
                     .service(web::resource("/email_exist/").route(web::post().to(views::auth::email_exist)))
                     .service(web::resource("/reset_password/").route(web::get().to(views::auth::reset_password))
                                                               .route(web::post().to(views::auth::save_changed_password))
@@ -89,25 +254,62 @@ async fn main() -> std::io::Result<()> {
                     .service(web::resource("/not_found/").route(web::get().to(views::post::page_404)))
                     .service(web::resource("/all_posts/").route(web::get().to(views::post::all_posts)))
                     .service(web::resource("/add_comment/").route(web::post().to(views::post::add_comment)))
-                    .service(web::resource("/user_likes/").route(web::post().to(views::post::user_likes)))
+                    .service(web::resource("/user_likes/").route(web::get().to(views::post::like_state))
+                                                          .route(web::post().to(views::post::user_likes))
+                                                          .route(web::delete().to(views::post::user_unlikes))
+                    )
+                    .service(web::resource("/subscribe/{id}/{expires}/{signature}/").route(web::get().to(views::post::subscribe))
+                                                                                    .route(web::post().to(views::post::subscribe))
+                    )
+                    .service(web::resource("/unsubscribe/{id}/{signature}/").route(web::get().to(views::post::unsubscribe))
+                                                                           .route(web::post().to(views::post::unsubscribe))
+                    )
+                    .service(web::resource("/newsletter/subscribe/").route(web::post().to(views::newsletter::subscribe)))
+                    .service(web::resource("/newsletter/confirm/{token}/").route(web::get().to(views::newsletter::confirm)))
+                    .service(web::resource("/newsletter/unsubscribe/{token}/").route(web::get().to(views::newsletter::unsubscribe))
+                                                                              .route(web::post().to(views::newsletter::unsubscribe))
+                    )
                     .service(web::resource("/add_contact/").route(web::post().to(views::post::add_contact)))
                     .service(web::resource("/search/").route(web::post().to(views::post::search)))
                     .service(web::resource("/page/{page_num}/").route(web::get().to(views::post::pagination)))
                     .service(web::resource("/article/{title}/").route(web::get().to(views::post::post_detail)))
                     .service(web::resource("/category/{year}/").route(web::get().to(views::post::show_posts_by_year)))
             )
-    )
-    .workers(workers);
+            // old permalinks of imported blogs
+            .default_service(web::route().to(views::post::not_found))
+    })
+    .workers(workers)
+    .shutdown_timeout(shutdown_timeout); // in-flight requests are drained on SIGTERM/SIGINT
     
-    // compile http1 capability if feature http1 enabled while http2 ignored
-    #[cfg(feature = "http1")]
-    {
-        blog_server.bind(format!("{}:{}", &address, &port))?.run().await
-    }
+    // the plain and tls listeners can be bound at the same time
+    let plain_address = format!("{}:{}", &address, &port);
+    let blog_server = if tls_config.plain_http.eq(&PlainHttp::Serve) {
+        blog_server.bind(&plain_address)?
+    } else {
+        blog_server
+    };
+    let blog_server = if tls_config.enabled {
+        let ssl_builder = tls::load_ssl(&tls_config.cert, &tls_config.key)
+                             .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
+        blog_server.bind_openssl(format!("{}:{}", &address, tls_config.port), ssl_builder)?
+    } else {
+        blog_server
+    };
     
-    #[cfg(feature = "http2")]
-    {
-        use crate::utils::utils::load_ssl;
-        blog_server.bind_ssl(format!("{}:{}", &address, &port), load_ssl()?)?.run().await
+    if tls_config.enabled && tls_config.plain_http.eq(&PlainHttp::Redirect) {
+        let tls_port = tls_config.port;
+        let redirect_server = HttpServer::new(move || {
+            App::new().data(tls_port).default_service(web::route().to(tls::redirect_to_https))
+        })
+        .workers(1)
+        .bind(&plain_address)?;
+        
+        let served = futures::future::try_join(blog_server.run(), redirect_server.run()).await.map(|_| ());
+        job_runner.shutdown(std::time::Duration::from_secs(shutdown_timeout)).await;
+        served
+    } else {
+        let served = blog_server.run().await;
+        job_runner.shutdown(std::time::Duration::from_secs(shutdown_timeout)).await;
+        served
     }
 }
@@ -0,0 +1,321 @@
+use actix_web::web::Data;
+use chrono::{ Duration, NaiveDate, NaiveDateTime, Utc };
+use diesel::prelude::*;
+use serde_derive::{ Deserialize, Serialize };
+use std::collections::{ BTreeMap, HashMap, HashSet };
+
+use crate::utils::utils::{ run_blocking, DbConnection, DbPool };
+use super::schema::{ self, comments, daily_referrers, daily_views, page_views, post_likes, posts, search_terms };
+
+#[derive(Queryable, Insertable, Debug, Serialize, Deserialize, Identifiable, Clone)]
+#[table_name = "page_views"]
+pub(crate) struct PageView {
+    pub(crate) id: i32,
+    pub(crate) post_id: i32,
+    pub(crate) visitor: String,
+    pub(crate) referrer: String,
+    pub(crate) viewed: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "page_views"]
+pub(crate) struct NewPageView {
+    pub(crate) post_id: i32,
+    pub(crate) visitor: String,
+    pub(crate) referrer: String,
+    pub(crate) viewed: NaiveDateTime,
+}
+
+#[derive(Queryable, Insertable, Debug, Serialize, Deserialize, Identifiable, Clone)]
+#[table_name = "daily_views"]
+pub(crate) struct DailyView {
+    pub(crate) id: i32,
+    pub(crate) day: NaiveDate,
+    pub(crate) post_id: i32,
+    pub(crate) views: i32,
+    pub(crate) visitors: i32,
+}
+
+#[derive(Queryable, Insertable, Debug, Serialize, Deserialize, Identifiable, Clone)]
+#[table_name = "daily_referrers"]
+pub(crate) struct DailyReferrer {
+    pub(crate) id: i32,
+    pub(crate) day: NaiveDate,
+    pub(crate) referrer: String,
+    pub(crate) views: i32,
+}
+
+#[derive(Queryable, Insertable, Debug, Serialize, Deserialize, Identifiable, Clone)]
+#[table_name = "search_terms"]
+pub(crate) struct SearchTerm {
+    pub(crate) id: i32,
+    pub(crate) day: NaiveDate,
+    pub(crate) term: String,
+    pub(crate) searches: i32,
+}
+
+// readers count a visitor once a day for every article
+#[derive(Debug, Default, Clone, Copy, Serialize, PartialEq)]
+pub(crate) struct ViewCount {
+    pub(crate) views: i32,
+    pub(crate) readers: i32,
+}
+
+impl ViewCount {
+    fn add(&mut self, other: ViewCount) {
+        self.views += other.views;
+        self.readers += other.readers;
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct DayStats {
+    pub(crate) day: NaiveDate,
+    pub(crate) views: i32,
+    pub(crate) readers: i32,
+    pub(crate) likes: i32,
+    pub(crate) comments: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct PostStats {
+    pub(crate) post_id: i32,
+    pub(crate) title: String,
+    pub(crate) views: i32,
+    pub(crate) readers: i32,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct Analytics {
+    pub(crate) days: Vec<DayStats>, // every day of the range, the oldest first
+    pub(crate) posts: Vec<PostStats>, // the most viewed first
+    pub(crate) referrers: Vec<(String, i32)>,
+    pub(crate) search_terms: Vec<(String, i32)>,
+}
+
+const TOP: usize = 20;
+
+type ViewCounts = BTreeMap<(NaiveDate, i32), ViewCount>;
+
+// views and readers of every article and day, and the views from every other site and day
+fn count_views(rows: &[PageView]) -> (ViewCounts, BTreeMap<(NaiveDate, String), i32>) {
+    let mut visitors: HashMap<(NaiveDate, i32), HashSet<&str>> = HashMap::new();
+    let mut referrers = BTreeMap::new();
+    for row in rows {
+        visitors.entry((row.viewed.date(), row.post_id)).or_default().insert(row.visitor.as_str());
+        if !row.referrer.is_empty() {
+            *referrers.entry((row.viewed.date(), row.referrer.clone())).or_insert(0) += 1;
+        }
+    }
+    let mut counts: ViewCounts = BTreeMap::new();
+    for row in rows {
+        counts.entry((row.viewed.date(), row.post_id)).or_default().views += 1;
+    }
+    for (key, readers) in visitors {
+        counts.entry(key).or_default().readers = readers.len() as i32;
+    }
+    (counts, referrers)
+}
+
+fn top<K: Ord + Clone>(counts: BTreeMap<K, i32>) -> Vec<(K, i32)> {
+    let mut counts: Vec<(K, i32)> = counts.into_iter().collect();
+    counts.sort_by(|(_, lhs), (_, rhs)| rhs.cmp(lhs));
+    counts.truncate(TOP);
+    counts
+}
+
+// an existing row of the day is added to, otherwise inserted
+fn add_daily_view(conn: &DbConnection, view_day: NaiveDate, post: i32, count: ViewCount) -> Result<(), failure::Error> {
+    use schema::daily_views::dsl::*;
+    let updated = diesel::update(daily_views.filter(day.eq(view_day)).filter(post_id.eq(post)))
+                         .set((views.eq(views + count.views), visitors.eq(visitors + count.readers)))
+                         .execute(conn)?;
+    if updated.eq(&0) {
+        diesel::insert_into(daily_views)
+               .values((day.eq(view_day), post_id.eq(post), views.eq(count.views), visitors.eq(count.readers)))
+               .execute(conn)?;
+    }
+    Ok(())
+}
+
+fn add_daily_referrer(conn: &DbConnection, view_day: NaiveDate, host: &str, count: i32) -> Result<(), failure::Error> {
+    use schema::daily_referrers::dsl::*;
+    let updated = diesel::update(daily_referrers.filter(day.eq(view_day)).filter(referrer.eq(host)))
+                         .set(views.eq(views + count))
+                         .execute(conn)?;
+    if updated.eq(&0) {
+        diesel::insert_into(daily_referrers).values((day.eq(view_day), referrer.eq(host), views.eq(count))).execute(conn)?;
+    }
+    Ok(())
+}
+
+// the number of rows of every day since the given one
+fn count_by_day(times: Vec<Option<NaiveDateTime>>) -> BTreeMap<NaiveDate, i32> {
+    let mut counts = BTreeMap::new();
+    times.into_iter().flatten().for_each(|time| *counts.entry(time.date()).or_insert(0) += 1);
+    counts
+}
+
+pub(crate) struct AnalyticsOperation;
+
+impl AnalyticsOperation {
+    pub(crate) fn record_view(new_view: &NewPageView, pool: &Data<DbPool>) -> Result<(), failure::Error> {
+        let conn = &*pool.get()?;
+        diesel::insert_into(page_views::table).values(new_view).execute(conn)?;
+        Ok(())
+    }
+
+    pub(crate) fn record_search(search_term: &str, pool: &Data<DbPool>) -> Result<(), failure::Error> {
+        use schema::search_terms::dsl::*;
+        let conn = &*pool.get()?;
+        let today = Utc::now().naive_utc().date();
+
+        // one statement, two concurrent searches of a new term can't both insert it
+        #[cfg(feature = "postgres")]
+        diesel::insert_into(search_terms)
+               .values((day.eq(today), term.eq(search_term), searches.eq(1)))
+               .on_conflict((day, term))
+               .do_update()
+               .set(searches.eq(searches + 1))
+               .execute(conn)?;
+        // diesel has no upsert for sqlite
+        #[cfg(feature = "sqlite")]
+        diesel::sql_query("INSERT INTO search_terms (day, term, searches) VALUES (?, ?, 1) \
+                           ON CONFLICT (day, term) DO UPDATE SET searches = searches + 1")
+               .bind::<diesel::sql_types::Date, _>(today)
+               .bind::<diesel::sql_types::Text, _>(search_term)
+               .execute(conn)?;
+        Ok(())
+    }
+
+    // the views of the days before the given one become daily rows, returns how many views were rolled up.
+    // the day is over for all of them, so the readers of a day are counted from all its views at once.
+    pub(crate) fn rollup(before: NaiveDate, pool: &Data<DbPool>) -> Result<usize, failure::Error> {
+        use schema::page_views::dsl::*;
+        let conn = &*pool.get()?;
+        let cutoff = before.and_hms(0, 0, 0);
+
+        conn.transaction::<_, failure::Error, _>(|| {
+            let rows = page_views.filter(viewed.lt(cutoff)).order(id.asc()).load::<PageView>(conn)?;
+            let last_id = match rows.last() {
+                Some(row) => row.id,
+                None => return Ok(0),
+            };
+            let (counts, referrers) = count_views(&rows);
+            for ((view_day, post), count) in counts {
+                add_daily_view(conn, view_day, post, count)?;
+            }
+            for ((view_day, host), count) in referrers {
+                add_daily_referrer(conn, view_day, &host, count)?;
+            }
+            diesel::delete(page_views.filter(viewed.lt(cutoff)).filter(id.le(last_id))).execute(conn)?;
+            Ok(rows.len())
+        })
+    }
+
+    // the views and visitors of today so far
+    pub(crate) fn today_views(pool: &Data<DbPool>) -> Result<(i64, i64), failure::Error> {
+        use schema::page_views::dsl::*;
+        let conn = &*pool.get()?;
+        let midnight = Utc::now().naive_utc().date().and_hms(0, 0, 0);
+
+        let today = page_views.filter(viewed.ge(midnight));
+        let views = today.count().get_result::<i64>(conn)?;
+        let visitors = today.select(visitor).distinct().load::<String>(conn)?.len() as i64;
+        Ok((views, visitors))
+    }
+
+    // the days from since to today, of an article or all of them. the referrers and search terms are of the whole blog.
+    pub(crate) fn analytics(since: NaiveDate, post: Option<i32>, pool: &Data<DbPool>) -> Result<Analytics, failure::Error> {
+        let conn = &*pool.get()?;
+        let today = Utc::now().naive_utc().date();
+        let midnight = since.and_hms(0, 0, 0);
+
+        // the rolled up days, and the views which aren't rolled up yet
+        let mut rolled_up = daily_views::table.filter(daily_views::day.ge(since)).into_boxed();
+        let mut raw = page_views::table.filter(page_views::viewed.ge(midnight)).into_boxed();
+        let mut likes = post_likes::table.filter(post_likes::created.ge(midnight)).select(post_likes::created.nullable()).into_boxed();
+        let mut new_comments = comments::table.filter(comments::committed_time.ge(midnight)).select(comments::committed_time).into_boxed();
+        if let Some(post) = post {
+            rolled_up = rolled_up.filter(daily_views::post_id.eq(post));
+            raw = raw.filter(page_views::post_id.eq(post));
+            likes = likes.filter(post_likes::post_id.eq(post));
+            new_comments = new_comments.filter(comments::post_id.eq(post));
+        }
+        let (mut counts, raw_referrers) = count_views(&raw.load::<PageView>(conn)?);
+        for row in rolled_up.load::<DailyView>(conn)? {
+            counts.entry((row.day, row.post_id)).or_default().add(ViewCount { views: row.views, readers: row.visitors });
+        }
+        let likes = count_by_day(likes.load::<Option<NaiveDateTime>>(conn)?);
+        let new_comments = count_by_day(new_comments.load::<Option<NaiveDateTime>>(conn)?);
+
+        let mut by_day: BTreeMap<NaiveDate, ViewCount> = BTreeMap::new();
+        let mut by_post: BTreeMap<i32, ViewCount> = BTreeMap::new();
+        for ((view_day, post_id), count) in counts.iter() {
+            by_day.entry(*view_day).or_default().add(*count);
+            by_post.entry(*post_id).or_default().add(*count);
+        }
+        let mut day = since;
+        let mut days = Vec::new();
+        while day.le(&today) {
+            let count = by_day.get(&day).cloned().unwrap_or_default();
+            days.push(DayStats {
+                day,
+                views: count.views,
+                readers: count.readers,
+                likes: likes.get(&day).cloned().unwrap_or_default(),
+                comments: new_comments.get(&day).cloned().unwrap_or_default(),
+            });
+            day = day + Duration::days(1);
+        }
+
+        let mut top_posts: Vec<(i32, ViewCount)> = by_post.into_iter().collect();
+        top_posts.sort_by(|(_, lhs), (_, rhs)| rhs.views.cmp(&lhs.views));
+        top_posts.truncate(TOP);
+        let ids: Vec<i32> = top_posts.iter().map(|(id, _)| *id).collect();
+        let titles: HashMap<i32, String> = posts::table.filter(posts::id.eq_any(ids)).select((posts::id, posts::title)).load::<(i32, String)>(conn)?.into_iter().collect();
+        let posts = top_posts.into_iter().map(|(post_id, count)| PostStats {
+            post_id,
+            title: titles.get(&post_id).cloned().unwrap_or_default(),
+            views: count.views,
+            readers: count.readers,
+        }).collect();
+
+        let mut referrers: BTreeMap<String, i32> = BTreeMap::new();
+        for ((_, host), count) in raw_referrers {
+            *referrers.entry(host).or_insert(0) += count;
+        }
+        for row in daily_referrers::table.filter(daily_referrers::day.ge(since)).load::<DailyReferrer>(conn)? {
+            *referrers.entry(row.referrer).or_insert(0) += row.views;
+        }
+        let mut terms: BTreeMap<String, i32> = BTreeMap::new();
+        for row in search_terms::table.filter(search_terms::day.ge(since)).load::<SearchTerm>(conn)? {
+            *terms.entry(row.term).or_insert(0) += row.searches;
+        }
+
+        Ok(Analytics { days, posts, referrers: top(referrers), search_terms: top(terms) })
+    }
+}
+
+// async wrappers of the operations above
+impl AnalyticsOperation {
+    pub(crate) async fn record_view_async(new_view: NewPageView, pool: &Data<DbPool>) -> Result<(), failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::record_view(&new_view, &pool)).await
+    }
+
+    pub(crate) async fn record_search_async(term: String, pool: &Data<DbPool>) -> Result<(), failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::record_search(&term, &pool)).await
+    }
+
+    pub(crate) async fn today_views_async(pool: &Data<DbPool>) -> Result<(i64, i64), failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::today_views(&pool)).await
+    }
+
+    pub(crate) async fn analytics_async(since: NaiveDate, post: Option<i32>, pool: &Data<DbPool>) -> Result<Analytics, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::analytics(since, post, &pool)).await
+    }
+}
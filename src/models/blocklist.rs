@@ -0,0 +1,77 @@
+use actix_web::web::Data;
+use chrono::{ NaiveDateTime, Utc };
+use diesel::prelude::*;
+use serde_derive::{ Deserialize, Serialize };
+
+use crate::utils::utils::{ last_insert_id, run_blocking, DbPool };
+use super::schema::{ self, blocklist };
+
+// an address, or every address of a domain as @example.com
+pub(crate) const EMAIL: &str = "email";
+// the name of a commenter, * and ? are wildcards
+pub(crate) const NAME: &str = "name";
+// an address, or a range as 10.0.0.0/8
+pub(crate) const IP: &str = "ip";
+pub(crate) const KINDS: [&str; 3] = [EMAIL, NAME, IP];
+
+#[derive(Queryable, Insertable, Serialize, Deserialize, Debug, Clone)]
+#[table_name = "blocklist"]
+pub(crate) struct BlockedEntry {
+    pub(crate) id: i32,
+    pub(crate) kind: String,
+    pub(crate) value: String,
+    pub(crate) reason: String,
+    pub(crate) created: NaiveDateTime,
+}
+
+pub(crate) struct BlocklistOperation;
+
+impl BlocklistOperation {
+    // the value is stored as it's given, normalize it first. None if it's blocked already.
+    pub(crate) fn add(entry_kind: &str, entry_value: &str, why: &str, pool: &Data<DbPool>) -> Result<Option<BlockedEntry>, failure::Error> {
+        use schema::blocklist::dsl::*;
+        let conn = &*pool.get()?;
+
+        conn.transaction::<_, failure::Error, _>(|| {
+            let existing = blocklist.filter(kind.eq(entry_kind)).filter(value.eq(entry_value)).count().get_result::<i64>(conn)?;
+            if existing.gt(&0) {
+                return Ok(None);
+            }
+            diesel::insert_into(blocklist)
+                   .values((kind.eq(entry_kind), value.eq(entry_value), reason.eq(why), created.eq(Utc::now().naive_utc())))
+                   .execute(conn)?;
+            Ok(blocklist.find(last_insert_id(conn)?).first::<BlockedEntry>(conn).optional()?)
+        })
+    }
+
+    pub(crate) fn remove(entry_id: i32, pool: &Data<DbPool>) -> Result<bool, failure::Error> {
+        use schema::blocklist::dsl::*;
+        let conn = &*pool.get()?;
+        Ok(diesel::delete(blocklist.find(entry_id)).execute(conn)?.gt(&0))
+    }
+
+    // the latest first
+    pub(crate) fn get_entries(pool: &Data<DbPool>) -> Result<Vec<BlockedEntry>, failure::Error> {
+        use schema::blocklist::dsl::*;
+        let conn = &*pool.get()?;
+        Ok(blocklist.order(id.desc()).load::<BlockedEntry>(conn)?)
+    }
+}
+
+// async wrappers of the operations above
+impl BlocklistOperation {
+    pub(crate) async fn add_async(entry_kind: String, entry_value: String, why: String, pool: &Data<DbPool>) -> Result<Option<BlockedEntry>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::add(&entry_kind, &entry_value, &why, &pool)).await
+    }
+
+    pub(crate) async fn remove_async(entry_id: i32, pool: &Data<DbPool>) -> Result<bool, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::remove(entry_id, &pool)).await
+    }
+
+    pub(crate) async fn get_entries_async(pool: &Data<DbPool>) -> Result<Vec<BlockedEntry>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::get_entries(&pool)).await
+    }
+}
@@ -3,10 +3,12 @@ use chrono::{ Utc, NaiveDateTime, NaiveDate, Datelike }; // Datelike for month()
 use diesel::prelude::*;
 use serde_derive::{ Deserialize, Serialize };
 
-use crate::utils::utils::PgPool;
+use crate::utils::{ cache, utils::{ run_blocking, DbPool } };
 use super::{ schema::{ self, comments }, post::Post };
 
-#[derive(Queryable, Serialize, Deserialize, AsChangeset, Debug, Identifiable, Associations)]
+sql_function!(fn lower(x: diesel::sql_types::Text) -> diesel::sql_types::Text);
+
+#[derive(Queryable, Insertable, Serialize, Deserialize, AsChangeset, Debug, Identifiable, Associations)]
 #[table_name="comments"]
 #[belongs_to(Post)] // must derive Associations
 pub(crate) struct Comment {
@@ -33,6 +35,8 @@ pub(crate) struct CreateComment {
     pub(crate) comment: String,
     pub(crate) username: String,
     pub(crate) email: String,
+    #[serde(default)]
+    pub(crate) notify_replies: bool, // mail the commenter about the next comments
 }
 
 impl NewComment {
@@ -50,7 +54,7 @@ impl NewComment {
 pub(crate) struct CommentOperation;
 
 impl CommentOperation {
-    pub(crate) fn get_all_comments(pool: &Data<PgPool>) -> Result<Vec<Comment>, failure::Error> {
+    pub(crate) fn get_all_comments(pool: &Data<DbPool>) -> Result<Vec<Comment>, failure::Error> {
         use super::schema::comments::dsl::*;
         let conn = &*pool.get()?;
     
@@ -58,7 +62,13 @@ impl CommentOperation {
         Ok(all_comments)
     }
 
-    pub(crate) fn get_comments_by_post(comment_id: i32, pool: &Data<PgPool>) -> Result<Vec<Comment>, failure::Error> {
+    pub(crate) fn count_comments(pool: &Data<DbPool>) -> Result<i64, failure::Error> {
+        use super::schema::comments::dsl::*;
+        let conn = &*pool.get()?;
+        Ok(comments.count().get_result(conn)?)
+    }
+
+    pub(crate) fn get_comments_by_post(comment_id: i32, pool: &Data<DbPool>) -> Result<Vec<Comment>, failure::Error> {
         use super::schema::comments::dsl::*;
         let conn = &*pool.get()?;
         
@@ -66,14 +76,45 @@ impl CommentOperation {
         Ok(all_comments)
     }
     
-    pub(crate) fn insert_comment(new_comment: NewComment, pool: &Data<PgPool>) -> Result<(), failure::Error> {
+    pub(crate) fn insert_comment(new_comment: NewComment, pool: &Data<DbPool>) -> Result<(), failure::Error> {
         use super::schema::comments::dsl::*;
         let conn = &*pool.get()?;
         diesel::insert_into(comments).values(&new_comment).execute(conn)?;
+        cache::invalidate_all();
         Ok(())
     }
     
-    pub(crate) fn get_today_comments(pool: &Data<PgPool>) -> Result<Vec<Comment>, failure::Error> {
+    pub(crate) fn get_comment(comment_id: i32, pool: &Data<DbPool>) -> Result<Option<Comment>, failure::Error> {
+        use super::schema::comments::dsl::*;
+        let conn = &*pool.get()?;
+        Ok(comments.find(comment_id).first::<Comment>(conn).optional()?)
+    }
+
+    // only the text changes, the commenter and the time stay. false if there's no such comment
+    pub(crate) fn update_comment(comment_id: i32, text: &str, pool: &Data<DbPool>) -> Result<bool, failure::Error> {
+        use super::schema::comments::dsl::*;
+        let conn = &*pool.get()?;
+        let updated = diesel::update(comments.find(comment_id)).set(comment.eq(text)).execute(conn)?;
+        cache::invalidate_all();
+        Ok(updated.gt(&0))
+    }
+
+    pub(crate) fn delete_comment(comment_id: i32, pool: &Data<DbPool>) -> Result<bool, failure::Error> {
+        use super::schema::comments::dsl::*;
+        let conn = &*pool.get()?;
+        let deleted = diesel::delete(comments.find(comment_id)).execute(conn)?;
+        cache::invalidate_all();
+        Ok(deleted.gt(&0))
+    }
+
+    // every comment written with this email, however it was typed. the latest first
+    pub(crate) fn get_comments_by_email(address: &str, pool: &Data<DbPool>) -> Result<Vec<Comment>, failure::Error> {
+        use super::schema::comments::dsl::*;
+        let conn = &*pool.get()?;
+        Ok(comments.filter(lower(email).eq(address.trim().to_lowercase())).order(id.desc()).load::<Comment>(conn)?)
+    }
+    
+    pub(crate) fn get_today_comments(pool: &Data<DbPool>) -> Result<Vec<Comment>, failure::Error> {
         use super::schema::comments::dsl::*;
         let conn = &*pool.get()?;
         
@@ -83,13 +124,60 @@ impl CommentOperation {
         Ok(today_comments)
     }
     
-    pub(crate) fn get_posts_by_comments(ids: impl AsRef<[i32]>, pool: &Data<PgPool>) -> Result<Vec<Post>, failure::Error> {
+    pub(crate) fn get_posts_by_comments(ids: impl AsRef<[i32]>, pool: &Data<DbPool>) -> Result<Vec<Post>, failure::Error> {
+        use super::schema::posts::dsl::*;
         let conn = &*pool.get()?;
         
-        // like [1, 2, 3, 4] => 1, 2, 3, 4
-        let rm_brackets: String = format!("{:?}", ids.as_ref()).chars().filter(|c| c.ne(&'[') && c.ne(&']')).collect();
-        let raw_sql = format!("SELECT * FROM posts WHERE id in ({})", rm_brackets);
-        let posts = diesel::sql_query(raw_sql).load::<Post>(conn)?;
-        Ok(posts)
+        // = ANY($1) on postgres, IN (...) on sqlite
+        let found_posts = posts.filter(schema::posts::id.eq_any(ids.as_ref())).load::<Post>(conn)?;
+        Ok(found_posts)
     }
-}
\ No newline at end of file
+}
+
+// async wrappers of the operations above
+impl CommentOperation {
+    pub(crate) async fn get_all_comments_async(pool: &Data<DbPool>) -> Result<Vec<Comment>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::get_all_comments(&pool)).await
+    }
+
+    pub(crate) async fn get_comments_by_post_async(post_id: i32, pool: &Data<DbPool>) -> Result<Vec<Comment>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::get_comments_by_post(post_id, &pool)).await
+    }
+
+    pub(crate) async fn insert_comment_async(new_comment: NewComment, pool: &Data<DbPool>) -> Result<(), failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::insert_comment(new_comment, &pool)).await
+    }
+
+    pub(crate) async fn get_comment_async(comment_id: i32, pool: &Data<DbPool>) -> Result<Option<Comment>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::get_comment(comment_id, &pool)).await
+    }
+
+    pub(crate) async fn update_comment_async(comment_id: i32, text: String, pool: &Data<DbPool>) -> Result<bool, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::update_comment(comment_id, &text, &pool)).await
+    }
+
+    pub(crate) async fn delete_comment_async(comment_id: i32, pool: &Data<DbPool>) -> Result<bool, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::delete_comment(comment_id, &pool)).await
+    }
+
+    pub(crate) async fn get_comments_by_email_async(address: String, pool: &Data<DbPool>) -> Result<Vec<Comment>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::get_comments_by_email(&address, &pool)).await
+    }
+
+    pub(crate) async fn get_today_comments_async(pool: &Data<DbPool>) -> Result<Vec<Comment>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::get_today_comments(&pool)).await
+    }
+
+    pub(crate) async fn get_posts_by_comments_async(ids: Vec<i32>, pool: &Data<DbPool>) -> Result<Vec<Post>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::get_posts_by_comments(ids, &pool)).await
+    }
+}
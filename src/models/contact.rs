@@ -3,11 +3,19 @@ use chrono::{ Utc, NaiveDateTime };
 use diesel::prelude::*;
 use serde_derive::{ Deserialize, Serialize };
 
-use crate::utils::utils::PgPool;
-use super::schema::{ self, contacts };
+use crate::utils::utils::{ last_insert_id, run_blocking, DbConnection, DbPool };
+use super::schema::{ contact_replies, contacts };
 
+// the views of the admin's inbox, every one but archived leaves the archived messages out
+pub(crate) const INBOX: &str = "inbox";
+pub(crate) const UNREAD: &str = "unread";
+pub(crate) const STARRED: &str = "starred";
+pub(crate) const ARCHIVED: &str = "archived";
+pub(crate) const FOLDERS: [&str; 4] = [INBOX, UNREAD, STARRED, ARCHIVED];
 
-#[derive(Queryable, Serialize, Deserialize, AsChangeset, Debug)]
+sql_function!(fn lower(x: diesel::sql_types::Text) -> diesel::sql_types::Text);
+
+#[derive(Queryable, Insertable, Serialize, Deserialize, AsChangeset, Debug)]
 #[table_name="contacts"]
 pub struct Contact {
     pub id: i32,
@@ -15,6 +23,9 @@ pub struct Contact {
     pub email: String,
     pub message: String,
     pub committed_time: Option<NaiveDateTime>,
+    pub is_read: bool,
+    pub starred: bool,
+    pub archived: bool,
 }
 
 #[derive(Insertable, Queryable, Serialize, Deserialize, AsChangeset, Debug)]
@@ -33,6 +44,66 @@ pub struct CreateContact {
     pub message: String,
 }
 
+// a change of the state of a message, the fields left None stay as they are
+#[derive(AsChangeset, Debug, Default, Clone, Copy, PartialEq)]
+#[table_name="contacts"]
+pub(crate) struct ContactState {
+    pub(crate) is_read: Option<bool>,
+    pub(crate) starred: Option<bool>,
+    pub(crate) archived: Option<bool>,
+}
+
+impl std::str::FromStr for ContactState {
+    type Err = failure::Error;
+
+    // the actions of the buttons in the inbox
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let state = ContactState::default();
+        match s {
+            "read" => Ok(ContactState { is_read: Some(true), ..state }),
+            "unread" => Ok(ContactState { is_read: Some(false), ..state }),
+            "star" => Ok(ContactState { starred: Some(true), ..state }),
+            "unstar" => Ok(ContactState { starred: Some(false), ..state }),
+            "archive" => Ok(ContactState { archived: Some(true), ..state }),
+            "unarchive" => Ok(ContactState { archived: Some(false), ..state }),
+            _ => Err(failure::format_err!("unknown action {}", s)),
+        }
+    }
+}
+
+#[derive(Queryable, Insertable, Serialize, Deserialize, Debug, Clone)]
+#[table_name="contact_replies"]
+pub(crate) struct ContactReply {
+    pub(crate) id: i32,
+    pub(crate) contact_id: i32,
+    pub(crate) user_id: i32,
+    pub(crate) body: String,
+    pub(crate) sent: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[table_name="contact_replies"]
+pub(crate) struct NewContactReply {
+    pub(crate) contact_id: i32,
+    pub(crate) user_id: i32,
+    pub(crate) body: String,
+    pub(crate) sent: NaiveDateTime,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct InboxPage {
+    pub(crate) contacts: Vec<Contact>,
+    pub(crate) total: i64, // the messages of the folder which match the search
+    pub(crate) page: i64,
+    pub(crate) pages: i64,
+}
+
+// % and _ of the search are matched as they are
+fn like_pattern(key_word: &str) -> String {
+    let escaped = key_word.to_lowercase().replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
 impl NewContact {
     pub fn new(new_contact: &CreateContact) -> Self {
         NewContact {
@@ -47,7 +118,7 @@ impl NewContact {
 pub(crate) struct ContactOperation;
 
 impl ContactOperation {
-    pub(crate) fn insert_contact(new_contact: NewContact, pool: &Data<PgPool>) -> Result<(), failure::Error> {
+    pub(crate) fn insert_contact(new_contact: NewContact, pool: &Data<DbPool>) -> Result<(), failure::Error> {
         use super::schema::contacts::dsl::*;
         let conn = &*pool.get()?;
         
@@ -55,11 +126,109 @@ impl ContactOperation {
         Ok(())
     }
     
-    pub(crate) fn get_all_contacts(pool: &Data<PgPool>) -> Result<Vec<Contact>, failure::Error> {
+    // a page of a folder, the latest first. the search looks into the name, the email and the message.
+    pub(crate) fn get_inbox(folder: &str, key_word: Option<&str>, page: i64, per_page: i64, pool: &Data<DbPool>) -> Result<InboxPage, failure::Error> {
         use super::schema::contacts::dsl::*;
         let conn = &*pool.get()?;
-        
-        let all_contacts = contacts.order(schema::contacts::id.desc()).load::<Contact>(conn)?;
-        Ok(all_contacts)
+
+        let filtered = || {
+            let mut query = contacts.into_boxed();
+            query = match folder {
+                UNREAD => query.filter(archived.eq(false)).filter(is_read.eq(false)),
+                STARRED => query.filter(archived.eq(false)).filter(starred.eq(true)),
+                ARCHIVED => query.filter(archived.eq(true)),
+                _ => query.filter(archived.eq(false)),
+            };
+            if let Some(key_word) = key_word.map(str::trim).filter(|key_word| !key_word.is_empty()) {
+                let pattern = like_pattern(key_word);
+                query = query.filter(lower(tourist_name).like(pattern.clone()).escape('\\')
+                                     .or(lower(email).like(pattern.clone()).escape('\\'))
+                                     .or(lower(message).like(pattern).escape('\\')));
+            }
+            query
+        };
+        let total = filtered().count().get_result::<i64>(conn)?;
+        let pages = ((total + per_page - 1) / per_page).max(1);
+        let page = page.max(1).min(pages);
+        let found = filtered().order(id.desc()).limit(per_page).offset((page - 1) * per_page).load::<Contact>(conn)?;
+        Ok(InboxPage { contacts: found, total, page, pages })
+    }
+
+    pub(crate) fn get_contact(contact_id: i32, pool: &Data<DbPool>) -> Result<Option<Contact>, failure::Error> {
+        use super::schema::contacts::dsl::*;
+        let conn = &*pool.get()?;
+        Ok(contacts.find(contact_id).first::<Contact>(conn).optional()?)
+    }
+
+    // false if there's no such message
+    pub(crate) fn set_state(contact_id: i32, state: ContactState, pool: &Data<DbPool>) -> Result<bool, failure::Error> {
+        use super::schema::contacts::dsl::*;
+        let conn = &*pool.get()?;
+        if state.eq(&ContactState::default()) {
+            return Ok(contacts.find(contact_id).count().get_result::<i64>(conn)?.gt(&0));
+        }
+        Ok(diesel::update(contacts.find(contact_id)).set(&state).execute(conn)?.gt(&0))
     }
-}
\ No newline at end of file
+
+    // the messages out of the archive, and how many of them are unread
+    pub(crate) fn inbox_counts(pool: &Data<DbPool>) -> Result<(i64, i64), failure::Error> {
+        use super::schema::contacts::dsl::*;
+        let conn = &*pool.get()?;
+        let total = contacts.filter(archived.eq(false)).count().get_result::<i64>(conn)?;
+        let unread = contacts.filter(archived.eq(false)).filter(is_read.eq(false)).count().get_result::<i64>(conn)?;
+        Ok((total, unread))
+    }
+
+    // the reply is logged and the message counts as read
+    pub(crate) fn insert_reply(new_reply: &NewContactReply, pool: &Data<DbPool>) -> Result<ContactReply, failure::Error> {
+        let conn = &*pool.get()?;
+        conn.transaction::<_, failure::Error, _>(|| Self::insert_reply_on(new_reply, conn))
+    }
+
+    // on a connection in a transaction already, like the one queueing the reply mail
+    pub(crate) fn insert_reply_on(new_reply: &NewContactReply, conn: &DbConnection) -> Result<ContactReply, failure::Error> {
+        diesel::insert_into(contact_replies::table).values(new_reply).execute(conn)?;
+        diesel::update(contacts::table.find(new_reply.contact_id)).set(contacts::is_read.eq(true)).execute(conn)?;
+        Ok(contact_replies::table.find(last_insert_id(conn)?).first::<ContactReply>(conn)?)
+    }
+
+    // the replies to a message, the first first
+    pub(crate) fn get_replies(contact: i32, pool: &Data<DbPool>) -> Result<Vec<ContactReply>, failure::Error> {
+        use super::schema::contact_replies::dsl::*;
+        let conn = &*pool.get()?;
+        Ok(contact_replies.filter(contact_id.eq(contact)).order(id.asc()).load::<ContactReply>(conn)?)
+    }
+}
+
+// async wrappers of the operations above
+impl ContactOperation {
+    pub(crate) async fn insert_contact_async(new_contact: NewContact, pool: &Data<DbPool>) -> Result<(), failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::insert_contact(new_contact, &pool)).await
+    }
+
+    pub(crate) async fn get_inbox_async(folder: String, key_word: Option<String>, page: i64, per_page: i64, pool: &Data<DbPool>) -> Result<InboxPage, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::get_inbox(&folder, key_word.as_deref(), page, per_page, &pool)).await
+    }
+
+    pub(crate) async fn get_contact_async(contact_id: i32, pool: &Data<DbPool>) -> Result<Option<Contact>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::get_contact(contact_id, &pool)).await
+    }
+
+    pub(crate) async fn set_state_async(contact_id: i32, state: ContactState, pool: &Data<DbPool>) -> Result<bool, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::set_state(contact_id, state, &pool)).await
+    }
+
+    pub(crate) async fn inbox_counts_async(pool: &Data<DbPool>) -> Result<(i64, i64), failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::inbox_counts(&pool)).await
+    }
+
+    pub(crate) async fn get_replies_async(contact: i32, pool: &Data<DbPool>) -> Result<Vec<ContactReply>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::get_replies(contact, &pool)).await
+    }
+}
@@ -0,0 +1,191 @@
+use actix_web::web::Data;
+use chrono::{ Duration, NaiveDateTime, Utc };
+use diesel::prelude::*;
+use serde_derive::{ Deserialize, Serialize };
+
+use crate::utils::mailer::{ self, Mail };
+use crate::utils::utils::{ last_insert_id, random_token, run_blocking, DbPool };
+use super::{ schema::{ self, invitations }, user::{ NewUser, User } };
+
+const CODE_LENGTH: usize = 24;
+const VERIFICATION_TOKEN_LENGTH: usize = 32;
+// the longest an invitation can stay valid
+pub(crate) const MAX_DAYS: i64 = 365;
+
+#[derive(Queryable, Insertable, Debug, Serialize, Deserialize, Identifiable)]
+#[table_name = "invitations"]
+pub(crate) struct Invitation {
+    pub(crate) id: i32,
+    pub(crate) code: String,
+    pub(crate) role: String,
+    pub(crate) created_by: i32,
+    pub(crate) created: NaiveDateTime,
+    pub(crate) expires: NaiveDateTime,
+    pub(crate) used_by: Option<i32>,
+    pub(crate) used: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "invitations"]
+pub(crate) struct NewInvitation {
+    pub(crate) code: String,
+    pub(crate) role: String,
+    pub(crate) created_by: i32,
+    pub(crate) created: NaiveDateTime,
+    pub(crate) expires: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct CreateInvitation {
+    pub(crate) role: String,
+    pub(crate) days: i64,
+}
+
+// the role preassigned to whoever registers with the invitation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Role {
+    Author, // can write posts
+    Staff,
+    Superuser, // can invite others
+}
+
+impl Role {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Role::Author => "author",
+            Role::Staff => "staff",
+            Role::Superuser => "superuser",
+        }
+    }
+
+    fn apply_to(self, user: &mut NewUser) {
+        user.is_superuser = self.eq(&Role::Superuser);
+        user.is_staff = self.ne(&Role::Author);
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "author" => Ok(Role::Author),
+            "staff" => Ok(Role::Staff),
+            "superuser" => Ok(Role::Superuser),
+            _ => Err(failure::format_err!("role should be one of author/staff/superuser, but got {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Registration {
+    Registered(String), // the email verification token
+    InvalidInvitation, // unknown, used or expired
+    DuplicatedUser,
+}
+
+impl Invitation {
+    pub(crate) fn is_valid(&self, now: &NaiveDateTime) -> bool {
+        self.used.is_none() && self.expires.gt(now)
+    }
+}
+
+pub(crate) struct InvitationOperation;
+
+impl InvitationOperation {
+    pub(crate) fn create_invitation(invitation_role: Role, days: i64, creator: i32, pool: &Data<DbPool>) -> Result<Invitation, failure::Error> {
+        use schema::invitations::dsl::*;
+        if !(1..=MAX_DAYS).contains(&days) {
+            return Err(failure::format_err!("an invitation lasts 1 to {} days, but got {}", MAX_DAYS, days));
+        }
+        let conn = &*pool.get()?;
+
+        let now = Utc::now().naive_utc();
+        let new_invitation = NewInvitation {
+            code: random_token(CODE_LENGTH),
+            role: invitation_role.name().to_owned(),
+            created_by: creator,
+            created: now,
+            expires: now + Duration::days(days),
+        };
+        diesel::insert_into(invitations).values(&new_invitation).execute(conn)?;
+        let invitation = invitations.filter(schema::invitations::code.eq(&new_invitation.code)).first::<Invitation>(conn)?;
+        Ok(invitation)
+    }
+
+    pub(crate) fn get_all_invitations(pool: &Data<DbPool>) -> Result<Vec<Invitation>, failure::Error> {
+        use schema::invitations::dsl::*;
+        let conn = &*pool.get()?;
+
+        let all_invitations = invitations.order(schema::invitations::id.desc()).load::<Invitation>(conn)?;
+        Ok(all_invitations)
+    }
+
+    // consume the invitation, create an inactive user with the preassigned role and queue the mail with the
+    // verification token in one transaction, so that a code cannot be used twice by concurrent registrations,
+    // and isn't used up if the mail can't be queued.
+    pub(crate) fn register_with_invitation<F>(invitation_code: &str, mut new_user: NewUser, verification_mail: F, pool: &Data<DbPool>)
+        -> Result<Registration, failure::Error>
+    where
+        F: FnOnce(&str) -> Result<Mail, failure::Error>,
+    {
+        use schema::invitations::dsl::*;
+        let conn = &*pool.get()?;
+
+        conn.transaction::<_, failure::Error, _>(|| {
+            let now = Utc::now().naive_utc();
+            let invitation = invitations.filter(schema::invitations::code.eq(invitation_code));
+            // sqlite locks the whole database in a write transaction instead
+            #[cfg(feature = "postgres")]
+            let invitation = invitation.for_update().load::<Invitation>(conn)?.pop();
+            #[cfg(feature = "sqlite")]
+            let invitation = invitation.load::<Invitation>(conn)?.pop();
+            let invitation = match invitation {
+                Some(invitation) if invitation.is_valid(&now) => invitation,
+                _ => return Ok(Registration::InvalidInvitation),
+            };
+
+            let duplicated = schema::users::table.filter(schema::users::username.eq(&new_user.username))
+                                                 .or_filter(schema::users::email.eq(&new_user.email))
+                                                 .load::<User>(conn)?;
+            if !duplicated.is_empty() {
+                return Ok(Registration::DuplicatedUser);
+            }
+
+            invitation.role.parse::<Role>()?.apply_to(&mut new_user);
+            let token = random_token(VERIFICATION_TOKEN_LENGTH);
+            new_user.is_active = false;
+            new_user.verification_token = Some(token.clone());
+            diesel::insert_into(schema::users::table).values(&new_user).execute(conn)?;
+            let user_id = last_insert_id(conn)?;
+
+            diesel::update(invitations.filter(schema::invitations::id.eq(invitation.id)))
+                   .set((schema::invitations::used_by.eq(Some(user_id)), schema::invitations::used.eq(Some(now))))
+                   .execute(conn)?;
+            mailer::queue_mail_on(&verification_mail(&token)?, conn)?;
+            Ok(Registration::Registered(token))
+        })
+    }
+}
+
+// async wrappers of the operations above
+impl InvitationOperation {
+    pub(crate) async fn create_invitation_async(invitation_role: Role, days: i64, creator: i32, pool: &Data<DbPool>) -> Result<Invitation, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::create_invitation(invitation_role, days, creator, &pool)).await
+    }
+
+    pub(crate) async fn get_all_invitations_async(pool: &Data<DbPool>) -> Result<Vec<Invitation>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::get_all_invitations(&pool)).await
+    }
+
+    pub(crate) async fn register_with_invitation_async<F>(invitation_code: String, new_user: NewUser, verification_mail: F, pool: &Data<DbPool>)
+        -> Result<Registration, failure::Error>
+    where
+        F: FnOnce(&str) -> Result<Mail, failure::Error> + Send + 'static,
+    {
+        let pool = pool.clone();
+        run_blocking(move || Self::register_with_invitation(&invitation_code, new_user, verification_mail, &pool)).await
+    }
+}
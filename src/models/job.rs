@@ -0,0 +1,168 @@
+use actix_web::web::Data;
+use chrono::{ Duration, NaiveDateTime, Utc };
+use diesel::prelude::*;
+use serde_derive::{ Deserialize, Serialize };
+
+use crate::utils::utils::{ last_insert_id, run_blocking, DbConnection, DbPool };
+use super::schema::{ self, jobs };
+
+pub(crate) const PENDING: &str = "pending";
+pub(crate) const RUNNING: &str = "running";
+pub(crate) const DONE: &str = "done";
+pub(crate) const FAILED: &str = "failed";
+
+const DEFAULT_MAX_ATTEMPTS: i32 = 5;
+// how long a runner owns a claimed job, a crashed runner's jobs are picked up again after it
+const LEASE_MINUTES: i64 = 10;
+
+#[derive(Queryable, Debug, Serialize, Deserialize, Identifiable, Clone)]
+pub(crate) struct Job {
+    pub(crate) id: i32,
+    pub(crate) kind: String,
+    pub(crate) payload: String,
+    pub(crate) status: String,
+    pub(crate) attempts: i32,
+    pub(crate) max_attempts: i32,
+    pub(crate) run_at: NaiveDateTime,
+    pub(crate) locked_until: Option<NaiveDateTime>,
+    pub(crate) last_error: Option<String>,
+    pub(crate) created: NaiveDateTime,
+    pub(crate) finished: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "jobs"]
+pub(crate) struct NewJob {
+    pub(crate) kind: String,
+    pub(crate) payload: String,
+    pub(crate) status: String,
+    pub(crate) max_attempts: i32,
+    pub(crate) run_at: NaiveDateTime,
+    pub(crate) created: NaiveDateTime,
+}
+
+impl NewJob {
+    pub(crate) fn new(kind: &str, payload: String, run_at: NaiveDateTime) -> Self {
+        NewJob {
+            kind: kind.to_owned(),
+            payload,
+            status: PENDING.to_owned(),
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            run_at,
+            created: Utc::now().naive_utc(),
+        }
+    }
+}
+
+// 30s, 1m, 2m, 4m... between the attempts, at most one day
+fn backoff(attempts: i32) -> Duration {
+    let exponent = attempts.max(1).min(12) as u32 - 1;
+    Duration::seconds(30 * 2i64.pow(exponent)).min(Duration::days(1))
+}
+
+pub(crate) struct JobOperation;
+
+impl JobOperation {
+    pub(crate) fn enqueue(new_job: &NewJob, pool: &Data<DbPool>) -> Result<Job, failure::Error> {
+        let conn = &*pool.get()?;
+        conn.transaction::<_, failure::Error, _>(|| Self::enqueue_on(new_job, conn))
+    }
+
+    // on a connection in a transaction already, the job is queued only if the rest of the transaction commits
+    pub(crate) fn enqueue_on(new_job: &NewJob, conn: &DbConnection) -> Result<Job, failure::Error> {
+        use schema::jobs::dsl::*;
+        diesel::insert_into(jobs).values(new_job).execute(conn)?;
+        Ok(jobs.find(last_insert_id(conn)?).first::<Job>(conn)?)
+    }
+
+    // lock the due jobs of the given kinds, skipping the ones locked by another runner, then mark them running.
+    // sqlite has no row locks, but its write transaction serializes the runners anyway.
+    pub(crate) fn claim_due_jobs(batch: i64, kinds: &[&str], pool: &Data<DbPool>) -> Result<Vec<Job>, failure::Error> {
+        use schema::jobs::dsl::*;
+        let conn = &*pool.get()?;
+
+        conn.transaction::<_, failure::Error, _>(|| {
+            let now = Utc::now().naive_utc();
+            let due = jobs.filter(kind.eq_any(kinds))
+                          .filter(status.eq(PENDING).and(run_at.le(now)).or(status.eq(RUNNING).and(locked_until.lt(now))))
+                          .order(run_at.asc())
+                          .limit(batch);
+            #[cfg(feature = "postgres")]
+            let mut due = due.for_update().skip_locked().load::<Job>(conn)?;
+            #[cfg(feature = "sqlite")]
+            let mut due = due.load::<Job>(conn)?;
+
+            let lease = now + Duration::minutes(LEASE_MINUTES);
+            let ids: Vec<i32> = due.iter().map(|job| job.id).collect();
+            diesel::update(jobs.filter(id.eq_any(&ids)))
+                   .set((status.eq(RUNNING), attempts.eq(attempts + 1), locked_until.eq(Some(lease))))
+                   .execute(conn)?;
+
+            due.iter_mut().for_each(|job| {
+                job.status = RUNNING.to_owned();
+                job.attempts += 1;
+                job.locked_until = Some(lease);
+            });
+            Ok(due)
+        })
+    }
+
+    // claimed but not started, the jobs are pending again as if they were never claimed
+    pub(crate) fn release(job_ids: &[i32], pool: &Data<DbPool>) -> Result<usize, failure::Error> {
+        use schema::jobs::dsl::*;
+        let conn = &*pool.get()?;
+
+        let released = diesel::update(jobs.filter(id.eq_any(job_ids)).filter(status.eq(RUNNING)))
+                              .set((status.eq(PENDING), attempts.eq(attempts - 1), locked_until.eq(None::<NaiveDateTime>)))
+                              .execute(conn)?;
+        Ok(released)
+    }
+
+    pub(crate) fn complete(job_id: i32, pool: &Data<DbPool>) -> Result<(), failure::Error> {
+        use schema::jobs::dsl::*;
+        let conn = &*pool.get()?;
+
+        diesel::update(jobs.filter(id.eq(job_id)))
+               .set((status.eq(DONE), finished.eq(Some(Utc::now().naive_utc())), locked_until.eq(None::<NaiveDateTime>)))
+               .execute(conn)?;
+        Ok(())
+    }
+
+    // retry later with backoff, or give up after max_attempts
+    pub(crate) fn fail(job: &Job, error: &str, pool: &Data<DbPool>) -> Result<(), failure::Error> {
+        use schema::jobs::dsl::*;
+        let conn = &*pool.get()?;
+
+        let now = Utc::now().naive_utc();
+        let target = jobs.filter(id.eq(job.id));
+        if job.attempts.ge(&job.max_attempts) {
+            diesel::update(target)
+                   .set((status.eq(FAILED), finished.eq(Some(now)), locked_until.eq(None::<NaiveDateTime>), last_error.eq(Some(error))))
+                   .execute(conn)?;
+        } else {
+            diesel::update(target)
+                   .set((status.eq(PENDING), run_at.eq(now + backoff(job.attempts)), locked_until.eq(None::<NaiveDateTime>),
+                         last_error.eq(Some(error))))
+                   .execute(conn)?;
+        }
+        Ok(())
+    }
+
+    // finished jobs are kept for a while to see what happened, failed ones are kept for the admin
+    pub(crate) fn purge_done_jobs(older_than: Duration, pool: &Data<DbPool>) -> Result<usize, failure::Error> {
+        use schema::jobs::dsl::*;
+        let conn = &*pool.get()?;
+
+        let cutoff = Utc::now().naive_utc() - older_than;
+        let deleted = diesel::delete(jobs.filter(status.eq(DONE)).filter(finished.lt(cutoff))).execute(conn)?;
+        Ok(deleted)
+    }
+}
+
+// async wrappers of the operations above
+impl JobOperation {
+    pub(crate) async fn enqueue_async(new_job: NewJob, pool: &Data<DbPool>) -> Result<Job, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::enqueue(&new_job, &pool)).await
+    }
+}
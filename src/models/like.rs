@@ -0,0 +1,115 @@
+use actix_web::web::Data;
+use chrono::{ NaiveDateTime, Utc };
+use diesel::prelude::*;
+use serde_derive::{ Deserialize, Serialize };
+
+use crate::utils::{ cache, utils::{ run_blocking, DbPool } };
+use super::schema::{ self, post_likes };
+
+#[derive(Queryable, Insertable, Debug, Serialize, Deserialize, Identifiable, Clone)]
+#[table_name = "post_likes"]
+pub(crate) struct PostLike {
+    pub(crate) id: i32,
+    pub(crate) post_id: i32,
+    pub(crate) visitor: String,
+    pub(crate) created: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "post_likes"]
+pub(crate) struct NewPostLike {
+    pub(crate) post_id: i32,
+    pub(crate) visitor: String,
+    pub(crate) created: NaiveDateTime,
+}
+
+// what the like button shows to a visitor
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub(crate) struct LikeState {
+    pub(crate) liked: bool,
+    pub(crate) likes: i32,
+}
+
+pub(crate) struct LikeOperation;
+
+impl LikeOperation {
+    // a like or an unlike of a published post, None if there's no such post.
+    // the post row is locked, so the row of the visitor and the count change together.
+    fn set_like(post: i32, who: &str, like: bool, pool: &Data<DbPool>) -> Result<Option<LikeState>, failure::Error> {
+        use schema::posts::dsl::*;
+        let conn = &*pool.get()?;
+
+        let state = conn.transaction::<_, failure::Error, _>(|| {
+            let target = posts.filter(id.eq(post)).filter(status.eq("publish")).select(likes);
+            // sqlite locks the whole database in a write transaction instead
+            #[cfg(feature = "postgres")]
+            let count = target.for_update().load::<i32>(conn)?.pop();
+            #[cfg(feature = "sqlite")]
+            let count = target.load::<i32>(conn)?.pop();
+            let count = match count {
+                Some(count) => count,
+                None => return Ok(None),
+            };
+
+            let visitor_like = post_likes::table.filter(post_likes::post_id.eq(post)).filter(post_likes::visitor.eq(who));
+            let liked = visitor_like.count().get_result::<i64>(conn)?.gt(&0);
+            if liked.eq(&like) {
+                return Ok(Some(LikeState { liked, likes: count }));
+            }
+            if like {
+                let new_like = NewPostLike { post_id: post, visitor: who.to_owned(), created: Utc::now().naive_utc() };
+                diesel::insert_into(post_likes::table).values(&new_like).execute(conn)?;
+                diesel::update(posts.filter(id.eq(post))).set(likes.eq(likes + 1)).execute(conn)?;
+            } else {
+                diesel::delete(visitor_like).execute(conn)?;
+                // imported posts may have likes without rows
+                diesel::update(posts.filter(id.eq(post)).filter(likes.gt(0))).set(likes.eq(likes - 1)).execute(conn)?;
+            }
+            let count = posts.filter(id.eq(post)).select(likes).first::<i32>(conn)?;
+            Ok(Some(LikeState { liked: like, likes: count }))
+        })?;
+        // the count is on the index and the article
+        if state.is_some() {
+            cache::invalidate_all();
+        }
+        Ok(state)
+    }
+
+    pub(crate) fn like(post: i32, visitor: &str, pool: &Data<DbPool>) -> Result<Option<LikeState>, failure::Error> {
+        Self::set_like(post, visitor, true, pool)
+    }
+
+    pub(crate) fn unlike(post: i32, visitor: &str, pool: &Data<DbPool>) -> Result<Option<LikeState>, failure::Error> {
+        Self::set_like(post, visitor, false, pool)
+    }
+
+    pub(crate) fn like_state(post: i32, who: &str, pool: &Data<DbPool>) -> Result<Option<LikeState>, failure::Error> {
+        use schema::posts::dsl::*;
+        let conn = &*pool.get()?;
+
+        let count = posts.filter(id.eq(post)).filter(status.eq("publish")).select(likes).load::<i32>(conn)?.pop();
+        let liked = post_likes::table.filter(post_likes::post_id.eq(post))
+                                     .filter(post_likes::visitor.eq(who))
+                                     .count()
+                                     .get_result::<i64>(conn)?;
+        Ok(count.map(|count| LikeState { liked: liked.gt(&0), likes: count }))
+    }
+}
+
+// async wrappers of the operations above
+impl LikeOperation {
+    pub(crate) async fn like_async(post: i32, visitor: String, pool: &Data<DbPool>) -> Result<Option<LikeState>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::like(post, &visitor, &pool)).await
+    }
+
+    pub(crate) async fn unlike_async(post: i32, visitor: String, pool: &Data<DbPool>) -> Result<Option<LikeState>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::unlike(post, &visitor, &pool)).await
+    }
+
+    pub(crate) async fn like_state_async(post: i32, visitor: String, pool: &Data<DbPool>) -> Result<Option<LikeState>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::like_state(post, &visitor, &pool)).await
+    }
+}
@@ -0,0 +1,99 @@
+use actix_web::web::Data;
+use chrono::{ NaiveDateTime, Utc };
+use diesel::prelude::*;
+use serde_derive::{ Deserialize, Serialize };
+
+use crate::utils::{ media::StoredImage, utils::{ last_insert_id, run_blocking, DbPool } };
+use super::schema::{ self, media };
+
+#[derive(Queryable, Insertable, Debug, Serialize, Deserialize, Identifiable, Clone)]
+#[table_name = "media"]
+pub(crate) struct Media {
+    pub(crate) id: i32,
+    pub(crate) file_name: String,
+    pub(crate) original_name: String,
+    pub(crate) content_type: String,
+    pub(crate) width: i32,
+    pub(crate) height: i32,
+    pub(crate) size: i32,
+    pub(crate) variants: String,
+    pub(crate) uploaded_by: i32,
+    pub(crate) created: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "media"]
+pub(crate) struct NewMedia {
+    pub(crate) file_name: String,
+    pub(crate) original_name: String,
+    pub(crate) content_type: String,
+    pub(crate) width: i32,
+    pub(crate) height: i32,
+    pub(crate) size: i32,
+    pub(crate) variants: String,
+    pub(crate) uploaded_by: i32,
+    pub(crate) created: NaiveDateTime,
+}
+
+impl NewMedia {
+    pub(crate) fn new(stored: StoredImage, original_name: String, uploaded_by: i32) -> Self {
+        NewMedia {
+            file_name: stored.file_name,
+            original_name,
+            content_type: stored.content_type,
+            width: stored.width as i32,
+            height: stored.height as i32,
+            size: stored.size as i32,
+            variants: stored.variants.iter().map(u32::to_string).collect::<Vec<_>>().join(","),
+            uploaded_by,
+            created: Utc::now().naive_utc(),
+        }
+    }
+}
+
+impl Media {
+    // the responsive widths written next to the original, ascending
+    pub(crate) fn variant_widths(&self) -> Vec<u32> {
+        self.variants.split(',').filter_map(|w| w.parse::<u32>().ok()).collect()
+    }
+}
+
+pub(crate) struct MediaOperation;
+
+impl MediaOperation {
+    pub(crate) fn insert_media(new_media: &NewMedia, pool: &Data<DbPool>) -> Result<Media, failure::Error> {
+        use schema::media::dsl::*;
+        let conn = &*pool.get()?;
+
+        let inserted = conn.transaction::<_, failure::Error, _>(|| {
+            diesel::insert_into(media).values(new_media).execute(conn)?;
+            Ok(media.find(last_insert_id(conn)?).first::<Media>(conn)?)
+        })?;
+        Ok(inserted)
+    }
+
+    pub(crate) fn get_media_by_file_name(name: &str, pool: &Data<DbPool>) -> Result<Option<Media>, failure::Error> {
+        use schema::media::dsl::*;
+        let conn = &*pool.get()?;
+
+        let found = media.filter(file_name.eq(name)).load::<Media>(conn)?.pop();
+        Ok(found)
+    }
+
+    // the latest uploads first
+    pub(crate) fn get_all_media(pool: &Data<DbPool>) -> Result<Vec<Media>, failure::Error> {
+        use schema::media::dsl::*;
+        let conn = &*pool.get()?;
+
+        let all_media = media.order(id.desc()).load::<Media>(conn)?;
+        Ok(all_media)
+    }
+}
+
+// async wrappers of the operations above
+impl MediaOperation {
+    pub(crate) async fn get_all_media_async(pool: &Data<DbPool>) -> Result<Vec<Media>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::get_all_media(&pool)).await
+    }
+}
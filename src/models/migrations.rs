@@ -0,0 +1,126 @@
+use diesel::connection::SimpleConnection;
+use diesel_migrations::{ Migration, MigrationConnection, RunMigrationsError };
+use std::collections::HashSet;
+
+use crate::utils::utils::DbConnection;
+
+// every backend has its own sql dialect, the versions must be the same in both folders
+#[cfg(feature = "postgres")]
+macro_rules! migrations_dir {
+    () => { "/migrations/" };
+}
+#[cfg(feature = "sqlite")]
+macro_rules! migrations_dir {
+    () => { "/migrations_sqlite/" };
+}
+
+// every migration is compiled into the binary, so a deployment doesn't need diesel_cli or the migrations folder.
+// remember to append the new migration here after `diesel migration generate`, and port it to sqlite.
+macro_rules! embed_migration {
+    ($dir: literal) => {
+        EmbeddedMigration::new(
+            $dir,
+            include_str!(concat!(env!("CARGO_MANIFEST_DIR"), migrations_dir!(), $dir, "/up.sql")),
+            include_str!(concat!(env!("CARGO_MANIFEST_DIR"), migrations_dir!(), $dir, "/down.sql")),
+        )
+    };
+}
+
+pub(crate) fn embedded_migrations() -> Vec<EmbeddedMigration> {
+    vec![
+        embed_migration!("00000000000000_diesel_initial_setup"),
+        embed_migration!("2018-10-13-125519_create_users"),
+        embed_migration!("2018-11-14-065248_create_posts"),
+        embed_migration!("2018-12-09-034917_create_comments"),
+        embed_migration!("2018-12-15-070552_create_contacts"),
+        embed_migration!("2020-03-01-000000_activate_users"),
+        embed_migration!("2020-03-08-000000_create_invitations"),
+        embed_migration!("2020-03-15-000000_create_jobs"),
+        embed_migration!("2020-03-22-000000_create_media"),
+        embed_migration!("2020-03-29-000000_add_post_seo"),
+        embed_migration!("2020-04-05-000000_create_redirects"),
+        embed_migration!("2020-04-12-000000_create_post_likes"),
+        embed_migration!("2020-04-19-000000_create_analytics"),
+        embed_migration!("2020-04-26-000000_create_comment_subscriptions"),
+        embed_migration!("2020-05-03-000000_create_subscribers"),
+        embed_migration!("2020-05-10-000000_create_contact_replies"),
+        embed_migration!("2020-05-17-000000_create_blocklist"),
+        embed_migration!("2020-05-24-000000_create_imported_posts"),
+    ]
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct EmbeddedMigration {
+    pub(crate) name: &'static str,
+    version: String,
+    up: &'static str,
+    down: &'static str,
+}
+
+impl EmbeddedMigration {
+    fn new(name: &'static str, up: &'static str, down: &'static str) -> Self {
+        // the same rule as diesel_cli, 2018-10-13-125519_create_users => 20181013125519
+        let version = name.split('_').next().unwrap_or_default().replace('-', "");
+        EmbeddedMigration { name, version, up, down }
+    }
+}
+
+impl Migration for EmbeddedMigration {
+    fn version(&self) -> &str {
+        &self.version
+    }
+
+    fn run(&self, conn: &dyn SimpleConnection) -> Result<(), RunMigrationsError> {
+        conn.batch_execute(self.up).map_err(RunMigrationsError::from)
+    }
+
+    fn revert(&self, conn: &dyn SimpleConnection) -> Result<(), RunMigrationsError> {
+        conn.batch_execute(self.down).map_err(RunMigrationsError::from)
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct MigrationStatus {
+    pub(crate) pending: Vec<String>, // embedded but not applied yet
+    pub(crate) unknown: Vec<String>, // applied but not embedded, the database is newer than this binary
+}
+
+impl MigrationStatus {
+    pub(crate) fn is_current(&self) -> bool {
+        self.pending.is_empty() && self.unknown.is_empty()
+    }
+}
+
+// the same table diesel_cli creates, the sql works on both backends
+fn setup_migrations_table(conn: &DbConnection) -> Result<(), failure::Error> {
+    conn.batch_execute("CREATE TABLE IF NOT EXISTS __diesel_schema_migrations (
+                            version VARCHAR(50) PRIMARY KEY NOT NULL,
+                            run_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+                        );")?;
+    Ok(())
+}
+
+pub(crate) fn migration_status(conn: &DbConnection) -> Result<MigrationStatus, failure::Error> {
+    setup_migrations_table(conn)?;
+    let applied: HashSet<String> = conn.previously_run_migration_versions()?;
+    let embedded = embedded_migrations();
+    let embedded_versions: HashSet<&str> = embedded.iter().map(|m| m.version()).collect();
+
+    let pending = embedded.iter().filter(|m| !applied.contains(m.version())).map(|m| m.name.to_owned()).collect();
+    let mut unknown: Vec<String> = applied.iter().filter(|v| !embedded_versions.contains(v.as_str())).cloned().collect();
+    unknown.sort();
+    Ok(MigrationStatus { pending, unknown })
+}
+
+// apply all pending migrations, return the names of the applied ones
+pub(crate) fn run_pending_migrations(conn: &DbConnection) -> Result<Vec<String>, failure::Error> {
+    let status = migration_status(conn)?;
+    if !status.unknown.is_empty() {
+        return Err(failure::format_err!("the database has migrations this binary doesn't know: {}", status.unknown.join(", ")));
+    }
+
+    let mut output = Vec::new();
+    diesel_migrations::run_migrations(conn, embedded_migrations(), &mut output)
+        .map_err(|e| failure::format_err!("failed to run migrations: {}", e))?;
+    Ok(status.pending)
+}
@@ -1,5 +1,15 @@
 pub(crate) mod user;
 pub(crate) mod post;
+pub(crate) mod like;
 pub(crate) mod contact;
 pub(crate) mod comment;
-pub(crate) mod schema;
\ No newline at end of file
+pub(crate) mod invitation;
+pub(crate) mod job;
+pub(crate) mod media;
+pub(crate) mod redirect;
+pub(crate) mod analytics;
+pub(crate) mod blocklist;
+pub(crate) mod subscription;
+pub(crate) mod subscriber;
+pub(crate) mod schema;
+pub(crate) mod migrations;
\ No newline at end of file
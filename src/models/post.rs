@@ -3,10 +3,10 @@ use chrono::{ NaiveDateTime, NaiveDate, Utc };
 use diesel::prelude::*;
 use serde_derive::{ Deserialize, Serialize };
 
-use crate::utils::utils::{ Status, PgPool };
-use super::{ schema::{ self, posts }, user::User };
+use crate::utils::{ cache::{ self, QUERY_CACHE, PUBLISHED_POSTS }, seo, utils::{ run_blocking, Status, DbConnection, DbPool } };
+use super::{ schema::{ self, imported_posts, posts }, user::User };
 
-#[derive(Queryable, Debug, Serialize, Deserialize, AsChangeset, Clone, Identifiable, Associations, QueryableByName)]
+#[derive(Queryable, Insertable, Debug, Serialize, Deserialize, AsChangeset, Clone, Identifiable, Associations, QueryableByName)]
 #[table_name = "posts"]
 #[belongs_to(User)]
 pub(crate) struct Post {
@@ -20,6 +20,10 @@ pub(crate) struct Post {
     pub(crate) status: String,
     pub(crate) user_id: i32,
     pub(crate) likes: i32,
+    pub(crate) excerpt: String,
+    pub(crate) featured_image: String,
+    pub(crate) meta_description: String,
+    pub(crate) canonical_url: String,
 }
 
 #[derive(Insertable, Serialize, Deserialize, Debug, AsChangeset)]
@@ -34,6 +38,21 @@ pub(crate) struct NewPost {
     pub(crate) status: String,
     pub(crate) user_id: i32,
     pub(crate) likes: i32,
+    pub(crate) excerpt: String,
+    pub(crate) featured_image: String,
+    pub(crate) meta_description: String,
+    pub(crate) canonical_url: String,
+}
+
+// a post imported from another blog, found by its old permalink when the blog is imported again
+#[derive(Queryable, Insertable, Debug, Serialize, Deserialize, Clone, Identifiable, Associations)]
+#[table_name = "imported_posts"]
+#[belongs_to(Post)]
+pub(crate) struct ImportedPost {
+    pub(crate) id: i32,
+    pub(crate) source: String,
+    pub(crate) post_id: i32,
+    pub(crate) created: NaiveDateTime,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -42,6 +61,15 @@ pub(crate) struct SubmitPost {
     pub(crate) slug: String,
     pub(crate) body: String,
     pub(crate) status: String,
+    // optional, the forms without them still work
+    #[serde(default)]
+    pub(crate) excerpt: String,
+    #[serde(default)]
+    pub(crate) featured_image: String,
+    #[serde(default)]
+    pub(crate) meta_description: String,
+    #[serde(default)]
+    pub(crate) canonical_url: String,
 }
 
 
@@ -53,6 +81,10 @@ pub(crate) struct UpdatedPost {
     pub(crate) body: String,
     pub(crate) status: String,
     pub(crate) updated: Option<NaiveDateTime>,
+    pub(crate) excerpt: String,
+    pub(crate) featured_image: String,
+    pub(crate) meta_description: String,
+    pub(crate) canonical_url: String,
 }
 
 impl NewPost {
@@ -67,7 +99,67 @@ impl NewPost {
             status: String::from(&new_post.status),
             likes: 0,
             user_id: uid,
+            excerpt: new_post.excerpt.trim().to_owned(),
+            featured_image: new_post.featured_image.trim().to_owned(),
+            meta_description: new_post.meta_description.trim().to_owned(),
+            canonical_url: new_post.canonical_url.trim().to_owned(),
+        }
+    }
+}
+
+impl SubmitPost {
+    // the urls are put into meta tags, only absolute http(s) urls or paths of this site are accepted
+    pub(crate) fn invalid_urls(&self) -> Vec<&'static str> {
+        let mut invalid = Vec::new();
+        if !seo::is_valid_url(self.featured_image.trim()) {
+            invalid.push("featured_image");
+        }
+        if !seo::is_valid_url(self.canonical_url.trim()) {
+            invalid.push("canonical_url");
         }
+        invalid
+    }
+}
+
+impl Post {
+    // the excerpt written by the author, or the beginning of the body as plain text
+    pub(crate) fn excerpt_or_generated(&self) -> String {
+        if self.excerpt.trim().is_empty() {
+            seo::generate_excerpt(&self.body, seo::EXCERPT_LENGTH)
+        } else {
+            self.excerpt.clone()
+        }
+    }
+}
+
+// what an upsert did, or would do in a dry run
+#[derive(Debug, PartialEq)]
+pub(crate) enum Upsert {
+    Created,
+    Updated(Vec<&'static str>), // the changed fields
+    Unchanged,
+    DuplicatedTitle, // a new post cannot take the title of another one
+}
+
+impl NewPost {
+    // the fields of post which this one would change, a None time is skipped by the changeset
+    fn changed_fields(&self, post: &Post) -> Vec<&'static str> {
+        let changes = [
+            ("title", self.title.ne(&post.title)),
+            ("slug", self.slug.ne(&post.slug)),
+            ("body", self.body.ne(&post.body)),
+            ("status", self.status.ne(&post.status)),
+            ("publish", self.publish.is_some() && self.publish.ne(&post.publish)),
+            ("created", self.created.is_some() && self.created.ne(&post.created)),
+            ("updated", self.updated.is_some() && self.updated.ne(&post.updated)),
+            ("author", self.user_id.ne(&post.user_id)),
+            ("likes", self.likes.ne(&post.likes)),
+            ("excerpt", self.excerpt.ne(&post.excerpt)),
+            ("featured_image", self.featured_image.ne(&post.featured_image)),
+            ("meta_description", self.meta_description.ne(&post.meta_description)),
+            ("canonical_url", self.canonical_url.ne(&post.canonical_url)),
+        ];
+        changes.iter().filter(|(_, changed)| *changed).map(|(field, _)| *field).collect()
     }
 }
 
@@ -82,26 +174,40 @@ pub(crate) enum PostStatus {
 pub(crate) struct PostOperation;
 
 impl PostOperation {
-    pub(crate) fn get_all_posts(post_status: PostStatus, pool: &Data<PgPool>) -> Result<Vec<Post>, failure::Error> {
+    pub(crate) fn get_all_posts(post_status: PostStatus, pool: &Data<DbPool>) -> Result<Vec<Post>, failure::Error> {
         use schema::posts::dsl::*;
         let conn = &*pool.get()?;
         
         let all_posts = match post_status {
             PostStatus::All => posts.order(schema::posts::id.desc()).load::<Post>(conn)?,
             PostStatus::Draft => posts.order(schema::posts::status.eq("draft")).load::<Post>(conn)?,
-            PostStatus::Published => posts.filter(schema::posts::status.eq("publish")).order(schema::posts::id.asc()).load::<Post>(conn)?,
+            PostStatus::Published => {
+                // published posts are the hottest query, every public page needs them
+                if let Some(cached) = QUERY_CACHE.get(PUBLISHED_POSTS) {
+                    return Ok(cached);
+                }
+                let published = posts.filter(schema::posts::status.eq("publish")).order(schema::posts::id.asc()).load::<Post>(conn)?;
+                QUERY_CACHE.insert(PUBLISHED_POSTS, published.clone());
+                published
+            }
         };
         Ok(all_posts)
     }
 
-    pub(crate) fn get_post_by_title(post_title: &str, pool: &Data<PgPool>) -> Result<Option<Post>, failure::Error> {
+    pub(crate) fn get_post_by_title(post_title: &str, pool: &Data<DbPool>) -> Result<Option<Post>, failure::Error> {
         use schema::posts::dsl::*;
         let conn = &*pool.get()?;
         let mut post = posts.filter(schema::posts::title.eq(&post_title)).load::<Post>(conn)?;
         Ok(post.pop())
     }
     
-    pub(crate) fn get_posts_by_author(author: &str, pool: &Data<PgPool>) -> Result<Vec<Post>, failure::Error> {
+    pub(crate) fn get_post_by_id(post_id: i32, pool: &Data<DbPool>) -> Result<Option<Post>, failure::Error> {
+        use schema::posts::dsl::*;
+        let conn = &*pool.get()?;
+        Ok(posts.find(post_id).load::<Post>(conn)?.pop())
+    }
+
+    pub(crate) fn get_posts_by_author(author: &str, pool: &Data<DbPool>) -> Result<Vec<Post>, failure::Error> {
         use schema::users::dsl::*;
         let conn = &*pool.get()?;
         
@@ -110,16 +216,7 @@ impl PostOperation {
         Ok(all_posts)
     }
     
-    pub(crate) fn update_likes((likes_num, post_id): (i32, i32), pool: &Data<PgPool>) -> Result<(), failure::Error> {
-        use schema::posts::dsl::*;
-        let conn = &*pool.get()?;
-        
-        let target_post = posts.filter(schema::posts::id.eq(&post_id));
-        diesel::update(target_post).set(schema::posts::likes.eq(&likes_num)).load::<Post>(conn)?;
-        Ok(())
-    }
-    
-    pub(crate) fn update_post(old_title: &str, updated_post: &UpdatedPost, pool: &Data<PgPool>) -> Result<Status, failure::Error> {
+    pub(crate) fn update_post(old_title: &str, updated_post: &UpdatedPost, pool: &Data<DbPool>) -> Result<Status, failure::Error> {
         use schema::posts::dsl::*;
         let conn = &*pool.get()?;
         
@@ -131,27 +228,135 @@ impl PostOperation {
             },
             |post| {
                 let post_filter = posts.filter(schema::posts::id.eq(&post.id));
-                diesel::update(post_filter).set(updated_post).load::<Post>(conn)?;
+                diesel::update(post_filter).set(updated_post).execute(conn)?;
+                cache::invalidate_all();
                 Ok(Status::Success)
             }
         );
         is_updated
     }
     
-    pub(crate) fn insert_post(new_post: &NewPost, pool: &Data<PgPool>) -> Result<Status, failure::Error> {
+    pub(crate) fn insert_post(new_post: &NewPost, pool: &Data<DbPool>) -> Result<Status, failure::Error> {
         use schema::posts::dsl::*;
         let conn = &*pool.get()?;
         
         let dup_title = posts.filter(schema::posts::title.eq(&new_post.title)).load::<Post>(conn)?;
         if dup_title.len().eq(&0) {
             diesel::insert_into(posts).values(new_post).execute(conn)?;
+            cache::invalidate_all();
             Ok(Status::Success)
         } else {
             Ok(Status::Failure)
         }
     }
     
-    pub(crate) fn get_posts_by_year(year: i32, pool: &Data<PgPool>) -> Result<Vec<Post>, failure::Error> {
+    // insert the post, or update the one with the same title. the slug holds the tags, many posts share it.
+    // nothing is written in a dry run.
+    pub(crate) fn upsert_by_title(new_post: &NewPost, dry_run: bool, pool: &Data<DbPool>) -> Result<Upsert, failure::Error> {
+        use schema::posts::dsl::*;
+        let conn = &*pool.get()?;
+        
+        let upsert = conn.transaction::<_, failure::Error, _>(|| {
+            let same_title = posts.filter(title.eq(&new_post.title)).load::<Post>(conn)?.pop();
+            let upsert = match same_title {
+                None => {
+                    if !dry_run {
+                        diesel::insert_into(posts).values(new_post).execute(conn)?;
+                    }
+                    Upsert::Created
+                }
+                Some(current) => Self::update_imported(current, new_post, dry_run, conn)?,
+            };
+            Ok(upsert)
+        })?;
+        
+        match upsert {
+            Upsert::Created | Upsert::Updated(_) if !dry_run => cache::invalidate_all(),
+            _ => (),
+        }
+        Ok(upsert)
+    }
+    
+    // insert the post, or update the one imported before from the same source, the old permalink of another blog.
+    // nothing is written in a dry run.
+    pub(crate) fn upsert_by_source(new_post: &NewPost, post_source: &str, dry_run: bool, pool: &Data<DbPool>) -> Result<Upsert, failure::Error> {
+        use schema::imported_posts::dsl::*;
+        let conn = &*pool.get()?;
+        
+        let upsert = conn.transaction::<_, failure::Error, _>(|| {
+            let imported = imported_posts.inner_join(posts::table)
+                                         .filter(source.eq(post_source))
+                                         .select(posts::all_columns)
+                                         .load::<Post>(conn)?.pop();
+            let upsert = match imported {
+                None => {
+                    if Self::title_taken(&new_post.title, None, conn)? {
+                        return Ok(Upsert::DuplicatedTitle);
+                    }
+                    if !dry_run {
+                        diesel::insert_into(posts::table).values(new_post).execute(conn)?;
+                        let new_id = posts::table.filter(posts::title.eq(&new_post.title)).select(posts::id).first::<i32>(conn)?;
+                        diesel::insert_into(imported_posts)
+                               .values((source.eq(post_source), post_id.eq(new_id), created.eq(Utc::now().naive_utc())))
+                               .execute(conn)?;
+                    }
+                    Upsert::Created
+                }
+                Some(current) => Self::update_imported(current, new_post, dry_run, conn)?,
+            };
+            Ok(upsert)
+        })?;
+        
+        match upsert {
+            Upsert::Created | Upsert::Updated(_) if !dry_run => cache::invalidate_all(),
+            _ => (),
+        }
+        Ok(upsert)
+    }
+    
+    fn title_taken(post_title: &str, except: Option<i32>, conn: &DbConnection) -> Result<bool, failure::Error> {
+        let same_title = posts::table.filter(posts::title.eq(post_title))
+                                     .filter(posts::id.ne(except.unwrap_or(0)))
+                                     .count().get_result::<i64>(conn)?;
+        Ok(same_title.gt(&0))
+    }
+    
+    fn update_imported(current: Post, new_post: &NewPost, dry_run: bool, conn: &DbConnection) -> Result<Upsert, failure::Error> {
+        let changed = new_post.changed_fields(&current);
+        if changed.is_empty() {
+            return Ok(Upsert::Unchanged);
+        }
+        if Self::title_taken(&new_post.title, Some(current.id), conn)? {
+            return Ok(Upsert::DuplicatedTitle);
+        }
+        if !dry_run {
+            diesel::update(posts::table.find(current.id)).set(new_post).execute(conn)?;
+        }
+        Ok(Upsert::Updated(changed))
+    }
+    
+    pub(crate) fn count_posts(pool: &Data<DbPool>) -> Result<i64, failure::Error> {
+        use schema::posts::dsl::*;
+        let conn = &*pool.get()?;
+        Ok(posts.count().get_result(conn)?)
+    }
+    
+    // turn a draft into a published post, the publish time is reset to now
+    pub(crate) fn publish_post(post_id: i32, pool: &Data<DbPool>) -> Result<Status, failure::Error> {
+        use schema::posts::dsl::*;
+        let conn = &*pool.get()?;
+        
+        let updated_rows = diesel::update(posts.find(post_id))
+                                  .set((schema::posts::status.eq("publish"), schema::posts::publish.eq(Some(Utc::now().naive_utc()))))
+                                  .execute(conn)?;
+        if updated_rows.eq(&0) {
+            return Ok(Status::Failure);
+        }
+        cache::invalidate_all();
+        Ok(Status::Success)
+    }
+    
+    pub(crate) fn get_posts_by_year(year: i32, pool: &Data<DbPool>) -> Result<Vec<Post>, failure::Error> {
         use schema::posts::dsl::*;
         let conn = &*pool.get()?;
         
@@ -162,4 +367,43 @@ impl PostOperation {
                              .order(schema::posts::id.asc()).load::<Post>(conn)?;
         Ok(all_posts)
     }
-}
\ No newline at end of file
+}
+
+// async wrappers of the operations above, the blocking diesel calls run on the thread pool
+// instead of the actix workers.
+impl PostOperation {
+    pub(crate) async fn get_all_posts_async(post_status: PostStatus, pool: &Data<DbPool>) -> Result<Vec<Post>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::get_all_posts(post_status, &pool)).await
+    }
+
+    pub(crate) async fn get_post_by_title_async(post_title: String, pool: &Data<DbPool>) -> Result<Option<Post>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::get_post_by_title(&post_title, &pool)).await
+    }
+
+    pub(crate) async fn get_post_by_id_async(post_id: i32, pool: &Data<DbPool>) -> Result<Option<Post>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::get_post_by_id(post_id, &pool)).await
+    }
+
+    pub(crate) async fn get_posts_by_author_async(author: String, pool: &Data<DbPool>) -> Result<Vec<Post>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::get_posts_by_author(&author, &pool)).await
+    }
+
+    pub(crate) async fn update_post_async(old_title: String, updated_post: UpdatedPost, pool: &Data<DbPool>) -> Result<Status, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::update_post(&old_title, &updated_post, &pool)).await
+    }
+
+    pub(crate) async fn insert_post_async(new_post: NewPost, pool: &Data<DbPool>) -> Result<Status, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::insert_post(&new_post, &pool)).await
+    }
+
+    pub(crate) async fn get_posts_by_year_async(year: i32, pool: &Data<DbPool>) -> Result<Vec<Post>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::get_posts_by_year(year, &pool)).await
+    }
+}
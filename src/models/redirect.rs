@@ -0,0 +1,71 @@
+use actix_web::web::Data;
+use chrono::{ NaiveDateTime, Utc };
+use diesel::prelude::*;
+use serde_derive::{ Deserialize, Serialize };
+
+use crate::utils::utils::{ run_blocking, DbPool };
+use super::schema::{ self, redirects };
+
+#[derive(Queryable, Insertable, Debug, Serialize, Deserialize, Identifiable, Clone)]
+#[table_name = "redirects"]
+pub(crate) struct Redirect {
+    pub(crate) id: i32,
+    pub(crate) old_path: String,
+    pub(crate) new_path: String,
+    pub(crate) created: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "redirects"]
+pub(crate) struct NewRedirect {
+    pub(crate) old_path: String,
+    pub(crate) new_path: String,
+    pub(crate) created: NaiveDateTime,
+}
+
+// the path a redirect is stored and looked up by: no query, no fragment and no trailing slash
+pub(crate) fn normalize_path(path: &str) -> String {
+    let path = path.split(|c| c == '?' || c == '#').next().unwrap_or_default();
+    let path = path.trim_end_matches('/');
+    if path.starts_with('/') {
+        path.to_owned()
+    } else {
+        format!("/{}", path)
+    }
+}
+
+pub(crate) struct RedirectOperation;
+
+impl RedirectOperation {
+    // a path imported again points to the latest article
+    pub(crate) fn record_redirect(old: &str, new: &str, pool: &Data<DbPool>) -> Result<(), failure::Error> {
+        use schema::redirects::dsl::*;
+        let conn = &*pool.get()?;
+        let old = normalize_path(old);
+
+        conn.transaction::<_, failure::Error, _>(|| {
+            let updated = diesel::update(redirects.filter(old_path.eq(&old))).set(new_path.eq(new)).execute(conn)?;
+            if updated.eq(&0) {
+                let new_redirect = NewRedirect { old_path: old.clone(), new_path: new.to_owned(), created: Utc::now().naive_utc() };
+                diesel::insert_into(redirects).values(&new_redirect).execute(conn)?;
+            }
+            Ok(())
+        })
+    }
+
+    pub(crate) fn get_redirect(path: &str, pool: &Data<DbPool>) -> Result<Option<Redirect>, failure::Error> {
+        use schema::redirects::dsl::*;
+        let conn = &*pool.get()?;
+
+        let found = redirects.filter(old_path.eq(normalize_path(path))).load::<Redirect>(conn)?.pop();
+        Ok(found)
+    }
+}
+
+// async wrappers of the operations above
+impl RedirectOperation {
+    pub(crate) async fn get_redirect_async(path: String, pool: &Data<DbPool>) -> Result<Option<Redirect>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::get_redirect(&path, &pool)).await
+    }
+}
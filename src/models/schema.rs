@@ -1,3 +1,22 @@
+table! {
+    blocklist (id) {
+        id -> Int4,
+        kind -> Varchar,
+        value -> Varchar,
+        reason -> Varchar,
+        created -> Timestamp,
+    }
+}
+
+table! {
+    comment_subscriptions (id) {
+        id -> Int4,
+        post_id -> Int4,
+        email -> Varchar,
+        created -> Timestamp,
+    }
+}
+
 table! {
     comments (id) {
         id -> Int4,
@@ -9,6 +28,16 @@ table! {
     }
 }
 
+table! {
+    contact_replies (id) {
+        id -> Int4,
+        contact_id -> Int4,
+        user_id -> Int4,
+        body -> Text,
+        sent -> Timestamp,
+    }
+}
+
 table! {
     contacts (id) {
         id -> Int4,
@@ -16,6 +45,111 @@ table! {
         email -> Varchar,
         message -> Varchar,
         committed_time -> Nullable<Timestamp>,
+        is_read -> Bool,
+        starred -> Bool,
+        archived -> Bool,
+    }
+}
+
+table! {
+    daily_referrers (id) {
+        id -> Int4,
+        day -> Date,
+        referrer -> Varchar,
+        views -> Int4,
+    }
+}
+
+table! {
+    daily_views (id) {
+        id -> Int4,
+        day -> Date,
+        post_id -> Int4,
+        views -> Int4,
+        visitors -> Int4,
+    }
+}
+
+table! {
+    imported_posts (id) {
+        id -> Int4,
+        source -> Varchar,
+        post_id -> Int4,
+        created -> Timestamp,
+    }
+}
+
+table! {
+    invitations (id) {
+        id -> Int4,
+        code -> Varchar,
+        role -> Varchar,
+        created_by -> Int4,
+        created -> Timestamp,
+        expires -> Timestamp,
+        used_by -> Nullable<Int4>,
+        used -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    jobs (id) {
+        id -> Int4,
+        kind -> Varchar,
+        payload -> Text,
+        status -> Varchar,
+        attempts -> Int4,
+        max_attempts -> Int4,
+        run_at -> Timestamp,
+        locked_until -> Nullable<Timestamp>,
+        last_error -> Nullable<Text>,
+        created -> Timestamp,
+        finished -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    media (id) {
+        id -> Int4,
+        file_name -> Varchar,
+        original_name -> Varchar,
+        content_type -> Varchar,
+        width -> Int4,
+        height -> Int4,
+        size -> Int4,
+        variants -> Varchar,
+        uploaded_by -> Int4,
+        created -> Timestamp,
+    }
+}
+
+table! {
+    newsletter_issues (id) {
+        id -> Int4,
+        kind -> Varchar,
+        post_id -> Nullable<Int4>,
+        created -> Timestamp,
+        sent -> Nullable<Timestamp>,
+        recipients -> Int4,
+    }
+}
+
+table! {
+    page_views (id) {
+        id -> Int4,
+        post_id -> Int4,
+        visitor -> Varchar,
+        referrer -> Varchar,
+        viewed -> Timestamp,
+    }
+}
+
+table! {
+    post_likes (id) {
+        id -> Int4,
+        post_id -> Int4,
+        visitor -> Varchar,
+        created -> Timestamp,
     }
 }
 
@@ -31,6 +165,41 @@ table! {
         status -> Varchar,
         user_id -> Int4,
         likes -> Int4,
+        excerpt -> Text,
+        featured_image -> Varchar,
+        meta_description -> Varchar,
+        canonical_url -> Varchar,
+    }
+}
+
+table! {
+    redirects (id) {
+        id -> Int4,
+        old_path -> Varchar,
+        new_path -> Varchar,
+        created -> Timestamp,
+    }
+}
+
+table! {
+    search_terms (id) {
+        id -> Int4,
+        day -> Date,
+        term -> Varchar,
+        searches -> Int4,
+    }
+}
+
+table! {
+    subscribers (id) {
+        id -> Int4,
+        email -> Varchar,
+        token -> Varchar,
+        frequency -> Varchar,
+        status -> Varchar,
+        bounces -> Int4,
+        created -> Timestamp,
+        confirmed -> Nullable<Timestamp>,
     }
 }
 
@@ -47,15 +216,40 @@ table! {
         is_active -> Bool,
         last_login -> Nullable<Timestamp>,
         date_joined -> Nullable<Timestamp>,
+        verification_token -> Nullable<Varchar>,
     }
 }
 
+joinable!(comment_subscriptions -> posts (post_id));
 joinable!(comments -> posts (post_id));
+joinable!(contact_replies -> contacts (contact_id));
+joinable!(contact_replies -> users (user_id));
+joinable!(daily_views -> posts (post_id));
+joinable!(imported_posts -> posts (post_id));
+joinable!(media -> users (uploaded_by));
+joinable!(newsletter_issues -> posts (post_id));
+joinable!(page_views -> posts (post_id));
+joinable!(post_likes -> posts (post_id));
 joinable!(posts -> users (user_id));
 
 allow_tables_to_appear_in_same_query!(
+    blocklist,
+    comment_subscriptions,
     comments,
+    contact_replies,
     contacts,
+    daily_referrers,
+    daily_views,
+    imported_posts,
+    invitations,
+    jobs,
+    media,
+    newsletter_issues,
+    page_views,
+    post_likes,
     posts,
+    redirects,
+    search_terms,
+    subscribers,
     users,
 );
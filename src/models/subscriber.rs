@@ -0,0 +1,288 @@
+use actix_web::web::Data;
+use chrono::{ Duration, NaiveDateTime, Utc };
+use diesel::prelude::*;
+use serde_derive::{ Deserialize, Serialize };
+
+use crate::utils::utils::{ last_insert_id, random_token, run_blocking, DbConnection, DbPool };
+use super::{ post::Post, schema::{ self, newsletter_issues, subscribers } };
+
+pub(crate) const PENDING: &str = "pending";
+pub(crate) const ACTIVE: &str = "active";
+pub(crate) const UNSUBSCRIBED: &str = "unsubscribed";
+pub(crate) const BOUNCED: &str = "bounced";
+pub(crate) const STATUSES: [&str; 4] = [PENDING, ACTIVE, UNSUBSCRIBED, BOUNCED];
+
+// a mail for every new post, or a digest once a week
+pub(crate) const EVERY_POST: &str = "post";
+pub(crate) const WEEKLY: &str = "weekly";
+
+pub(crate) const POST_ISSUE: &str = "post";
+pub(crate) const DIGEST_ISSUE: &str = "digest";
+
+const TOKEN_LENGTH: usize = 32;
+// an address which rejected this many mails gets no more
+const MAX_BOUNCES: i32 = 3;
+
+#[derive(Queryable, Insertable, Debug, Serialize, Deserialize, Identifiable, Clone)]
+#[table_name = "subscribers"]
+pub(crate) struct Subscriber {
+    pub(crate) id: i32,
+    pub(crate) email: String,
+    pub(crate) token: String,
+    pub(crate) frequency: String,
+    pub(crate) status: String,
+    pub(crate) bounces: i32,
+    pub(crate) created: NaiveDateTime,
+    pub(crate) confirmed: Option<NaiveDateTime>,
+}
+
+#[derive(Queryable, Insertable, Debug, Serialize, Deserialize, Identifiable, Clone)]
+#[table_name = "newsletter_issues"]
+pub(crate) struct NewsletterIssue {
+    pub(crate) id: i32,
+    pub(crate) kind: String,
+    pub(crate) post_id: Option<i32>,
+    pub(crate) created: NaiveDateTime,
+    pub(crate) sent: Option<NaiveDateTime>,
+    pub(crate) recipients: i32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct Subscribe {
+    pub(crate) email: String,
+    #[serde(default)]
+    pub(crate) frequency: String,
+}
+
+pub(crate) struct SubscriberOperation;
+
+impl SubscriberOperation {
+    // a new or returning address is pending with a new token, Some if a confirmation should be mailed.
+    // an active subscriber only changes the frequency, so nobody can unsubscribe someone else this way.
+    pub(crate) fn subscribe(address: &str, how_often: &str, pool: &Data<DbPool>) -> Result<Option<Subscriber>, failure::Error> {
+        use schema::subscribers::dsl::*;
+        let conn = &*pool.get()?;
+        let now = Utc::now().naive_utc();
+
+        conn.transaction::<_, failure::Error, _>(|| {
+            let existing = subscribers.filter(email.eq(address)).load::<Subscriber>(conn)?.pop();
+            match existing {
+                Some(subscriber) if subscriber.status.eq(ACTIVE) => {
+                    diesel::update(subscribers.find(subscriber.id)).set(frequency.eq(how_often)).execute(conn)?;
+                    Ok(None)
+                }
+                Some(subscriber) => {
+                    diesel::update(subscribers.find(subscriber.id))
+                           .set((token.eq(random_token(TOKEN_LENGTH)), frequency.eq(how_often), status.eq(PENDING), bounces.eq(0), created.eq(now)))
+                           .execute(conn)?;
+                    Ok(subscribers.find(subscriber.id).first::<Subscriber>(conn).optional()?)
+                }
+                None => {
+                    diesel::insert_into(subscribers)
+                           .values((email.eq(address), token.eq(random_token(TOKEN_LENGTH)), frequency.eq(how_often), status.eq(PENDING), created.eq(now)))
+                           .execute(conn)?;
+                    Ok(subscribers.find(last_insert_id(conn)?).first::<Subscriber>(conn).optional()?)
+                }
+            }
+        })
+    }
+
+    // the confirmation link works while the subscriber is pending and not expired
+    pub(crate) fn confirm(link_token: &str, expiry: Duration, pool: &Data<DbPool>) -> Result<Option<Subscriber>, failure::Error> {
+        use schema::subscribers::dsl::*;
+        let conn = &*pool.get()?;
+        let now = Utc::now().naive_utc();
+
+        let pending = subscribers.filter(token.eq(link_token)).filter(status.eq(PENDING)).filter(created.gt(now - expiry));
+        let updated = diesel::update(pending).set((status.eq(ACTIVE), confirmed.eq(Some(now)))).execute(conn)?;
+        if updated.eq(&0) {
+            return Ok(None);
+        }
+        Ok(subscribers.filter(token.eq(link_token)).load::<Subscriber>(conn)?.pop())
+    }
+
+    pub(crate) fn get_subscriber_by_token(link_token: &str, pool: &Data<DbPool>) -> Result<Option<Subscriber>, failure::Error> {
+        use schema::subscribers::dsl::*;
+        let conn = &*pool.get()?;
+        Ok(subscribers.filter(token.eq(link_token)).load::<Subscriber>(conn)?.pop())
+    }
+
+    pub(crate) fn unsubscribe(link_token: &str, pool: &Data<DbPool>) -> Result<bool, failure::Error> {
+        use schema::subscribers::dsl::*;
+        let conn = &*pool.get()?;
+        let updated = diesel::update(subscribers.filter(token.eq(link_token)).filter(status.ne(UNSUBSCRIBED)))
+                             .set(status.eq(UNSUBSCRIBED))
+                             .execute(conn)?;
+        Ok(updated.gt(&0))
+    }
+
+    // a permanent failure of a mail to this address, it's dropped after a few. false if it isn't a subscriber.
+    pub(crate) fn record_bounce(address: &str, pool: &Data<DbPool>) -> Result<bool, failure::Error> {
+        use schema::subscribers::dsl::*;
+        let conn = &*pool.get()?;
+
+        conn.transaction::<_, failure::Error, _>(|| {
+            let updated = diesel::update(subscribers.filter(email.eq(address))).set(bounces.eq(bounces + 1)).execute(conn)?;
+            diesel::update(subscribers.filter(email.eq(address)).filter(status.eq(ACTIVE)).filter(bounces.ge(MAX_BOUNCES)))
+                   .set(status.eq(BOUNCED))
+                   .execute(conn)?;
+            Ok(updated.gt(&0))
+        })
+    }
+
+    pub(crate) fn set_status(subscriber_id: i32, new_status: &str, pool: &Data<DbPool>) -> Result<bool, failure::Error> {
+        use schema::subscribers::dsl::*;
+        let conn = &*pool.get()?;
+        Ok(diesel::update(subscribers.find(subscriber_id)).set(status.eq(new_status)).execute(conn)?.gt(&0))
+    }
+
+    pub(crate) fn remove(subscriber_id: i32, pool: &Data<DbPool>) -> Result<bool, failure::Error> {
+        use schema::subscribers::dsl::*;
+        let conn = &*pool.get()?;
+        Ok(diesel::delete(subscribers.find(subscriber_id)).execute(conn)?.gt(&0))
+    }
+
+    // the latest first, of one status or all of them
+    pub(crate) fn get_subscribers(with_status: Option<&str>, pool: &Data<DbPool>) -> Result<Vec<Subscriber>, failure::Error> {
+        use schema::subscribers::dsl::*;
+        let conn = &*pool.get()?;
+        let mut query = subscribers.order(id.desc()).into_boxed();
+        if let Some(with_status) = with_status {
+            query = query.filter(status.eq(with_status));
+        }
+        Ok(query.load::<Subscriber>(conn)?)
+    }
+
+    pub(crate) fn get_active_subscribers(how_often: &str, pool: &Data<DbPool>) -> Result<Vec<Subscriber>, failure::Error> {
+        use schema::subscribers::dsl::*;
+        let conn = &*pool.get()?;
+        Ok(subscribers.filter(status.eq(ACTIVE)).filter(frequency.eq(how_often)).order(id.asc()).load::<Subscriber>(conn)?)
+    }
+
+    // unconfirmed addresses aren't kept after their link expired
+    pub(crate) fn purge_pending(expiry: Duration, pool: &Data<DbPool>) -> Result<usize, failure::Error> {
+        use schema::subscribers::dsl::*;
+        let conn = &*pool.get()?;
+        Ok(diesel::delete(subscribers.filter(status.eq(PENDING)).filter(created.lt(Utc::now().naive_utc() - expiry))).execute(conn)?)
+    }
+}
+
+impl SubscriberOperation {
+    // the announcement of a published post, None if it was announced before or isn't published
+    pub(crate) fn create_post_issue(post: i32, pool: &Data<DbPool>) -> Result<Option<NewsletterIssue>, failure::Error> {
+        use schema::newsletter_issues::dsl::*;
+        let conn = &*pool.get()?;
+
+        conn.transaction::<_, failure::Error, _>(|| {
+            let published = schema::posts::table.find(post).filter(schema::posts::status.eq("publish")).count().get_result::<i64>(conn)?;
+            let announced = newsletter_issues.filter(post_id.eq(post)).count().get_result::<i64>(conn)?;
+            if published.eq(&0) || announced.gt(&0) {
+                return Ok(None);
+            }
+            diesel::insert_into(newsletter_issues)
+                   .values((kind.eq(POST_ISSUE), post_id.eq(Some(post)), created.eq(Utc::now().naive_utc())))
+                   .execute(conn)?;
+            Ok(newsletter_issues.find(last_insert_id(conn)?).first::<NewsletterIssue>(conn).optional()?)
+        })
+    }
+
+    // a digest of the posts published since the last one, if that was at least a period ago and there are new posts
+    pub(crate) fn create_digest_issue(period: Duration, pool: &Data<DbPool>) -> Result<Option<NewsletterIssue>, failure::Error> {
+        use schema::newsletter_issues::dsl::*;
+        let conn = &*pool.get()?;
+        let now = Utc::now().naive_utc();
+
+        conn.transaction::<_, failure::Error, _>(|| {
+            let last_digest = newsletter_issues.filter(kind.eq(DIGEST_ISSUE)).select(created).order(created.desc()).first::<NaiveDateTime>(conn).optional()?;
+            let since = match last_digest {
+                Some(last_digest) if last_digest.gt(&(now - period)) => return Ok(None),
+                Some(last_digest) => last_digest,
+                None => now - period,
+            };
+            let new_posts = schema::posts::table.filter(schema::posts::status.eq("publish"))
+                                                .filter(schema::posts::publish.gt(since))
+                                                .count()
+                                                .get_result::<i64>(conn)?;
+            if new_posts.eq(&0) {
+                return Ok(None);
+            }
+            diesel::insert_into(newsletter_issues).values((kind.eq(DIGEST_ISSUE), created.eq(now))).execute(conn)?;
+            Ok(newsletter_issues.find(last_insert_id(conn)?).first::<NewsletterIssue>(conn).optional()?)
+        })
+    }
+
+    pub(crate) fn get_issue(issue_id: i32, pool: &Data<DbPool>) -> Result<Option<NewsletterIssue>, failure::Error> {
+        use schema::newsletter_issues::dsl::*;
+        let conn = &*pool.get()?;
+        Ok(newsletter_issues.find(issue_id).load::<NewsletterIssue>(conn)?.pop())
+    }
+
+    // the posts of an issue: the announced one, or what was published between the digest before and this one
+    pub(crate) fn get_issue_posts(issue: &NewsletterIssue, period: Duration, pool: &Data<DbPool>) -> Result<Vec<Post>, failure::Error> {
+        use schema::newsletter_issues::dsl::*;
+        let conn = &*pool.get()?;
+
+        if let Some(post) = issue.post_id {
+            return Ok(schema::posts::table.find(post).filter(schema::posts::status.eq("publish")).load::<Post>(conn)?);
+        }
+        let since = newsletter_issues.filter(kind.eq(DIGEST_ISSUE))
+                                     .filter(id.lt(issue.id))
+                                     .select(created)
+                                     .order(id.desc())
+                                     .first::<NaiveDateTime>(conn)
+                                     .optional()?
+                                     .unwrap_or(issue.created - period);
+        Ok(schema::posts::table.filter(schema::posts::status.eq("publish"))
+                               .filter(schema::posts::publish.gt(since))
+                               .filter(schema::posts::publish.le(issue.created))
+                               .order(schema::posts::publish.asc())
+                               .load::<Post>(conn)?)
+    }
+
+    // on a connection in a transaction already, false when another run has sent the issue
+    pub(crate) fn mark_sent_on(issue_id: i32, sent_to: i32, conn: &DbConnection) -> Result<bool, failure::Error> {
+        use schema::newsletter_issues::dsl::*;
+        let updated = diesel::update(newsletter_issues.find(issue_id).filter(sent.is_null()))
+            .set((sent.eq(Some(Utc::now().naive_utc())), recipients.eq(sent_to)))
+            .execute(conn)?;
+        Ok(updated > 0)
+    }
+}
+
+// async wrappers of the operations above
+impl SubscriberOperation {
+    pub(crate) async fn subscribe_async(address: String, how_often: String, pool: &Data<DbPool>) -> Result<Option<Subscriber>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::subscribe(&address, &how_often, &pool)).await
+    }
+
+    pub(crate) async fn confirm_async(link_token: String, expiry: Duration, pool: &Data<DbPool>) -> Result<Option<Subscriber>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::confirm(&link_token, expiry, &pool)).await
+    }
+
+    pub(crate) async fn get_subscriber_by_token_async(link_token: String, pool: &Data<DbPool>) -> Result<Option<Subscriber>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::get_subscriber_by_token(&link_token, &pool)).await
+    }
+
+    pub(crate) async fn unsubscribe_async(link_token: String, pool: &Data<DbPool>) -> Result<bool, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::unsubscribe(&link_token, &pool)).await
+    }
+
+    pub(crate) async fn set_status_async(subscriber_id: i32, new_status: String, pool: &Data<DbPool>) -> Result<bool, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::set_status(subscriber_id, &new_status, &pool)).await
+    }
+
+    pub(crate) async fn remove_async(subscriber_id: i32, pool: &Data<DbPool>) -> Result<bool, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::remove(subscriber_id, &pool)).await
+    }
+
+    pub(crate) async fn get_subscribers_async(with_status: Option<String>, pool: &Data<DbPool>) -> Result<Vec<Subscriber>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::get_subscribers(with_status.as_deref(), &pool)).await
+    }
+}
@@ -0,0 +1,98 @@
+use actix_web::web::Data;
+use chrono::{ NaiveDateTime, Utc };
+use diesel::prelude::*;
+use serde_derive::{ Deserialize, Serialize };
+
+use crate::utils::utils::{ run_blocking, DbPool };
+use super::{ post::Post, schema::{ self, comment_subscriptions }, user::User };
+
+#[derive(Queryable, Insertable, Debug, Serialize, Deserialize, Identifiable, Clone)]
+#[table_name = "comment_subscriptions"]
+pub(crate) struct CommentSubscription {
+    pub(crate) id: i32,
+    pub(crate) post_id: i32,
+    pub(crate) email: String,
+    pub(crate) created: NaiveDateTime,
+}
+
+// who hears about a new comment on a post
+#[derive(Debug)]
+pub(crate) struct CommentAudience {
+    pub(crate) post: Post,
+    pub(crate) author: Option<User>,
+    pub(crate) subscribers: Vec<CommentSubscription>, // without the commenter
+}
+
+pub(crate) struct SubscriptionOperation;
+
+impl SubscriptionOperation {
+    // subscribing twice keeps the first subscription
+    pub(crate) fn subscribe(post: i32, address: &str, pool: &Data<DbPool>) -> Result<CommentSubscription, failure::Error> {
+        use schema::comment_subscriptions::dsl::*;
+        let conn = &*pool.get()?;
+
+        conn.transaction::<_, failure::Error, _>(|| {
+            let subscribed = comment_subscriptions.filter(post_id.eq(post)).filter(email.eq(address));
+            if let Some(subscription) = subscribed.load::<CommentSubscription>(conn)?.pop() {
+                return Ok(subscription);
+            }
+            diesel::insert_into(comment_subscriptions)
+                   .values((post_id.eq(post), email.eq(address), created.eq(Utc::now().naive_utc())))
+                   .execute(conn)?;
+            Ok(subscribed.first::<CommentSubscription>(conn)?)
+        })
+    }
+
+    pub(crate) fn is_subscribed(post: i32, address: &str, pool: &Data<DbPool>) -> Result<bool, failure::Error> {
+        use schema::comment_subscriptions::dsl::*;
+        let conn = &*pool.get()?;
+        let subscribed = comment_subscriptions.filter(post_id.eq(post)).filter(email.eq(address)).count().get_result::<i64>(conn)?;
+        Ok(subscribed.gt(&0))
+    }
+
+    pub(crate) fn get_subscription(subscription_id: i32, pool: &Data<DbPool>) -> Result<Option<CommentSubscription>, failure::Error> {
+        use schema::comment_subscriptions::dsl::*;
+        let conn = &*pool.get()?;
+        Ok(comment_subscriptions.find(subscription_id).load::<CommentSubscription>(conn)?.pop())
+    }
+
+    pub(crate) fn unsubscribe(subscription_id: i32, pool: &Data<DbPool>) -> Result<bool, failure::Error> {
+        use schema::comment_subscriptions::dsl::*;
+        let conn = &*pool.get()?;
+        Ok(diesel::delete(comment_subscriptions.find(subscription_id)).execute(conn)?.gt(&0))
+    }
+
+    // the post, its author and the subscribers, None if there's no such post
+    pub(crate) fn comment_audience(post: i32, commenter: &str, pool: &Data<DbPool>) -> Result<Option<CommentAudience>, failure::Error> {
+        let conn = &*pool.get()?;
+
+        let post = match schema::posts::table.find(post).load::<Post>(conn)?.pop() {
+            Some(post) => post,
+            None => return Ok(None),
+        };
+        let author = schema::users::table.find(post.user_id).load::<User>(conn)?.pop();
+        let subscribers = comment_subscriptions::table.filter(comment_subscriptions::post_id.eq(post.id))
+                                                      .filter(comment_subscriptions::email.ne(commenter))
+                                                      .order(comment_subscriptions::id.asc())
+                                                      .load::<CommentSubscription>(conn)?;
+        Ok(Some(CommentAudience { post, author, subscribers }))
+    }
+}
+
+// async wrappers of the operations above
+impl SubscriptionOperation {
+    pub(crate) async fn subscribe_async(post: i32, address: String, pool: &Data<DbPool>) -> Result<CommentSubscription, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::subscribe(post, &address, &pool)).await
+    }
+
+    pub(crate) async fn get_subscription_async(subscription_id: i32, pool: &Data<DbPool>) -> Result<Option<CommentSubscription>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::get_subscription(subscription_id, &pool)).await
+    }
+
+    pub(crate) async fn unsubscribe_async(subscription_id: i32, pool: &Data<DbPool>) -> Result<bool, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::unsubscribe(subscription_id, &pool)).await
+    }
+}
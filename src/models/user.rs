@@ -4,10 +4,12 @@ use diesel::prelude::*;
 use serde_derive::{ Deserialize, Serialize };
 use std::convert::TryFrom;
 
-use crate::utils::utils::{ Status, PgPool };
+use crate::utils::utils::{ run_blocking, Status, DbPool };
 use super::schema::{ self, users };
 
-#[derive(Queryable, Debug, Serialize, Deserialize, Identifiable)]
+// Insertable for the restore of a backup, which keeps the ids
+#[derive(Queryable, Insertable, Debug, Serialize, Deserialize, Identifiable)]
+#[table_name = "users"]
 pub(crate) struct User {
     pub(crate) id: i32,
     pub(crate) password: String,
@@ -20,6 +22,7 @@ pub(crate) struct User {
     pub(crate) is_active: bool,
     pub(crate) last_login: Option<NaiveDateTime>,
     pub(crate) date_joined: Option<NaiveDateTime>,
+    pub(crate) verification_token: Option<String>,
 }
 
 #[derive(Insertable, Serialize, Deserialize, Debug)]
@@ -30,9 +33,12 @@ pub(crate) struct NewUser {
     pub(crate) first_name: String,
     pub(crate) last_name: String,
     pub(crate) email: String,
+    pub(crate) is_superuser: bool,
     pub(crate) is_staff: bool,
+    pub(crate) is_active: bool,
     pub(crate) last_login: Option<NaiveDateTime>,
     pub(crate) date_joined: Option<NaiveDateTime>,
+    pub(crate) verification_token: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -48,6 +54,8 @@ pub(crate) struct CreateUser {
     pub(crate) first_name: String,
     pub(crate) last_name: String,
     pub(crate) email: String,
+    #[serde(default)]
+    pub(crate) invitation_code: String,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -66,9 +74,12 @@ impl TryFrom<Form<CreateUser>> for NewUser {
             first_name: user.first_name.clone(), 
             last_name: user.last_name.clone(), 
             email: user.email.clone(),
+            is_superuser: false,
             is_staff: false,
+            is_active: false, // until the email is verified
             last_login: Some(Utc::now().naive_utc()),
             date_joined: Some(Utc::now().naive_utc()),
+            verification_token: None,
         };
         Ok(new_user)
     }
@@ -77,7 +88,7 @@ impl TryFrom<Form<CreateUser>> for NewUser {
 pub(crate) struct UserOperation;
 
 impl UserOperation {
-    pub(crate) fn get_user_by_name(user_name: &str, pool: &Data<PgPool>) -> Result<Option<User>, failure::Error> {
+    pub(crate) fn get_user_by_name(user_name: &str, pool: &Data<DbPool>) -> Result<Option<User>, failure::Error> {
         use schema::users::dsl::*;
         let conn = &*pool.get()?;
         
@@ -86,11 +97,12 @@ impl UserOperation {
                                  .or_filter(schema::users::email.eq(&user_name));
         
         // if found, update the time of last login
-        let mut user_found = diesel::update(user_filter).set(schema::users::last_login.eq(&Some(Utc::now().naive_utc()))).load::<User>(conn)?;
+        diesel::update(user_filter.clone()).set(schema::users::last_login.eq(&Some(Utc::now().naive_utc()))).execute(conn)?;
+        let mut user_found = user_filter.load::<User>(conn)?;
         Ok(user_found.pop())
     }
     
-    pub(crate) fn get_user_by_email(email_addr: &str, pool: &Data<PgPool>) -> Result<Option<User>, failure::Error> {
+    pub(crate) fn get_user_by_email(email_addr: &str, pool: &Data<DbPool>) -> Result<Option<User>, failure::Error> {
         use schema::users::dsl::*;
         let conn = &*pool.get()?;
         
@@ -98,8 +110,33 @@ impl UserOperation {
         Ok(user_found.pop())
     }
     
-    #[allow(dead_code)]
-    pub(crate) fn get_user_by_id(uid: i32, pool: &Data<PgPool>) -> Result<Option<User>, failure::Error> {
+    // unlike get_user_by_name, the last login time stays untouched
+    pub(crate) fn find_user(user_name: &str, pool: &Data<DbPool>) -> Result<Option<User>, failure::Error> {
+        use schema::users::dsl::*;
+        let conn = &*pool.get()?;
+        
+        let mut user_found = users.filter(schema::users::username.eq(&user_name))
+                                  .or_filter(schema::users::email.eq(&user_name))
+                                  .load::<User>(conn)?;
+        Ok(user_found.pop())
+    }
+    
+    // activate the user who owns this token, a token can only be used once
+    pub(crate) fn verify_email(token: &str, pool: &Data<DbPool>) -> Result<Status, failure::Error> {
+        use schema::users::dsl::*;
+        let conn = &*pool.get()?;
+        
+        let user_filter = users.filter(schema::users::verification_token.eq(token));
+        let updated_rows = diesel::update(user_filter)
+                                  .set((schema::users::is_active.eq(true), schema::users::verification_token.eq(None::<String>)))
+                                  .execute(conn)?;
+        match updated_rows {
+            0 => Ok(Status::Failure),
+            _ => Ok(Status::Success),
+        }
+    }
+    
+    pub(crate) fn get_user_by_id(uid: i32, pool: &Data<DbPool>) -> Result<Option<User>, failure::Error> {
         use schema::users::dsl::*;
         let conn = &*pool.get()?;
         
@@ -107,7 +144,7 @@ impl UserOperation {
         Ok(user_found.pop())
     }
     
-    pub(crate) fn insert_user(new_user: &NewUser, pool: &Data<PgPool>) -> Result<Status, failure::Error> {
+    pub(crate) fn insert_user(new_user: &NewUser, pool: &Data<DbPool>) -> Result<Status, failure::Error> {
         use schema::users::dsl::*;
         let conn = &*pool.get()?;
         
@@ -124,18 +161,31 @@ impl UserOperation {
         }
     }
     
-    pub(crate) fn modify_password(new_password: &str, user_name: &str, pool: &Data<PgPool>) -> Result<Status, failure::Error> {
+    pub(crate) fn modify_password(new_password: &str, user_name: &str, pool: &Data<DbPool>) -> Result<Status, failure::Error> {
         use schema::users::dsl::*;
         let conn = &*pool.get()?;
         
         // find the current user
         let user_filter = users.filter(schema::users::username.eq(&user_name))
                                  .or_filter(schema::users::email.eq(&user_name));
-        diesel::update(user_filter).set(schema::users::password.eq(new_password)).load::<User>(conn)?;
+        diesel::update(user_filter).set(schema::users::password.eq(new_password)).execute(conn)?;
         Ok(Status::Success)
     }
     
-    pub(crate) fn get_id_by_username(user_name: &str, pool: &Data<PgPool>) -> Result<i32, failure::Error> {
+    // deactivated users can't login anymore
+    pub(crate) fn set_active(user_name: &str, active: bool, pool: &Data<DbPool>) -> Result<Status, failure::Error> {
+        use schema::users::dsl::*;
+        let conn = &*pool.get()?;
+        
+        let user_filter = users.filter(schema::users::username.eq(&user_name))
+                                 .or_filter(schema::users::email.eq(&user_name));
+        match diesel::update(user_filter).set(schema::users::is_active.eq(active)).execute(conn)? {
+            0 => Ok(Status::Failure),
+            _ => Ok(Status::Success),
+        }
+    }
+    
+    pub(crate) fn get_id_by_username(user_name: &str, pool: &Data<DbPool>) -> Result<i32, failure::Error> {
         use schema::users::dsl::*;
         let conn = &*pool.get()?;
         
@@ -146,4 +196,55 @@ impl UserOperation {
         let uid = user_found.pop().ok_or(failure::err_msg("didn't find this user in database"))?;
         Ok(uid.id)
     }
-}
\ No newline at end of file
+}
+
+// async wrappers of the operations above, bcrypt hashing/verifying is cpu bound,
+// so it's moved to the thread pool as well.
+impl UserOperation {
+    pub(crate) async fn get_user_by_name_async(user_name: String, pool: &Data<DbPool>) -> Result<Option<User>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::get_user_by_name(&user_name, &pool)).await
+    }
+
+    pub(crate) async fn get_user_by_id_async(uid: i32, pool: &Data<DbPool>) -> Result<Option<User>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::get_user_by_id(uid, &pool)).await
+    }
+
+    pub(crate) async fn find_user_async(user_name: String, pool: &Data<DbPool>) -> Result<Option<User>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::find_user(&user_name, &pool)).await
+    }
+
+    pub(crate) async fn verify_email_async(token: String, pool: &Data<DbPool>) -> Result<Status, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::verify_email(&token, &pool)).await
+    }
+
+    pub(crate) async fn get_user_by_email_async(email_addr: String, pool: &Data<DbPool>) -> Result<Option<User>, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::get_user_by_email(&email_addr, &pool)).await
+    }
+
+    pub(crate) async fn modify_password_async(new_password: String, user_name: String, pool: &Data<DbPool>) -> Result<Status, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::modify_password(&new_password, &user_name, &pool)).await
+    }
+
+    pub(crate) async fn get_id_by_username_async(user_name: String, pool: &Data<DbPool>) -> Result<i32, failure::Error> {
+        let pool = pool.clone();
+        run_blocking(move || Self::get_id_by_username(&user_name, &pool)).await
+    }
+
+    pub(crate) async fn hash_password_async(password: String) -> Result<String, failure::Error> {
+        run_blocking(move || Ok(bcrypt::hash(&password, bcrypt::DEFAULT_COST)?)).await
+    }
+
+    pub(crate) async fn verify_password_async(password: String, hashed: String) -> Result<bool, failure::Error> {
+        run_blocking(move || Ok(bcrypt::verify(&password, &hashed)?)).await
+    }
+
+    pub(crate) async fn new_user_async(user: Form<CreateUser>) -> Result<NewUser, failure::Error> {
+        run_blocking(move || NewUser::try_from(user)).await
+    }
+}
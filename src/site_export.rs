@@ -0,0 +1,203 @@
+use actix_web::web::Data;
+use chrono::{ Datelike, NaiveDateTime };
+use serde_derive::{ Deserialize, Serialize };
+use std::collections::{ BTreeMap, BTreeSet };
+use std::fmt;
+use std::fs;
+use std::path::{ Path, PathBuf };
+
+use crate::models::comment::CommentOperation;
+use crate::models::post::{ PostOperation, PostStatus };
+use crate::models::user::UserOperation;
+use crate::utils::{ config, media::MEDIA_URL, sitemap, utils::{ render_template, DbPool } };
+use crate::views::post as pages;
+
+// what has been exported into the folder, so that the next export only renders what changed
+pub(crate) const MANIFEST: &str = ".export.json";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    // post id => the title and the time the article was last modified
+    articles: BTreeMap<i32, (String, Option<NaiveDateTime>)>,
+    pages: usize,
+    years: BTreeSet<i32>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct SiteExport {
+    pub(crate) articles: usize, // rendered articles
+    pub(crate) unchanged: usize,
+    pub(crate) removed: usize,
+    pub(crate) pages: usize, // index, archives and the other pages
+    pub(crate) files: usize, // copied static and media files
+    pub(crate) skipped: Vec<String>,
+}
+
+impl fmt::Display for SiteExport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} article(s) rendered, {} unchanged, {} removed, {} page(s) rendered, {} file(s) copied",
+               self.articles, self.unchanged, self.removed, self.pages, self.files)?;
+        for skipped in self.skipped.iter() {
+            write!(f, "\nskipped: {}", skipped)?;
+        }
+        Ok(())
+    }
+}
+
+// /about/ is about/index.html, so that the links of the templates work on any static file server
+fn page_file(out: &Path, path: &str) -> PathBuf {
+    path.split('/').filter(|segment| !segment.is_empty()).fold(out.to_path_buf(), |file, segment| file.join(segment)).join("index.html")
+}
+
+// a title becomes a folder, it cannot climb out of article/
+fn safe_title(title: &str) -> bool {
+    !title.is_empty() && title.ne(".") && title.ne("..") && !title.contains(|c| c == '/' || c == '\\' || c == '\0')
+}
+
+// written aside and renamed, a server of the folder never sees half a page
+fn write_file(file: &Path, contents: &[u8]) -> Result<(), failure::Error> {
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let part = file.with_extension("part");
+    fs::write(&part, contents)?;
+    fs::rename(&part, file)?;
+    Ok(())
+}
+
+fn render(template: &str, ctx: &tera::Context) -> Result<String, failure::Error> {
+    render_template(template, ctx).map_err(|e| failure::format_err!("failed to render {}: {}", template, e))
+}
+
+fn remove_page(out: &Path, path: &str) -> Result<(), failure::Error> {
+    let folder = page_file(out, path);
+    let folder = folder.parent().unwrap_or(out);
+    if folder.is_dir() {
+        fs::remove_dir_all(folder)?;
+    }
+    Ok(())
+}
+
+// copy the files which are missing or older in the copy, returns how many were copied
+fn copy_dir(from: &Path, to: &Path) -> Result<usize, failure::Error> {
+    if !from.is_dir() {
+        return Ok(0);
+    }
+    let mut copied = 0;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let (source, target) = (entry.path(), to.join(entry.file_name()));
+        if entry.file_type()?.is_dir() {
+            copied += copy_dir(&source, &target)?;
+            continue;
+        }
+        let outdated = match (fs::metadata(&target).and_then(|meta| meta.modified()), entry.metadata()?.modified()) {
+            (Ok(copy), Ok(original)) => copy.lt(&original),
+            _ => true,
+        };
+        if outdated {
+            fs::create_dir_all(to)?;
+            fs::copy(&source, &target)?;
+            copied += 1;
+        }
+    }
+    Ok(copied)
+}
+
+fn read_manifest(out: &Path) -> Manifest {
+    fs::read(out.join(MANIFEST)).ok().and_then(|json| serde_json::from_slice(&json).ok()).unwrap_or_default()
+}
+
+// render the public pages of the published posts into a folder which any static file server can serve.
+// articles are rendered again only when the post or its comments changed since the last export, unless full is set.
+// the pages of posts gone since the last export are removed either way.
+// search, likes and new comments need the blog server, they don't work on the copy.
+pub(crate) fn export_site(out: &Path, full: bool, pool: &Data<DbPool>) -> Result<SiteExport, failure::Error> {
+    fs::create_dir_all(out)?;
+    let manifest = read_manifest(out);
+    let site_url = config::current().site_url.clone();
+    let posts = PostOperation::get_all_posts(PostStatus::Published, pool)?;
+    let mut report = SiteExport::default();
+    let mut exported = Manifest::default();
+
+    for post in posts.iter() {
+        if !safe_title(&post.title) {
+            report.skipped.push(format!("{}: the title cannot be a file name", post.title));
+            continue;
+        }
+        let comments = CommentOperation::get_comments_by_post(post.id, pool)?;
+        let last_modified = pages::article_last_modified(post, &comments);
+        let path = format!("/article/{}/", post.title);
+        exported.articles.insert(post.id, (post.title.clone(), last_modified));
+
+        let unchanged = manifest.articles.get(&post.id).map_or(false, |(title, time)| title.eq(&post.title) && time.eq(&last_modified));
+        if !full && unchanged && page_file(out, &path).is_file() {
+            report.unchanged += 1;
+            continue;
+        }
+        let author = pages::author_name(UserOperation::get_user_by_id(post.user_id, pool)?);
+        let ctx = pages::article_context(post, &author, Some(&comments), &site_url);
+        write_file(&page_file(out, &path), render("post_detail.html", &ctx)?.as_bytes())?;
+        report.articles += 1;
+    }
+    // renamed, unpublished or deleted since the last export
+    let titles: BTreeSet<&String> = exported.articles.values().map(|(title, _)| title).collect();
+    for (title, _) in manifest.articles.values() {
+        if !titles.contains(title) && safe_title(title) {
+            remove_page(out, &format!("/article/{}/", title))?;
+            report.removed += 1;
+        }
+    }
+
+    // the lists show every post, any change renders all of them again
+    exported.pages = if posts.len() > pages::PAGE { pages::pages_num(&posts) } else { 0 };
+    exported.years = posts.iter().filter_map(|post| post.created.map(|time| time.year())).collect();
+    let changed = full || report.articles.gt(&0) || report.removed.gt(&0) || !out.join("index.html").is_file();
+    if changed {
+        let index = render("index.html", &pages::index_context(&posts))?;
+        write_file(&out.join("index.html"), index.as_bytes())?;
+        write_file(&page_file(out, "/index/"), index.as_bytes())?;
+        for page_num in 1..=exported.pages {
+            let page = render("index.html", &pages::page_context(&posts, page_num))?;
+            write_file(&page_file(out, &format!("/page/{}/", page_num)), page.as_bytes())?;
+        }
+        write_file(&page_file(out, "/all_posts/"), render("all_posts.html", &pages::posts_context(&posts))?.as_bytes())?;
+        for year in exported.years.iter() {
+            let year_posts = PostOperation::get_posts_by_year(*year, pool)?;
+            write_file(&page_file(out, &format!("/category/{}/", year)), render("all_posts.html", &pages::posts_context(&year_posts))?.as_bytes())?;
+        }
+        for page in ["about", "contact"].iter() {
+            write_file(&page_file(out, &format!("/{}/", page)), render(&format!("{}.html", page), &tera::Context::new())?.as_bytes())?;
+        }
+        write_file(&out.join("404.html"), render("page_404.html", &tera::Context::new())?.as_bytes())?;
+        report.pages = 6 + exported.pages + exported.years.len();
+
+        // the sitemap needs absolute urls
+        if site_url.is_empty() {
+            report.skipped.push("sitemap.xml and robots.txt: site_url isn't configured".to_owned());
+        } else {
+            let urls = sitemap::site_urls(&posts, &site_url);
+            if urls.len() <= sitemap::MAX_URLS {
+                write_file(&out.join("sitemap.xml"), sitemap::urlset(&site_url, &urls).as_bytes())?;
+            } else {
+                write_file(&out.join("sitemap.xml"), sitemap::sitemap_index(&site_url, &urls, sitemap::MAX_URLS).as_bytes())?;
+                for (i, chunk) in urls.chunks(sitemap::MAX_URLS).enumerate() {
+                    write_file(&out.join(format!("sitemap-{}.xml", i + 1)), sitemap::urlset(&site_url, chunk).as_bytes())?;
+                }
+            }
+            write_file(&out.join("robots.txt"), sitemap::robots_txt(&config::current().robots, &site_url).as_bytes())?;
+        }
+    }
+    for page_num in exported.pages + 1..=manifest.pages {
+        remove_page(out, &format!("/page/{}/", page_num))?;
+    }
+    for year in manifest.years.difference(&exported.years) {
+        remove_page(out, &format!("/category/{}/", year))?;
+    }
+
+    report.files += copy_dir(Path::new(&config::current().static_dir), &out.join("static"))?;
+    report.files += copy_dir(Path::new(&config::current().media.dir), &out.join(MEDIA_URL.trim_start_matches('/')))?;
+
+    write_file(&out.join(MANIFEST), &serde_json::to_vec(&exported)?)?;
+    Ok(report)
+}
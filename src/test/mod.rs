@@ -1,5 +1,23 @@
+pub(self) mod test_analytics;
 pub(self) mod test_auth_views;
+pub(self) mod test_backup;
+pub(self) mod test_blocklist;
 pub(self) mod test_post_views;
+pub(self) mod test_cache;
+pub(self) mod test_config;
+pub(self) mod test_cli;
+pub(self) mod test_health;
+pub(self) mod test_inbox;
+pub(self) mod test_logging;
+pub(self) mod test_jobs;
+pub(self) mod test_media;
+pub(self) mod test_newsletter;
+pub(self) mod test_notifications;
+pub(self) mod test_seo;
+pub(self) mod test_site_export;
+pub(self) mod test_sitemap;
+pub(self) mod test_tls;
+pub(self) mod test_transfer;
 
 use actix_web::web;
 use chrono::Utc;
@@ -7,12 +25,16 @@ use std::iter;
 use rand::{Rng, thread_rng};
 use rand::distributions::Alphanumeric;
 
-use diesel::{ r2d2::{ ConnectionManager, Pool }, pg::PgConnection };
 use failure;
 
 use crate::models::post::{ NewPost, PostOperation, PostStatus };
 use crate::models::user::{ NewUser, UserOperation };
-use crate::utils::utils::{ PgPool, Status };
+use crate::utils::utils::{ db_pool, DbPool, Status };
+use crate::utils::config;
+use crate::models::invitation::{ InvitationOperation, Role };
+use crate::models::migrations;
+
+static MIGRATIONS: std::sync::Once = std::sync::Once::new();
 
 const USERNAME_WITH_PWD: &[u8] = b"username=actix&password=welcome";
 
@@ -22,11 +44,26 @@ pub(self) fn generate_random_string(length: usize) -> String {
                     .collect::<String>()
 }
 
-pub(self) fn test_db_pool() -> Result<PgPool, failure::Error> {
+pub(self) fn test_db_pool() -> Result<DbPool, failure::Error> {
     dotenv::dotenv().ok();
-    let database_url = dotenv::var("TEST_DATABASE_URL")?;
-    let manager = ConnectionManager::<PgConnection>::new(database_url);
-    let pool = Pool::new(manager)?;
+    // TEST_DATABASE_URL still wins over the [test] profile
+    let database_url = match dotenv::var("TEST_DATABASE_URL") {
+        Ok(database_url) => database_url,
+        // sqlite needs no server, a file in the temp folder is enough
+        #[cfg(feature = "sqlite")]
+        Err(_) => std::env::temp_dir().join("actix_blog_test.sqlite3").to_string_lossy().into_owned(),
+        #[cfg(feature = "postgres")]
+        Err(_) => config::BlogConfig::load(None, Some(config::Profile::Test)).map_err(|e| failure::err_msg(e.to_string()))?.database_url,
+    };
+    let pool = db_pool(&database_url)?;
+    
+    // bootstrap the test database with the embedded migrations and the test user,
+    // only once for all test cases. posts written by tests belong to the user of id 1.
+    let conn = pool.get()?;
+    MIGRATIONS.call_once(|| {
+        migrations::run_pending_migrations(&conn).expect("failed to migrate the test database");
+        seed_test_user(&web::Data::new(pool.clone()));
+    });
     Ok(pool)
 }
 
@@ -48,6 +85,10 @@ pub(self) fn insert_posts() {
                         status: "publish".to_owned(),
                         user_id: 1,
                         likes: 0,
+                        excerpt: String::new(),
+                        featured_image: String::new(),
+                        meta_description: String::new(),
+                        canonical_url: String::new(),
                     };
                     match PostOperation::insert_post(&new_post, &db) {
                         Ok(lhs) => assert_eq!(lhs, Status::Success),
@@ -73,6 +114,10 @@ pub(self) fn insert_posts() {
                 status: "publish".to_owned(),
                 user_id: 1,
                 likes: 0,
+                excerpt: String::new(),
+                featured_image: String::new(),
+                meta_description: String::new(),
+                canonical_url: String::new(),
             };
             match PostOperation::insert_post(&new_post, &db) {
                 Ok(lhs) => assert_eq!(lhs, Status::Success),
@@ -83,11 +128,41 @@ pub(self) fn insert_posts() {
     }
 }
 
+// a published post by the test user
+pub(self) fn new_post(title: &str) -> NewPost {
+    NewPost {
+        title: title.to_owned(),
+        slug: generate_random_string(5),
+        body: generate_random_string(40),
+        publish: Some(Utc::now().naive_utc()),
+        created: Some(Utc::now().naive_utc()),
+        updated: Some(Utc::now().naive_utc()),
+        status: "publish".to_owned(),
+        user_id: 1,
+        likes: 0,
+        excerpt: String::new(),
+        featured_image: String::new(),
+        meta_description: String::new(),
+        canonical_url: String::new(),
+    }
+}
+
+// a post of its own, for the tests which count what happens to a post
+pub(self) fn insert_new_post(title: &str) -> i32 {
+    let db = web::Data::new(test_db_pool().unwrap());
+    assert_eq!(PostOperation::insert_post(&new_post(title), &db).unwrap(), Status::Success);
+    PostOperation::get_post_by_title(title, &db).unwrap().unwrap().id
+}
+
 // for testing
 pub(self) fn insert_new_user() {
     let db = web::Data::new(test_db_pool().unwrap().clone());
+    seed_test_user(&db);
+}
+
+fn seed_test_user(db: &web::Data<DbPool>) {
     // It will not insert user if this user exists
-    if let Ok(Some(_)) = UserOperation::get_user_by_name("actix", &db) {
+    if let Ok(Some(_)) = UserOperation::get_user_by_name("actix", db) {
         assert!(true);
     } else {
         let new_user = NewUser {
@@ -97,13 +172,40 @@ pub(self) fn insert_new_user() {
             first_name: "Jim".to_owned(),
             last_name: "Bob".to_owned(),
             email: "jim.bob@actix.com".to_owned(),
+            is_superuser: false,
             is_staff: false,
+            is_active: true,
             last_login: Some(Utc::now().naive_utc()),
             date_joined: Some(Utc::now().naive_utc()),
+            verification_token: None,
         };
-        match UserOperation::insert_user(&new_user, &db) {
+        match UserOperation::insert_user(&new_user, db) {
             Ok(lhs) => assert!(true),
             _ => assert!(false),
         }
     }
-}
\ No newline at end of file
+}
+
+// registration is closed by default, the tests of registration need it open.
+pub(self) fn open_registration() {
+    let mut blog_config = (*config::current()).clone();
+    blog_config.allow_registration = true;
+    config::install(blog_config);
+}
+
+pub(self) const METRICS_TOKEN: &str = "metrics-token";
+
+// /metrics is off by default
+pub(self) fn set_metrics_token() {
+    let mut blog_config = (*config::current()).clone();
+    blog_config.metrics_token = METRICS_TOKEN.to_owned();
+    config::install(blog_config);
+}
+
+// a fresh invitation issued by the test user
+pub(self) fn new_invitation_code() -> String {
+    insert_new_user();
+    let db = web::Data::new(test_db_pool().unwrap().clone());
+    let creator = UserOperation::get_id_by_username("actix", &db).unwrap();
+    InvitationOperation::create_invitation(Role::Author, 1, creator, &db).unwrap().code
+}
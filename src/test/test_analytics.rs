@@ -0,0 +1,110 @@
+use actix_web::{ http::header, test, web };
+use chrono::{ Duration, Utc };
+use diesel::prelude::*;
+
+use crate::models::analytics::{ AnalyticsOperation, NewPageView };
+use crate::models::schema::{ daily_referrers, search_terms };
+use crate::utils::analytics::{ self, is_bot, referrer_host, visitor_hash };
+use super::{ generate_random_string, insert_new_post, insert_new_user, test_db_pool };
+
+const BROWSER: &str = "Mozilla/5.0 (X11; Linux x86_64; rv:75.0) Gecko/20100101 Firefox/75.0";
+
+#[test]
+fn test_is_bot() {
+    assert!(!is_bot(BROWSER));
+    assert!(is_bot("Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)"));
+    assert!(is_bot("facebookexternalhit/1.1"));
+    assert!(is_bot("curl/7.68.0"));
+    assert!(is_bot(""));
+}
+
+#[test]
+fn test_visitor_hash() {
+    let today = Utc::now().naive_utc().date();
+    let visitor = visitor_hash("127.0.0.1", BROWSER, today);
+    assert_eq!(visitor, visitor_hash("127.0.0.1", BROWSER, today));
+    assert_ne!(visitor, visitor_hash("127.0.0.2", BROWSER, today));
+    assert!(!visitor.contains("127.0.0.1"));
+}
+
+#[test]
+fn test_referrer_host() {
+    let site = ["https://blog.example.com"];
+    assert_eq!(referrer_host("https://news.ycombinator.com/item?id=1", &site), "news.ycombinator.com");
+    assert_eq!(referrer_host("http://www.Reddit.com:80/r/rust/", &site), "reddit.com");
+    assert_eq!(referrer_host("https://blog.example.com/index/", &site), "");
+    assert_eq!(referrer_host("", &site), "");
+}
+
+#[test]
+fn test_page_view_and_search_term() {
+    let req = test::TestRequest::default().header(header::USER_AGENT, BROWSER)
+                                          .header(header::REFERER, "https://lobste.rs/s/abc")
+                                          .to_http_request();
+    let view = analytics::page_view(&req, 1).unwrap();
+    assert_eq!(view.referrer, "lobste.rs");
+    assert_eq!(view.visitor.len(), 64);
+    assert_eq!(analytics::search_term(&req, "  Actix   WEB "), Some("actix web".to_owned()));
+    assert_eq!(analytics::search_term(&req, "   "), None);
+
+    let bot = test::TestRequest::default().header(header::USER_AGENT, "Googlebot/2.1").to_http_request();
+    assert!(analytics::page_view(&bot, 1).is_none());
+    let prefetch = test::TestRequest::default().header(header::USER_AGENT, BROWSER).header("Sec-Purpose", "prefetch").to_http_request();
+    assert!(analytics::page_view(&prefetch, 1).is_none());
+}
+
+#[test]
+fn test_analytics() {
+    insert_new_user();
+    let db = web::Data::new(test_db_pool().unwrap());
+    let post_id = insert_new_post(&generate_random_string(10));
+    let now = Utc::now().naive_utc();
+    let referrer = format!("{}.example.org", generate_random_string(8).to_lowercase());
+    let view = |visitor: &str, referrer: &str, viewed| NewPageView { post_id, visitor: visitor.to_owned(), referrer: referrer.to_owned(), viewed };
+
+    // two readers two days ago, one of them read it twice, and one today
+    let two_days_ago = now - Duration::days(2);
+    AnalyticsOperation::record_view(&view("a", &referrer, two_days_ago), &db).unwrap();
+    AnalyticsOperation::record_view(&view("a", "", two_days_ago), &db).unwrap();
+    AnalyticsOperation::record_view(&view("b", &referrer, two_days_ago), &db).unwrap();
+    AnalyticsOperation::record_view(&view("c", "", now), &db).unwrap();
+
+    let since = now.date() - Duration::days(2);
+    let before = AnalyticsOperation::analytics(since, Some(post_id), &db).unwrap();
+    assert!(AnalyticsOperation::rollup(now.date(), &db).unwrap().ge(&3));
+    // rolled up or not, the counts are the same
+    let after = AnalyticsOperation::analytics(since, Some(post_id), &db).unwrap();
+    for analytics in [before, after].iter() {
+        assert_eq!(analytics.days.len(), 3);
+        assert_eq!((analytics.days[0].views, analytics.days[0].readers), (3, 2));
+        assert_eq!((analytics.days[1].views, analytics.days[1].readers), (0, 0));
+        assert_eq!((analytics.days[2].views, analytics.days[2].readers), (1, 1));
+        assert_eq!(analytics.posts.len(), 1);
+        assert_eq!((analytics.posts[0].post_id, analytics.posts[0].views), (post_id, 4));
+    }
+    // the top lists are of the whole blog, other tests fill them as well
+    let conn = db.get().unwrap();
+    let referred = daily_referrers::table.filter(daily_referrers::referrer.eq(&referrer))
+                                         .select((daily_referrers::day, daily_referrers::views))
+                                         .load::<(chrono::NaiveDate, i32)>(&conn)
+                                         .unwrap();
+    assert_eq!(referred, vec![(two_days_ago.date(), 2)]);
+    // today's views are left for the next rollup
+    assert!(AnalyticsOperation::today_views(&db).unwrap().0.ge(&1));
+}
+
+#[test]
+fn test_search_terms() {
+    let db = web::Data::new(test_db_pool().unwrap());
+    let term = generate_random_string(12).to_lowercase();
+    AnalyticsOperation::record_search(&term, &db).unwrap();
+    AnalyticsOperation::record_search(&term, &db).unwrap();
+
+    let today = Utc::now().naive_utc().date();
+    let searches = search_terms::table.filter(search_terms::term.eq(&term))
+                                      .select((search_terms::day, search_terms::searches))
+                                      .load::<(chrono::NaiveDate, i32)>(&db.get().unwrap())
+                                      .unwrap();
+    assert_eq!(searches, vec![(today, 2)]);
+    assert!(!AnalyticsOperation::analytics(today, None, &db).unwrap().search_terms.is_empty());
+}
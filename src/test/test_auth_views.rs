@@ -3,10 +3,14 @@ use actix_files as fs;
 use actix_identity::{ CookieIdentityPolicy, IdentityService };
 use actix_service::Service;
 use bytes::Bytes;
+use chrono::Utc;
 use serde::{ Serialize, Deserialize };
 
+use crate::models::invitation::{ self, InvitationOperation, Registration, Role };
+use crate::models::user::{ NewUser, UserOperation };
+use crate::utils::mailer::Mail;
 use crate::views;
-use super::{ generate_random_string, insert_posts, insert_new_user, test_db_pool, USERNAME_WITH_PWD };
+use super::{ generate_random_string, insert_posts, insert_new_user, new_invitation_code, open_registration, test_db_pool, USERNAME_WITH_PWD };
 
 #[actix_rt::test]
 async fn test_login() {
@@ -88,6 +92,7 @@ async fn test_handle_login_failure() {
 
 #[actix_rt::test]
 async fn test_register() {
+    open_registration();
     let mut app = test::init_service(App::new()
         .service(fs::Files::new("/static", "static/").show_files_listing())
         .service(
@@ -102,6 +107,7 @@ async fn test_register() {
 
 #[actix_rt::test]
 async fn test_handle_registration() {
+    open_registration();
     let mut app = test::init_service(App::new().data(test_db_pool().unwrap().clone())
         .wrap(
             IdentityService::new(
@@ -120,21 +126,23 @@ async fn test_handle_registration() {
         )
     ).await;
 
-    let new_user = format!("username={}&password={}&first_name={}&last_name={}&email={}",
+    let new_user = format!("username={}&password={}&first_name={}&last_name={}&email={}&invitation_code={}",
                         generate_random_string(10), generate_random_string(8), generate_random_string(6),
-                        generate_random_string(6), generate_random_string(15));
+                        generate_random_string(6), generate_random_string(15), new_invitation_code());
     let req = test::TestRequest::post()
                 .uri("/admin/register/")
                 .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
                 .set_payload(Bytes::from(new_user.into_bytes()))
                 .to_request();
 
+    // the account needs the email verification before login
     let resp = app.call(req).await.unwrap();
-    assert_eq!(resp.status(), http::StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(resp.status(), http::StatusCode::OK);
 }
 
 #[actix_rt::test]
 async fn test_handle_registration_failure() {
+    open_registration();
     let mut app = test::init_service(App::new().data(test_db_pool().unwrap().clone())
         .wrap(
             IdentityService::new(
@@ -154,15 +162,29 @@ async fn test_handle_registration_failure() {
     ).await;
 
     // cannot register a user with a username that has been existed.
-    let new_user = b"username=actix&password=welcome&first_name=jack&last_name=jones&email=jack.jones@actix.com";
+    let new_user = format!("username=actix&password=welcome&first_name=jack&last_name=jones&email=jack.jones@actix.com&invitation_code={}",
+                           new_invitation_code());
     let req = test::TestRequest::post()
                 .uri("/admin/register/")
                 .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
-                .set_payload(Bytes::from_static(new_user))
+                .set_payload(Bytes::from(new_user.into_bytes()))
                 .to_request();
 
+    // the form again, with what was typed
     let resp = app.call(req).await.unwrap();
-    assert_eq!(resp.status(), http::StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(resp.status(), http::StatusCode::CONFLICT);
+    
+    // cannot register without a valid invitation code.
+    let new_user = format!("username={}&password=welcome&first_name=jack&last_name=jones&email={}&invitation_code={}",
+                           generate_random_string(10), generate_random_string(15), generate_random_string(24));
+    let req = test::TestRequest::post()
+                .uri("/admin/register/")
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .set_payload(Bytes::from(new_user.into_bytes()))
+                .to_request();
+
+    let resp = app.call(req).await.unwrap();
+    assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
 }
 
 #[actix_rt::test]
@@ -844,3 +866,97 @@ async fn test_save_modified_post() {
     let resp = app.call(req).await.unwrap();
     assert_eq!(resp.status(), http::StatusCode::TEMPORARY_REDIRECT);
 }
+
+#[actix_rt::test]
+async fn test_deactivated_user_is_logged_out() {
+    let db = web::Data::new(test_db_pool().unwrap());
+    let username = generate_random_string(12);
+    let new_user = NewUser {
+        username: username.clone(),
+        // welcome as password
+        password: "$2y$12$G6QbkGaOodmtzMZg5N29ReuOiJFB0/pFhnqEA3TOBlefDDzUUMmES".to_owned(),
+        first_name: "Jamie".to_owned(),
+        last_name: "Deng".to_owned(),
+        email: format!("{}@actix.com", username.to_lowercase()),
+        is_superuser: false,
+        is_staff: false,
+        is_active: true,
+        last_login: None,
+        date_joined: Some(Utc::now().naive_utc()),
+        verification_token: None,
+    };
+    UserOperation::insert_user(&new_user, &db).unwrap();
+
+    let mut app = test::init_service(App::new().data(test_db_pool().unwrap().clone())
+        .wrap(
+            IdentityService::new(
+                CookieIdentityPolicy::new(&[0;32])
+                    .name("admin")
+                    .path("/admin")
+                    .max_age(60i64)
+                    .secure(false)
+            )
+        )
+        .service(
+            web::scope("/admin").service(web::resource("/login/").route(web::post().to(views::auth::handle_login)))
+                                .service(web::resource("/cache_stats/").route(web::get().to(views::auth::cache_stats)))
+        )
+    ).await;
+
+    let req = test::TestRequest::post()
+                .uri("/admin/login/")
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .set_payload(Bytes::from(format!("username={}&password=welcome", username).into_bytes()))
+                .to_request();
+    let resp = app.call(req).await.unwrap();
+    let identity = resp.response().cookies().next().unwrap().into_owned();
+
+    let req = test::TestRequest::get().uri("/admin/cache_stats/").cookie(identity.clone()).to_request();
+    assert_eq!(app.call(req).await.unwrap().status(), http::StatusCode::OK);
+
+    // the cookie is still valid, the account isn't
+    UserOperation::set_active(&username, false, &db).unwrap();
+    let req = test::TestRequest::get().uri("/admin/cache_stats/").cookie(identity).to_request();
+    let resp = app.call(req).await.unwrap();
+    assert_eq!(resp.status(), http::StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(resp.headers().get(header::LOCATION).unwrap(), "/admin/login/");
+}
+
+#[test]
+fn test_invitation_days() {
+    insert_new_user();
+    let db = web::Data::new(test_db_pool().unwrap());
+    assert!(InvitationOperation::create_invitation(Role::Author, 0, 1, &db).is_err());
+    assert!(InvitationOperation::create_invitation(Role::Author, i64::max_value(), 1, &db).is_err());
+    assert!(InvitationOperation::create_invitation(Role::Author, invitation::MAX_DAYS, 1, &db).is_ok());
+}
+
+#[test]
+fn test_invitation_kept_if_mail_fails() {
+    let db = web::Data::new(test_db_pool().unwrap());
+    let code = new_invitation_code();
+    let new_user = || {
+        let username = generate_random_string(12);
+        NewUser {
+            email: format!("{}@actix.com", username.to_lowercase()),
+            username,
+            password: String::new(),
+            first_name: "Jamie".to_owned(),
+            last_name: "Deng".to_owned(),
+            is_superuser: false,
+            is_staff: false,
+            is_active: false,
+            last_login: None,
+            date_joined: Some(Utc::now().naive_utc()),
+            verification_token: None,
+        }
+    };
+
+    let failed = InvitationOperation::register_with_invitation(&code, new_user(), |_| Err(failure::err_msg("no template")), &db);
+    assert!(failed.is_err());
+    // nothing happened, the code can still be used
+    let registered = InvitationOperation::register_with_invitation(&code, new_user(), |token| Ok(Mail {
+        to: "jamie@actix.com".to_owned(), subject: "Verify".to_owned(), body: token.to_owned(), unsubscribe: None,
+    }), &db).unwrap();
+    assert!(matches!(registered, Registration::Registered(_)));
+}
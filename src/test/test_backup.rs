@@ -0,0 +1,121 @@
+use actix_web::web;
+use std::fs::{ self, File };
+use std::io::{ Read, Write };
+use zip::{ write::FileOptions, ZipArchive, ZipWriter };
+
+use crate::backup::{ self, BackupManifest, MANIFEST, TABLES };
+use crate::models::user::UserOperation;
+use super::{ generate_random_string, insert_new_user, insert_posts, test_db_pool };
+
+fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("actix_blog_{}_{}", name, generate_random_string(8)))
+}
+
+#[test]
+fn test_backup() {
+    insert_new_user();
+    insert_posts();
+    let db = web::Data::new(test_db_pool().unwrap());
+    let media_dir = temp_path("media");
+    fs::create_dir_all(media_dir.join("thumbs")).unwrap();
+    fs::write(media_dir.join("cat.png"), b"png").unwrap();
+    fs::write(media_dir.join("thumbs").join("cat.png"), b"thumb").unwrap();
+    
+    let out = temp_path("backup.zip");
+    let manifest = backup::backup(&out, &media_dir, &db).unwrap();
+    assert_eq!(manifest.media_files, 2);
+    assert!(manifest.tables["users"].ge(&1) && manifest.tables["posts"].ge(&1));
+    
+    let mut archive = ZipArchive::new(File::open(&out).unwrap()).unwrap();
+    assert_eq!(backup::read_manifest(&mut archive).unwrap(), manifest);
+    for table in TABLES.iter() {
+        assert!(archive.by_name(&format!("{}.ndjson", table)).is_ok(), "{} is missing", table);
+    }
+    // the password hashes are kept
+    let password = UserOperation::get_user_by_name("actix", &db).unwrap().unwrap().password;
+    let mut users = String::new();
+    archive.by_name("users.ndjson").unwrap().read_to_string(&mut users).unwrap();
+    assert!(users.lines().any(|line| line.contains(&password)));
+    let mut thumb = Vec::new();
+    archive.by_name("media/thumbs/cat.png").unwrap().read_to_end(&mut thumb).unwrap();
+    assert_eq!(thumb, b"thumb");
+    
+    // the test database has rows already
+    let error = backup::restore(&out, &media_dir, &db).unwrap_err().to_string();
+    assert!(error.contains("isn't empty"), "{}", error);
+    
+    fs::remove_file(&out).unwrap();
+    fs::remove_dir_all(&media_dir).unwrap();
+}
+
+#[test]
+fn test_restore_checks_the_manifest() {
+    let db = web::Data::new(test_db_pool().unwrap());
+    let write_backup = |manifest: &BackupManifest| {
+        let out = temp_path("manifest.zip");
+        let mut zip = ZipWriter::new(File::create(&out).unwrap());
+        zip.start_file(MANIFEST, FileOptions::default()).unwrap();
+        zip.write_all(serde_json::to_string(manifest).unwrap().as_bytes()).unwrap();
+        zip.finish().unwrap();
+        out
+    };
+    let media_dir = temp_path("media");
+    let out = temp_path("backup.zip");
+    let manifest = backup::backup(&out, &media_dir, &db).unwrap();
+    fs::remove_file(&out).unwrap();
+    
+    let older = BackupManifest { migrations: manifest.migrations[..manifest.migrations.len() - 1].to_vec(), ..manifest };
+    let out = write_backup(&older);
+    let error = backup::restore(&out, &media_dir, &db).unwrap_err().to_string();
+    assert!(error.contains("schema"), "{}", error);
+    fs::remove_file(&out).unwrap();
+    
+    let newer_format = BackupManifest { format_version: backup::FORMAT_VERSION + 1, ..older };
+    let out = write_backup(&newer_format);
+    assert!(backup::restore(&out, &media_dir, &db).unwrap_err().to_string().contains("isn't supported"));
+    fs::remove_file(&out).unwrap();
+    assert!(!media_dir.exists());
+}
+
+// a fresh sqlite file is an empty database, postgres would need a server for it
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_restore_into_empty_database() {
+    use crate::models::post::{ NewPost, PostOperation, PostStatus };
+    use crate::utils::utils::{ db_pool, Status };
+    use super::new_post;
+
+    insert_new_user();
+    insert_posts();
+    let db = web::Data::new(test_db_pool().unwrap());
+    let media_dir = temp_path("media");
+    fs::create_dir_all(&media_dir).unwrap();
+    fs::write(media_dir.join("cat.png"), b"png").unwrap();
+    let out = temp_path("backup.zip");
+    let manifest = backup::backup(&out, &media_dir, &db).unwrap();
+    
+    let database = temp_path("restored.sqlite3");
+    let restored_db = web::Data::new(db_pool(&database.to_string_lossy()).unwrap());
+    let restored_media = temp_path("restored_media");
+    assert_eq!(backup::restore(&out, &restored_media, &restored_db).unwrap(), manifest);
+    assert_eq!(fs::read(restored_media.join("cat.png")).unwrap(), b"png");
+    
+    let user = UserOperation::get_user_by_name("actix", &restored_db).unwrap().unwrap();
+    assert_eq!(user.password, UserOperation::get_user_by_name("actix", &db).unwrap().unwrap().password);
+    let posts = PostOperation::get_all_posts(PostStatus::All, &restored_db).unwrap();
+    assert_eq!(posts.len(), manifest.tables["posts"]);
+    
+    // new rows get ids after the restored ones
+    let new_post = NewPost { user_id: user.id, ..new_post(&generate_random_string(10)) };
+    assert_eq!(PostOperation::insert_post(&new_post, &restored_db).unwrap(), Status::Success);
+    
+    // restoring again refuses to mix the rows
+    assert!(backup::restore(&out, &restored_media, &restored_db).is_err());
+    
+    for path in [out, database].iter() {
+        fs::remove_file(path).unwrap();
+    }
+    for dir in [media_dir, restored_media].iter() {
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,160 @@
+use actix_web::{ http::header, test, web, App };
+use chrono::Utc;
+use std::net::IpAddr;
+
+use crate::models::blocklist::{ BlockedEntry, BlocklistOperation, EMAIL, IP, NAME };
+use crate::models::comment::{ CommentOperation, NewComment };
+use crate::models::contact::{ ContactOperation, CreateContact, INBOX };
+use crate::utils::blocklist::{ address_behind_proxies, blocked_by, normalize_entry, Author };
+use crate::views;
+use super::{ generate_random_string, insert_new_post, insert_new_user, test_db_pool };
+
+fn entry(kind: &str, value: &str) -> BlockedEntry {
+    BlockedEntry { id: 1, kind: kind.to_owned(), value: value.to_owned(), reason: String::new(), created: Utc::now().naive_utc() }
+}
+
+fn is_blocked(entry: &BlockedEntry, email: &str, name: &str, address: &str) -> bool {
+    let author = Author { email, name, address: address.parse::<IpAddr>().ok() };
+    blocked_by(std::slice::from_ref(entry), &author).is_some()
+}
+
+#[test]
+fn test_normalize_entry() {
+    assert_eq!(normalize_entry(EMAIL, " Jim@Example.com "), Ok("jim@example.com".to_owned()));
+    assert_eq!(normalize_entry(EMAIL, "@Example.com"), Ok("@example.com".to_owned()));
+    assert!(normalize_entry(EMAIL, "jim").is_err());
+    assert!(normalize_entry(EMAIL, "@com").is_err());
+
+    assert_eq!(normalize_entry(NAME, " Cheap* "), Ok("Cheap*".to_owned()));
+    assert!(normalize_entry(NAME, "*").is_err());
+    assert!(normalize_entry(NAME, "").is_err());
+
+    assert_eq!(normalize_entry(IP, "10.1.2.3"), Ok("10.1.2.3".to_owned()));
+    assert_eq!(normalize_entry(IP, "10.1.2.3/32"), Ok("10.1.2.3".to_owned()));
+    assert_eq!(normalize_entry(IP, "10.0.0.0/8"), Ok("10.0.0.0/8".to_owned()));
+    assert_eq!(normalize_entry(IP, "2001:DB8::/32"), Ok("2001:db8::/32".to_owned()));
+    assert!(normalize_entry(IP, "0.0.0.0/0").is_err());
+    assert!(normalize_entry(IP, "10.0.0.0/33").is_err());
+    assert!(normalize_entry(IP, "example.com").is_err());
+
+    assert!(normalize_entry("phone", "123").is_err());
+}
+
+#[test]
+fn test_blocked_by() {
+    let jim = entry(EMAIL, "jim@example.com");
+    assert!(is_blocked(&jim, " JIM@example.com", "Jim", ""));
+    assert!(!is_blocked(&jim, "jimmy@example.com", "Jim", ""));
+
+    let domain = entry(EMAIL, "@spam.com");
+    assert!(is_blocked(&domain, "anyone@spam.com", "", ""));
+    assert!(!is_blocked(&domain, "anyone@notspam.com.cn", "", ""));
+
+    let name = entry(NAME, "cheap*pills");
+    assert!(is_blocked(&name, "a@example.com", "Cheap blue pills", ""));
+    assert!(!is_blocked(&name, "a@example.com", "Not cheap pills", ""));
+    assert!(is_blocked(&entry(NAME, "b?b"), "a@example.com", "Bob", ""));
+    // regex characters in a name are matched as they are
+    assert!(!is_blocked(&entry(NAME, "a.c"), "a@example.com", "abc", ""));
+
+    let range = entry(IP, "10.0.0.0/8");
+    assert!(is_blocked(&range, "a@example.com", "", "10.200.3.4"));
+    assert!(is_blocked(&range, "a@example.com", "", "::ffff:10.200.3.4"));
+    assert!(!is_blocked(&range, "a@example.com", "", "11.0.0.1"));
+    // nothing is known of the address
+    assert!(!is_blocked(&range, "a@example.com", "", ""));
+    assert!(is_blocked(&entry(IP, "2001:db8::/32"), "a@example.com", "", "2001:db8:1::1"));
+    assert!(is_blocked(&entry(IP, "192.168.1.1"), "a@example.com", "", "192.168.1.1"));
+    assert!(!is_blocked(&entry(IP, "192.168.1.1"), "a@example.com", "", "192.168.1.2"));
+}
+
+#[test]
+fn test_address_behind_proxies() {
+    let ip = |address: &str| address.parse::<IpAddr>().ok();
+    let proxies = vec!["10.0.0.0/8".to_owned(), "192.168.1.1".to_owned()];
+    // without a trusted proxy in front, the header is whatever the client made up
+    assert_eq!(address_behind_proxies(ip("203.0.113.7"), Some("1.2.3.4"), &proxies), ip("203.0.113.7"));
+    assert_eq!(address_behind_proxies(ip("203.0.113.7"), Some("1.2.3.4"), &[]), ip("203.0.113.7"));
+    assert_eq!(address_behind_proxies(ip("10.0.0.2"), Some("203.0.113.7"), &proxies), ip("203.0.113.7"));
+    // the client's own X-Forwarded-For is kept in front of what the proxies appended
+    assert_eq!(address_behind_proxies(ip("10.0.0.2"), Some("1.2.3.4, 203.0.113.7, 192.168.1.1"), &proxies), ip("203.0.113.7"));
+    assert_eq!(address_behind_proxies(ip("10.0.0.2"), Some("[2001:db8::1]:5678"), &proxies), ip("2001:db8::1"));
+    // nothing forwarded, or garbage, the proxy is the best known
+    assert_eq!(address_behind_proxies(ip("10.0.0.2"), None, &proxies), ip("10.0.0.2"));
+    assert_eq!(address_behind_proxies(ip("10.0.0.2"), Some("unknown"), &proxies), ip("10.0.0.2"));
+    assert_eq!(address_behind_proxies(None, Some("1.2.3.4"), &proxies), None);
+}
+
+#[test]
+fn test_blocklist_entries() {
+    let db = web::Data::new(test_db_pool().unwrap());
+    let value = format!("{}@example.com", generate_random_string(12).to_lowercase());
+
+    let added = BlocklistOperation::add(EMAIL, &value, "spam", &db).unwrap().unwrap();
+    assert_eq!((added.kind.as_str(), added.value.as_str(), added.reason.as_str()), (EMAIL, value.as_str(), "spam"));
+    assert!(BlocklistOperation::add(EMAIL, &value, "again", &db).unwrap().is_none());
+    // the same value of another kind is another entry
+    let as_name = BlocklistOperation::add(NAME, &value, "", &db).unwrap().unwrap();
+    assert!(BlocklistOperation::get_entries(&db).unwrap().iter().any(|entry| entry.id.eq(&added.id)));
+
+    assert!(BlocklistOperation::remove(added.id, &db).unwrap());
+    assert!(BlocklistOperation::remove(as_name.id, &db).unwrap());
+    assert!(!BlocklistOperation::remove(added.id, &db).unwrap());
+}
+
+#[test]
+fn test_moderate_comments() {
+    insert_new_user();
+    let db = web::Data::new(test_db_pool().unwrap());
+    let post_id = insert_new_post(&generate_random_string(10));
+    let email = format!("{}@example.com", generate_random_string(12).to_lowercase());
+    for (i, typed) in [email.clone(), email.to_uppercase()].iter().enumerate() {
+        let new_comment = NewComment {
+            username: "Troll".to_owned(),
+            email: typed.clone(),
+            comment: format!("comment {}", i),
+            committed_time: Some(Utc::now().naive_utc()),
+            post_id,
+        };
+        CommentOperation::insert_comment(new_comment, &db).unwrap();
+    }
+
+    // however the email was typed, the latest first
+    let comments = CommentOperation::get_comments_by_email(&format!(" {} ", email.to_uppercase()), &db).unwrap();
+    assert_eq!(comments.iter().map(|comment| comment.comment.as_str()).collect::<Vec<_>>(), vec!["comment 1", "comment 0"]);
+
+    assert!(CommentOperation::update_comment(comments[0].id, "fixed a typo", &db).unwrap());
+    assert_eq!(CommentOperation::get_comment(comments[0].id, &db).unwrap().unwrap().comment, "fixed a typo");
+    assert!(CommentOperation::delete_comment(comments[1].id, &db).unwrap());
+    assert!(CommentOperation::get_comment(comments[1].id, &db).unwrap().is_none());
+    assert!(!CommentOperation::update_comment(comments[1].id, "gone", &db).unwrap());
+    assert_eq!(CommentOperation::get_comments_by_email(&email, &db).unwrap().len(), 1);
+}
+
+#[actix_rt::test]
+async fn test_blocked_contact() {
+    let db = web::Data::new(test_db_pool().unwrap());
+    let word = generate_random_string(16);
+    let email = format!("{}@example.com", word.to_lowercase());
+    let entry = BlocklistOperation::add(EMAIL, &email, "", &db).unwrap().unwrap();
+
+    let mut app = test::init_service(App::new().data(test_db_pool().unwrap().clone())
+        .service(
+            web::scope("/").service(web::resource("/add_contact/").route(web::post().to(views::post::add_contact)))
+        )
+    ).await;
+    let new_contact = CreateContact {
+        tourist_name: "jamie".to_owned(),
+        email: email.to_uppercase(),
+        message: format!("buy now {}", word),
+    };
+    let req = test::TestRequest::post().uri("/add_contact/")
+                                       .header(header::CONTENT_TYPE, "application/json")
+                                       .set_json(&new_contact)
+                                       .to_request();
+    // the sender can't tell, but the message is gone
+    let result: bool = test::read_response_json(&mut app, req).await;
+    assert_eq!(result, true);
+    assert_eq!(ContactOperation::get_inbox(INBOX, Some(&word), 1, 20, &db).unwrap().total, 0);
+    BlocklistOperation::remove(entry.id, &db).unwrap();
+}
@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+use crate::utils::cache::{ CacheStats, LruCache };
+
+#[test]
+fn test_cache_hit_and_miss() {
+    let cache: LruCache<String> = LruCache::new(2, Duration::from_secs(60));
+    cache.insert("index", "rendered".to_owned());
+
+    assert_eq!(cache.get("index"), Some("rendered".to_owned()));
+    assert_eq!(cache.get("about"), None);
+    assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 1, entries: 1, capacity: 2 });
+}
+
+#[test]
+fn test_cache_evicts_least_recently_used() {
+    let cache: LruCache<i32> = LruCache::new(2, Duration::from_secs(60));
+    cache.insert("first", 1);
+    cache.insert("second", 2);
+    // touch the first one, so the second one becomes the least recently used.
+    assert_eq!(cache.get("first"), Some(1));
+    cache.insert("third", 3);
+
+    assert_eq!(cache.get("first"), Some(1));
+    assert_eq!(cache.get("second"), None);
+    assert_eq!(cache.get("third"), Some(3));
+}
+
+#[test]
+fn test_cache_expired_entry() {
+    let cache: LruCache<i32> = LruCache::new(2, Duration::from_millis(10));
+    cache.insert("index", 1);
+    std::thread::sleep(Duration::from_millis(20));
+
+    assert_eq!(cache.get("index"), None);
+    cache.insert("index", 2);
+    std::thread::sleep(Duration::from_millis(20));
+    assert_eq!(cache.prune(), 1);
+    assert_eq!(cache.stats().entries, 0);
+}
+
+#[test]
+fn test_cache_shrink() {
+    let cache: LruCache<i32> = LruCache::new(3, Duration::from_secs(60));
+    (0..3).for_each(|i| cache.insert(i.to_string(), i));
+    cache.configure(1, Duration::from_secs(60));
+
+    assert_eq!(cache.stats().entries, 1);
+    assert_eq!(cache.get("2"), Some(2));
+}
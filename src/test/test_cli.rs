@@ -0,0 +1,76 @@
+use crate::cli::Command;
+use crate::transfer::{ ExportOptions, ImportSource };
+
+fn args(line: &str) -> Vec<String> {
+    line.split_whitespace().map(str::to_owned).collect()
+}
+
+#[test]
+fn test_serve_is_default() {
+    assert_eq!(Command::from_args(&args("main")).unwrap(), Command::Serve);
+    assert_eq!(Command::from_args(&args("main --config blog.toml")).unwrap(), Command::Serve);
+    assert_eq!(Command::from_args(&args("main serve")).unwrap(), Command::Serve);
+}
+
+#[test]
+fn test_parse_commands() {
+    let command = Command::from_args(&args("main --config=blog.toml createsuperuser admin admin@actix.com --password secret")).unwrap();
+    assert_eq!(command, Command::CreateSuperuser {
+        username: "admin".to_owned(), email: "admin@actix.com".to_owned(), password: Some("secret".to_owned())
+    });
+    
+    let command = Command::from_args(&args("main set-password admin")).unwrap();
+    assert_eq!(command, Command::SetPassword { username: "admin".to_owned(), password: None });
+    
+    let command = Command::from_args(&args("main publish 42")).unwrap();
+    assert_eq!(command, Command::Publish { post: "42".to_owned() });
+}
+
+#[test]
+fn test_invalid_commands() {
+    assert!(Command::from_args(&args("main publish")).is_err());
+    assert!(Command::from_args(&args("main drop-database")).is_err());
+    assert!(Command::from_args(&args("main set-password admin --password")).is_err());
+}
+
+#[test]
+fn test_parse_transfer_commands() {
+    let command = Command::from_args(&args("main export-posts --out posts.zip --status=publish --format yaml")).unwrap();
+    assert_eq!(command, Command::ExportPosts {
+        out: "posts.zip".to_owned(),
+        options: ExportOptions { status: "publish".to_owned(), format: "yaml".to_owned(), ..ExportOptions::default() },
+    });
+    
+    let command = Command::from_args(&args("main import-posts posts.zip --dry-run --author actix")).unwrap();
+    assert_eq!(command, Command::ImportPosts {
+        path: "posts.zip".to_owned(), source: ImportSource::Markdown, author: Some("actix".to_owned()), dry_run: true
+    });
+    
+    let command = Command::from_args(&args("main import-posts export.xml --from wordpress")).unwrap();
+    assert_eq!(command, Command::ImportPosts { path: "export.xml".to_owned(), source: ImportSource::WordPress, author: None, dry_run: false });
+    
+    assert!(Command::from_args(&args("main export-posts")).is_err());
+    assert!(Command::from_args(&args("main export-posts --out posts.zip --since yesterday")).is_err());
+    assert!(Command::from_args(&args("main export-posts --out posts.zip --format json")).is_err());
+    assert!(Command::from_args(&args("main import-posts blog --from ghost")).is_err());
+}
+
+#[test]
+fn test_parse_export_site() {
+    let command = Command::from_args(&args("main export-site --out public")).unwrap();
+    assert_eq!(command, Command::ExportSite { out: "public".to_owned(), full: false });
+    
+    let command = Command::from_args(&args("main export-site --full --out=public")).unwrap();
+    assert_eq!(command, Command::ExportSite { out: "public".to_owned(), full: true });
+    
+    assert!(Command::from_args(&args("main export-site")).is_err());
+}
+
+#[test]
+fn test_parse_backup_commands() {
+    assert_eq!(Command::from_args(&args("main backup --out blog.zip")).unwrap(), Command::Backup { out: "blog.zip".to_owned() });
+    assert_eq!(Command::from_args(&args("main restore blog.zip")).unwrap(), Command::Restore { path: "blog.zip".to_owned() });
+    
+    assert!(Command::from_args(&args("main backup")).is_err());
+    assert!(Command::from_args(&args("main restore")).is_err());
+}
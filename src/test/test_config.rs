@@ -0,0 +1,115 @@
+use std::path::PathBuf;
+
+use crate::utils::config::{ apply_env_overrides, config_path_from_args, BlogConfig, Profile };
+
+#[test]
+fn test_env_overrides() {
+    let mut table = r#"
+        port = 8088
+        [tls]
+        enabled = false
+    "#.parse::<toml::Value>().unwrap().as_table().cloned().unwrap();
+
+    let vars = vec![
+        ("ACTIX_BLOG_PORT".to_owned(), "80".to_owned()),
+        ("ACTIX_BLOG_TLS__ENABLED".to_owned(), "true".to_owned()),
+        ("ACTIX_BLOG_DATABASE_URL".to_owned(), "postgres://actix@localhost/blog".to_owned()),
+        ("ACTIX_BLOG_ENV".to_owned(), "production".to_owned()),
+        ("PORT".to_owned(), "90".to_owned()),
+    ];
+    let raw_env = apply_env_overrides(&mut table, vars.into_iter());
+
+    assert_eq!(table["port"].as_integer(), Some(80));
+    assert_eq!(raw_env.get("tls.enabled").map(String::as_str), Some("true"));
+    assert_eq!(table["tls"]["enabled"].as_bool(), Some(true));
+    assert_eq!(table["database_url"].as_str(), Some("postgres://actix@localhost/blog"));
+    // the profile selector isn't a setting
+    assert!(!table.contains_key("env"));
+}
+
+#[test]
+fn test_config_path_from_args() {
+    let args: Vec<String> = vec!["main".to_owned(), "--config".to_owned(), "/etc/blog.toml".to_owned()];
+    assert_eq!(config_path_from_args(&args), Some(PathBuf::from("/etc/blog.toml")));
+
+    let args: Vec<String> = vec!["main".to_owned(), "--config=blog.toml".to_owned()];
+    assert_eq!(config_path_from_args(&args), Some(PathBuf::from("blog.toml")));
+
+    let args: Vec<String> = vec!["main".to_owned()];
+    assert_eq!(config_path_from_args(&args), None);
+}
+
+#[test]
+fn test_report_every_problem() {
+    let path = std::env::temp_dir().join("actix_blog_invalid_config.toml");
+    std::fs::write(&path, r#"
+        [default]
+        port = "8088"
+        workers = 0
+        log = "loud"
+        trusted_proxies = ["10.0.0.0/8", "proxy.local"]
+        [test.tls]
+        enabled = "yes"
+    "#).unwrap();
+
+    let errors = BlogConfig::load(Some(&path), Some(Profile::Test)).unwrap_err().0;
+    let _ = std::fs::remove_file(&path);
+
+    assert!(errors.iter().any(|e| e.starts_with("port")));
+    assert!(errors.iter().any(|e| e.starts_with("workers")));
+    assert!(errors.iter().any(|e| e.starts_with("log")));
+    assert!(errors.iter().any(|e| e.starts_with("trusted_proxies") && e.ends_with("proxy.local")));
+    assert!(errors.iter().any(|e| e.starts_with("tls.enabled")));
+}
+
+#[test]
+fn test_missing_explicit_config() {
+    let path = std::env::temp_dir().join("actix_blog_not_existed.toml");
+    assert!(BlogConfig::load(Some(&path), Some(Profile::Test)).is_err());
+}
+
+#[test]
+fn test_mail_config() {
+    let path = std::env::temp_dir().join("actix_blog_mail_config.toml");
+    std::fs::write(&path, r#"
+        [test.mail]
+        backend = "smtp"
+        smtp_security = "pigeon"
+        secret = "short"
+    "#).unwrap();
+
+    let errors = BlogConfig::load(Some(&path), Some(Profile::Test)).unwrap_err().0;
+    let _ = std::fs::remove_file(&path);
+
+    assert!(errors.iter().any(|e| e.starts_with("mail.smtp_security")));
+    assert!(errors.iter().any(|e| e.starts_with("mail.smtp_host")));
+    assert!(errors.iter().any(|e| e.starts_with("mail.secret")));
+}
+
+// a url the build accepts
+#[cfg(not(feature = "sqlite"))]
+const DATABASE_URL: &str = "postgres://actix@localhost/blog";
+#[cfg(feature = "sqlite")]
+const DATABASE_URL: &str = "blog.sqlite3";
+
+#[test]
+fn test_env_strings_stay_strings() {
+    let path = std::env::temp_dir().join("actix_blog_env_strings.toml");
+    std::fs::write(&path, format!("[test]\ndatabase_url = \"{}\"\n", DATABASE_URL)).unwrap();
+
+    // the variables are given, the other tests share the process environment
+    let vars = vec![("ACTIX_BLOG_MAIL__SMTP_PASSWORD".to_owned(), "123456".to_owned())];
+    let loaded = BlogConfig::load_with_vars(Some(&path), Some(Profile::Test), vars.into_iter());
+    let _ = std::fs::remove_file(&path);
+
+    let loaded = loaded.unwrap();
+    assert_eq!(loaded.database_url, DATABASE_URL);
+    assert_eq!(loaded.mail.smtp_password, "123456");
+}
+
+#[test]
+fn test_default_dirs() {
+    // relative to where the blog runs, not where it was built
+    let defaults = BlogConfig::default();
+    assert_eq!((defaults.templates_dir.as_str(), defaults.static_dir.as_str()), ("templates", "static"));
+}
@@ -0,0 +1,81 @@
+use actix_web::{ test, web, App, http };
+use actix_service::Service;
+
+use crate::views;
+use crate::utils::metrics;
+use super::{ set_metrics_token, test_db_pool, METRICS_TOKEN };
+
+#[actix_rt::test]
+async fn test_healthz() {
+    let mut app = test::init_service(App::new()
+        .service(web::resource("/healthz").route(web::get().to(views::health::healthz)))
+    ).await;
+
+    let req = test::TestRequest::get().uri("/healthz").to_request();
+    let resp = app.call(req).await.unwrap();
+    assert_eq!(resp.status(), http::StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn test_readyz() {
+    // the test pool applies all migrations, so it's ready
+    let mut app = test::init_service(App::new().data(test_db_pool().unwrap().clone())
+        .service(web::resource("/readyz").route(web::get().to(views::health::readyz)))
+    ).await;
+
+    let req = test::TestRequest::get().uri("/readyz").to_request();
+    let resp = app.call(req).await.unwrap();
+    assert_eq!(resp.status(), http::StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn test_metrics() {
+    set_metrics_token();
+    let mut app = test::init_service(App::new().data(test_db_pool().unwrap().clone())
+        .service(web::resource("/metrics").route(web::get().to(views::health::metrics)))
+    ).await;
+
+    // hidden without the token
+    let req = test::TestRequest::get().uri("/metrics").to_request();
+    let resp = app.call(req).await.unwrap();
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+
+    let req = test::TestRequest::get().uri("/metrics").header(http::header::AUTHORIZATION, "Bearer wrong-token").to_request();
+    let resp = app.call(req).await.unwrap();
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+
+    let req = test::TestRequest::get().uri("/metrics").header(http::header::AUTHORIZATION, format!("Bearer {}", METRICS_TOKEN)).to_request();
+    let body = test::read_response(&mut app, req).await;
+    let body = std::str::from_utf8(&body).unwrap();
+    assert!(body.contains("# TYPE db_pool_connections gauge"));
+    assert!(body.contains("blog_posts "));
+}
+
+#[test]
+fn test_scrape_allowed() {
+    assert!(metrics::scrape_allowed("token", Some("Bearer token")));
+    assert!(!metrics::scrape_allowed("token", Some("Bearer tokens")));
+    assert!(!metrics::scrape_allowed("token", Some("token")));
+    assert!(!metrics::scrape_allowed("token", None));
+    // an empty token turns it off
+    assert!(!metrics::scrape_allowed("", Some("Bearer ")));
+}
+
+#[test]
+fn test_route_label() {
+    let req = test::TestRequest::with_uri("/article/a%20b%2Fc/").param("title", "a b/c").to_http_request();
+    assert_eq!(metrics::route_label(&req, 200), "/article/{title}/");
+
+    let req = test::TestRequest::with_uri("/sitemap-7.xml").param("page", "7").to_http_request();
+    assert_eq!(metrics::route_label(&req, 404), "/sitemap-{page}.xml");
+
+    let req = test::TestRequest::with_uri("/subscribe/3/1600000000/abc/").param("id", "3").to_http_request();
+    assert_eq!(metrics::route_label(&req, 200), "/subscribe/{id}/{expires}/{signature}/");
+
+    let req = test::TestRequest::with_uri("/about/").to_http_request();
+    assert_eq!(metrics::route_label(&req, 200), "/about/");
+    let req = test::TestRequest::with_uri("/no/such/page/").to_http_request();
+    assert_eq!(metrics::route_label(&req, 404), "unmatched");
+    let req = test::TestRequest::with_uri("/media/cat-640.jpg").to_http_request();
+    assert_eq!(metrics::route_label(&req, 200), "/media");
+}
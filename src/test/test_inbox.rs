@@ -0,0 +1,89 @@
+use actix_web::web;
+use chrono::Utc;
+
+use crate::models::contact::{ Contact, ContactOperation, ContactState, CreateContact, NewContact, NewContactReply, ARCHIVED, INBOX, STARRED, UNREAD };
+use crate::utils::utils::DbPool;
+use super::{ generate_random_string, insert_new_user, test_db_pool };
+
+// the tests share a database, a word of its own finds what a test sent
+fn send_message(word: &str, db: &web::Data<DbPool>) -> Contact {
+    let new_contact = CreateContact {
+        tourist_name: "Jamie".to_owned(),
+        email: "jamie@example.com".to_owned(),
+        message: format!("I like your content, {}", word),
+    };
+    ContactOperation::insert_contact(NewContact::new(&new_contact), db).unwrap();
+    ContactOperation::get_inbox(INBOX, Some(word), 1, 1, db).unwrap().contacts.pop().unwrap()
+}
+
+fn found_in(folder: &str, word: &str, db: &web::Data<DbPool>) -> i64 {
+    ContactOperation::get_inbox(folder, Some(word), 1, 20, db).unwrap().total
+}
+
+#[test]
+fn test_contact_state() {
+    assert_eq!("star".parse::<ContactState>().unwrap(), ContactState { starred: Some(true), ..ContactState::default() });
+    assert_eq!("unread".parse::<ContactState>().unwrap(), ContactState { is_read: Some(false), ..ContactState::default() });
+    assert_eq!("archive".parse::<ContactState>().unwrap(), ContactState { archived: Some(true), ..ContactState::default() });
+    assert!("delete".parse::<ContactState>().is_err());
+}
+
+#[test]
+fn test_inbox_folders() {
+    let db = web::Data::new(test_db_pool().unwrap());
+    let word = generate_random_string(16);
+    let contact = send_message(&word, &db);
+    assert!(!contact.is_read && !contact.starred && !contact.archived);
+    assert_eq!((found_in(INBOX, &word, &db), found_in(UNREAD, &word, &db)), (1, 1));
+
+    assert!(ContactOperation::set_state(contact.id, "read".parse().unwrap(), &db).unwrap());
+    assert!(ContactOperation::set_state(contact.id, "star".parse().unwrap(), &db).unwrap());
+    assert_eq!((found_in(UNREAD, &word, &db), found_in(STARRED, &word, &db)), (0, 1));
+
+    // an archived message is in the archive only, starred or not
+    assert!(ContactOperation::set_state(contact.id, "archive".parse().unwrap(), &db).unwrap());
+    assert_eq!((found_in(INBOX, &word, &db), found_in(STARRED, &word, &db), found_in(ARCHIVED, &word, &db)), (0, 0, 1));
+    let archived = ContactOperation::get_contact(contact.id, &db).unwrap().unwrap();
+    assert!(archived.is_read && archived.starred && archived.archived);
+
+    assert!(!ContactOperation::set_state(-1, "read".parse().unwrap(), &db).unwrap());
+}
+
+#[test]
+fn test_inbox_search_and_pages() {
+    let db = web::Data::new(test_db_pool().unwrap());
+    let word = generate_random_string(16);
+    let ids: Vec<i32> = (0..3).map(|_| send_message(&word, &db).id).collect();
+
+    // the search ignores the case, and takes % as it is
+    assert_eq!(found_in(INBOX, &word.to_uppercase(), &db), 3);
+    assert_eq!(found_in(INBOX, &format!("{}%", word), &db), 0);
+
+    // the latest first, a page past the end is the last one
+    let first = ContactOperation::get_inbox(INBOX, Some(&word), 1, 2, &db).unwrap();
+    assert_eq!((first.total, first.page, first.pages), (3, 1, 2));
+    assert_eq!(first.contacts.iter().map(|contact| contact.id).collect::<Vec<_>>(), vec![ids[2], ids[1]]);
+    let last = ContactOperation::get_inbox(INBOX, Some(&word), 9, 2, &db).unwrap();
+    assert_eq!(last.page, 2);
+    assert_eq!(last.contacts.iter().map(|contact| contact.id).collect::<Vec<_>>(), vec![ids[0]]);
+
+    let (total, unread) = ContactOperation::inbox_counts(&db).unwrap();
+    assert!(total.ge(&3) && unread.ge(&3));
+}
+
+#[test]
+fn test_contact_replies() {
+    insert_new_user();
+    let db = web::Data::new(test_db_pool().unwrap());
+    let contact = send_message(&generate_random_string(16), &db);
+
+    let new_reply = NewContactReply { contact_id: contact.id, user_id: 1, body: "Thanks!".to_owned(), sent: Utc::now().naive_utc() };
+    let reply = ContactOperation::insert_reply(&new_reply, &db).unwrap();
+    assert_eq!((reply.contact_id, reply.body.as_str()), (contact.id, "Thanks!"));
+    ContactOperation::insert_reply(&NewContactReply { body: "Anytime.".to_owned(), ..new_reply }, &db).unwrap();
+
+    // replying reads the message
+    assert!(ContactOperation::get_contact(contact.id, &db).unwrap().unwrap().is_read);
+    let replies = ContactOperation::get_replies(contact.id, &db).unwrap();
+    assert_eq!(replies.iter().map(|reply| reply.body.as_str()).collect::<Vec<_>>(), vec!["Thanks!", "Anytime."]);
+}
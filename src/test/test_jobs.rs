@@ -0,0 +1,61 @@
+use actix_web::web;
+use chrono::{ Duration, Utc };
+use diesel::prelude::*;
+
+use crate::models::job::{ Job, JobOperation, NewJob, DONE, PENDING, RUNNING };
+use crate::models::schema::jobs;
+use super::{ generate_random_string, test_db_pool };
+
+fn load_job(id: i32, db: &web::Data<crate::utils::utils::DbPool>) -> Job {
+    let conn = &*db.get().unwrap();
+    jobs::table.filter(jobs::id.eq(id)).first::<Job>(conn).unwrap()
+}
+
+#[test]
+fn test_job_retry_and_complete() {
+    let db = web::Data::new(test_db_pool().unwrap().clone());
+    // a kind of its own, the jobs of other tests are left alone
+    let kind = format!("test_{}", generate_random_string(8));
+    let new_job = NewJob::new(&kind, String::new(), Utc::now().naive_utc() - Duration::seconds(1));
+    let job = JobOperation::enqueue(&new_job, &db).unwrap();
+    assert_eq!(job.status, PENDING);
+    
+    let mut claimed = JobOperation::claim_due_jobs(10, &[&kind], &db).unwrap();
+    assert_eq!(claimed.iter().map(|j| j.id).collect::<Vec<_>>(), vec![job.id]);
+    let claimed = claimed.remove(0);
+    assert_eq!(claimed.status, RUNNING);
+    assert_eq!(claimed.attempts, 1);
+    
+    // failed once, it's retried later instead of right away
+    JobOperation::fail(&claimed, "smtp is down", &db).unwrap();
+    let retried = load_job(job.id, &db);
+    assert_eq!(retried.status, PENDING);
+    assert!(retried.run_at.gt(&Utc::now().naive_utc()));
+    assert_eq!(retried.last_error.as_deref(), Some("smtp is down"));
+    
+    JobOperation::complete(job.id, &db).unwrap();
+    let done = load_job(job.id, &db);
+    assert_eq!(done.status, DONE);
+    assert!(done.finished.is_some());
+}
+
+#[test]
+fn test_release_claimed_jobs() {
+    let db = web::Data::new(test_db_pool().unwrap().clone());
+    let kind = format!("test_{}", generate_random_string(8));
+    let new_job = NewJob::new(&kind, String::new(), Utc::now().naive_utc() - Duration::seconds(1));
+    let job = JobOperation::enqueue(&new_job, &db).unwrap();
+    
+    let claimed = JobOperation::claim_due_jobs(10, &[&kind], &db).unwrap();
+    assert_eq!(claimed.len(), 1);
+    assert!(JobOperation::claim_due_jobs(10, &[&kind], &db).unwrap().is_empty());
+    
+    // never started, so it's due again right away and the attempt doesn't count
+    assert_eq!(JobOperation::release(&[job.id], &db).unwrap(), 1);
+    let released = load_job(job.id, &db);
+    assert_eq!((released.status.as_str(), released.attempts, released.locked_until), (PENDING, 0, None));
+    assert_eq!(JobOperation::claim_due_jobs(10, &[&kind], &db).unwrap().len(), 1);
+    JobOperation::complete(job.id, &db).unwrap();
+    // a finished job isn't released
+    assert_eq!(JobOperation::release(&[job.id], &db).unwrap(), 0);
+}
@@ -0,0 +1,49 @@
+use actix_web::{ test, HttpMessage };
+
+use crate::utils::logging::{ assign_request_id, filters, json_line, LogFormat, RequestId, REQUEST_ID_HEADER };
+
+#[test]
+fn test_propagate_request_id() {
+    let req = test::TestRequest::default().header(REQUEST_ID_HEADER, "edge-42_a").to_srv_request();
+    assert_eq!(assign_request_id(&req), "edge-42_a");
+    assert_eq!(req.extensions().get::<RequestId>().map(|id| id.0.as_str()), Some("edge-42_a"));
+}
+
+#[test]
+fn test_generate_request_id() {
+    let req = test::TestRequest::default().to_srv_request();
+    assert_eq!(assign_request_id(&req).len(), 16);
+    
+    // a forwarded id that doesn't look like an id is replaced
+    let req = test::TestRequest::default().header(REQUEST_ID_HEADER, "\"}, {\"user\": \"admin").to_srv_request();
+    let request_id = assign_request_id(&req);
+    assert_eq!(request_id.len(), 16);
+    assert!(request_id.chars().all(|c| c.is_ascii_alphanumeric()));
+}
+
+#[test]
+fn test_log_format() {
+    assert_eq!("json".parse::<LogFormat>().unwrap(), LogFormat::Json);
+    assert_eq!("text".parse::<LogFormat>().unwrap(), LogFormat::Text);
+    assert!("xml".parse::<LogFormat>().is_err());
+}
+
+#[test]
+fn test_log_filters() {
+    // the binary is built as main, the filter has to name it or the logs of the blog are dropped
+    let crate_root = module_path!().split("::").next().unwrap();
+    let filters = filters("info");
+    assert!(filters.split(',').any(|filter| filter.eq(&format!("{}=info", crate_root))));
+    assert!(filters.split(',').any(|filter| filter.eq("actix_blog=info")));
+}
+
+#[test]
+fn test_json_line() {
+    let line = json_line(log::Level::Info, "main::jobs", "applied migrations: a, b");
+    assert_eq!((line["level"].as_str(), line["message"].as_str()), (Some("INFO"), Some("applied migrations: a, b")));
+
+    // a json message is nested, it can't overwrite the fields of the line
+    let line = json_line(log::Level::Info, "actix_blog::access", r#"{"level": "ERROR", "status": 200}"#);
+    assert_eq!(line["level"].as_str(), Some("INFO"));
+    assert_eq!((line["message"]["level"].as_str(), line["message"]["status"].as_u64()), (Some("ERROR"), Some(200)));
+}
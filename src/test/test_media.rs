@@ -0,0 +1,160 @@
+use actix_web::{ test, web, App, http::header };
+use actix_identity::{ CookieIdentityPolicy, IdentityService };
+use actix_service::Service;
+use bytes::Bytes;
+use image::{ DynamicImage, GenericImageView, ImageOutputFormat, Rgb };
+use rand::{ thread_rng, Rng };
+
+use crate::utils::media::{ self, MediaConfig, MediaError };
+use crate::views;
+use super::{ insert_new_user, test_db_pool, USERNAME_WITH_PWD };
+
+// an image with a random pixel, so that every test stores a new file
+fn random_image(width: u32, height: u32, format: ImageOutputFormat) -> Vec<u8> {
+    let mut image = DynamicImage::new_rgb8(width, height).to_rgb8();
+    image.put_pixel(0, 0, Rgb([thread_rng().gen(), thread_rng().gen(), thread_rng().gen()]));
+    let mut encoded = Vec::new();
+    DynamicImage::ImageRgb8(image).write_to(&mut encoded, format).unwrap();
+    encoded
+}
+
+fn temp_media_config() -> MediaConfig {
+    let dir = std::env::temp_dir().join(format!("actix_blog_media_{}", super::generate_random_string(8)));
+    MediaConfig { dir: dir.to_string_lossy().into_owned(), ..MediaConfig::default() }
+}
+
+#[test]
+fn test_detect_format() {
+    let media_config = MediaConfig::default();
+    let png = random_image(10, 10, ImageOutputFormat::Png);
+    assert_eq!(media::detect_format(&png, &media_config), Ok(image::ImageFormat::Png));
+    // the content decides, not the name
+    assert!(media::detect_format(b"<svg xmlns='http://www.w3.org/2000/svg'/>", &media_config).is_err());
+
+    let only_jpeg = MediaConfig { allowed_types: vec!["jpeg".to_owned()], ..MediaConfig::default() };
+    assert_eq!(media::detect_format(&png, &only_jpeg), Err(MediaError::UnsupportedType("jpeg".to_owned())));
+}
+
+#[test]
+fn test_variant_file_name() {
+    assert_eq!(media::variant_file_name("abc.jpg", "640"), "abc-640.jpg");
+    assert_eq!(media::variant_file_name("abc.png", "thumb"), "abc-thumb.png");
+}
+
+#[test]
+fn test_process_image_strips_exif() {
+    let media_config = temp_media_config();
+    let jpeg = random_image(700, 350, ImageOutputFormat::Jpeg(90));
+    // an APP1 segment right after SOI, like a camera writes
+    let exif: &[u8] = b"Exif\0\0MM\0*\0\0\0\x08GPS-SECRET";
+    let mut with_exif = vec![0xFF, 0xD8, 0xFF, 0xE1, 0, (exif.len() + 2) as u8];
+    with_exif.extend_from_slice(exif);
+    with_exif.extend_from_slice(&jpeg[2..]);
+
+    let stored = media::process_image(&with_exif, &media_config).unwrap();
+    assert_eq!(stored.file_name, media::content_file_name(&with_exif, image::ImageFormat::Jpeg));
+    assert_eq!((stored.width, stored.height), (700, 350));
+    // only scaled down
+    assert_eq!(stored.variants, vec![320, 640]);
+
+    let dir = std::path::Path::new(&media_config.dir);
+    let original = std::fs::read(dir.join(&stored.file_name)).unwrap();
+    assert!(!original.windows(4).any(|w| w.eq(b"Exif")));
+    assert_eq!(original.len(), stored.size);
+
+    let medium = image::open(dir.join(media::variant_file_name(&stored.file_name, "640"))).unwrap();
+    assert_eq!(medium.dimensions(), (640, 320));
+    let thumbnail = image::open(dir.join(media::variant_file_name(&stored.file_name, "thumb"))).unwrap();
+    assert!(thumbnail.width().le(&200) && thumbnail.height().le(&200));
+
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn test_process_image_orientation() {
+    let media_config = temp_media_config();
+    let jpeg = random_image(700, 350, ImageOutputFormat::Jpeg(90));
+    // Orientation = 6, taken with the camera turned right
+    let exif: &[u8] = b"Exif\0\0MM\0*\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01\0\x06\0\0\0\0\0\0";
+    let mut with_exif = vec![0xFF, 0xD8, 0xFF, 0xE1, 0, (exif.len() + 2) as u8];
+    with_exif.extend_from_slice(exif);
+    with_exif.extend_from_slice(&jpeg[2..]);
+
+    let stored = media::process_image(&with_exif, &media_config).unwrap();
+    assert_eq!((stored.width, stored.height), (350, 700));
+    assert_eq!(stored.variants, vec![320]);
+
+    let dir = std::path::Path::new(&media_config.dir);
+    let original = image::open(dir.join(&stored.file_name)).unwrap();
+    assert_eq!(original.dimensions(), (350, 700));
+    let small = image::open(dir.join(media::variant_file_name(&stored.file_name, "320"))).unwrap();
+    assert_eq!(small.dimensions(), (320, 640));
+
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn test_process_image_limits() {
+    let media_config = MediaConfig { max_upload_size: 100, max_dimension: 50, ..temp_media_config() };
+    let large = random_image(40, 40, ImageOutputFormat::Png);
+    assert!(large.len().gt(&100));
+    let too_large = media::process_image(&large, &media_config).unwrap_err();
+    assert_eq!(too_large.downcast_ref::<MediaError>(), Some(&MediaError::TooLarge(100)));
+
+    let media_config = MediaConfig { max_dimension: 50, ..temp_media_config() };
+    let too_wide = media::process_image(&random_image(60, 10, ImageOutputFormat::Png), &media_config).unwrap_err();
+    assert_eq!(too_wide.downcast_ref::<MediaError>(), Some(&MediaError::TooManyPixels(50)));
+}
+
+#[actix_rt::test]
+async fn test_upload_media() {
+    // There is one user in database at least for testing.
+    insert_new_user();
+
+    let mut app = test::init_service(App::new().data(test_db_pool().unwrap().clone())
+        .wrap(
+            IdentityService::new(
+                CookieIdentityPolicy::new(&[0;32])
+                    .name("admin")
+                    .path("/admin")
+                    .max_age(60i64)
+                    .secure(false)
+            )
+        )
+        .service(
+            web::scope("/admin").service(web::resource("/login/").route(web::post().to(views::auth::handle_login)))
+                                .service(web::resource("/media/").route(web::get().to(views::media::gallery))
+                                                                 .route(web::post().to(views::media::upload_media)))
+        )
+    ).await;
+
+    let req = test::TestRequest::post()
+                .uri("/admin/login/")
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .set_payload(Bytes::from_static(USERNAME_WITH_PWD))
+                .to_request();
+    let resp = app.call(req).await.unwrap();
+    let identity = resp.response().cookies().next().unwrap().into_owned();
+
+    let boundary = "actixblogboundary";
+    let mut body = format!("--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"sunset.png\"\r\n\
+                            Content-Type: image/png\r\n\r\n", boundary).into_bytes();
+    body.extend_from_slice(&random_image(400, 200, ImageOutputFormat::Png));
+    body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+
+    let req = test::TestRequest::post()
+                .uri("/admin/media/")
+                .cookie(identity.clone())
+                .header(header::CONTENT_TYPE, format!("multipart/form-data; boundary={}", boundary))
+                .header(header::ACCEPT, "application/json")
+                .set_payload(body)
+                .to_request();
+    let uploaded: serde_json::Value = test::read_response_json(&mut app, req).await;
+    assert_eq!(uploaded["width"], 400);
+    assert!(uploaded["markdown"].as_str().unwrap().starts_with("![sunset](/media/"));
+
+    let req = test::TestRequest::get().uri("/admin/media/").cookie(identity).to_request();
+    let body = test::read_response(&mut app, req).await;
+    let thumbnail = uploaded["thumbnail_url"].as_str().unwrap().rsplit('/').next().unwrap();
+    assert!(std::str::from_utf8(&body).unwrap().contains(thumbnail));
+}
@@ -0,0 +1,137 @@
+use actix_web::web;
+use chrono::{ Duration, Utc };
+
+use crate::models::subscriber::{ Subscriber, SubscriberOperation, ACTIVE, BOUNCED, EVERY_POST, PENDING, UNSUBSCRIBED, WEEKLY };
+use crate::utils::newsletter::{ self, frequency_of, is_valid_email, issue_mails, subscribers_csv, unsubscribe_link };
+use super::{ generate_random_string, insert_new_post, insert_new_user, test_db_pool };
+
+const SITE: &str = "https://blog.example.com";
+
+// the tests share a database, so every test subscribes addresses of its own
+fn random_email() -> String {
+    format!("{}@example.com", generate_random_string(12).to_lowercase())
+}
+
+#[test]
+fn test_is_valid_email() {
+    assert!(is_valid_email("jim@example.com"));
+    assert!(is_valid_email("jim.bob+news@mail.example.co.uk"));
+    assert!(!is_valid_email("jim"));
+    assert!(!is_valid_email("@example.com"));
+    assert!(!is_valid_email("jim@localhost"));
+    assert!(!is_valid_email("jim@example.com."));
+    assert!(!is_valid_email("jim bob@example.com"));
+    assert!(!is_valid_email("jim@example.com, bob@example.com"));
+    assert_eq!(newsletter::normalize_subscriber_email(" Jim@Example.com "), Some("jim@example.com".to_owned()));
+
+    assert_eq!(frequency_of("weekly"), WEEKLY);
+    assert_eq!(frequency_of(" Weekly"), WEEKLY);
+    assert_eq!(frequency_of(""), EVERY_POST);
+    assert_eq!(frequency_of("daily"), EVERY_POST);
+}
+
+#[test]
+fn test_subscribers_csv() {
+    let now = Utc::now().naive_utc();
+    let subscriber = Subscriber {
+        id: 1,
+        email: "jim,\"bob\"@example.com".to_owned(),
+        token: "abc".to_owned(),
+        frequency: WEEKLY.to_owned(),
+        status: ACTIVE.to_owned(),
+        bounces: 1,
+        created: now,
+        confirmed: None,
+    };
+    let csv = subscribers_csv(&[subscriber]);
+    let mut lines = csv.split("\r\n");
+    assert_eq!(lines.next(), Some("email,status,frequency,bounces,created,confirmed"));
+    assert!(lines.next().unwrap().starts_with("\"jim,\"\"bob\"\"@example.com\",active,weekly,1,"));
+}
+
+#[test]
+fn test_subscribe_flow() {
+    let db = web::Data::new(test_db_pool().unwrap());
+    let email = random_email();
+
+    // a new address waits for the confirmation
+    let pending = SubscriberOperation::subscribe(&email, EVERY_POST, &db).unwrap().unwrap();
+    assert_eq!(pending.status, PENDING);
+    assert!(pending.confirmed.is_none());
+    let mail = newsletter::confirm_mail(&pending, SITE).unwrap();
+    assert_eq!(mail.to, email);
+    assert!(mail.body.contains(&format!("{}/newsletter/confirm/{}/", SITE, pending.token)));
+
+    // a stale link doesn't confirm, a fresh one does once
+    assert!(SubscriberOperation::confirm(&pending.token, Duration::zero(), &db).unwrap().is_none());
+    let active = SubscriberOperation::confirm(&pending.token, Duration::days(newsletter::CONFIRM_DAYS), &db).unwrap().unwrap();
+    assert_eq!(active.status, ACTIVE);
+    assert!(active.confirmed.is_some());
+    assert!(SubscriberOperation::confirm(&pending.token, Duration::days(newsletter::CONFIRM_DAYS), &db).unwrap().is_none());
+
+    // subscribing again only changes how often, nobody gets a confirmation
+    assert!(SubscriberOperation::subscribe(&email, WEEKLY, &db).unwrap().is_none());
+    let weekly = SubscriberOperation::get_subscriber_by_token(&pending.token, &db).unwrap().unwrap();
+    assert_eq!((weekly.status.as_str(), weekly.frequency.as_str()), (ACTIVE, WEEKLY));
+
+    assert!(SubscriberOperation::unsubscribe(&pending.token, &db).unwrap());
+    assert!(!SubscriberOperation::unsubscribe(&pending.token, &db).unwrap());
+    let unsubscribed = SubscriberOperation::get_subscriber_by_token(&pending.token, &db).unwrap().unwrap();
+    assert_eq!(unsubscribed.status, UNSUBSCRIBED);
+
+    // coming back needs a new confirmation with a new token
+    let returning = SubscriberOperation::subscribe(&email, EVERY_POST, &db).unwrap().unwrap();
+    assert_eq!(returning.status, PENDING);
+    assert_ne!(returning.token, pending.token);
+    assert!(SubscriberOperation::remove(returning.id, &db).unwrap());
+}
+
+#[test]
+fn test_bounces() {
+    let db = web::Data::new(test_db_pool().unwrap());
+    let email = random_email();
+    let pending = SubscriberOperation::subscribe(&email, EVERY_POST, &db).unwrap().unwrap();
+    SubscriberOperation::confirm(&pending.token, Duration::days(1), &db).unwrap().unwrap();
+
+    assert!(SubscriberOperation::record_bounce(&email, &db).unwrap());
+    assert!(SubscriberOperation::record_bounce(&email, &db).unwrap());
+    assert_eq!(SubscriberOperation::get_subscriber_by_token(&pending.token, &db).unwrap().unwrap().status, ACTIVE);
+    assert!(SubscriberOperation::record_bounce(&email, &db).unwrap());
+    let bounced = SubscriberOperation::get_subscriber_by_token(&pending.token, &db).unwrap().unwrap();
+    assert_eq!((bounced.status.as_str(), bounced.bounces), (BOUNCED, 3));
+    assert!(!SubscriberOperation::get_active_subscribers(EVERY_POST, &db).unwrap().iter().any(|s| s.id.eq(&bounced.id)));
+
+    // a stranger's bounce changes nothing
+    assert!(!SubscriberOperation::record_bounce(&random_email(), &db).unwrap());
+}
+
+#[test]
+fn test_post_announcement() {
+    insert_new_user();
+    let db = web::Data::new(test_db_pool().unwrap());
+    let email = random_email();
+    let pending = SubscriberOperation::subscribe(&email, EVERY_POST, &db).unwrap().unwrap();
+    let subscriber = SubscriberOperation::confirm(&pending.token, Duration::days(1), &db).unwrap().unwrap();
+
+    // a post is announced once
+    let post_id = insert_new_post(&generate_random_string(10));
+    let issue = SubscriberOperation::create_post_issue(post_id, &db).unwrap().unwrap();
+    assert_eq!(issue.post_id, Some(post_id));
+    assert!(SubscriberOperation::create_post_issue(post_id, &db).unwrap().is_none());
+    assert!(!newsletter::announce_post(post_id, &db).unwrap());
+
+    let posts = SubscriberOperation::get_issue_posts(&issue, Duration::days(newsletter::DIGEST_DAYS), &db).unwrap();
+    assert_eq!(posts.len(), 1);
+    let mails = issue_mails(&issue, &posts, &[subscriber.clone()], SITE).unwrap();
+    assert_eq!(mails.len(), 1);
+    assert_eq!(mails[0].to, email);
+    assert!(mails[0].subject.contains(&posts[0].title));
+    assert!(mails[0].body.contains(&format!("{}/article/", SITE)));
+    assert_eq!(mails[0].unsubscribe, Some(unsubscribe_link(&subscriber, SITE)));
+
+    // the other active subscribers of the shared database get it too, but nobody gets it twice
+    assert!(newsletter::send_issue(issue.id, &db).unwrap().ge(&1));
+    assert_eq!(newsletter::send_issue(issue.id, &db).unwrap(), 0);
+    assert!(SubscriberOperation::get_issue(issue.id, &db).unwrap().unwrap().sent.is_some());
+    SubscriberOperation::remove(subscriber.id, &db).unwrap();
+}
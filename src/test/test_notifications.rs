@@ -0,0 +1,140 @@
+use actix_web::{ http, test, web, App };
+use chrono::Utc;
+
+use crate::models::comment::NewComment;
+use crate::models::subscription::SubscriptionOperation;
+use crate::models::user::UserOperation;
+use crate::utils::mailer::{ self, Mail };
+use crate::utils::notifications::{ self, comment_mails, is_valid_subscribe, is_valid_unsubscribe, notify_new_comment, subscribe_link, unsubscribe_link };
+use crate::views;
+use super::{ generate_random_string, insert_new_post, insert_new_user, test_db_pool };
+
+const SITE: &str = "https://blog.example.com";
+
+fn new_comment(post_id: i32, username: &str, email: &str) -> NewComment {
+    NewComment {
+        username: username.to_owned(),
+        email: email.to_owned(),
+        comment: "nice post".to_owned(),
+        committed_time: Some(Utc::now().naive_utc()),
+        post_id,
+    }
+}
+
+#[test]
+fn test_render_mail() {
+    let mut ctx = tera::Context::new();
+    ctx.insert("link", "https://blog.example.com/admin/verify_email/abc/");
+    let mail = mailer::render_mail("mail/verify_email.txt", &ctx, "jim@example.com").unwrap();
+    assert_eq!(mail.to, "jim@example.com");
+    assert_eq!(mail.subject, "Verify your email");
+    assert!(mail.body.contains("https://blog.example.com/admin/verify_email/abc/"));
+    assert_eq!(mail.unsubscribe, None);
+}
+
+#[test]
+fn test_format_mail() {
+    let mail = Mail {
+        to: "jim@example.com".to_owned(),
+        subject: "New reply".to_owned(),
+        body: "first\nsecond".to_owned(),
+        unsubscribe: Some(format!("{}/unsubscribe/1/abc/", SITE)),
+    };
+    let text = mailer::format_mail(&mail, "blog@example.com").unwrap();
+    assert!(text.starts_with("From: blog@example.com\r\nTo: jim@example.com\r\nSubject: New reply\r\n"));
+    assert!(text.contains("List-Unsubscribe: <https://blog.example.com/unsubscribe/1/abc/>\r\n"));
+    assert!(text.ends_with("\r\n\r\nfirst\r\nsecond\r\n"));
+
+    // no header can be smuggled in
+    let injected = Mail { to: "jim@example.com\r\nBcc: all@example.com".to_owned(), ..mail.clone() };
+    assert!(mailer::format_mail(&injected, "blog@example.com").is_err());
+    let injected = Mail { subject: "hi\nBcc: all@example.com".to_owned(), ..mail };
+    assert!(mailer::format_mail(&injected, "blog@example.com").is_err());
+
+    // a queued mail of an older version has no unsubscribe link
+    let queued: Mail = serde_json::from_str(r#"{"to": "jim@example.com", "subject": "hi", "body": "hello"}"#).unwrap();
+    assert_eq!(queued.unsubscribe, None);
+}
+
+#[test]
+fn test_sign() {
+    let signature = mailer::sign("unsubscribe:1:jim@example.com");
+    assert_eq!(signature.len(), 64);
+    assert!(mailer::verify("unsubscribe:1:jim@example.com", &signature));
+    assert!(!mailer::verify("unsubscribe:2:jim@example.com", &signature));
+    assert!(!mailer::verify("unsubscribe:1:jim@example.com", &signature[1..]));
+}
+
+#[test]
+fn test_comment_notifications() {
+    insert_new_user();
+    let db = web::Data::new(test_db_pool().unwrap());
+    let post_id = insert_new_post(&generate_random_string(10));
+    let author = UserOperation::get_user_by_id(1, &db).unwrap().unwrap();
+
+    // the author hears about the first comment, and its writer, who asks for the next ones, has to confirm first
+    let alice_comment = new_comment(post_id, "Alice", " Alice@Example.com");
+    assert_eq!(notify_new_comment(&alice_comment, true, SITE, &db).unwrap(), 2);
+    assert!(!SubscriptionOperation::is_subscribed(post_id, "alice@example.com", &db).unwrap());
+    let alice = SubscriptionOperation::subscribe(post_id, "alice@example.com", &db).unwrap();
+    assert_eq!(alice.email, "alice@example.com");
+    // no more confirmations once subscribed
+    assert_eq!(notify_new_comment(&alice_comment, true, SITE, &db).unwrap(), 1);
+    // a reply reaches the author and alice, but not its own writer
+    assert_eq!(notify_new_comment(&new_comment(post_id, "Bob", "bob@example.com"), false, SITE, &db).unwrap(), 2);
+    let audience = SubscriptionOperation::comment_audience(post_id, "alice@example.com", &db).unwrap().unwrap();
+    assert!(audience.subscribers.is_empty());
+
+    let audience = SubscriptionOperation::comment_audience(post_id, &notifications::normalize_email(&author.email), &db).unwrap().unwrap();
+    let mails = comment_mails(&audience, &new_comment(post_id, "Jim", &author.email), SITE).unwrap();
+    assert_eq!(mails.len(), 1);
+    assert_eq!(mails[0].to, "alice@example.com");
+    assert!(mails[0].body.contains(&format!("{}/article/", SITE)));
+
+    // the link of the mail unsubscribes alice only
+    let link = mails[0].unsubscribe.clone().unwrap();
+    assert_eq!(link, unsubscribe_link(&alice, SITE));
+    let signature = link.trim_end_matches('/').rsplit('/').next().unwrap();
+    assert!(is_valid_unsubscribe(&alice, signature));
+    let bob = SubscriptionOperation::subscribe(post_id, "bob@example.com", &db).unwrap();
+    assert!(!is_valid_unsubscribe(&bob, signature));
+    assert!(SubscriptionOperation::unsubscribe(alice.id, &db).unwrap());
+    assert!(SubscriptionOperation::get_subscription(alice.id, &db).unwrap().is_none());
+}
+
+#[actix_rt::test]
+async fn test_confirm_comment_subscription() {
+    insert_new_user();
+    let db = web::Data::new(test_db_pool().unwrap());
+    let post_id = insert_new_post(&generate_random_string(10));
+    let email = format!("{}+blog@example.com", generate_random_string(10).to_lowercase());
+
+    let link = subscribe_link(post_id, &email, SITE);
+    let path = link.trim_start_matches(SITE).to_owned();
+    let expires = path.split('/').nth(3).unwrap().parse::<i64>().unwrap();
+    let signature = path.split('/').nth(4).unwrap().to_owned();
+    assert!(is_valid_subscribe(post_id, &email, expires, &signature));
+    assert!(!is_valid_subscribe(post_id + 1, &email, expires, &signature));
+    assert!(!is_valid_subscribe(post_id, "mallory@example.com", expires, &signature));
+    // an expired link, even a rightly signed one
+    let expired = Utc::now().timestamp() - 1;
+    assert!(!is_valid_subscribe(post_id, &email, expired, &mailer::sign(&format!("subscribe:{}:{}:{}", post_id, expired, email))));
+
+    let mut app = test::init_service(App::new().data(test_db_pool().unwrap())
+        .service(web::resource("/subscribe/{id}/{expires}/{signature}/").route(web::get().to(views::post::subscribe))
+                                                                         .route(web::post().to(views::post::subscribe)))
+    ).await;
+    // a get only asks, mail scanners follow links
+    let resp = test::call_service(&mut app, test::TestRequest::get().uri(&path).to_request()).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert!(!SubscriptionOperation::is_subscribed(post_id, &email, &db).unwrap());
+
+    let forged = path.replace("%2Bblog", "%2Bother");
+    let resp = test::call_service(&mut app, test::TestRequest::post().uri(&forged).to_request()).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+    assert!(!SubscriptionOperation::is_subscribed(post_id, &email.replace("+blog", "+other"), &db).unwrap());
+
+    let resp = test::call_service(&mut app, test::TestRequest::post().uri(&path).to_request()).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert!(SubscriptionOperation::is_subscribed(post_id, &email, &db).unwrap());
+}
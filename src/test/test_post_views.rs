@@ -10,6 +10,8 @@ use serde::{ Serialize, Deserialize };
 use crate::views;
 use crate::models::comment::CreateComment;
 use crate::models::contact::CreateContact;
+use crate::models::like::LikeState;
+use crate::models::post::PostOperation;
 use super::{ insert_posts, test_db_pool };
 
 
@@ -55,6 +57,36 @@ async fn test_about() {
     assert_eq!(resp.status(), http::StatusCode::OK);
 }
 
+#[actix_rt::test]
+async fn test_about_not_modified() {
+    let mut app = test::init_service(App::new()
+        .service(fs::Files::new("/static", "static/").show_files_listing())
+        .service(
+            web::scope("/").service(web::resource("/about/").route(web::get().to(views::post::about)))
+        )
+    ).await;
+    
+    let req = test::TestRequest::get().uri("/about/").to_request();
+    let resp = app.call(req).await.unwrap();
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert!(resp.headers().contains_key(header::CACHE_CONTROL));
+    
+    // the same page comes back with the ETag it had
+    let etag = resp.headers().get(header::ETAG).cloned().unwrap();
+    // weak, the body may go out compressed
+    assert!(etag.to_str().unwrap().starts_with("W/\""));
+    assert_eq!(resp.headers().get(header::VARY).unwrap(), "Accept-Encoding");
+    let req = test::TestRequest::get().uri("/about/").header(header::IF_NONE_MATCH, etag.clone()).to_request();
+    let resp = app.call(req).await.unwrap();
+    assert_eq!(resp.status(), http::StatusCode::NOT_MODIFIED);
+    let req = test::TestRequest::get().uri("/about/").header(header::IF_NONE_MATCH, &etag.to_str().unwrap()[2..]).to_request();
+    assert_eq!(app.call(req).await.unwrap().status(), http::StatusCode::NOT_MODIFIED);
+    
+    let req = test::TestRequest::get().uri("/about/").header(header::IF_NONE_MATCH, "\"stale\"").to_request();
+    let resp = app.call(req).await.unwrap();
+    assert_eq!(resp.status(), http::StatusCode::OK);
+}
+
 #[actix_rt::test]
 async fn test_contact() {
     let mut app = test::init_service(App::new()
@@ -185,46 +217,78 @@ async fn test_add_comment() {
         comment: "good post!".to_owned(),
         username: "Bob".to_owned(),
         email: "djptux@gmail.com".to_owned(),
+        notify_replies: false,
     };
+    let cookie = cookie.unwrap();
     let req = test::TestRequest::post().uri("/add_comment/")
                                        .header(header::CONTENT_TYPE, "application/json")
                                        .set_json(&comment)
-                                       .cookie(cookie.unwrap())
+                                       .cookie(cookie.clone())
                                        .to_request();
     let result: bool = test::read_response_json(&mut app, req).await;
     assert_eq!(result, true);
+
+    // the address ends up in the To header of mails
+    let injected = CreateComment { email: "bob@example.com\r\nBcc: all@example.com".to_owned(), ..comment };
+    let req = test::TestRequest::post().uri("/add_comment/")
+                                       .header(header::CONTENT_TYPE, "application/json")
+                                       .set_json(&injected)
+                                       .cookie(cookie)
+                                       .to_request();
+    assert_eq!(app.call(req).await.unwrap().status(), http::StatusCode::BAD_REQUEST);
 }
 
 #[actix_rt::test]
 async fn test_user_likes() {
     // before run this test case, it needs a default post.
     insert_posts();
+    let db = web::Data::new(test_db_pool().unwrap());
+    let post = PostOperation::get_post_by_title("python", &db).unwrap().unwrap();
     
     let mut app = test::init_service(App::new().data(test_db_pool().unwrap().clone())
         .wrap(CookieSession::signed(&[0; 32]).name("post_session").secure(false))
-        .service(fs::Files::new("/static", "static/").show_files_listing())
         .service(
-            web::scope("/").service(web::resource("/article/{title}/").route(web::get().to(views::post::post_detail)))
-                           .service(web::resource("/user_likes/").route(web::post().to(views::post::user_likes)))
+            web::scope("/").service(web::resource("/user_likes/").route(web::get().to(views::post::like_state))
+                                                                  .route(web::post().to(views::post::user_likes))
+                                                                  .route(web::delete().to(views::post::user_unlikes)))
         )
     ).await;
     
-    // set session
-    let req = test::TestRequest::get().uri("/article/python/").to_request();
+    // the count comes from the server, not from the client
+    new_struct!(Like, pub, [Debug, Clone, Serialize, Deserialize], (likes_count=>i32));
+    let req = test::TestRequest::post().uri("/user_likes/").set_json(&Like { likes_count: 1000 }).to_request();
     let resp = app.call(req).await.unwrap();
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
     
-    let cookie = resp.response().cookies().find(|c| c.name() == "post_session").clone();
-    assert!(cookie.is_some());
+    let target = views::post::LikeTarget { post_id: post.id };
+    let req = test::TestRequest::post().uri("/user_likes/").set_json(&target).to_request();
+    let resp = app.call(req).await.unwrap();
+    let cookie = resp.response().cookies().find(|c| c.name() == "post_session").map(|c| c.into_owned()).unwrap();
+    let liked: LikeState = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+    assert!(liked.liked);
     
-    new_struct!(Like, pub, [Debug, Clone, Serialize, Deserialize], (likes_count=>i32));
-    let likes = Like { likes_count: 6 };
-    let req = test::TestRequest::post().uri("/user_likes/")
-                                       .header(header::CONTENT_TYPE, "application/json")
-                                       .set_json(&likes)
-                                       .cookie(cookie.unwrap())
-                                       .to_request();
-    let result: bool = test::read_response_json(&mut app, req).await;
-    assert_eq!(result, true);
+    // liking again changes nothing
+    let req = test::TestRequest::post().uri("/user_likes/").set_json(&target).cookie(cookie.clone()).to_request();
+    let again: LikeState = test::read_response_json(&mut app, req).await;
+    assert_eq!(again, liked);
+    
+    let req = test::TestRequest::get().uri(&format!("/user_likes/?post_id={}", post.id)).cookie(cookie.clone()).to_request();
+    let state: LikeState = test::read_response_json(&mut app, req).await;
+    assert_eq!(state, liked);
+    
+    let req = test::TestRequest::delete().uri("/user_likes/").set_json(&target).cookie(cookie.clone()).to_request();
+    let unliked: LikeState = test::read_response_json(&mut app, req).await;
+    assert_eq!(unliked, LikeState { liked: false, likes: liked.likes - 1 });
+    
+    let req = test::TestRequest::delete().uri("/user_likes/").set_json(&target).cookie(cookie).to_request();
+    let again: LikeState = test::read_response_json(&mut app, req).await;
+    assert_eq!(again, unliked);
+    
+    // drafts and missing posts cannot be liked
+    let target = views::post::LikeTarget { post_id: -1 };
+    let req = test::TestRequest::post().uri("/user_likes/").set_json(&target).to_request();
+    let resp = app.call(req).await.unwrap();
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
 }
 
 #[actix_rt::test]
@@ -271,3 +335,23 @@ async fn test_search() {
     let resp = app.call(req).await.unwrap();
     assert_eq!(resp.status(), http::StatusCode::OK);
 }
+
+#[actix_rt::test]
+async fn test_post_detail_meta() {
+    insert_posts();
+    
+    let mut app = test::init_service(App::new().data(test_db_pool().unwrap().clone())
+        .wrap(CookieSession::signed(&[0; 32]).secure(false))
+        .service(
+            web::scope("/").service(web::resource("/article/{title}/").route(web::get().to(views::post::post_detail)))
+        )
+    ).await;
+    
+    let req = test::TestRequest::get().uri("/article/python/").to_request();
+    let body = test::read_response(&mut app, req).await;
+    let body = std::str::from_utf8(&body).unwrap();
+    assert!(body.contains(r#"<meta property="og:type" content="article"/>"#));
+    assert!(body.contains(r#"<meta property="og:title" content="python"/>"#));
+    assert!(body.contains(r#""@type":"BlogPosting""#));
+    assert!(body.contains(r#"<link rel="canonical""#));
+}
@@ -0,0 +1,71 @@
+use chrono::NaiveDate;
+
+use crate::models::post::Post;
+use crate::utils::seo::{ self, PostMeta };
+
+fn sample_post() -> Post {
+    Post {
+        id: 1,
+        title: "Hello </script> world".to_owned(),
+        slug: "rust".to_owned(),
+        body: "# Title\n\nSome **bold** text with [a link](https://example.com).\n\n```rust\nfn main() {}\n```\n\n![image](/media/a.jpg)".to_owned(),
+        publish: Some(NaiveDate::from_ymd(2020, 3, 1).and_hms(8, 0, 0)),
+        created: None,
+        updated: None,
+        status: "publish".to_owned(),
+        user_id: 1,
+        likes: 0,
+        excerpt: String::new(),
+        featured_image: "/media/a-640.jpg".to_owned(),
+        meta_description: String::new(),
+        canonical_url: String::new(),
+    }
+}
+
+#[test]
+fn test_generate_excerpt() {
+    let post = sample_post();
+    assert_eq!(seo::generate_excerpt(&post.body, 200), "Title Some bold text with a link.");
+    assert_eq!(seo::generate_excerpt("one two three four", 12), "one two…");
+    
+    // the one written by the author wins
+    let post = Post { excerpt: "Hand written".to_owned(), ..sample_post() };
+    assert_eq!(post.excerpt_or_generated(), "Hand written");
+}
+
+#[test]
+fn test_is_valid_url() {
+    assert!(seo::is_valid_url(""));
+    assert!(seo::is_valid_url("/media/a.jpg"));
+    assert!(seo::is_valid_url("https://example.com/a.jpg"));
+    assert!(!seo::is_valid_url("//evil.com/a.jpg"));
+    assert!(!seo::is_valid_url("javascript:alert(1)"));
+    assert!(!seo::is_valid_url("/a.jpg\" onload=\"alert(1)"));
+}
+
+#[test]
+fn test_article_path() {
+    assert_eq!(seo::article_path("rust & me"), "/article/rust%20&%20me/");
+    assert_eq!(seo::article_path("C# or C++? 100%"), "/article/C%23%20or%20C%2B%2B%3F%20100%25/");
+    assert_eq!(seo::article_path("and/or"), "/article/and%2For/");
+    assert_eq!(seo::article_path("café"), "/article/caf%C3%A9/");
+
+    // what actix leaves encoded of the segment
+    assert_eq!(seo::article_title("and%2For C%2B%2B"), "and/or C++");
+}
+
+#[test]
+fn test_post_meta() {
+    let meta = PostMeta::new(&sample_post(), "Jim Bob", "https://blog.example.com");
+    assert_eq!(meta.canonical_url, "https://blog.example.com/article/Hello%20%3C%2Fscript%3E%20world/");
+    assert_eq!(meta.image.as_deref(), Some("https://blog.example.com/media/a-640.jpg"));
+    assert_eq!(meta.published.as_deref(), Some("2020-03-01T08:00:00Z"));
+    assert_eq!(meta.description, "Title Some bold text with a link.");
+    
+    // the json-ld cannot close the script tag around it
+    assert!(!meta.json_ld.contains("</script>"));
+    let json_ld: serde_json::Value = serde_json::from_str(&meta.json_ld).unwrap();
+    assert_eq!(json_ld["@type"], "BlogPosting");
+    assert_eq!(json_ld["headline"], "Hello </script> world");
+    assert_eq!(json_ld["author"]["name"], "Jim Bob");
+}
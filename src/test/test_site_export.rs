@@ -0,0 +1,58 @@
+use actix_web::web;
+use chrono::{ Duration, Utc };
+use std::fs;
+
+use crate::models::post::{ PostOperation, UpdatedPost };
+use crate::site_export::{ self, MANIFEST };
+use crate::utils::utils::Status;
+use super::{ generate_random_string, insert_new_post, insert_posts, test_db_pool };
+
+#[test]
+fn test_export_site() {
+    insert_posts();
+    let db = web::Data::new(test_db_pool().unwrap());
+    let title = format!("static {}", generate_random_string(8));
+    insert_new_post(&title);
+    
+    let out = std::env::temp_dir().join(format!("actix_blog_site_{}", generate_random_string(8)));
+    let report = site_export::export_site(&out, false, &db).unwrap();
+    assert!(report.articles.ge(&1));
+    let article = out.join("article").join(&title).join("index.html");
+    assert!(fs::read_to_string(&article).unwrap().contains(&title));
+    for page in ["index.html", "index/index.html", "all_posts/index.html", "about/index.html", "contact/index.html", "404.html", MANIFEST].iter() {
+        assert!(out.join(page).is_file(), "{} is missing", page);
+    }
+    assert!(out.join("static/css/base.css").is_file());
+    
+    // an unchanged article isn't rendered again
+    fs::write(&article, "stale").unwrap();
+    site_export::export_site(&out, false, &db).unwrap();
+    assert_eq!(fs::read_to_string(&article).unwrap(), "stale");
+    
+    // a renamed article moves
+    let renamed = format!("{} renamed", title);
+    let updated_post = UpdatedPost {
+        title: renamed.clone(),
+        slug: "#static".to_owned(),
+        body: "exported again".to_owned(),
+        status: "publish".to_owned(),
+        updated: Some(Utc::now().naive_utc() + Duration::seconds(1)),
+        excerpt: String::new(),
+        featured_image: String::new(),
+        meta_description: String::new(),
+        canonical_url: String::new(),
+    };
+    assert_eq!(PostOperation::update_post(&title, &updated_post, &db).unwrap(), Status::Success);
+    let report = site_export::export_site(&out, false, &db).unwrap();
+    assert!(report.removed.ge(&1));
+    assert!(!article.exists());
+    let article = out.join("article").join(&renamed).join("index.html");
+    assert!(fs::read_to_string(&article).unwrap().contains("exported again"));
+    
+    // --full renders everything
+    fs::write(&article, "stale").unwrap();
+    site_export::export_site(&out, true, &db).unwrap();
+    assert!(fs::read_to_string(&article).unwrap().contains("exported again"));
+    
+    fs::remove_dir_all(&out).unwrap();
+}
@@ -0,0 +1,99 @@
+use actix_web::{ test, web, App, http };
+use actix_service::Service;
+use chrono::{ NaiveDate, NaiveDateTime };
+
+use crate::models::post::Post;
+use crate::utils::sitemap::{ self, RobotsConfig, SitemapUrl };
+use crate::views;
+use super::{ insert_posts, test_db_pool };
+
+fn time(year: i32, month: u32, day: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd(year, month, day).and_hms(0, 0, 0)
+}
+
+fn post(id: i32, title: &str, created: NaiveDateTime, updated: NaiveDateTime) -> Post {
+    Post {
+        id,
+        title: title.to_owned(),
+        slug: "rust".to_owned(),
+        body: String::new(),
+        publish: Some(created),
+        created: Some(created),
+        updated: Some(updated),
+        status: "publish".to_owned(),
+        user_id: 1,
+        likes: 0,
+        excerpt: String::new(),
+        featured_image: String::new(),
+        meta_description: String::new(),
+        canonical_url: String::new(),
+    }
+}
+
+#[test]
+fn test_site_urls() {
+    let mut elsewhere = post(3, "syndicated", time(2020, 1, 1), time(2020, 1, 2));
+    elsewhere.canonical_url = "https://medium.com/syndicated".to_owned();
+    let posts = vec![
+        post(1, "rust & me", time(2019, 5, 1), time(2019, 6, 1)),
+        post(2, "actix", time(2020, 2, 1), time(2020, 3, 1)),
+        elsewhere,
+    ];
+    let urls = sitemap::site_urls(&posts, "https://blog.example.com");
+    let paths: Vec<&str> = urls.iter().map(|url| url.path.as_str()).collect();
+    assert_eq!(paths, vec!["/", "/all_posts/", "/about/", "/contact/", "/category/2019/", "/category/2020/",
+                           "/article/rust%20&%20me/", "/article/actix/"]);
+    // the latest update of the posts in it
+    assert_eq!(urls[0].lastmod, Some(time(2020, 3, 1)));
+    assert_eq!(urls[4].lastmod, Some(time(2019, 6, 1)));
+
+    let xml = sitemap::urlset("https://blog.example.com", &urls);
+    assert!(xml.contains("<url><loc>https://blog.example.com/article/rust%20&amp;%20me/</loc><lastmod>2019-06-01T00:00:00+00:00</lastmod></url>"));
+    assert!(xml.contains("<url><loc>https://blog.example.com/about/</loc></url>"));
+}
+
+#[test]
+fn test_sitemap_index() {
+    let urls: Vec<SitemapUrl> = (0..5).map(|i| SitemapUrl { path: format!("/article/{}/", i), lastmod: Some(time(2020, 1, i + 1)) }).collect();
+    let index = sitemap::sitemap_index("https://blog.example.com", &urls, 2);
+    assert!(index.contains("<sitemap><loc>https://blog.example.com/sitemap-1.xml</loc><lastmod>2020-01-02T00:00:00+00:00</lastmod></sitemap>"));
+    assert!(index.contains("https://blog.example.com/sitemap-3.xml"));
+    assert!(!index.contains("sitemap-4.xml"));
+
+    assert_eq!(sitemap::sitemap_page(&urls, 3, 2).map(|page| page.len()), Some(1));
+    assert_eq!(sitemap::sitemap_page(&urls, 0, 2), None);
+    assert_eq!(sitemap::sitemap_page(&urls, 4, 2), None);
+}
+
+#[test]
+fn test_robots_txt() {
+    let robots_config = RobotsConfig { disallow: vec!["/search/".to_owned()], allow: vec!["/static/".to_owned()] };
+    let robots = sitemap::robots_txt(&robots_config, "https://blog.example.com");
+    assert_eq!(robots, "User-agent: *\nAllow: /static/\nDisallow: /admin/\nDisallow: /search/\n\nSitemap: https://blog.example.com/sitemap.xml\n");
+}
+
+#[actix_rt::test]
+async fn test_sitemap_view() {
+    insert_posts();
+
+    let mut app = test::init_service(App::new().data(test_db_pool().unwrap().clone())
+        .service(web::resource("/robots.txt").route(web::get().to(views::sitemap::robots)))
+        .service(web::resource("/sitemap.xml").route(web::get().to(views::sitemap::sitemap)))
+        .service(web::resource("/sitemap-{page}.xml").route(web::get().to(views::sitemap::sitemap_page)))
+    ).await;
+
+    let req = test::TestRequest::get().uri("/sitemap.xml").to_request();
+    let body = test::read_response(&mut app, req).await;
+    let body = std::str::from_utf8(&body).unwrap();
+    assert!(body.starts_with("<?xml"));
+    assert!(body.contains("/article/python/</loc>"));
+
+    // a single sitemap is enough for the test database
+    let req = test::TestRequest::get().uri("/sitemap-2.xml").to_request();
+    let resp = app.call(req).await.unwrap();
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+
+    let req = test::TestRequest::get().uri("/robots.txt").to_request();
+    let body = test::read_response(&mut app, req).await;
+    assert!(std::str::from_utf8(&body).unwrap().contains("Disallow: /admin/"));
+}
@@ -0,0 +1,38 @@
+use actix_web::{ http::header, test, web, App };
+use actix_service::Service;
+
+use crate::utils::tls::{ self, host_without_port, TlsConfig };
+
+#[test]
+fn test_host_without_port() {
+    assert_eq!(host_without_port("example.com:8080"), "example.com");
+    assert_eq!(host_without_port("example.com"), "example.com");
+    assert_eq!(host_without_port("[::1]:8080"), "[::1]");
+    assert_eq!(host_without_port("[2001:db8::1]"), "[2001:db8::1]");
+}
+
+#[actix_rt::test]
+async fn test_redirect_to_https() {
+    for (tls_port, host, location) in vec![
+        (443u16, "example.com:8080", "https://example.com/article/rust/?page=2"),
+        (8443u16, "example.com", "https://example.com:8443/article/rust/?page=2"),
+        (8443u16, "[::1]:8080", "https://[::1]:8443/article/rust/?page=2"),
+    ] {
+        let mut app = test::init_service(App::new().data(tls_port).default_service(web::route().to(tls::redirect_to_https))).await;
+        let req = test::TestRequest::get().uri("/article/rust/?page=2").header(header::HOST, host).to_request();
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), actix_web::http::StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(resp.headers().get(header::LOCATION).unwrap(), location);
+    }
+}
+
+#[test]
+fn test_hsts_only_over_tls() {
+    let tls_config = TlsConfig { enabled: true, hsts: true, hsts_max_age: 600, ..TlsConfig::default() };
+    assert_eq!(tls_config.hsts_for(true).unwrap(), "max-age=600; includeSubDomains");
+    // the plain listener serving the blog
+    assert!(tls_config.hsts_for(false).is_none());
+
+    assert!(TlsConfig { hsts: false, ..tls_config.clone() }.hsts_for(true).is_none());
+    assert!(TlsConfig { enabled: false, ..tls_config }.hsts_for(true).is_none());
+}
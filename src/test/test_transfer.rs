@@ -0,0 +1,325 @@
+use actix_web::{ http, test, web, App };
+use std::collections::HashMap;
+
+use crate::models::{ comment::CommentOperation, post::{ PostOperation, PostStatus }, user::UserOperation };
+use crate::transfer::{ self, static_site, wordpress, Document, ExportOptions, FrontMatter, FrontMatterFormat, ImportReport, ImportSource };
+use crate::utils::{ seo, utils::DbPool };
+use crate::views;
+use super::{ generate_random_string, test_db_pool };
+
+fn import_markdown(file_name: &str, bytes: &[u8], default_author: Option<&str>, dry_run: bool, db: &web::Data<DbPool>) -> Result<ImportReport, failure::Error> {
+    transfer::import_posts(transfer::read_import(ImportSource::Markdown, file_name, bytes)?, default_author, dry_run, db)
+}
+
+fn front_matter(title: &str, slug: &str) -> FrontMatter {
+    FrontMatter {
+        title: title.to_owned(),
+        slug: slug.to_owned(),
+        status: "publish".to_owned(),
+        publish: Some("2020-03-01T08:30:00Z".to_owned()),
+        author: Some("actix".to_owned()),
+        likes: 3,
+        tags: transfer::tags_of(slug),
+        ..FrontMatter::default()
+    }
+}
+
+#[test]
+fn test_front_matter_round_trip() {
+    let document = Document {
+        front_matter: FrontMatter { excerpt: "a short one".to_owned(), ..front_matter("Hello, Actix", "#rust, #actix") },
+        body: "# Hello\n\n+++ is fine in the body\n---".to_owned(),
+    };
+    for format in [FrontMatterFormat::Toml, FrontMatterFormat::Yaml].iter() {
+        let text = transfer::render_document(&document, *format).unwrap();
+        assert!(text.starts_with(if format.eq(&FrontMatterFormat::Toml) { "+++\n" } else { "---\n" }));
+        assert_eq!(transfer::parse_document(&text).unwrap(), document);
+    }
+}
+
+#[test]
+fn test_parse_front_matter() {
+    // toml datetimes and yaml dates are read as strings
+    let toml = "+++\ntitle = \"Zola\"\ntags = [\"web\"]\npublish = 2020-01-31T10:00:00+08:00\n+++\nbody";
+    let document = transfer::parse_document(toml).unwrap();
+    assert_eq!(document.front_matter.tags, vec!["web".to_owned()]);
+    assert_eq!(transfer::parse_time(document.front_matter.publish.as_deref().unwrap()).unwrap().to_string(), "2020-01-31 02:00:00");
+    assert_eq!(document.body, "body");
+
+    let yaml = "---\r\ntitle: Jekyll\r\nstatus: draft\r\ncreated: 2019-12-01\r\n---\r\n\r\nbody\r\n";
+    let document = transfer::parse_document(yaml).unwrap();
+    assert_eq!(document.front_matter.status, "draft");
+    assert_eq!(transfer::parse_time(document.front_matter.created.as_deref().unwrap()).unwrap().to_string(), "2019-12-01 00:00:00");
+    assert_eq!(document.body, "body");
+
+    assert!(transfer::parse_document("no front matter").is_err());
+    assert!(transfer::parse_document("+++\ntitle = \"unclosed\"\n").is_err());
+}
+
+#[test]
+fn test_tags_and_slug() {
+    assert_eq!(transfer::tags_of("#rust, #actix,"), vec!["rust".to_owned(), "actix".to_owned()]);
+    assert_eq!(transfer::slug_of(&["rust".to_owned(), "#actix".to_owned()]), "#rust, #actix");
+}
+
+#[test]
+fn test_import_dry_run_and_upsert() {
+    let db = web::Data::new(test_db_pool().unwrap().clone());
+    let slug = format!("#{}", generate_random_string(12));
+    let title = generate_random_string(12);
+    let text = transfer::render_document(&Document { front_matter: front_matter(&title, &slug), body: "first".to_owned() },
+                                         FrontMatterFormat::Yaml).unwrap();
+    let unknown_author = transfer::render_document(&Document {
+        front_matter: FrontMatter { author: Some("nobody".to_owned()), ..front_matter(&generate_random_string(12), "#orphan") },
+        body: String::new(),
+    }, FrontMatterFormat::Toml).unwrap();
+
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    // d.md is latin-1
+    let files: [(&str, &[u8]); 4] = [("a.md", text.as_bytes()), ("b.md", unknown_author.as_bytes()), ("c.md", b"not a post"), ("d.md", b"caf\xe9")];
+    for (name, text) in files.iter() {
+        zip.start_file(*name, zip::write::FileOptions::default()).unwrap();
+        std::io::Write::write_all(&mut zip, text).unwrap();
+    }
+    let bytes = zip.finish().unwrap().into_inner();
+
+    let report = import_markdown("posts.zip", &bytes, None, true, &db).unwrap();
+    assert_eq!((report.created, report.skipped), (1, 3));
+    let latin1 = report.entries.iter().find(|entry| entry.file.eq("d.md")).unwrap();
+    assert_eq!((latin1.action.as_str(), latin1.detail.as_str()), ("skip", "not utf-8 text, invalid byte at 3"));
+    // nothing written in a dry run
+    assert!(PostOperation::get_post_by_title(&title, &db).unwrap().is_none());
+
+    let report = import_markdown("posts.zip", &bytes, None, false, &db).unwrap();
+    assert_eq!(report.created, 1);
+    let post = PostOperation::get_post_by_title(&title, &db).unwrap().unwrap();
+    assert_eq!((post.slug.as_str(), post.likes), (slug.as_str(), 3));
+
+    let report = import_markdown("a.md", text.as_bytes(), None, false, &db).unwrap();
+    assert_eq!(report.unchanged, 1);
+    let changed = text.replace("first", "second");
+    let report = import_markdown("a.md", changed.as_bytes(), None, false, &db).unwrap();
+    assert_eq!(report.updated, 1);
+    assert_eq!(report.entries[0].detail, "body");
+    assert_eq!(PostOperation::get_post_by_title(&title, &db).unwrap().unwrap().body, "second");
+
+    // the slug is the tags, a post sharing them is another post
+    let sibling_title = generate_random_string(12);
+    let sibling = changed.replace(&title, &sibling_title);
+    let report = import_markdown("b.md", sibling.as_bytes(), None, false, &db).unwrap();
+    assert_eq!(report.created, 1);
+    assert_eq!(PostOperation::get_post_by_title(&title, &db).unwrap().unwrap().body, "second");
+
+    let retagged = changed.replace(&slug, "#retagged");
+    let report = import_markdown("a.md", retagged.as_bytes(), None, false, &db).unwrap();
+    assert_eq!((report.updated, report.entries[0].detail.as_str()), (1, "slug"));
+    assert_eq!(PostOperation::get_post_by_title(&title, &db).unwrap().unwrap().id, post.id);
+    assert_eq!(PostOperation::get_post_by_title(&sibling_title, &db).unwrap().unwrap().slug, slug);
+}
+
+#[test]
+fn test_read_oversized_document() {
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    zip.start_file("huge.md", zip::write::FileOptions::default()).unwrap();
+    std::io::Write::write_all(&mut zip, &vec![b'a'; 10 * 1024 * 1024 + 1]).unwrap();
+    zip.start_file("small.md", zip::write::FileOptions::default()).unwrap();
+    std::io::Write::write_all(&mut zip, b"small").unwrap();
+    let bytes = zip.finish().unwrap().into_inner();
+
+    // skipped rather than imported cut short
+    let documents = transfer::read_documents("posts.zip", &bytes).unwrap();
+    assert_eq!(documents[0].1.as_ref().unwrap_err().to_string(), "larger than 10485760 bytes");
+    assert_eq!(documents[1].1.as_ref().unwrap(), "small");
+}
+
+#[test]
+fn test_export_posts() {
+    let db = web::Data::new(test_db_pool().unwrap().clone());
+    super::insert_posts();
+    let published = PostOperation::get_all_posts(PostStatus::Published, &db).unwrap();
+
+    let options = ExportOptions { status: "publish".to_owned(), ..ExportOptions::default() };
+    let (bytes, exported) = transfer::export_posts(&options, &db).unwrap();
+    // other tests may publish posts meanwhile
+    assert!(exported.ge(&published.len()));
+    let documents = transfer::parse_documents("posts.zip", &bytes).unwrap();
+    assert_eq!(documents.len(), exported);
+    assert!(documents.iter().all(|(_, document)| document.as_ref().unwrap().front_matter.status.eq("publish")));
+
+    let bad_since = ExportOptions { since: "last week".to_owned(), ..ExportOptions::default() };
+    assert!(transfer::export_posts(&bad_since, &db).is_err());
+}
+
+fn wxr(suffix: &str) -> String {
+    format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:excerpt="http://wordpress.org/export/1.2/excerpt/" xmlns:content="http://purl.org/rss/1.0/modules/content/"
+     xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:wp="http://wordpress.org/export/1.2/">
+<channel>
+    <title>Old Blog</title>
+    <link>https://old-{0}.example.com</link>
+    <wp:base_blog_url>https://old-{0}.example.com</wp:base_blog_url>
+    <wp:author>
+        <wp:author_login><![CDATA[wp{0}]]></wp:author_login>
+        <wp:author_email><![CDATA[wp{0}@example.com]]></wp:author_email>
+        <wp:author_first_name><![CDATA[Word]]></wp:author_first_name>
+        <wp:author_last_name><![CDATA[Press]]></wp:author_last_name>
+    </wp:author>
+    <item>
+        <title>Hello {0}</title>
+        <link>https://old-{0}.example.com/2019/05/hello-{0}/</link>
+        <dc:creator><![CDATA[wp{0}]]></dc:creator>
+        <category domain="category" nicename="uncategorized"><![CDATA[Uncategorized]]></category>
+        <category domain="post_tag" nicename="rust"><![CDATA[Rust]]></category>
+        <content:encoded><![CDATA[<!-- wp:paragraph -->
+<p>See <a href="http://old-{0}.example.com/2019/06/second-{0}/#top">the next one</a>.</p>
+<!-- /wp:paragraph -->]]></content:encoded>
+        <excerpt:encoded><![CDATA[]]></excerpt:encoded>
+        <wp:post_id>1</wp:post_id>
+        <wp:post_date_gmt><![CDATA[2019-05-01 10:00:00]]></wp:post_date_gmt>
+        <wp:post_modified_gmt><![CDATA[2019-05-02 10:00:00]]></wp:post_modified_gmt>
+        <wp:status><![CDATA[publish]]></wp:status>
+        <wp:post_type><![CDATA[post]]></wp:post_type>
+        <wp:comment>
+            <wp:comment_author><![CDATA[reader]]></wp:comment_author>
+            <wp:comment_author_email><![CDATA[reader@example.com]]></wp:comment_author_email>
+            <wp:comment_date_gmt><![CDATA[2019-05-03 10:00:00]]></wp:comment_date_gmt>
+            <wp:comment_content><![CDATA[Nice post]]></wp:comment_content>
+            <wp:comment_approved><![CDATA[1]]></wp:comment_approved>
+            <wp:comment_type><![CDATA[]]></wp:comment_type>
+        </wp:comment>
+        <wp:comment>
+            <wp:comment_author><![CDATA[spammer]]></wp:comment_author>
+            <wp:comment_content><![CDATA[Buy now]]></wp:comment_content>
+            <wp:comment_approved><![CDATA[spam]]></wp:comment_approved>
+        </wp:comment>
+    </item>
+    <item>
+        <title>Second {0}</title>
+        <link>https://old-{0}.example.com/?p=2</link>
+        <dc:creator><![CDATA[wp{0}]]></dc:creator>
+        <content:encoded><![CDATA[Draft]]></content:encoded>
+        <wp:post_id>2</wp:post_id>
+        <wp:post_date_gmt><![CDATA[0000-00-00 00:00:00]]></wp:post_date_gmt>
+        <wp:status><![CDATA[draft]]></wp:status>
+        <wp:post_type><![CDATA[post]]></wp:post_type>
+    </item>
+    <item>
+        <title>logo.png</title>
+        <wp:post_id>3</wp:post_id>
+        <wp:status><![CDATA[inherit]]></wp:status>
+        <wp:post_type><![CDATA[attachment]]></wp:post_type>
+    </item>
+</channel>
+</rss>"#, suffix)
+}
+
+#[test]
+fn test_parse_wxr() {
+    let import = wordpress::parse_wxr(&wxr("x")).unwrap();
+    assert_eq!(import.site_url, "https://old-x.example.com");
+    assert_eq!(import.authors[0].username, "wpx");
+    // attachments aren't posts
+    assert_eq!(import.posts.len(), 2);
+
+    let hello = import.posts[0].1.as_ref().unwrap();
+    assert_eq!(hello.document.front_matter.tags, vec!["Rust".to_owned()]);
+    assert_eq!(hello.document.front_matter.publish.as_deref(), Some("2019-05-01T10:00:00Z"));
+    assert!(!hello.document.body.contains("wp:paragraph"));
+    assert_eq!(hello.old_paths, vec!["/2019/05/hello-x/".to_owned()]);
+    assert_eq!(hello.comments.len(), 1);
+
+    let draft = import.posts[1].1.as_ref().unwrap();
+    assert_eq!(draft.document.front_matter.status, "draft");
+    assert_eq!(draft.source.as_deref(), Some("https://old-x.example.com/?p=2"));
+    assert_eq!(draft.document.front_matter.tags, vec!["Uncategorized".to_owned()]);
+    assert!(draft.old_paths.is_empty());
+}
+
+#[test]
+fn test_rewrite_links() {
+    let targets: HashMap<String, String> = vec![("/2019/05/hello".to_owned(), "/article/Hello/".to_owned())].into_iter().collect();
+    let body = "[hello](/2019/05/hello/) <a href=\"https://old.example.com/2019/05/hello/#part\">it</a> [other](/2019/05/other/)";
+    assert_eq!(transfer::rewrite_links(body, "http://old.example.com", &targets),
+               "[hello](/article/Hello/) <a href=\"/article/Hello/#part\">it</a> [other](/2019/05/other/)");
+}
+
+fn readable(documents: Vec<(String, String)>) -> transfer::Documents {
+    documents.into_iter().map(|(path, text)| (path, Ok(text))).collect()
+}
+
+#[test]
+fn test_parse_static_sites() {
+    let hugo = static_site::parse_hugo(readable(vec![
+        ("blog/content/_index.md".to_owned(), "+++\ntitle = \"Home\"\n+++\n".to_owned()),
+        ("blog/content/about.md".to_owned(), "+++\ntitle = \"About\"\n+++\n".to_owned()),
+        ("blog/content/posts/hello.md".to_owned(),
+         "+++\ntitle = \"Hello\"\ndate = 2020-01-31\ntags = [\"rust\"]\naliases = [\"/old/hello/\"]\n+++\nNext: [world]({{< ref \"posts/world/index.md\" >}})".to_owned()),
+        ("blog/content/posts/world/index.md".to_owned(), "---\ntitle: World\ndraft: true\n---\n".to_owned()),
+    ]));
+    // _index.md is a list page
+    assert_eq!(hugo.posts.len(), 3);
+    assert!(hugo.posts[0].1.is_err());
+    let hello = hugo.posts[1].1.as_ref().unwrap();
+    assert_eq!(hello.old_paths, vec!["/posts/hello/".to_owned(), "/old/hello/".to_owned()]);
+    assert_eq!(hello.document.body, "Next: [world](/posts/world/)");
+    assert_eq!(hugo.posts[2].1.as_ref().unwrap().document.front_matter.status, "draft");
+
+    let jekyll = static_site::parse_jekyll(readable(vec![
+        ("_posts/2019-05-01-hello.md".to_owned(),
+         "---\ntitle: Hello\ncategories: dev rust\ndate: 2019-05-01 10:00:00 +0800\n---\nNext: [world]({% post_url 2019-06-01-world %})".to_owned()),
+        ("_posts/2019-06-01-world.md".to_owned(), "---\ntitle: World\npermalink: /world/\n---\n".to_owned()),
+        ("_drafts/idea.md".to_owned(), "---\ntitle: Idea\n---\n".to_owned()),
+    ]));
+    let hello = jekyll.posts[0].1.as_ref().unwrap();
+    assert_eq!(hello.old_paths, vec!["/dev/rust/2019/05/01/hello.html".to_owned()]);
+    assert_eq!(hello.document.front_matter.publish.as_deref(), Some("2019-05-01T02:00:00Z"));
+    assert_eq!(hello.document.body, "Next: [world](/world/)");
+    let idea = jekyll.posts[2].1.as_ref().unwrap();
+    assert_eq!(idea.document.front_matter.status, "draft");
+    assert!(idea.old_paths.is_empty());
+}
+
+#[actix_rt::test]
+async fn test_import_wordpress() {
+    let db = web::Data::new(test_db_pool().unwrap().clone());
+    let suffix = generate_random_string(8).to_lowercase();
+
+    let report = transfer::import_posts(wordpress::parse_wxr(&wxr(&suffix)).unwrap(), None, true, &db).unwrap();
+    assert_eq!((report.created, report.users, report.comments, report.redirects), (2, 1, 1, 1));
+    assert!(UserOperation::find_user(&format!("wp{}", suffix), &db).unwrap().is_none());
+
+    let report = transfer::import_posts(wordpress::parse_wxr(&wxr(&suffix)).unwrap(), None, false, &db).unwrap();
+    assert_eq!(report.created, 2);
+    let author = UserOperation::find_user(&format!("wp{}", suffix), &db).unwrap().unwrap();
+    assert!(!author.is_active);
+    let hello = PostOperation::get_post_by_title(&format!("Hello {}", suffix), &db).unwrap().unwrap();
+    assert_eq!(hello.user_id, author.id);
+    assert_eq!(CommentOperation::get_comments_by_post(hello.id, &db).unwrap().len(), 1);
+
+    // importing again neither repeats the comments nor creates the posts
+    let report = transfer::import_posts(wordpress::parse_wxr(&wxr(&suffix)).unwrap(), None, false, &db).unwrap();
+    assert_eq!((report.created, report.unchanged, report.users, report.comments), (0, 2, 0, 0));
+
+    let mut app = test::init_service(App::new().data(db.get_ref().clone())
+                                               .default_service(web::route().to(views::post::not_found))).await;
+    let req = test::TestRequest::get().uri(&format!("/2019/05/hello-{}/", suffix)).to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(resp.headers().get(http::header::LOCATION).unwrap(), &seo::article_path(&hello.title));
+
+    let req = test::TestRequest::get().uri("/2019/05/never-there/").to_request();
+    assert_eq!(test::call_service(&mut app, req).await.status(), http::StatusCode::NOT_FOUND);
+
+    // the old permalink finds the post, whatever its title is now
+    let retitled = wxr(&suffix).replace(&format!("<title>Hello {}", suffix), &format!("<title>Hello again {}", suffix));
+    let report = transfer::import_posts(wordpress::parse_wxr(&retitled).unwrap(), None, false, &db).unwrap();
+    assert_eq!((report.created, report.updated, report.unchanged), (0, 1, 1));
+    assert_eq!(PostOperation::get_post_by_title(&format!("Hello again {}", suffix), &db).unwrap().unwrap().id, hello.id);
+
+    // another blog cannot take over a post of the same title
+    let other = generate_random_string(8).to_lowercase();
+    let same_title = wxr(&other).replace(&format!("<title>Hello {}", other), &format!("<title>Hello again {}", suffix));
+    let report = transfer::import_posts(wordpress::parse_wxr(&same_title).unwrap(), None, false, &db).unwrap();
+    assert_eq!((report.created, report.skipped), (1, 1));
+    assert_eq!(PostOperation::get_post_by_title(&format!("Hello again {}", suffix), &db).unwrap().unwrap().user_id, author.id);
+}
@@ -0,0 +1,661 @@
+use actix_web::web::Data;
+use chrono::{ DateTime, NaiveDate, NaiveDateTime, Utc };
+use lazy_static::lazy_static;
+use rand::{ distributions::Alphanumeric, thread_rng, Rng };
+use regex::{ Captures, Regex };
+use serde_derive::{ Deserialize, Serialize };
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{ Cursor, Read, Write };
+use std::path::Path;
+use zip::{ write::FileOptions, CompressionMethod, ZipArchive, ZipWriter };
+
+use crate::models::comment::{ CommentOperation, NewComment };
+use crate::models::post::{ NewPost, Post, PostOperation, PostStatus, Upsert };
+use crate::models::redirect::{ normalize_path, RedirectOperation };
+use crate::models::user::{ NewUser, UserOperation };
+use crate::utils::{ seo, utils::{ DbPool, Status } };
+
+pub(crate) mod static_site;
+pub(crate) mod wordpress;
+
+// a zip bomb is refused instead of filling the memory
+const MAX_DOCUMENT_SIZE: u64 = 10 * 1024 * 1024;
+const MAX_DOCUMENTS: usize = 10_000;
+
+// +++ toml +++ like hugo and zola, or --- yaml --- like jekyll
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum FrontMatterFormat {
+    Toml,
+    Yaml,
+}
+
+impl std::str::FromStr for FrontMatterFormat {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "toml" | "" => Ok(FrontMatterFormat::Toml),
+            "yaml" => Ok(FrontMatterFormat::Yaml),
+            _ => Err(failure::format_err!("format should be one of toml/yaml, but got {}", s)),
+        }
+    }
+}
+
+impl FrontMatterFormat {
+    fn fence(self) -> &'static str {
+        match self {
+            FrontMatterFormat::Toml => "+++",
+            FrontMatterFormat::Yaml => "---",
+        }
+    }
+}
+
+// the times are strings, so that both formats can carry any of the usual spellings
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct FrontMatter {
+    pub(crate) title: String,
+    #[serde(default)]
+    pub(crate) slug: String,
+    #[serde(default)]
+    pub(crate) status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) publish: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) created: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) updated: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) author: Option<String>,
+    #[serde(default)]
+    pub(crate) likes: i32,
+    #[serde(default)]
+    pub(crate) tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub(crate) excerpt: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub(crate) featured_image: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub(crate) meta_description: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub(crate) canonical_url: String,
+}
+
+// a markdown file, the front matter and the body
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Document {
+    pub(crate) front_matter: FrontMatter,
+    pub(crate) body: String,
+}
+
+// the slug of a post holds its tags, like "#rust, #actix"
+pub(crate) fn tags_of(slug: &str) -> Vec<String> {
+    slug.split(',').map(|tag| tag.trim().trim_start_matches('#').to_owned()).filter(|tag| !tag.is_empty()).collect()
+}
+
+pub(crate) fn slug_of(tags: &[String]) -> String {
+    tags.iter().map(|tag| format!("#{}", tag.trim_start_matches('#'))).collect::<Vec<_>>().join(", ")
+}
+
+// the times are stored in utc
+pub(crate) fn format_time(time: &NaiveDateTime) -> String {
+    format!("{}Z", time.format("%Y-%m-%dT%H:%M:%S%.f"))
+}
+
+// rfc3339 or jekyll's time with any offset, or a local looking time taken as utc, or only the date
+pub(crate) fn parse_time(time: &str) -> Option<NaiveDateTime> {
+    let time = time.trim();
+    DateTime::parse_from_rfc3339(time).map(|time| time.naive_utc()).ok()
+        // jekyll writes 2020-01-31 10:00:00 +0800
+        .or_else(|| DateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S %z").map(|time| time.naive_utc()).ok())
+        .or_else(|| NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M:%S%.f").ok())
+        .or_else(|| NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S%.f").ok())
+        .or_else(|| NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").ok())
+        .or_else(|| NaiveDate::parse_from_str(time, "%Y-%m-%d").ok().map(|date| date.and_hms(0, 0, 0)))
+}
+
+pub(crate) fn render_document(document: &Document, format: FrontMatterFormat) -> Result<String, failure::Error> {
+    let front_matter = match format {
+        FrontMatterFormat::Toml => toml::to_string(&document.front_matter)?,
+        FrontMatterFormat::Yaml => serde_yaml::to_string(&document.front_matter)?.trim_start_matches("---").trim_start().to_owned(),
+    };
+    let fence = format.fence();
+    Ok(format!("{}\n{}\n{}\n\n{}\n", fence, front_matter.trim_end(), fence, document.body.trim_end()))
+}
+
+// a toml datetime becomes a string, like a yaml one
+fn toml_to_json(value: toml::Value) -> serde_json::Value {
+    match value {
+        toml::Value::String(s) => serde_json::Value::String(s),
+        toml::Value::Integer(i) => serde_json::Value::from(i),
+        toml::Value::Float(f) => serde_json::Value::from(f),
+        toml::Value::Boolean(b) => serde_json::Value::Bool(b),
+        toml::Value::Datetime(time) => serde_json::Value::String(time.to_string()),
+        toml::Value::Array(values) => serde_json::Value::Array(values.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => serde_json::Value::Object(table.into_iter().map(|(k, v)| (k, toml_to_json(v))).collect()),
+    }
+}
+
+// the front matter as json, whatever format it's written in
+pub(crate) fn split_front_matter(text: &str) -> Result<(serde_json::Value, String), failure::Error> {
+    let text = text.trim_start_matches('\u{feff}');
+    let format = if text.starts_with("+++") {
+        FrontMatterFormat::Toml
+    } else if text.starts_with("---") {
+        FrontMatterFormat::Yaml
+    } else {
+        return Err(failure::err_msg("no front matter, the file should start with +++ or ---"));
+    };
+
+    let fence = format.fence();
+    let rest = text[fence.len()..].trim_start_matches(|c| c == ' ' || c == '\t' || c == '\r');
+    if !rest.starts_with('\n') {
+        return Err(failure::format_err!("{} should be followed by a new line", fence));
+    }
+    let rest = &rest[1..];
+    let (front, body) = if rest.starts_with(fence) {
+        ("", &rest[fence.len()..])
+    } else {
+        let end = rest.find(&format!("\n{}", fence)).ok_or_else(|| failure::format_err!("the front matter isn't closed by {}", fence))?;
+        (&rest[..end], &rest[end + 1 + fence.len()..])
+    };
+    // the rest of the closing line
+    let body = body.splitn(2, '\n').nth(1).unwrap_or_default();
+
+    let front_matter = match format {
+        FrontMatterFormat::Toml => toml_to_json(front.parse::<toml::Value>()?),
+        FrontMatterFormat::Yaml if front.trim().is_empty() => serde_json::Value::Object(Default::default()),
+        FrontMatterFormat::Yaml => serde_yaml::from_str(front)?,
+    };
+    Ok((front_matter, body.trim_start_matches(|c| c == '\r' || c == '\n').trim_end().to_owned()))
+}
+
+pub(crate) fn parse_document(text: &str) -> Result<Document, failure::Error> {
+    let (front_matter, body) = split_front_matter(text)?;
+    let front_matter = serde_json::from_value(front_matter)?;
+    Ok(Document { front_matter, body })
+}
+
+fn document_of(post: &Post, author: Option<String>) -> Document {
+    Document {
+        front_matter: FrontMatter {
+            title: post.title.clone(),
+            slug: post.slug.clone(),
+            status: post.status.clone(),
+            publish: post.publish.as_ref().map(format_time),
+            created: post.created.as_ref().map(format_time),
+            updated: post.updated.as_ref().map(format_time),
+            author,
+            likes: post.likes,
+            tags: tags_of(&post.slug),
+            excerpt: post.excerpt.clone(),
+            featured_image: post.featured_image.clone(),
+            meta_description: post.meta_description.clone(),
+            canonical_url: post.canonical_url.clone(),
+        },
+        body: post.body.clone(),
+    }
+}
+
+// <id>-<title in lowercase and dashes>.md
+fn file_name_of(post: &Post) -> String {
+    let mut name = String::new();
+    for c in post.title.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            name.push(c);
+        } else if !name.ends_with('-') {
+            name.push('-');
+        }
+    }
+    let name: String = name.trim_matches('-').chars().take(60).collect();
+    format!("{}-{}.md", post.id, if name.is_empty() { "post" } else { name.trim_end_matches('-') })
+}
+
+// which posts to export and how, empty means any
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub(crate) struct ExportOptions {
+    #[serde(default)]
+    pub(crate) status: String, // publish/draft
+    #[serde(default)]
+    pub(crate) author: String,
+    #[serde(default)]
+    pub(crate) since: String, // created on or after, yyyy-mm-dd
+    #[serde(default)]
+    pub(crate) format: String, // toml/yaml
+}
+
+impl ExportOptions {
+    // the format and the since time, an invalid one is refused before the export starts
+    pub(crate) fn parse(&self) -> Result<(FrontMatterFormat, Option<NaiveDateTime>), failure::Error> {
+        let format = self.format.parse::<FrontMatterFormat>()?;
+        let since = match self.since.as_str() {
+            "" => None,
+            since => Some(NaiveDate::parse_from_str(since, "%Y-%m-%d")
+                              .map_err(|_| failure::format_err!("since should be like 2020-01-31, but got {}", since))?.and_hms(0, 0, 0)),
+        };
+        Ok((format, since))
+    }
+
+    fn matches(&self, post: &Post, author: Option<&str>, since: Option<NaiveDateTime>) -> bool {
+        (self.status.is_empty() || self.status.eq(&post.status))
+            && (self.author.is_empty() || author.map_or(false, |author| author.eq(&self.author)))
+            && since.map_or(true, |since| post.created.map_or(false, |created| created.ge(&since)))
+    }
+}
+
+// a zip of markdown files, and how many posts are in it
+pub(crate) fn export_posts(options: &ExportOptions, pool: &Data<DbPool>) -> Result<(Vec<u8>, usize), failure::Error> {
+    let (format, since) = options.parse()?;
+
+    let mut posts = PostOperation::get_all_posts(PostStatus::All, pool)?;
+    posts.sort_by_key(|post| post.id);
+    let mut authors: HashMap<i32, Option<String>> = HashMap::new();
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let file_options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut exported = 0;
+    for post in posts.iter() {
+        if !authors.contains_key(&post.user_id) {
+            let author = UserOperation::get_user_by_id(post.user_id, pool)?.map(|user| user.username);
+            authors.insert(post.user_id, author);
+        }
+        let author = authors[&post.user_id].clone();
+        if !options.matches(post, author.as_deref(), since) {
+            continue;
+        }
+        zip.start_file(file_name_of(post), file_options)?;
+        zip.write_all(render_document(&document_of(post, author), format)?.as_bytes())?;
+        exported += 1;
+    }
+    Ok((zip.finish()?.into_inner(), exported))
+}
+
+// the markdown files to import, each with its text or why it cannot be read
+pub(crate) type Documents = Vec<(String, Result<String, failure::Error>)>;
+
+// a file which isn't utf-8 is skipped, not the whole import
+fn document_text(bytes: Vec<u8>) -> Result<String, failure::Error> {
+    String::from_utf8(bytes).map_err(|e| failure::format_err!("not utf-8 text, invalid byte at {}", e.utf8_error().valid_up_to()))
+}
+
+// the markdown files in a zip, or the single uploaded one
+pub(crate) fn read_documents(file_name: &str, bytes: &[u8]) -> Result<Documents, failure::Error> {
+    if file_name.ends_with(".md") || file_name.ends_with(".markdown") {
+        return Ok(vec![(file_name.to_owned(), document_text(bytes.to_vec()))]);
+    }
+
+    let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+    let mut documents = Vec::new();
+    for i in 0..archive.len() {
+        let file = archive.by_index(i)?;
+        let name = file.name().to_owned();
+        if file.is_dir() || name.starts_with("__MACOSX/") || !(name.ends_with(".md") || name.ends_with(".markdown")) {
+            continue;
+        }
+        if documents.len().ge(&MAX_DOCUMENTS) {
+            return Err(failure::format_err!("more than {} markdown files in the archive", MAX_DOCUMENTS));
+        }
+        // the size in the header can lie, so a byte past the limit is read to tell a cut document
+        let mut text = Vec::new();
+        file.take(MAX_DOCUMENT_SIZE + 1).read_to_end(&mut text)?;
+        if (text.len() as u64).gt(&MAX_DOCUMENT_SIZE) {
+            documents.push((name, Err(failure::format_err!("larger than {} bytes", MAX_DOCUMENT_SIZE))));
+            continue;
+        }
+        documents.push((name, document_text(text)));
+    }
+    Ok(documents)
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ImportEntry {
+    pub(crate) file: String,
+    pub(crate) title: String,
+    pub(crate) action: String, // create/update/unchanged/skip
+    pub(crate) detail: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct ImportReport {
+    pub(crate) dry_run: bool,
+    pub(crate) created: usize,
+    pub(crate) updated: usize,
+    pub(crate) unchanged: usize,
+    pub(crate) skipped: usize,
+    // what comes along with the posts of another blog
+    pub(crate) users: usize,
+    pub(crate) comments: usize,
+    pub(crate) redirects: usize,
+    pub(crate) entries: Vec<ImportEntry>,
+}
+
+impl ImportReport {
+    fn push(&mut self, file: &str, title: &str, action: &str, detail: String) {
+        match action {
+            "create" => self.created += 1,
+            "update" => self.updated += 1,
+            "unchanged" => self.unchanged += 1,
+            _ => self.skipped += 1,
+        }
+        self.entries.push(ImportEntry { file: file.to_owned(), title: title.to_owned(), action: action.to_owned(), detail });
+    }
+}
+
+impl fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for entry in self.entries.iter() {
+            writeln!(f, "{:<10} {}  {} {}", entry.action, entry.file, entry.title, entry.detail)?;
+        }
+        let verb = if self.dry_run { "would be" } else { "were" };
+        if self.users + self.comments + self.redirects > 0 {
+            writeln!(f, "{} users, {} comments and {} redirects {} created", self.users, self.comments, self.redirects, verb)?;
+        }
+        write!(f, "{} created, {} updated, {} unchanged, {} skipped posts {} imported{}", self.created, self.updated, self.unchanged,
+               self.skipped, verb, if self.dry_run { " (dry run)" } else { "" })
+    }
+}
+
+// a post from a file or another blog, with what comes along
+#[derive(Debug)]
+pub(crate) struct Imported {
+    pub(crate) document: Document,
+    pub(crate) old_paths: Vec<String>, // the permalinks on the old blog, redirected to the article
+    pub(crate) comments: Vec<NewComment>, // post_id is set once the post is in
+    // where the post was on the old blog, what importing it again finds it by.
+    // none for the markdown of this blog, which is found by its slug
+    pub(crate) source: Option<String>,
+}
+
+impl From<Document> for Imported {
+    fn from(document: Document) -> Self {
+        Imported { document, old_paths: Vec::new(), comments: Vec::new(), source: None }
+    }
+}
+
+// an author of another blog, who gets an inactive account until the password is reset
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ImportedAuthor {
+    pub(crate) username: String,
+    pub(crate) email: String,
+    pub(crate) first_name: String,
+    pub(crate) last_name: String,
+}
+
+// everything an importer read, every post comes with the file or item it's read from
+#[derive(Debug, Default)]
+pub(crate) struct Import {
+    pub(crate) site_url: String, // of the old blog, its absolute links are rewritten as well
+    pub(crate) authors: Vec<ImportedAuthor>,
+    pub(crate) posts: Vec<(String, Result<Imported, failure::Error>)>,
+}
+
+impl Import {
+    pub(crate) fn from_documents(documents: Vec<(String, Result<Document, failure::Error>)>) -> Self {
+        let posts = documents.into_iter().map(|(file, document)| (file, document.map(Imported::from))).collect();
+        Import { posts, ..Import::default() }
+    }
+}
+
+lazy_static! {
+    // [text](url), [id]: url, href="url" and href='url'
+    static ref LINK: Regex = Regex::new(r#"(\]\(|\]:[ \t]*|href=["'])([^)"'\s]+)"#).unwrap();
+}
+
+// links to the old permalinks point to the articles here
+pub(crate) fn rewrite_links(body: &str, site_url: &str, targets: &HashMap<String, String>) -> String {
+    // http or https, the old blog might have served both
+    let host = site_url.splitn(2, "://").nth(1).unwrap_or_default().trim_end_matches('/');
+    LINK.replace_all(body, |caps: &Captures| {
+        let url = &caps[2];
+        let path = match url.splitn(2, "://").nth(1) {
+            Some(rest) if !host.is_empty() && rest.starts_with(host) => &rest[host.len()..],
+            _ => url,
+        };
+        let fragment = path.find('#').map_or("", |i| &path[i..]);
+        match targets.get(&normalize_path(path)) {
+            Some(target) if path.starts_with('/') || path.is_empty() => format!("{}{}{}", &caps[1], target, fragment),
+            _ => caps[0].to_owned(),
+        }
+    }).into_owned()
+}
+
+// an inactive account with a password nobody knows, the author sets one by resetting it
+fn create_author(author: &ImportedAuthor, pool: &Data<DbPool>) -> Result<(), failure::Error> {
+    let random: String = thread_rng().sample_iter(&Alphanumeric).take(32).collect();
+    let new_user = NewUser {
+        username: author.username.clone(),
+        password: bcrypt::hash(&random, bcrypt::DEFAULT_COST)?,
+        first_name: author.first_name.clone(),
+        last_name: author.last_name.clone(),
+        email: author.email.clone(),
+        is_superuser: false,
+        is_staff: false,
+        is_active: false,
+        last_login: None,
+        date_joined: Some(Utc::now().naive_utc()),
+        verification_token: None,
+    };
+    match UserOperation::insert_user(&new_user, pool)? {
+        Status::Success => Ok(()),
+        Status::Failure => Err(failure::format_err!("the email of author {} belongs to another user", author.username)),
+    }
+}
+
+// the post to upsert, or why it cannot be
+fn new_post_of(document: &Document, user_id: i32) -> Result<NewPost, String> {
+    let front_matter = &document.front_matter;
+    if front_matter.title.trim().is_empty() {
+        return Err("the title is empty".to_owned());
+    }
+    let slug = if front_matter.slug.trim().is_empty() { slug_of(&front_matter.tags) } else { front_matter.slug.trim().to_owned() };
+    if slug.is_empty() {
+        return Err("neither slug nor tags is given".to_owned());
+    }
+    let status = match front_matter.status.as_str() {
+        "publish" | "published" => "publish",
+        "draft" | "" => "draft",
+        other => return Err(format!("unknown status {}", other)),
+    };
+    let time = |name: &str, time: &Option<String>| match time {
+        Some(time) => parse_time(time).map(Some).ok_or_else(|| format!("invalid {} time {}", name, time)),
+        None => Ok(None),
+    };
+
+    Ok(NewPost {
+        title: front_matter.title.trim().to_owned(),
+        slug,
+        body: document.body.clone(),
+        publish: time("publish", &front_matter.publish)?,
+        created: time("created", &front_matter.created)?,
+        updated: time("updated", &front_matter.updated)?,
+        status: status.to_owned(),
+        user_id,
+        likes: front_matter.likes,
+        excerpt: front_matter.excerpt.clone(),
+        featured_image: front_matter.featured_image.clone(),
+        meta_description: front_matter.meta_description.clone(),
+        canonical_url: front_matter.canonical_url.clone(),
+    })
+}
+
+// upsert every post by its source, or by title, a post without a known author belongs to default_author.
+// a post which cannot be imported is reported and skipped, the others still go in.
+pub(crate) fn import_posts(
+    import: Import,
+    default_author: Option<&str>,
+    dry_run: bool,
+    pool: &Data<DbPool>
+) -> Result<ImportReport, failure::Error> {
+    let mut report = ImportReport { dry_run, ..ImportReport::default() };
+
+    let mut user_ids: HashMap<String, Option<i32>> = HashMap::new();
+    for author in import.authors.iter() {
+        let user_id = UserOperation::find_user(&author.username, pool)?.map(|user| user.id);
+        if user_id.is_none() {
+            report.users += 1;
+            if !dry_run {
+                create_author(author, pool)?;
+            }
+        }
+        // a dry run has no id for a new author, the placeholder only counts as an author change of existing posts
+        let user_id = match user_id {
+            Some(user_id) => Some(user_id),
+            None if dry_run => Some(0),
+            None => UserOperation::find_user(&author.username, pool)?.map(|user| user.id),
+        };
+        user_ids.insert(author.username.clone(), user_id);
+    }
+    let mut user_id_of = |username: &str| -> Result<Option<i32>, failure::Error> {
+        if !user_ids.contains_key(username) {
+            let user_id = UserOperation::find_user(username, pool)?.map(|user| user.id);
+            user_ids.insert(username.to_owned(), user_id);
+        }
+        Ok(user_ids[username])
+    };
+
+    // every old permalink leads to the new article
+    let targets: HashMap<String, String> = import.posts.iter()
+        .filter_map(|(_, post)| post.as_ref().ok())
+        .flat_map(|post| post.old_paths.iter().map(move |path| (normalize_path(path), seo::article_path(post.document.front_matter.title.trim()))))
+        // the home page of the old blog is the home page here
+        .filter(|(path, _)| path.ne("/"))
+        .collect();
+
+    for (file, post) in import.posts {
+        let mut post = match post {
+            Ok(post) => post,
+            Err(e) => {
+                report.push(&file, "", "skip", e.to_string());
+                continue;
+            }
+        };
+        let title = post.document.front_matter.title.clone();
+        if !targets.is_empty() {
+            post.document.body = rewrite_links(&post.document.body, &import.site_url, &targets);
+        }
+
+        let author = post.document.front_matter.author.as_deref().filter(|author| !author.is_empty());
+        let user_id = match author.map(|author| user_id_of(author)).transpose()?.flatten() {
+            Some(user_id) => Some(user_id),
+            None => default_author.map(|author| user_id_of(author)).transpose()?.flatten(),
+        };
+        let user_id = match user_id {
+            Some(user_id) => user_id,
+            None => {
+                report.push(&file, &title, "skip", format!("unknown author {}", author.or(default_author).unwrap_or_default()));
+                continue;
+            }
+        };
+
+        let new_post = match new_post_of(&post.document, user_id) {
+            Ok(new_post) => new_post,
+            Err(reason) => {
+                report.push(&file, &title, "skip", reason);
+                continue;
+            }
+        };
+        let upsert = match &post.source {
+            Some(source) => PostOperation::upsert_by_source(&new_post, source, dry_run, pool)?,
+            None => PostOperation::upsert_by_title(&new_post, dry_run, pool)?,
+        };
+        match &upsert {
+            Upsert::Created => report.push(&file, &title, "create", String::new()),
+            Upsert::Updated(fields) => report.push(&file, &title, "update", fields.join(", ")),
+            Upsert::Unchanged => report.push(&file, &title, "unchanged", String::new()),
+            Upsert::DuplicatedTitle => report.push(&file, &title, "skip", "another post has this title".to_owned()),
+        }
+
+        // the comments come only with a new post, so that importing again doesn't repeat them
+        if upsert.eq(&Upsert::Created) && !post.comments.is_empty() {
+            report.comments += post.comments.len();
+            if !dry_run {
+                let post_id = PostOperation::get_post_by_title(&new_post.title, pool)?
+                                           .ok_or_else(|| failure::format_err!("post {} is gone", new_post.title))?.id;
+                for comment in post.comments {
+                    CommentOperation::insert_comment(NewComment { post_id, ..comment }, pool)?;
+                }
+            }
+        }
+        let new_path = seo::article_path(&new_post.title);
+        for old_path in post.old_paths.iter().filter(|path| normalize_path(path).ne("/") && normalize_path(path).ne(&normalize_path(&new_path))) {
+            report.redirects += 1;
+            if !dry_run {
+                RedirectOperation::record_redirect(old_path, &new_path, pool)?;
+            }
+        }
+    }
+    Ok(report)
+}
+
+// the documents of an uploaded zip or markdown file, a document which cannot be parsed is kept with its error
+pub(crate) fn parse_documents(file_name: &str, bytes: &[u8]) -> Result<Vec<(String, Result<Document, failure::Error>)>, failure::Error> {
+    Ok(read_documents(file_name, bytes)?.into_iter().map(|(file, text)| (file, text.and_then(|text| parse_document(&text)))).collect())
+}
+
+// the markdown files under dir, named by their path relative to it
+pub(crate) fn read_directory(dir: &Path) -> Result<Documents, failure::Error> {
+    let mut documents = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(current) = dirs.pop() {
+        for entry in std::fs::read_dir(&current)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().map_or(false, |ext| ext.eq("md") || ext.eq("markdown")) {
+                let name = path.strip_prefix(dir)?.to_string_lossy().replace('\\', "/");
+                documents.push((name, document_text(std::fs::read(&path)?)));
+            }
+        }
+    }
+    documents.sort_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+    Ok(documents)
+}
+
+// which kind of blog the files come from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ImportSource {
+    Markdown, // exported by this blog
+    WordPress,
+    Hugo,
+    Jekyll,
+}
+
+impl std::str::FromStr for ImportSource {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "markdown" | "" => Ok(ImportSource::Markdown),
+            "wordpress" => Ok(ImportSource::WordPress),
+            "hugo" => Ok(ImportSource::Hugo),
+            "jekyll" => Ok(ImportSource::Jekyll),
+            _ => Err(failure::format_err!("source should be one of markdown/wordpress/hugo/jekyll, but got {}", s)),
+        }
+    }
+}
+
+// read what an importer needs: a wxr file of wordpress, or markdown files in a zip or a single file
+pub(crate) fn read_import(source: ImportSource, file_name: &str, bytes: &[u8]) -> Result<Import, failure::Error> {
+    match source {
+        ImportSource::WordPress => wordpress::parse_wxr(std::str::from_utf8(bytes)?),
+        ImportSource::Markdown => Ok(Import::from_documents(parse_documents(file_name, bytes)?)),
+        ImportSource::Hugo => Ok(static_site::parse_hugo(read_documents(file_name, bytes)?)),
+        ImportSource::Jekyll => Ok(static_site::parse_jekyll(read_documents(file_name, bytes)?)),
+    }
+}
+
+// a directory can be imported from the command line, like the content folder of hugo
+pub(crate) fn read_import_path(source: ImportSource, path: &Path) -> Result<Import, failure::Error> {
+    if !path.is_dir() {
+        return read_import(source, &path.to_string_lossy(), &std::fs::read(path)?);
+    }
+    let documents = read_directory(path)?;
+    match source {
+        ImportSource::WordPress => Err(failure::err_msg("a wordpress export is a single xml file")),
+        ImportSource::Markdown => {
+            Ok(Import::from_documents(documents.into_iter().map(|(file, text)| (file, text.and_then(|text| parse_document(&text)))).collect()))
+        }
+        ImportSource::Hugo => Ok(static_site::parse_hugo(documents)),
+        ImportSource::Jekyll => Ok(static_site::parse_jekyll(documents)),
+    }
+}
@@ -0,0 +1,222 @@
+use chrono::NaiveDate;
+use lazy_static::lazy_static;
+use regex::{ Captures, Regex };
+use serde_json::Value;
+use std::collections::HashMap;
+
+use super::{ format_time, parse_time, split_front_matter, Document, Documents, FrontMatter, Import, Imported };
+
+lazy_static! {
+    // {% post_url 2020-01-31-hello %} and {% link _posts/2020-01-31-hello.md %} of jekyll
+    static ref JEKYLL_LINK: Regex = Regex::new(r"\{%-?\s*(?:post_url|link)\s+([^\s%]+)\s*-?%\}").unwrap();
+    static ref JEKYLL_BASEURL: Regex = Regex::new(r"\{\{-?\s*site\.baseurl\s*-?\}\}").unwrap();
+    // {{< ref "posts/hello.md" >}} and {{% relref "hello" %}} of hugo
+    static ref HUGO_REF: Regex = Regex::new(r#"\{\{[<%]\s*(?:ref|relref)\s+"?([^"\s>%]+)"?\s*[>%]\}\}"#).unwrap();
+    static ref JEKYLL_POST_NAME: Regex = Regex::new(r"^(\d{4})-(\d{2})-(\d{2})-(.+)$").unwrap();
+}
+
+// a markdown file which is a post, before the links to other posts are resolved
+struct SourcePost {
+    names: Vec<String>, // how other posts refer to this one
+    front_matter: FrontMatter,
+    body: String,
+    old_paths: Vec<String>, // the first one is the permalink
+}
+
+fn string(front: &Value, key: &str) -> Option<String> {
+    match &front[key] {
+        Value::String(s) if !s.trim().is_empty() => Some(s.trim().to_owned()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+// a list, or words in a string like jekyll's "tags: rust actix"
+fn strings(front: &Value, key: &str) -> Vec<String> {
+    match &front[key] {
+        Value::Array(values) => values.iter().filter_map(Value::as_str).map(|s| s.trim().to_owned()).filter(|s| !s.is_empty()).collect(),
+        Value::String(s) => s.split_whitespace().map(str::to_owned).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn time(front: &Value, keys: &[&str]) -> Option<String> {
+    keys.iter().filter_map(|key| string(front, key)).filter_map(|time| parse_time(&time)).next().as_ref().map(format_time)
+}
+
+// the path of a file without its extension, and the name
+fn stem(path: &str) -> &str {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    name.rsplitn(2, '.').last().unwrap_or(name)
+}
+
+fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() || c.eq(&'_') {
+            slug.push(c);
+        } else if !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_matches('-').to_owned()
+}
+
+fn common_front_matter(front: &Value, status: &str, tags: Vec<String>) -> FrontMatter {
+    let author = string(front, "author").or_else(|| strings(front, "authors").into_iter().next());
+    let mut unique_tags: Vec<String> = Vec::new();
+    tags.into_iter().for_each(|tag| if !unique_tags.contains(&tag) { unique_tags.push(tag) });
+    // the slug here is made of the tags and cannot be empty
+    if unique_tags.is_empty() {
+        unique_tags.push("Uncategorized".to_owned());
+    }
+    FrontMatter {
+        title: string(front, "title").unwrap_or_default(),
+        status: status.to_owned(),
+        author,
+        tags: unique_tags,
+        excerpt: string(front, "summary").or_else(|| string(front, "excerpt")).unwrap_or_default(),
+        featured_image: string(front, "image").or_else(|| string(front, "featured_image")).unwrap_or_default(),
+        meta_description: string(front, "description").unwrap_or_default(),
+        ..FrontMatter::default()
+    }
+}
+
+// the shortcodes linking to other posts become links to their old permalinks, which are rewritten later
+fn resolve_links(regex: &Regex, body: &str, permalinks: &HashMap<String, String>) -> String {
+    regex.replace_all(body, |caps: &Captures| {
+        let target = caps[1].trim_start_matches('/');
+        permalinks.get(target).or_else(|| permalinks.get(stem(target))).cloned().unwrap_or_else(|| caps[0].to_owned())
+    }).into_owned()
+}
+
+fn into_import(posts: Vec<(String, Result<SourcePost, failure::Error>)>, regex: &Regex) -> Import {
+    let permalinks: HashMap<String, String> = posts.iter()
+        .filter_map(|(_, post)| post.as_ref().ok())
+        .flat_map(|post| post.names.iter().filter_map(move |name| post.old_paths.first().map(|permalink| (name.clone(), permalink.clone()))))
+        .collect();
+
+    let posts = posts.into_iter().map(|(file, post)| {
+        // a draft has no permalink yet, its file stands for it
+        let source = post.as_ref().ok().map(|post| post.old_paths.first().cloned().unwrap_or_else(|| file.clone()));
+        let post = post.map(|post| Imported {
+            document: Document { front_matter: post.front_matter, body: resolve_links(regex, &post.body, &permalinks) },
+            old_paths: post.old_paths,
+            comments: Vec::new(),
+            source,
+        });
+        (file, post)
+    }).collect();
+    Import { posts, ..Import::default() }
+}
+
+// content/<section>/<name>.md or content/<section>/<name>/index.md is a post, the url is /<section>/<name>/
+fn hugo_post(path: &str, text: &str) -> Option<Result<SourcePost, failure::Error>> {
+    let segments: Vec<&str> = path.split('/').collect();
+    let segments = match segments.iter().position(|segment| segment.eq(&"content")) {
+        Some(i) => &segments[i + 1..],
+        None => &segments[..],
+    };
+    let file = segments.last()?;
+    if file.starts_with("_index.") {
+        return None;
+    }
+    if segments.len().lt(&2) || (segments.len().eq(&2) && file.starts_with("index.")) {
+        return Some(Err(failure::err_msg("pages out of a section are not imported")));
+    }
+    let section = segments[0];
+    let name = if file.starts_with("index.") { segments[segments.len() - 2] } else { stem(file) };
+
+    let parsed = split_front_matter(text).map(|(front, body)| {
+        let draft = front["draft"].as_bool().unwrap_or(false);
+        let mut tags = strings(&front, "categories");
+        tags.extend(strings(&front, "tags"));
+        let mut front_matter = common_front_matter(&front, if draft { "draft" } else { "publish" }, tags);
+        front_matter.publish = time(&front, &["publishDate", "date"]);
+        front_matter.created = time(&front, &["date", "publishDate"]);
+        front_matter.updated = time(&front, &["lastmod", "date"]);
+
+        let slug = string(&front, "slug").unwrap_or_else(|| name.to_owned());
+        let mut old_paths = vec![string(&front, "url").unwrap_or_else(|| format!("/{}/{}/", section, slug))];
+        old_paths.extend(strings(&front, "aliases"));
+
+        let relative = segments.join("/");
+        let names = vec![relative, name.to_owned(), format!("{}/{}", section, name)];
+        SourcePost { names, front_matter, body, old_paths }
+    });
+    Some(parsed)
+}
+
+// the markdown files of a hugo site, or of its content folder
+pub(crate) fn parse_hugo(documents: Documents) -> Import {
+    let posts = documents.into_iter().filter_map(|(path, text)| {
+        let post = match text {
+            Ok(text) => hugo_post(&path, &text)?,
+            Err(e) => Err(e),
+        };
+        Some((path, post))
+    }).collect();
+    into_import(posts, &HUGO_REF)
+}
+
+// _posts/<yyyy-mm-dd>-<name>.md is a post, _drafts/<name>.md a draft. the url is /<categories>/<yyyy>/<mm>/<dd>/<name>.html
+fn jekyll_post(path: &str, text: &str) -> Option<Result<SourcePost, failure::Error>> {
+    let segments: Vec<&str> = path.split('/').collect();
+    let folder = segments.iter().position(|segment| segment.eq(&"_posts") || segment.eq(&"_drafts"));
+    let folder = match folder {
+        Some(i) => i,
+        None => return Some(Err(failure::err_msg("only _posts and _drafts are imported"))),
+    };
+    let is_draft = segments[folder].eq("_drafts");
+    let file_stem = stem(path);
+    let (date, name) = match JEKYLL_POST_NAME.captures(file_stem) {
+        Some(caps) => {
+            let date = NaiveDate::from_ymd_opt(caps[1].parse().ok()?, caps[2].parse().ok()?, caps[3].parse().ok()?);
+            (date, caps[4].to_owned())
+        }
+        None if is_draft => (None, file_stem.to_owned()),
+        None => return Some(Err(failure::err_msg("the name of a post should be like 2020-01-31-hello.md"))),
+    };
+
+    let parsed = split_front_matter(text).map(|(front, body)| {
+        let published = front["published"].as_bool().unwrap_or(true);
+        let mut categories = strings(&front, "categories");
+        categories.extend(string(&front, "category"));
+        let mut tags = categories.clone();
+        tags.extend(strings(&front, "tags"));
+        let mut front_matter = common_front_matter(&front, if is_draft || !published { "draft" } else { "publish" }, tags);
+        let date = time(&front, &["date"]).or_else(|| date.map(|date| format_time(&date.and_hms(0, 0, 0))));
+        front_matter.publish = date.clone();
+        front_matter.created = date.clone();
+        front_matter.updated = time(&front, &["last_modified_at"]).or(date);
+        let body = JEKYLL_BASEURL.replace_all(&body, "").into_owned();
+
+        // a draft has never been published, so it has no permalink
+        let slug = string(&front, "slug").unwrap_or_else(|| name.clone());
+        let mut old_paths = Vec::new();
+        if let Some(permalink) = string(&front, "permalink") {
+            old_paths.push(permalink);
+        } else if let (false, Some(publish)) = (is_draft, front_matter.publish.as_deref().and_then(parse_time)) {
+            let categories: String = categories.iter().map(|category| format!("/{}", slugify(category))).collect();
+            old_paths.push(format!("{}/{}/{}.html", categories, publish.format("%Y/%m/%d"), slug));
+        }
+        old_paths.extend(strings(&front, "redirect_from"));
+
+        let relative = segments[folder..].join("/");
+        let names = vec![relative, file_stem.to_owned()];
+        SourcePost { names, front_matter, body, old_paths }
+    });
+    Some(parsed)
+}
+
+// the markdown files of a jekyll site
+pub(crate) fn parse_jekyll(documents: Documents) -> Import {
+    let posts = documents.into_iter().filter_map(|(path, text)| {
+        let post = match text {
+            Ok(text) => jekyll_post(&path, &text)?,
+            Err(e) => Err(e),
+        };
+        Some((path, post))
+    }).collect();
+    into_import(posts, &JEKYLL_LINK)
+}
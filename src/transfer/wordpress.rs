@@ -0,0 +1,138 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use roxmltree::{ Document as Xml, Node };
+
+use crate::models::comment::NewComment;
+use super::{ format_time, parse_time, Document, FrontMatter, Import, ImportedAuthor, Imported };
+
+lazy_static! {
+    // the block editor wraps every block in <!-- wp:paragraph --> ... <!-- /wp:paragraph -->
+    static ref BLOCK_COMMENT: Regex = Regex::new(r"<!-- /?wp:[^>]*-->\n?").unwrap();
+}
+
+// the prefixes of the namespaces in a wxr file, whichever version of the export it is
+fn prefix_of(node: &Node) -> &'static str {
+    match node.tag_name().namespace() {
+        Some("http://purl.org/rss/1.0/modules/content/") => "content",
+        Some("http://purl.org/dc/elements/1.1/") => "dc",
+        Some(uri) if uri.starts_with("http://wordpress.org/export/") && uri.ends_with("/excerpt/") => "excerpt",
+        Some(uri) if uri.starts_with("http://wordpress.org/export/") => "wp",
+        Some(_) => "?",
+        None => "",
+    }
+}
+
+fn children<'a, 'input>(node: Node<'a, 'input>, prefix: &'static str, name: &'static str) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(move |child| child.is_element() && child.tag_name().name().eq(name) && prefix_of(child).eq(prefix))
+}
+
+fn text(node: Node, prefix: &'static str, name: &'static str) -> String {
+    children(node, prefix, name).next().and_then(|child| child.text()).unwrap_or_default().trim().to_owned()
+}
+
+// a time of wordpress, drafts have 0000-00-00 00:00:00
+fn time(node: Node, name: &'static str) -> Option<String> {
+    parse_time(&text(node, "wp", name)).as_ref().map(format_time)
+}
+
+// /2019/05/hello-world/ of https://example.com/2019/05/hello-world/
+fn path_of(url: &str) -> String {
+    match url.splitn(2, "://").nth(1) {
+        Some(rest) => rest.find('/').map_or_else(|| "/".to_owned(), |i| rest[i..].to_owned()),
+        None => url.to_owned(),
+    }
+}
+
+fn author_of(node: Node) -> ImportedAuthor {
+    ImportedAuthor {
+        username: text(node, "wp", "author_login"),
+        email: text(node, "wp", "author_email"),
+        first_name: text(node, "wp", "author_first_name"),
+        last_name: text(node, "wp", "author_last_name"),
+    }
+}
+
+// approved comments, pingbacks and trackbacks are left out
+fn comments_of(item: Node) -> Vec<NewComment> {
+    children(item, "wp", "comment")
+        .filter(|comment| text(*comment, "wp", "comment_approved").eq("1"))
+        .filter(|comment| {
+            let comment_type = text(*comment, "wp", "comment_type");
+            comment_type.is_empty() || comment_type.eq("comment")
+        })
+        .map(|comment| NewComment {
+            username: text(comment, "wp", "comment_author"),
+            email: text(comment, "wp", "comment_author_email"),
+            comment: text(comment, "wp", "comment_content"),
+            committed_time: parse_time(&text(comment, "wp", "comment_date_gmt")),
+            post_id: 0,
+        })
+        .collect()
+}
+
+// None for what isn't a post at all, like attachments and menu items
+fn post_of(item: Node) -> Option<Result<Imported, failure::Error>> {
+    match text(item, "wp", "post_type").as_str() {
+        "post" => (),
+        "page" => return Some(Err(failure::err_msg("pages are not imported"))),
+        _ => return None,
+    }
+    let status = match text(item, "wp", "status").as_str() {
+        "publish" => "publish",
+        "draft" | "pending" | "future" | "private" => "draft",
+        other => return Some(Err(failure::format_err!("{} posts are not imported", other))),
+    };
+
+    // categories and tags are both tags here, except the default category
+    let mut tags: Vec<String> = Vec::new();
+    for category in children(item, "", "category") {
+        let domain = category.attribute("domain").unwrap_or_default();
+        let tag = category.text().unwrap_or_default().trim().to_owned();
+        let default_category = domain.eq("category") && category.attribute("nicename").eq(&Some("uncategorized"));
+        if (domain.eq("category") || domain.eq("post_tag")) && !default_category && !tag.is_empty() && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    // the slug here is made of the tags and cannot be empty
+    if tags.is_empty() {
+        tags.push("Uncategorized".to_owned());
+    }
+
+    let publish = time(item, "post_date_gmt").or_else(|| time(item, "post_date"));
+    let front_matter = FrontMatter {
+        title: text(item, "", "title"),
+        status: status.to_owned(),
+        publish: publish.clone(),
+        created: publish,
+        updated: time(item, "post_modified_gmt"),
+        author: Some(text(item, "dc", "creator")),
+        tags,
+        excerpt: text(item, "excerpt", "encoded"),
+        ..FrontMatter::default()
+    };
+    let body = BLOCK_COMMENT.replace_all(&text(item, "content", "encoded"), "").trim().to_owned();
+
+    // drafts have ?p=<id> links, which aren't worth a redirect, but still tell the post apart
+    let link = text(item, "", "link");
+    let old_paths = if link.contains('?') || link.is_empty() { Vec::new() } else { vec![path_of(&link)] };
+    let source = if link.is_empty() { format!("?p={}", text(item, "wp", "post_id")) } else { link };
+    Some(Ok(Imported { document: Document { front_matter, body }, old_paths, comments: comments_of(item), source: Some(source) }))
+}
+
+// the posts, their comments and the authors of a wordpress export(Tools => Export)
+pub(crate) fn parse_wxr(xml: &str) -> Result<Import, failure::Error> {
+    let xml = Xml::parse(xml)?;
+    let channel = children(xml.root_element(), "", "channel").next()
+                                                            .ok_or_else(|| failure::err_msg("no channel, it's not a wordpress export"))?;
+    let site_url = match text(channel, "wp", "base_blog_url") {
+        base_blog_url if base_blog_url.is_empty() => text(channel, "", "link"),
+        base_blog_url => base_blog_url,
+    };
+
+    let authors = children(channel, "wp", "author").map(author_of).filter(|author| !author.username.is_empty()).collect();
+    let posts = children(channel, "", "item").filter_map(|item| {
+        let name = format!("item {}", text(item, "wp", "post_id"));
+        post_of(item).map(|post| (name, post))
+    }).collect();
+    Ok(Import { site_url, authors, posts })
+}
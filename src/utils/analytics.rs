@@ -0,0 +1,92 @@
+use actix_web::{ http::header, HttpRequest };
+use chrono::{ NaiveDate, Utc };
+use lazy_static::lazy_static;
+use regex::Regex;
+use sha2::{ Digest, Sha256 };
+use std::sync::Mutex;
+
+use crate::models::analytics::NewPageView;
+use super::{ blocklist, seo, utils::random_token };
+
+// longer terms are cut, nobody searches a paragraph
+const MAX_TERM_LENGTH: usize = 100;
+
+lazy_static! {
+    // crawlers, link previews, monitors and scripts, which aren't readers
+    static ref BOT: Regex = Regex::new(
+        r"(?i)bot|crawl|spider|slurp|archiver|fetcher|scrap|preview|facebookexternalhit|headless|lighthouse|pingdom|uptime|monitor|curl|wget|python|java/|go-http|okhttp|httpclient|libwww"
+    ).unwrap();
+    // the salt of the visitor hashes lives in memory for a day, so a visitor cannot be followed from one day to the next
+    static ref DAILY_SALT: Mutex<(NaiveDate, String)> = Mutex::new((NaiveDate::from_ymd(1970, 1, 1), String::new()));
+}
+
+fn header_of<'a>(req: &'a HttpRequest, name: header::HeaderName) -> &'a str {
+    req.headers().get(name).and_then(|value| value.to_str().ok()).unwrap_or_default()
+}
+
+pub(crate) fn is_bot(user_agent: &str) -> bool {
+    user_agent.trim().is_empty() || BOT.is_match(user_agent)
+}
+
+// a page fetched ahead by the browser may never be read
+fn is_prefetch(req: &HttpRequest) -> bool {
+    ["purpose", "sec-purpose", "x-moz"].iter().any(|name| {
+        req.headers().get(*name).and_then(|value| value.to_str().ok()).map_or(false, |value| value.contains("prefetch"))
+    })
+}
+
+fn daily_salt(today: NaiveDate) -> String {
+    let mut salt = DAILY_SALT.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if salt.0.ne(&today) {
+        *salt = (today, random_token(32));
+    }
+    salt.1.clone()
+}
+
+pub(crate) fn visitor_hash(address: &str, user_agent: &str, today: NaiveDate) -> String {
+    format!("{:x}", Sha256::digest(format!("{}|{}|{}", daily_salt(today), address, user_agent).as_bytes()))
+}
+
+fn host_of(url: &str) -> &str {
+    let rest = url.splitn(2, "://").nth(1).unwrap_or_default();
+    let authority = rest.split(|c| c == '/' || c == '?' || c == '#').next().unwrap_or_default();
+    let host = authority.rsplit('@').next().unwrap_or_default();
+    host.split(':').next().unwrap_or_default()
+}
+
+// the host of the referring site, empty when it's this site or there's none
+pub(crate) fn referrer_host(referrer: &str, site_hosts: &[&str]) -> String {
+    let host = host_of(referrer).to_lowercase();
+    let host = host.trim_start_matches("www.");
+    if site_hosts.iter().any(|site_host| host_of(site_host).to_lowercase().trim_start_matches("www.").eq(host)) {
+        return String::new();
+    }
+    host.to_owned()
+}
+
+// the view of an article by this request, none for bots and prefetches
+pub(crate) fn page_view(req: &HttpRequest, post_id: i32) -> Option<NewPageView> {
+    let user_agent = header_of(req, header::USER_AGENT);
+    if is_bot(user_agent) || is_prefetch(req) {
+        return None;
+    }
+    let now = Utc::now().naive_utc();
+    let connection_info = req.connection_info();
+    let address = blocklist::client_address(req).map(|address| address.to_string()).unwrap_or_default();
+    let this_site = format!("{}://{}", connection_info.scheme(), connection_info.host());
+    Some(NewPageView {
+        post_id,
+        visitor: visitor_hash(&address, user_agent, now.date()),
+        referrer: referrer_host(header_of(req, header::REFERER), &[&seo::site_url(req), &this_site]),
+        viewed: now,
+    })
+}
+
+// lowercase and single spaced, so that the same search is counted once. none for an empty one or a bot
+pub(crate) fn search_term(req: &HttpRequest, key_word: &str) -> Option<String> {
+    if is_bot(header_of(req, header::USER_AGENT)) {
+        return None;
+    }
+    let term: String = key_word.split_whitespace().collect::<Vec<&str>>().join(" ").to_lowercase().chars().take(MAX_TERM_LENGTH).collect();
+    if term.is_empty() { None } else { Some(term) }
+}
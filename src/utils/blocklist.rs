@@ -0,0 +1,121 @@
+use actix_web::HttpRequest;
+use std::net::{ IpAddr, SocketAddr };
+
+use crate::models::blocklist::{ BlockedEntry, EMAIL, IP, NAME };
+use super::{ config, newsletter::is_valid_email, notifications::normalize_email };
+
+// who wrote a comment or a message to the contact page
+pub(crate) struct Author<'a> {
+    pub(crate) email: &'a str,
+    pub(crate) name: &'a str,
+    pub(crate) address: Option<IpAddr>,
+}
+
+// the address of the client, the peer itself unless it's one of the trusted proxies
+pub(crate) fn client_address(req: &HttpRequest) -> Option<IpAddr> {
+    let forwarded_for = req.headers().get("x-forwarded-for").and_then(|value| value.to_str().ok());
+    address_behind_proxies(req.peer_addr().map(|peer| peer.ip()), forwarded_for, &config::current().trusted_proxies)
+}
+
+// anyone can send X-Forwarded-For, only the part appended by the trusted proxies is believed.
+// walking back from the peer, the first address which isn't a trusted proxy is the client
+pub(crate) fn address_behind_proxies(peer: Option<IpAddr>, forwarded_for: Option<&str>, trusted_proxies: &[String]) -> Option<IpAddr> {
+    let is_trusted = |address: IpAddr| trusted_proxies.iter().filter_map(|proxy| parse_range(proxy))
+                                                      .any(|(network, prefix)| in_range(address, network, prefix));
+    let mut client = peer?;
+    let mut forwarded = forwarded_for.unwrap_or_default().rsplit(',').map(str::trim).filter(|hop| !hop.is_empty());
+    while is_trusted(client) {
+        match forwarded.next().and_then(parse_address) {
+            Some(address) => client = address,
+            None => break,
+        }
+    }
+    Some(client)
+}
+
+// 10.1.2.3, 10.1.2.3:5678, 2001:db8::1 or [2001:db8::1]:5678
+fn parse_address(address: &str) -> Option<IpAddr> {
+    address.parse::<SocketAddr>().map(|socket| socket.ip())
+           .or_else(|_| address.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>())
+           .ok()
+}
+
+// an address is a range of its own length
+pub(crate) fn parse_range(value: &str) -> Option<(IpAddr, u8)> {
+    let mut parts = value.splitn(2, '/');
+    let network = parts.next()?.trim().parse::<IpAddr>().ok()?;
+    let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+    let prefix = match parts.next() {
+        Some(prefix) => prefix.trim().parse::<u8>().ok().filter(|prefix| prefix.le(&max_prefix))?,
+        None => max_prefix,
+    };
+    Some((network, prefix))
+}
+
+fn in_range(address: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    // an ipv4 client on a dual stack listener shows up as ::ffff:a.b.c.d
+    let address = match (address, network) {
+        (IpAddr::V6(v6), IpAddr::V4(_)) => v6.to_ipv4().map_or(address, IpAddr::V4),
+        _ => address,
+    };
+    match (address, network) {
+        (IpAddr::V4(address), IpAddr::V4(network)) => {
+            let mask = u32::max_value().checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+            u32::from(address) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(address), IpAddr::V6(network)) => {
+            let mask = u128::max_value().checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+            u128::from(address) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+// a glob of * and ?, the whole name has to match, the case doesn't matter
+fn name_matches(pattern: &str, name: &str) -> bool {
+    let expression: String = pattern.chars().map(|c| match c {
+        '*' => ".*".to_owned(),
+        '?' => ".".to_owned(),
+        c => regex::escape(&c.to_string()),
+    }).collect();
+    regex::Regex::new(&format!("(?i)^{}$", expression)).map(|re| re.is_match(name.trim())).unwrap_or(false)
+}
+
+fn email_matches(entry: &str, email: &str) -> bool {
+    let email = normalize_email(email);
+    if entry.starts_with('@') { email.ends_with(entry) } else { email.eq(entry) }
+}
+
+// the value as it's stored, or why it can't be blocked
+pub(crate) fn normalize_entry(kind: &str, value: &str) -> Result<String, String> {
+    let value = value.trim();
+    match kind {
+        EMAIL => {
+            let email = normalize_email(value);
+            let domain = email.starts_with('@') && is_valid_email(&format!("x{}", email));
+            if domain || is_valid_email(&email) { Ok(email) } else { Err(format!("{} is neither an email nor an @domain", value)) }
+        }
+        NAME if value.chars().all(|c| c.eq(&'*') || c.eq(&'?')) => Err("this pattern would block everyone".to_owned()),
+        NAME => Ok(value.to_owned()),
+        IP => match parse_range(value) {
+            Some((network, prefix)) if prefix.eq(&0) => Err(format!("{}/0 would block everyone", network)),
+            Some((network, prefix)) if prefix.eq(&(if network.is_ipv4() { 32 } else { 128 })) => Ok(network.to_string()),
+            Some((network, prefix)) => Ok(format!("{}/{}", network, prefix)),
+            None => Err(format!("{} is neither an ip address nor a range like 10.0.0.0/8", value)),
+        },
+        _ => Err(format!("unknown kind {}, use email/name/ip", kind)),
+    }
+}
+
+// the first entry which blocks this author
+pub(crate) fn blocked_by<'a>(entries: &'a [BlockedEntry], author: &Author) -> Option<&'a BlockedEntry> {
+    entries.iter().find(|entry| match entry.kind.as_str() {
+        EMAIL => email_matches(&entry.value, author.email),
+        NAME => name_matches(&entry.value, author.name),
+        IP => match (author.address, parse_range(&entry.value)) {
+            (Some(address), Some((network, prefix))) => in_range(address, network, prefix),
+            _ => false,
+        },
+        _ => false,
+    })
+}
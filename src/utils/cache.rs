@@ -0,0 +1,168 @@
+use chrono::NaiveDateTime;
+use lazy_static::lazy_static;
+use serde_derive::Serialize;
+use std::collections::HashMap;
+use std::sync::{ Mutex, MutexGuard, atomic::{ AtomicU64, Ordering } };
+use std::time::{ Duration, Instant };
+
+use crate::models::post::Post;
+
+pub(crate) const DEFAULT_CAPACITY: usize = 256;
+pub(crate) const DEFAULT_TTL: u64 = 300; // seconds
+
+// keys of the cached pages and queries
+pub(crate) const INDEX_PAGE: &str = "page:index";
+pub(crate) const ALL_POSTS_PAGE: &str = "page:all_posts";
+pub(crate) const PUBLISHED_POSTS: &str = "query:published_posts";
+
+pub(crate) fn article_page(title: &str) -> String {
+    format!("page:article:{}", title)
+}
+
+// a rendered public page, post_id is kept for the pages that need to restore session state on a hit
+#[derive(Debug, Clone)]
+pub(crate) struct CachedPage {
+    pub(crate) body: String,
+    pub(crate) post_id: Option<i32>,
+    pub(crate) last_modified: Option<NaiveDateTime>,
+}
+
+lazy_static! {
+    // rendered public pages
+    pub(crate) static ref PAGE_CACHE: LruCache<CachedPage> = LruCache::new(DEFAULT_CAPACITY, Duration::from_secs(DEFAULT_TTL));
+    // results of hot queries
+    pub(crate) static ref QUERY_CACHE: LruCache<Vec<Post>> = LruCache::new(DEFAULT_CAPACITY, Duration::from_secs(DEFAULT_TTL));
+}
+
+struct Entry<V> {
+    value: V,
+    expires_at: Instant,
+    last_used: u64,
+}
+
+struct Inner<V> {
+    entries: HashMap<String, Entry<V>>,
+    capacity: usize,
+    ttl: Duration,
+    tick: u64, // grows on every access, the entry with the smallest tick is the least recently used one
+}
+
+impl<V> Inner<V> {
+    fn evict_lru(&mut self) {
+        let lru_key = self.entries.iter().min_by_key(|(_, entry)| entry.last_used).map(|(key, _)| key.clone());
+        if let Some(key) = lru_key {
+            self.entries.remove(&key);
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub(crate) struct CacheStats {
+    pub(crate) hits: u64,
+    pub(crate) misses: u64,
+    pub(crate) entries: usize,
+    pub(crate) capacity: usize,
+}
+
+// a LRU cache with a time to live for each entry
+pub(crate) struct LruCache<V> {
+    inner: Mutex<Inner<V>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<V: Clone> LruCache<V> {
+    pub(crate) fn new(capacity: usize, ttl: Duration) -> Self {
+        LruCache {
+            inner: Mutex::new(Inner { entries: HashMap::new(), capacity, ttl, tick: 0 }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    // a poisoned lock only means another thread panicked while holding it, the map is still usable.
+    fn lock(&self) -> MutexGuard<'_, Inner<V>> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(crate) fn configure(&self, capacity: usize, ttl: Duration) {
+        let mut inner = self.lock();
+        inner.capacity = capacity;
+        inner.ttl = ttl;
+        while inner.entries.len() > inner.capacity {
+            inner.evict_lru();
+        }
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<V> {
+        let mut guard = self.lock();
+        let inner = &mut *guard;
+        inner.tick += 1;
+        let tick = inner.tick;
+
+        let expired = inner.entries.get(key).map_or(false, |entry| entry.expires_at <= Instant::now());
+        if expired {
+            inner.entries.remove(key);
+        }
+
+        let found = inner.entries.get_mut(key).map(|entry| {
+            entry.last_used = tick;
+            entry.value.clone()
+        });
+        match found {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        found
+    }
+
+    pub(crate) fn insert(&self, key: impl Into<String>, value: V) {
+        let mut inner = self.lock();
+        if inner.capacity.eq(&0) {
+            return;
+        }
+
+        let key = key.into();
+        if !inner.entries.contains_key(&key) && inner.entries.len() >= inner.capacity {
+            inner.evict_lru();
+        }
+
+        inner.tick += 1;
+        let entry = Entry { value, expires_at: Instant::now() + inner.ttl, last_used: inner.tick };
+        inner.entries.insert(key, entry);
+    }
+
+    pub(crate) fn clear(&self) {
+        self.lock().entries.clear();
+    }
+
+    // drop all expired entries, return how many entries were dropped
+    pub(crate) fn prune(&self) -> usize {
+        let mut inner = self.lock();
+        let now = Instant::now();
+        let before = inner.entries.len();
+        inner.entries.retain(|_, entry| entry.expires_at > now);
+        before - inner.entries.len()
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        let inner = self.lock();
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: inner.entries.len(),
+            capacity: inner.capacity,
+        }
+    }
+}
+
+pub(crate) fn configure(capacity: usize, ttl: Duration) {
+    PAGE_CACHE.configure(capacity, ttl);
+    QUERY_CACHE.configure(capacity, ttl);
+}
+
+// any write to posts or comments may change every public page, so just drop all of them.
+pub(crate) fn invalidate_all() {
+    PAGE_CACHE.clear();
+    QUERY_CACHE.clear();
+}
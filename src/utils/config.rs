@@ -0,0 +1,548 @@
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::path::{ Path, PathBuf };
+use std::sync::{ Arc, RwLock };
+
+use crate::jobs::JobsConfig;
+use super::{ blocklist, cache, logging::LogFormat, mailer::{ MailBackend, MailConfig, SmtpSecurity }, media::MediaConfig, sitemap::RobotsConfig, tls::{ PlainHttp, TlsConfig } };
+
+pub(crate) const ENV_PREFIX: &str = "ACTIX_BLOG_";
+// selects the profile, development by default
+pub(crate) const PROFILE_VAR: &str = "ACTIX_BLOG_ENV";
+// path of the config file if --config isn't given
+pub(crate) const CONFIG_VAR: &str = "ACTIX_BLOG_CONFIG";
+pub(crate) const DEFAULT_CONFIG_FILE: &str = "actix_blog.toml";
+
+const LOG_LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+const MEDIA_TYPES: [&str; 3] = ["jpeg", "png", "gif"];
+
+lazy_static! {
+    static ref CURRENT: RwLock<Arc<BlogConfig>> = RwLock::new(Arc::new(BlogConfig::default()));
+}
+
+// make the loaded config visible to the whole process
+pub(crate) fn install(config: BlogConfig) {
+    *CURRENT.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(config);
+}
+
+// the installed config, or the defaults if nothing installed yet(like in tests)
+pub(crate) fn current() -> Arc<BlogConfig> {
+    CURRENT.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Profile {
+    Development,
+    Test,
+    Production,
+}
+
+impl Profile {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Profile::Development => "development",
+            Profile::Test => "test",
+            Profile::Production => "production",
+        }
+    }
+
+    // ACTIX_BLOG_ENV, or development if not set
+    pub(crate) fn from_env() -> Result<Self, failure::Error> {
+        match std::env::var(PROFILE_VAR) {
+            Ok(profile) => profile.parse(),
+            Err(_) => Ok(Profile::Development),
+        }
+    }
+}
+
+impl std::str::FromStr for Profile {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "development" | "dev" => Ok(Profile::Development),
+            "test" => Ok(Profile::Test),
+            "production" | "prod" => Ok(Profile::Production),
+            _ => Err(failure::format_err!("unknown profile {}, use development/test/production", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct BlogConfig {
+    pub(crate) profile: Profile,
+    pub(crate) address: String,
+    pub(crate) port: u16,
+    pub(crate) site_url: String, // the public address for absolute urls, like https://blog.example.com
+    pub(crate) workers: usize,
+    pub(crate) log: String,
+    pub(crate) log_format: LogFormat,
+    pub(crate) database_url: String,
+    pub(crate) auto_migrate: bool, // apply pending migrations at startup
+    pub(crate) allow_registration: bool, // registration with an invitation code, closed by default
+    pub(crate) trusted_proxies: Vec<String>, // addresses or ranges whose X-Forwarded-For is believed
+    pub(crate) metrics_token: String, // the bearer token of /metrics, disabled when empty
+    pub(crate) templates_dir: String,
+    pub(crate) static_dir: String,
+    pub(crate) cache_capacity: usize,
+    pub(crate) cache_ttl: u64,
+    pub(crate) cache_control: HashMap<String, String>,
+    pub(crate) compression: bool,
+    pub(crate) tls: TlsConfig,
+    pub(crate) shutdown_timeout: u64, // seconds to drain requests and running jobs
+    pub(crate) jobs: JobsConfig,
+    pub(crate) media: MediaConfig,
+    pub(crate) robots: RobotsConfig,
+    pub(crate) mail: MailConfig,
+}
+
+impl Default for BlogConfig {
+    fn default() -> Self {
+        BlogConfig {
+            profile: Profile::Development,
+            address: "127.0.0.1".to_owned(),
+            port: 8088,
+            site_url: String::new(),
+            workers: num_cpus::get(),
+            log: "info".to_owned(),
+            log_format: LogFormat::Json,
+            database_url: String::new(),
+            auto_migrate: false,
+            allow_registration: false,
+            trusted_proxies: Vec::new(),
+            metrics_token: String::new(),
+            templates_dir: default_dir("templates"),
+            static_dir: default_dir("static"),
+            cache_capacity: cache::DEFAULT_CAPACITY,
+            cache_ttl: cache::DEFAULT_TTL,
+            cache_control: HashMap::new(),
+            compression: true,
+            tls: TlsConfig::default(),
+            shutdown_timeout: 30,
+            jobs: JobsConfig::default(),
+            media: MediaConfig::default(),
+            robots: RobotsConfig::default(),
+            mail: MailConfig::default(),
+        }
+    }
+}
+
+// under the working directory, or next to the executable if it isn't there
+fn default_dir(name: &str) -> String {
+    let beside_executable = std::env::current_exe().ok()
+                                .and_then(|exe| exe.parent().map(|dir| dir.join(name)))
+                                .filter(|dir| dir.is_dir());
+    match beside_executable {
+        Some(dir) if !Path::new(name).is_dir() => dir.to_string_lossy().into_owned(),
+        _ => name.to_owned(),
+    }
+}
+
+// all the problems found while loading the config, reported at once
+#[derive(Debug)]
+pub(crate) struct ConfigErrors(pub(crate) Vec<String>);
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "found {} problem(s) in the configuration:", self.0.len())?;
+        for error in self.0.iter() {
+            writeln!(f, "  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+// the value of --config or --config=path in the command line
+pub(crate) fn config_path_from_args(args: &[String]) -> Option<PathBuf> {
+    args.iter().enumerate().find_map(|(i, arg)| {
+        if arg.eq("--config") {
+            args.get(i + 1).map(PathBuf::from)
+        } else {
+            arg.strip_prefix("--config=").map(PathBuf::from)
+        }
+    })
+}
+
+impl BlogConfig {
+    // layers, from the lowest to the highest priority:
+    // built-in defaults => [default] section => [<profile>] section => ACTIX_BLOG_* env variables
+    pub(crate) fn load(path: Option<&Path>, profile: Option<Profile>) -> Result<Self, ConfigErrors> {
+        dotenv::dotenv().ok();
+        Self::load_with_vars(path, profile, std::env::vars())
+    }
+
+    // the same with the ACTIX_BLOG_* overrides taken from vars instead of the process environment
+    pub(crate) fn load_with_vars(path: Option<&Path>, profile: Option<Profile>, vars: impl Iterator<Item = (String, String)>) -> Result<Self, ConfigErrors> {
+        let mut errors = Vec::new();
+
+        let profile = match profile.map_or_else(Profile::from_env, Ok) {
+            Ok(profile) => profile,
+            Err(e) => return Err(ConfigErrors(vec![e.to_string()])),
+        };
+
+        // an explicit path must exist, the default one is optional
+        let explicit_path = path.map(Path::to_path_buf).or_else(|| std::env::var(CONFIG_VAR).ok().map(PathBuf::from));
+        let file_path = explicit_path.clone().unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_FILE));
+        let file = match std::fs::read_to_string(&file_path) {
+            Ok(contents) => match contents.parse::<toml::Value>() {
+                Ok(toml::Value::Table(table)) => table,
+                Ok(_) => return Err(ConfigErrors(vec![format!("{} is not a toml table", file_path.display())])),
+                Err(e) => return Err(ConfigErrors(vec![format!("failed to parse {}: {}", file_path.display(), e)])),
+            },
+            Err(e) if explicit_path.is_some() => {
+                return Err(ConfigErrors(vec![format!("failed to read {}: {}", file_path.display(), e)]));
+            }
+            Err(_) => toml::value::Table::new(),
+        };
+
+        let mut merged = toml::value::Table::new();
+        for section in ["default", profile.name()].iter() {
+            match file.get(*section) {
+                Some(toml::Value::Table(table)) => merge(&mut merged, table),
+                Some(_) => errors.push(format!("[{}] should be a table", section)),
+                None => (),
+            }
+        }
+        let raw_env = apply_env_overrides(&mut merged, vars);
+
+        let config = Self::from_table(profile, &merged, &raw_env, &mut errors);
+        config.validate(&mut errors);
+
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigErrors(errors))
+        }
+    }
+
+    fn from_table(profile: Profile, table: &toml::value::Table, raw_env: &HashMap<String, String>, errors: &mut Vec<String>) -> Self {
+        let defaults = BlogConfig::default();
+        // the closures below can't borrow defaults once its strings are moved out
+        let (default_log_format, default_compression) = (defaults.log_format, defaults.compression);
+        let mut reader = Reader { table, section: "", raw_env, errors };
+
+        let address = reader.string("address", defaults.address);
+        let port = reader.integer("port", defaults.port);
+        let site_url = reader.string("site_url", defaults.site_url).trim_end_matches('/').to_owned();
+        let workers = reader.integer("workers", defaults.workers);
+        let log = reader.string("log", defaults.log);
+        let log_format = reader.string("log_format", "json".to_owned()).parse::<LogFormat>().unwrap_or_else(|e| {
+            reader.errors.push(e.to_string());
+            default_log_format
+        });
+        let database_url = reader.string("database_url", defaults.database_url);
+        let auto_migrate = reader.boolean("auto_migrate", defaults.auto_migrate);
+        let allow_registration = reader.boolean("allow_registration", defaults.allow_registration);
+        let trusted_proxies = reader.strings("trusted_proxies", defaults.trusted_proxies);
+        let metrics_token = reader.string("metrics_token", defaults.metrics_token);
+        let templates_dir = reader.string("templates_dir", defaults.templates_dir);
+        let static_dir = reader.string("static_dir", defaults.static_dir);
+        let cache_capacity = reader.integer("cache_capacity", defaults.cache_capacity);
+        let cache_ttl = reader.integer("cache_ttl", defaults.cache_ttl);
+
+        let cache_control = reader.section("cache_control").map_or_else(HashMap::new, |mut section| {
+            section.table.keys().cloned().collect::<Vec<_>>().into_iter()
+                   .map(|route| {
+                       let policy = section.string(&route, String::new());
+                       (route, policy)
+                   })
+                   .collect()
+        });
+
+        let compression = reader.section("compression").map_or(default_compression, |mut section| {
+            section.boolean("enabled", default_compression)
+        });
+
+        let shutdown_timeout = reader.integer("shutdown_timeout", defaults.shutdown_timeout);
+        let jobs = reader.section("jobs").map_or_else(JobsConfig::default, |mut section| {
+            let default_jobs = JobsConfig::default();
+            JobsConfig {
+                enabled: section.boolean("enabled", default_jobs.enabled),
+                poll_interval: section.integer("poll_interval", default_jobs.poll_interval),
+            }
+        });
+
+        let media = reader.section("media").map_or_else(MediaConfig::default, |mut section| {
+            let default_media = MediaConfig::default();
+            MediaConfig {
+                dir: section.string("dir", default_media.dir),
+                max_upload_size: section.integer("max_upload_size", default_media.max_upload_size),
+                max_dimension: section.integer("max_dimension", default_media.max_dimension),
+                allowed_types: section.strings("allowed_types", default_media.allowed_types),
+            }
+        });
+
+        let robots = reader.section("robots").map_or_else(RobotsConfig::default, |mut section| {
+            let default_robots = RobotsConfig::default();
+            RobotsConfig {
+                disallow: section.strings("disallow", default_robots.disallow),
+                allow: section.strings("allow", default_robots.allow),
+            }
+        });
+
+        let mail = reader.section("mail").map_or_else(MailConfig::default, |mut section| {
+            let default_mail = MailConfig::default();
+            let backend = section.string("backend", "log".to_owned()).parse::<MailBackend>().unwrap_or_else(|e| {
+                section.errors.push(format!("mail.backend: {}", e));
+                default_mail.backend
+            });
+            let smtp_security = section.string("smtp_security", "starttls".to_owned()).parse::<SmtpSecurity>().unwrap_or_else(|e| {
+                section.errors.push(format!("mail.smtp_security: {}", e));
+                default_mail.smtp_security
+            });
+            MailConfig {
+                backend,
+                from: section.string("from", default_mail.from),
+                spool_dir: section.string("spool_dir", default_mail.spool_dir),
+                smtp_host: section.string("smtp_host", default_mail.smtp_host),
+                smtp_port: section.integer("smtp_port", default_mail.smtp_port),
+                smtp_security,
+                smtp_user: section.string("smtp_user", default_mail.smtp_user),
+                smtp_password: section.string("smtp_password", default_mail.smtp_password),
+                secret: section.string("secret", default_mail.secret),
+            }
+        });
+
+        let tls = reader.section("tls").map_or_else(TlsConfig::default, |mut section| {
+            let default_tls = TlsConfig::default();
+            let plain_http = section.string("plain_http", "serve".to_owned());
+            let plain_http = plain_http.parse::<PlainHttp>().unwrap_or_else(|e| {
+                section.errors.push(format!("tls.plain_http: {}", e));
+                default_tls.plain_http
+            });
+            TlsConfig {
+                enabled: section.boolean("enabled", default_tls.enabled),
+                port: section.integer("port", default_tls.port),
+                cert: section.string("cert", default_tls.cert),
+                key: section.string("key", default_tls.key),
+                plain_http,
+                hsts: section.boolean("hsts", default_tls.hsts),
+                hsts_max_age: section.integer("hsts_max_age", default_tls.hsts_max_age),
+            }
+        });
+
+        BlogConfig {
+            profile, address, port, site_url, workers, log, log_format, database_url, auto_migrate, allow_registration, trusted_proxies, templates_dir, static_dir,
+            metrics_token, cache_capacity, cache_ttl, cache_control, compression, tls, shutdown_timeout, jobs, media, robots, mail,
+        }
+    }
+
+    // semantic checks, the types are already checked while reading
+    fn validate(&self, errors: &mut Vec<String>) {
+        if self.database_url.is_empty() {
+            errors.push(format!("database_url is missing, set it in [{}] or {}DATABASE_URL", self.profile.name(), ENV_PREFIX));
+        }
+        #[cfg(feature = "sqlite")]
+        {
+            if self.database_url.starts_with("postgres://") || self.database_url.starts_with("postgresql://") {
+                errors.push(format!("this build uses sqlite, database_url should be a file path, but got {}", self.database_url));
+            }
+        }
+        if !self.site_url.is_empty() && !self.site_url.starts_with("http://") && !self.site_url.starts_with("https://") {
+            errors.push(format!("site_url should start with http:// or https://, but got {}", self.site_url));
+        }
+        if self.port.eq(&0) {
+            errors.push("port must not be 0".to_owned());
+        }
+        for proxy in self.trusted_proxies.iter() {
+            if blocklist::parse_range(proxy).is_none() {
+                errors.push(format!("trusted_proxies should be ip addresses or ranges like 10.0.0.0/8, but got {}", proxy));
+            }
+        }
+        if self.jobs.poll_interval.eq(&0) {
+            errors.push("jobs.poll_interval must be at least 1".to_owned());
+        }
+        if self.media.max_upload_size.eq(&0) {
+            errors.push("media.max_upload_size must be at least 1".to_owned());
+        }
+        let unknown_types: Vec<&String> = self.media.allowed_types.iter().filter(|t| !MEDIA_TYPES.contains(&t.as_str())).collect();
+        if !unknown_types.is_empty() {
+            errors.push(format!("media.allowed_types should be some of {:?}, but got {:?}", MEDIA_TYPES, unknown_types));
+        }
+        for path in self.robots.disallow.iter().chain(self.robots.allow.iter()) {
+            if !path.starts_with('/') {
+                errors.push(format!("the paths in robots should start with /, but got {}", path));
+            }
+        }
+        if self.mail.from.is_empty() {
+            errors.push("mail.from must not be empty".to_owned());
+        }
+        if self.mail.backend.eq(&MailBackend::Spool) && self.mail.spool_dir.is_empty() {
+            errors.push("mail.spool_dir must be set for the spool backend".to_owned());
+        }
+        if self.mail.backend.eq(&MailBackend::Smtp) {
+            if self.mail.smtp_host.is_empty() {
+                errors.push("mail.smtp_host must be set for the smtp backend".to_owned());
+            }
+            // the unsubscribe links go out to strangers, they must not be forgeable
+            if self.mail.secret.len() < 16 {
+                errors.push(format!("mail.secret must be at least 16 characters for the smtp backend, set it with {}MAIL__SECRET", ENV_PREFIX));
+            }
+        }
+        if self.workers.eq(&0) {
+            errors.push("workers must be at least 1".to_owned());
+        }
+        if !LOG_LEVELS.contains(&self.log.as_str()) {
+            errors.push(format!("log should be one of {:?}, but got {}", LOG_LEVELS, self.log));
+        }
+        if !Path::new(&self.templates_dir).is_dir() {
+            errors.push(format!("templates_dir {} is not a directory", self.templates_dir));
+        }
+        if !Path::new(&self.static_dir).is_dir() {
+            errors.push(format!("static_dir {} is not a directory", self.static_dir));
+        }
+        if self.tls.enabled {
+            if !Path::new(&self.tls.cert).is_file() {
+                errors.push(format!("tls.cert {} doesn't exist", self.tls.cert));
+            }
+            if !Path::new(&self.tls.key).is_file() {
+                errors.push(format!("tls.key {} doesn't exist", self.tls.key));
+            }
+            if self.tls.port.eq(&0) {
+                errors.push("tls.port must not be 0".to_owned());
+            }
+            if self.tls.port.eq(&self.port) && self.tls.plain_http.ne(&PlainHttp::Off) {
+                errors.push("tls.port must differ from port while the plain listener is on".to_owned());
+            }
+        } else if self.tls.plain_http.ne(&PlainHttp::Serve) {
+            errors.push("tls.plain_http must be serve when tls is disabled, or nothing will be listening".to_owned());
+        }
+    }
+}
+
+// deep merge, the values of overlay win
+fn merge(base: &mut toml::value::Table, overlay: &toml::value::Table) {
+    for (key, value) in overlay.iter() {
+        match (base.get_mut(key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(overlay_table)) => merge(base_table, overlay_table),
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+// ACTIX_BLOG_PORT=80 => port = 80, ACTIX_BLOG_TLS__CERT=a.pem => tls.cert = "a.pem".
+// returns the values as they were given by the dotted keys, for the string settings which look like numbers
+pub(crate) fn apply_env_overrides(table: &mut toml::value::Table, vars: impl Iterator<Item = (String, String)>) -> HashMap<String, String> {
+    let mut raw_env = HashMap::new();
+    for (name, raw) in vars {
+        let key = match name.strip_prefix(ENV_PREFIX) {
+            Some(key) if name.ne(PROFILE_VAR) && name.ne(CONFIG_VAR) && !key.is_empty() => key.to_lowercase(),
+            _ => continue,
+        };
+
+        // a number or a boolean keeps its type, everything else is a string
+        let value = format!("value = {}", raw).parse::<toml::Value>().ok()
+                        .and_then(|parsed| parsed.get("value").cloned())
+                        .filter(|value| !value.is_table())
+                        .unwrap_or_else(|| toml::Value::String(raw.clone()));
+
+        raw_env.insert(key.replace("__", "."), raw.clone());
+
+        let mut path: Vec<&str> = key.split("__").collect();
+        let last = path.pop().unwrap_or_default();
+        let mut current = &mut *table;
+        for section in path {
+            let entry = current.entry(section.to_owned()).or_insert_with(|| toml::Value::Table(toml::value::Table::new()));
+            if !entry.is_table() {
+                *entry = toml::Value::Table(toml::value::Table::new());
+            }
+            current = match entry {
+                toml::Value::Table(table) => table,
+                _ => unreachable!("it has just been replaced by a table."),
+            };
+        }
+        current.insert(last.to_owned(), value);
+    }
+    raw_env
+}
+
+// reads typed values from a table, a wrong type is recorded instead of returned
+struct Reader<'a> {
+    table: &'a toml::value::Table,
+    section: &'a str,
+    raw_env: &'a HashMap<String, String>,
+    errors: &'a mut Vec<String>,
+}
+
+impl<'a> Reader<'a> {
+    fn key_name(&self, key: &str) -> String {
+        if self.section.is_empty() { key.to_owned() } else { format!("{}.{}", self.section, key) }
+    }
+
+    fn string(&mut self, key: &str, default: String) -> String {
+        let table = self.table;
+        match table.get(key) {
+            None => default,
+            Some(toml::Value::String(s)) => s.clone(),
+            Some(other) => match self.raw_env.get(&self.key_name(key)) {
+                // ACTIX_BLOG_MAIL__SMTP_PASSWORD=123456 is still a password
+                Some(raw) => raw.clone(),
+                None => {
+                    self.errors.push(format!("{} should be a string, but got {}", self.key_name(key), other));
+                    default
+                }
+            },
+        }
+    }
+
+    fn integer<T: TryFrom<i64>>(&mut self, key: &str, default: T) -> T {
+        let table = self.table;
+        match table.get(key) {
+            None => default,
+            Some(toml::Value::Integer(i)) => T::try_from(*i).unwrap_or_else(|_| {
+                self.errors.push(format!("{} = {} is out of range", self.key_name(key), i));
+                default
+            }),
+            Some(other) => {
+                self.errors.push(format!("{} should be an integer, but got {}", self.key_name(key), other));
+                default
+            }
+        }
+    }
+
+    fn strings(&mut self, key: &str, default: Vec<String>) -> Vec<String> {
+        let table = self.table;
+        match table.get(key) {
+            None => default,
+            Some(toml::Value::Array(values)) if values.iter().all(toml::Value::is_str) => {
+                values.iter().filter_map(|v| v.as_str().map(str::to_owned)).collect()
+            }
+            // a comma separated string, what an env variable can hold
+            Some(toml::Value::String(s)) => s.split(',').map(|v| v.trim().to_owned()).filter(|v| !v.is_empty()).collect(),
+            Some(other) => {
+                self.errors.push(format!("{} should be an array of strings, but got {}", self.key_name(key), other));
+                default
+            }
+        }
+    }
+
+    fn boolean(&mut self, key: &str, default: bool) -> bool {
+        let table = self.table;
+        match table.get(key) {
+            None => default,
+            Some(toml::Value::Boolean(b)) => *b,
+            Some(other) => {
+                self.errors.push(format!("{} should be a boolean, but got {}", self.key_name(key), other));
+                default
+            }
+        }
+    }
+
+    fn section<'b>(&'b mut self, name: &'b str) -> Option<Reader<'b>> {
+        let table = self.table;
+        match table.get(name) {
+            None => None,
+            Some(toml::Value::Table(table)) => Some(Reader { table, section: name, raw_env: self.raw_env, errors: &mut *self.errors }),
+            Some(other) => {
+                self.errors.push(format!("{} should be a table, but got {}", name, other));
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use actix_web::{ HttpRequest, HttpResponse, http::header::{ self, HeaderValue, HttpDate } };
+use chrono::NaiveDateTime;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{ Mutex, RwLock };
+use std::time::{ Duration, SystemTime, UNIX_EPOCH };
+
+// names of the routes that have a cache policy
+pub(crate) const INDEX: &str = "index";
+pub(crate) const ARTICLE: &str = "article";
+pub(crate) const ALL_POSTS: &str = "all_posts";
+pub(crate) const CATEGORY: &str = "category";
+pub(crate) const STATIC_PAGE: &str = "static_page";
+pub(crate) const STATIC_FILES: &str = "static_files";
+pub(crate) const SITEMAP: &str = "sitemap";
+
+const DEFAULT_POLICY: &str = "no-cache";
+
+lazy_static! {
+    static ref CACHE_POLICIES: RwLock<HashMap<String, String>> = {
+        let mut policies = HashMap::new();
+        policies.insert(INDEX.to_owned(), "public, max-age=60".to_owned());
+        // the article page sets a session cookie, don't let a shared cache keep it
+        policies.insert(ARTICLE.to_owned(), "private, no-cache".to_owned());
+        policies.insert(ALL_POSTS.to_owned(), "public, max-age=60".to_owned());
+        policies.insert(CATEGORY.to_owned(), "public, max-age=300".to_owned());
+        policies.insert(STATIC_PAGE.to_owned(), "public, max-age=3600".to_owned());
+        policies.insert(SITEMAP.to_owned(), "public, max-age=3600".to_owned());
+        // urls of static files are fingerprinted, so they never change
+        policies.insert(STATIC_FILES.to_owned(), "public, max-age=31536000, immutable".to_owned());
+        RwLock::new(policies)
+    };
+
+    static ref FINGERPRINTS: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+}
+
+// override the default policies with the ones from config file
+pub(crate) fn set_policies(policies: HashMap<String, String>) {
+    let mut current = CACHE_POLICIES.write().unwrap_or_else(|e| e.into_inner());
+    current.extend(policies);
+}
+
+pub(crate) fn policy(route: &str) -> String {
+    let policies = CACHE_POLICIES.read().unwrap_or_else(|e| e.into_inner());
+    policies.get(route).map_or_else(|| DEFAULT_POLICY.to_owned(), Clone::clone)
+}
+
+// 64 bits FNV-1a, it's stable between builds unlike the DefaultHasher of std.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3))
+}
+
+// weak, the tag is of the rendered page but the bytes sent may be gzip or br encoded by Compress
+pub(crate) fn weak_etag(body: &str) -> String {
+    format!("W/\"{:016x}-{:x}\"", fnv1a(body.as_bytes()), body.len())
+}
+
+fn to_system_time(time: &NaiveDateTime) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(time.timestamp().max(0) as u64)
+}
+
+fn etag_matches(req: &HttpRequest, etag: &str) -> Option<bool> {
+    let if_none_match = req.headers().get(header::IF_NONE_MATCH)?.to_str().ok()?;
+    let matched = if_none_match.split(',').map(str::trim).any(|tag| {
+        // a weak comparison is allowed for If-None-Match
+        tag.eq("*") || tag.trim_start_matches("W/").eq(etag.trim_start_matches("W/"))
+    });
+    Some(matched)
+}
+
+fn not_modified_since(req: &HttpRequest, last_modified: &NaiveDateTime) -> Option<bool> {
+    let if_modified_since = req.headers().get(header::IF_MODIFIED_SINCE)?.to_str().ok()?;
+    let since: SystemTime = HttpDate::from_str(if_modified_since).ok()?.into();
+    Some(to_system_time(last_modified).le(&since))
+}
+
+// build a html response with ETag/Last-Modified/Cache-Control, or 304 if the client already has it.
+pub(crate) fn conditional_html(req: &HttpRequest, route: &str, body: String, last_modified: Option<NaiveDateTime>) -> HttpResponse {
+    conditional_response(req, route, "text/html", body, last_modified)
+}
+
+pub(crate) fn conditional_response(
+    req: &HttpRequest,
+    route: &str,
+    content_type: &str,
+    body: String,
+    last_modified: Option<NaiveDateTime>
+) -> HttpResponse {
+    let etag = weak_etag(&body);
+
+    // If-Modified-Since must be ignored when If-None-Match is present
+    let not_modified = match etag_matches(req, &etag) {
+        Some(matched) => matched,
+        None => last_modified.as_ref().and_then(|time| not_modified_since(req, time)).unwrap_or(false),
+    };
+
+    let mut builder = if not_modified { HttpResponse::NotModified() } else { HttpResponse::Ok() };
+    builder.header(header::ETAG, etag.as_str())
+           .header(header::CACHE_CONTROL, policy(route))
+           .header(header::VARY, "Accept-Encoding");
+    if let Some(time) = last_modified.as_ref() {
+        builder.header(header::LAST_MODIFIED, HttpDate::from(to_system_time(time)).to_string());
+    }
+
+    if not_modified {
+        builder.finish()
+    } else {
+        builder.content_type(content_type).body(body)
+    }
+}
+
+pub(crate) fn static_files_policy() -> HeaderValue {
+    HeaderValue::from_str(&policy(STATIC_FILES)).unwrap_or_else(|_| HeaderValue::from_static(DEFAULT_POLICY))
+}
+
+// tera function, {{ static_url(path='css/base.css') }} => /static/css/base.css?v=fingerprint
+pub(crate) fn static_url(args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+    let path = args.get("path").and_then(|p| p.as_str())
+                   .ok_or_else(|| tera::Error::msg("static_url requires a path argument"))?;
+
+    let mut fingerprints = FINGERPRINTS.lock().unwrap_or_else(|e| e.into_inner());
+    if !fingerprints.contains_key(path) {
+        let file = std::path::Path::new(&super::config::current().static_dir).join(path);
+        let contents = std::fs::read(&file).map_err(|e| tera::Error::msg(format!("cannot read {}: {}", path, e)))?;
+        fingerprints.insert(path.to_owned(), format!("{:016x}", fnv1a(&contents)));
+    }
+
+    Ok(tera::Value::String(format!("/static/{}?v={}", path, fingerprints[path])))
+}
@@ -0,0 +1,108 @@
+use actix_identity::RequestIdentity;
+use actix_web::{ dev::{ ServiceRequest, ServiceResponse }, http::header::{ HeaderName, HeaderValue }, HttpMessage };
+use chrono::Utc;
+use serde_json::{ json, Value };
+use std::io::Write;
+use std::time::Duration;
+
+use super::{ metrics, utils::random_token };
+
+pub(crate) const REQUEST_ID_HEADER: &str = "x-request-id";
+const REQUEST_ID_LENGTH: usize = 16;
+const MAX_REQUEST_ID_LENGTH: usize = 64;
+
+// what the log lines look like
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum LogFormat {
+    Json, // one json object per line, for the log pipeline
+    Text, // human readable, for development
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(LogFormat::Json),
+            "text" => Ok(LogFormat::Text),
+            _ => Err(failure::format_err!("log_format should be one of json/text, but got {}", s)),
+        }
+    }
+}
+
+// the id of the current request, stored in the request extensions
+#[derive(Debug, Clone)]
+pub(crate) struct RequestId(pub(crate) String);
+
+// the modules of the blog log under the name of the crate they're built into, main for the binary.
+// actix_blog is the target of the access and error lines.
+pub(crate) fn filters(level: &str) -> String {
+    let crate_root = module_path!().split("::").next().unwrap_or_default();
+    format!("{}={},actix_blog={},actix_server={},actix_web={}", crate_root, level, level, level, level)
+}
+
+// a message which is a json object is nested as an object instead of being escaped as a string,
+// but never merged, so it can't overwrite the level or the timestamp
+pub(crate) fn json_line(level: log::Level, target: &str, message: &str) -> Value {
+    let message = match serde_json::from_str::<Value>(message) {
+        Ok(fields @ Value::Object(_)) => fields,
+        _ => Value::String(message.to_owned()),
+    };
+    json!({
+        "timestamp": Utc::now().to_rfc3339(),
+        "level": level.to_string(),
+        "target": target,
+        "message": message,
+    })
+}
+
+pub(crate) fn init(level: &str, format: LogFormat) {
+    let mut builder = env_logger::Builder::new();
+    builder.parse_filters(&filters(level));
+    if format.eq(&LogFormat::Json) {
+        builder.format(|buf, record| {
+            writeln!(buf, "{}", json_line(record.level(), record.target(), &record.args().to_string()))
+        });
+    }
+    builder.init();
+}
+
+// a forwarded id is kept only if it looks sane, so a client cannot inject anything into the logs
+fn valid_request_id(id: &str) -> bool {
+    !id.is_empty() && id.len().le(&MAX_REQUEST_ID_LENGTH)
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || c.eq(&'-') || c.eq(&'_'))
+}
+
+// propagate X-Request-Id from a proxy, or generate a new one
+pub(crate) fn assign_request_id(req: &ServiceRequest) -> String {
+    let forwarded = req.headers().get(REQUEST_ID_HEADER).and_then(|id| id.to_str().ok()).filter(|id| valid_request_id(id));
+    let id = forwarded.map_or_else(|| random_token(REQUEST_ID_LENGTH), str::to_owned);
+    req.extensions_mut().insert(RequestId(id.clone()));
+    id
+}
+
+// the access log line, and the error behind an error response with the same request id
+pub(crate) fn log_response<B>(res: &mut ServiceResponse<B>, request_id: &str, latency: Duration) {
+    if let Ok(value) = HeaderValue::from_str(request_id) {
+        res.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+
+    let status = res.status().as_u16();
+    let route = metrics::route_label(res.request(), status);
+    let user = res.request().get_identity();
+    let access = json!({
+        "request_id": request_id,
+        "method": res.request().method().as_str(),
+        "path": res.request().path(),
+        "route": route,
+        "status": status,
+        "latency_ms": latency.as_secs_f64() * 1000.0,
+        "user": user,
+    });
+    log::info!(target: "actix_blog::access", "{}", access);
+
+    if let Some(error) = res.response().error() {
+        let failure = json!({ "request_id": request_id, "route": route, "status": status, "error": error.to_string() });
+        log::error!(target: "actix_blog::error", "{}", failure);
+    }
+}
@@ -0,0 +1,224 @@
+use actix_web::web::Data;
+use chrono::Utc;
+use failure::Fail;
+use hmac::{ Hmac, Mac };
+use lettre::{ smtp::{ authentication::Credentials, error::Error as SmtpError }, ClientSecurity, ClientTlsParameters, SmtpClient, Transport };
+use lettre_email::EmailBuilder;
+use native_tls::TlsConnector;
+use serde_derive::{ Deserialize, Serialize };
+use sha2::Sha256;
+use std::fs;
+use std::path::Path;
+
+use crate::jobs::SEND_MAIL;
+use crate::models::job::{ JobOperation, NewJob };
+use super::{ config, utils::{ random_token, render_template, DbConnection, DbPool } };
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Mail {
+    pub(crate) to: String,
+    pub(crate) subject: String,
+    pub(crate) body: String,
+    // where the recipient stops these mails, sent as List-Unsubscribe too
+    #[serde(default)]
+    pub(crate) unsubscribe: Option<String>,
+}
+
+// the server refused the mail for good, sending it again won't help
+#[derive(Debug, Fail, PartialEq)]
+pub(crate) enum MailError {
+    #[fail(display = "the mail to {} was rejected: {}", _0, _1)]
+    Rejected(String, String),
+}
+
+// where the outgoing mails go
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum MailBackend {
+    Log, // written to the log, so an admin can forward them by hand
+    Spool, // a .eml file for each mail in spool_dir, for local testing
+    Smtp,
+}
+
+impl std::str::FromStr for MailBackend {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "log" => Ok(MailBackend::Log),
+            "spool" => Ok(MailBackend::Spool),
+            "smtp" => Ok(MailBackend::Smtp),
+            _ => Err(failure::format_err!("unknown mail backend {}, use log/spool/smtp", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SmtpSecurity {
+    StartTls, // upgrades a plain connection, port 587 usually
+    Tls, // tls from the start, port 465 usually
+    None, // a relay on localhost only
+}
+
+impl std::str::FromStr for SmtpSecurity {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "starttls" => Ok(SmtpSecurity::StartTls),
+            "tls" => Ok(SmtpSecurity::Tls),
+            "none" => Ok(SmtpSecurity::None),
+            _ => Err(failure::format_err!("unknown smtp security {}, use starttls/tls/none", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct MailConfig {
+    pub(crate) backend: MailBackend,
+    pub(crate) from: String,
+    pub(crate) spool_dir: String,
+    pub(crate) smtp_host: String,
+    pub(crate) smtp_port: u16,
+    pub(crate) smtp_security: SmtpSecurity,
+    pub(crate) smtp_user: String,
+    pub(crate) smtp_password: String,
+    pub(crate) secret: String, // signs the unsubscribe links
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        MailConfig {
+            backend: MailBackend::Log,
+            from: "actix-blog@localhost".to_owned(),
+            spool_dir: "mail_spool".to_owned(),
+            smtp_host: String::new(),
+            smtp_port: 587,
+            smtp_security: SmtpSecurity::StartTls,
+            smtp_user: String::new(),
+            smtp_password: String::new(),
+            secret: String::new(),
+        }
+    }
+}
+
+// a mail from a template under mail/, its first line is the subject and the rest is the body
+pub(crate) fn render_mail(template: &str, ctx: &tera::Context, to: &str) -> Result<Mail, failure::Error> {
+    let rendered = render_template(template, ctx)?;
+    let mut lines = rendered.splitn(2, '\n');
+    let subject = lines.next().unwrap_or_default().trim().to_owned();
+    let body = lines.next().unwrap_or_default().trim().to_owned();
+    if subject.is_empty() {
+        return Err(failure::format_err!("the first line of {} should be the subject", template));
+    }
+    Ok(Mail { to: to.to_owned(), subject, body, unsubscribe: None })
+}
+
+// the text of the mail as it goes over the wire, with crlf line endings.
+// a line break in a header would start another header, such a mail is refused.
+pub(crate) fn format_mail(mail: &Mail, from: &str) -> Result<String, failure::Error> {
+    let mut header_values = vec![from, mail.to.as_str(), mail.subject.as_str()];
+    header_values.extend(mail.unsubscribe.as_deref());
+    if header_values.iter().any(|value| value.contains(|c| c == '\r' || c == '\n')) {
+        return Err(failure::format_err!("a line break in the headers of the mail to {:?}", mail.to));
+    }
+    let mut headers = vec![
+        format!("From: {}", from),
+        format!("To: {}", mail.to),
+        format!("Subject: {}", mail.subject),
+        format!("Date: {}", Utc::now().to_rfc2822()),
+        "Content-Type: text/plain; charset=utf-8".to_owned(),
+    ];
+    if let Some(link) = &mail.unsubscribe {
+        headers.push(format!("List-Unsubscribe: <{}>", link));
+        headers.push("List-Unsubscribe-Post: List-Unsubscribe=One-Click".to_owned());
+    }
+    Ok(format!("{}\r\n\r\n{}\r\n", headers.join("\r\n"), mail.body.replace("\r\n", "\n").replace('\n', "\r\n")))
+}
+
+// written aside first, so whatever reads the spool never sees half a mail
+fn spool_mail(mail: &Mail, mail_config: &MailConfig) -> Result<(), failure::Error> {
+    let spool_dir = Path::new(&mail_config.spool_dir);
+    fs::create_dir_all(spool_dir)?;
+    let name = format!("{}-{}.eml", Utc::now().format("%Y%m%d%H%M%S%f"), random_token(8));
+    let part = spool_dir.join(format!("{}.part", name));
+    fs::write(&part, format_mail(mail, &mail_config.from)?)?;
+    fs::rename(&part, spool_dir.join(name))?;
+    Ok(())
+}
+
+fn smtp_mail(mail: &Mail, mail_config: &MailConfig) -> Result<(), failure::Error> {
+    let mut builder = EmailBuilder::new().to(mail.to.as_str())
+                                         .from(mail_config.from.as_str())
+                                         .subject(mail.subject.as_str())
+                                         .text(mail.body.as_str());
+    if let Some(link) = &mail.unsubscribe {
+        builder = builder.header(("List-Unsubscribe", format!("<{}>", link)))
+                         .header(("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"));
+    }
+    let email = builder.build().map_err(|e| failure::err_msg(e.to_string()))?;
+
+    let tls_parameters = || -> Result<ClientTlsParameters, failure::Error> {
+        Ok(ClientTlsParameters::new(mail_config.smtp_host.clone(), TlsConnector::builder().build()?))
+    };
+    let security = match mail_config.smtp_security {
+        SmtpSecurity::StartTls => ClientSecurity::Required(tls_parameters()?),
+        SmtpSecurity::Tls => ClientSecurity::Wrapper(tls_parameters()?),
+        SmtpSecurity::None => ClientSecurity::None,
+    };
+    let mut client = SmtpClient::new((mail_config.smtp_host.as_str(), mail_config.smtp_port), security)?;
+    if !mail_config.smtp_user.is_empty() {
+        client = client.credentials(Credentials::new(mail_config.smtp_user.clone(), mail_config.smtp_password.clone()));
+    }
+    match client.transport().send(email.into()) {
+        Ok(_) => Ok(()),
+        // 55x is about the mailbox, other permanent errors like a failed login aren't the recipient's fault
+        Err(SmtpError::Permanent(ref response)) if response.code.to_string().starts_with("55") => {
+            Err(MailError::Rejected(mail.to.clone(), response.message.join(" ")).into())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub(crate) fn send_mail(mail: &Mail) -> Result<(), failure::Error> {
+    let mail_config = config::current().mail.clone();
+    match mail_config.backend {
+        MailBackend::Log => {
+            log::info!("mail to {}, subject: {}\n{}", mail.to, mail.subject, mail.body);
+            Ok(())
+        }
+        MailBackend::Spool => spool_mail(mail, &mail_config),
+        MailBackend::Smtp => smtp_mail(mail, &mail_config),
+    }
+}
+
+// sent by the job runner, so a failed delivery is retried instead of failing the request
+pub(crate) fn queue_mail(mail: &Mail, pool: &Data<DbPool>) -> Result<(), failure::Error> {
+    let new_job = NewJob::new(SEND_MAIL, serde_json::to_string(mail)?, Utc::now().naive_utc());
+    JobOperation::enqueue(&new_job, pool).map(|_| ())
+}
+
+// within the transaction of conn, see JobOperation::enqueue_on
+pub(crate) fn queue_mail_on(mail: &Mail, conn: &DbConnection) -> Result<(), failure::Error> {
+    let new_job = NewJob::new(SEND_MAIL, serde_json::to_string(mail)?, Utc::now().naive_utc());
+    JobOperation::enqueue_on(&new_job, conn).map(|_| ())
+}
+
+pub(crate) async fn queue_mail_async(mail: Mail, pool: &Data<DbPool>) -> Result<(), failure::Error> {
+    let new_job = NewJob::new(SEND_MAIL, serde_json::to_string(&mail)?, Utc::now().naive_utc());
+    JobOperation::enqueue_async(new_job, pool).await.map(|_| ())
+}
+
+// hmac-sha256 of the message with mail.secret, in hex
+pub(crate) fn sign(message: &str) -> String {
+    let secret = config::current().mail.secret.clone();
+    let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes()).expect("hmac takes a key of any length");
+    mac.input(message.as_bytes());
+    mac.result().code().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// compares every byte, so the time taken doesn't tell how much of the signature was right
+pub(crate) fn verify(message: &str, signature: &str) -> bool {
+    let expected = sign(message);
+    expected.len().eq(&signature.len())
+        && expected.bytes().zip(signature.bytes()).fold(0, |diff, (lhs, rhs)| diff | (lhs ^ rhs)).eq(&0)
+}
@@ -0,0 +1,262 @@
+use failure::Fail;
+use image::{ imageops::FilterType, DynamicImage, GenericImageView, ImageFormat, ImageOutputFormat };
+use serde_derive::Serialize;
+use sha2::{ Digest, Sha256 };
+use std::io::Cursor;
+use std::path::Path;
+
+use crate::models::media::Media;
+
+// where the uploads are served from
+pub(crate) const MEDIA_URL: &str = "/media";
+// the responsive widths, an image is only scaled down
+pub(crate) const RESPONSIVE_WIDTHS: [u32; 3] = [320, 640, 1280];
+const THUMBNAIL_SIZE: u32 = 200;
+const JPEG_QUALITY: u8 = 85;
+
+#[derive(Debug, Clone)]
+pub(crate) struct MediaConfig {
+    pub(crate) dir: String,
+    pub(crate) max_upload_size: usize, // bytes
+    pub(crate) max_dimension: u32, // pixels of the longer side, refuses decompression bombs
+    pub(crate) allowed_types: Vec<String>, // jpeg/png/gif
+}
+
+impl Default for MediaConfig {
+    fn default() -> Self {
+        MediaConfig {
+            dir: "media".to_owned(),
+            max_upload_size: 5 * 1024 * 1024,
+            max_dimension: 8000,
+            allowed_types: vec!["jpeg".to_owned(), "png".to_owned(), "gif".to_owned()],
+        }
+    }
+}
+
+#[derive(Debug, Fail, PartialEq)]
+pub(crate) enum MediaError {
+    #[fail(display = "the upload is larger than {} bytes", _0)]
+    TooLarge(usize),
+    #[fail(display = "only {} images are accepted", _0)]
+    UnsupportedType(String),
+    #[fail(display = "the image is larger than {}x{} pixels", _0, _0)]
+    TooManyPixels(u32),
+    #[fail(display = "the upload is not a valid image: {}", _0)]
+    InvalidImage(String),
+}
+
+// the formats which can be both decoded and encoded, named like in the config
+fn format_name(format: ImageFormat) -> Option<&'static str> {
+    match format {
+        ImageFormat::Jpeg => Some("jpeg"),
+        ImageFormat::Png => Some("png"),
+        ImageFormat::Gif => Some("gif"),
+        _ => None,
+    }
+}
+
+fn extension(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Jpeg => "jpg",
+        ImageFormat::Gif => "gif",
+        _ => "png",
+    }
+}
+
+fn output_format(format: ImageFormat) -> ImageOutputFormat {
+    match format {
+        ImageFormat::Jpeg => ImageOutputFormat::Jpeg(JPEG_QUALITY),
+        ImageFormat::Gif => ImageOutputFormat::Gif,
+        _ => ImageOutputFormat::Png,
+    }
+}
+
+// the type is sniffed from the content, the file name and Content-Type of the client aren't trusted
+pub(crate) fn detect_format(bytes: &[u8], media_config: &MediaConfig) -> Result<ImageFormat, MediaError> {
+    let allowed = media_config.allowed_types.join("/");
+    let format = image::guess_format(bytes).map_err(|_| MediaError::UnsupportedType(allowed.clone()))?;
+    match format_name(format) {
+        Some(name) if media_config.allowed_types.iter().any(|t| t.eq(name)) => Ok(format),
+        _ => Err(MediaError::UnsupportedType(allowed)),
+    }
+}
+
+// <sha256 of the upload>.<ext>, the same image uploaded twice is stored once
+pub(crate) fn content_file_name(bytes: &[u8], format: ImageFormat) -> String {
+    format!("{:x}.{}", Sha256::digest(bytes), extension(format))
+}
+
+// hash.jpg => hash-640.jpg, hash-thumb.jpg
+pub(crate) fn variant_file_name(file_name: &str, variant: &str) -> String {
+    match file_name.rfind('.') {
+        Some(dot) => format!("{}-{}{}", &file_name[..dot], variant, &file_name[dot..]),
+        None => format!("{}-{}", file_name, variant),
+    }
+}
+
+// what process_image wrote to the media dir
+#[derive(Debug, Clone)]
+pub(crate) struct StoredImage {
+    pub(crate) file_name: String,
+    pub(crate) content_type: String,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) size: usize,
+    pub(crate) variants: Vec<u32>,
+}
+
+fn encode(image: &DynamicImage, format: ImageFormat) -> Result<Vec<u8>, failure::Error> {
+    let mut encoded = Vec::new();
+    image.write_to(&mut encoded, output_format(format))?;
+    Ok(encoded)
+}
+
+// the Orientation tag in the EXIF of a jpeg, how the camera was held.
+// the segments before the image data are walked, the tag is in the first IFD.
+fn exif_orientation(bytes: &[u8]) -> Option<u16> {
+    let mut pos = 2;
+    while bytes.get(pos).eq(&Some(&0xFF)) {
+        let marker = *bytes.get(pos + 1)?;
+        let length = usize::from(u16::from_be_bytes([*bytes.get(pos + 2)?, *bytes.get(pos + 3)?]));
+        // start of scan, no more metadata
+        if marker.eq(&0xDA) {
+            return None;
+        }
+        let segment = bytes.get(pos + 4..pos + 2 + length)?;
+        if marker.eq(&0xE1) && segment.starts_with(b"Exif\0\0") {
+            return tiff_orientation(&segment[6..]);
+        }
+        pos += 2 + length;
+    }
+    None
+}
+
+fn tiff_orientation(tiff: &[u8]) -> Option<u16> {
+    let big_endian = match tiff.get(0..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let u16_at = |at: usize| tiff.get(at..at + 2).map(|b| if big_endian { u16::from_be_bytes([b[0], b[1]]) } else { u16::from_le_bytes([b[0], b[1]]) });
+    let u32_at = |at: usize| tiff.get(at..at + 4).map(|b| {
+        let b = [b[0], b[1], b[2], b[3]];
+        if big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) }
+    });
+
+    let ifd = u32_at(4)? as usize;
+    (0..usize::from(u16_at(ifd)?)).map(|i| ifd + 2 + i * 12)
+                                   .find(|entry| u16_at(*entry).eq(&Some(0x0112)))
+                                   .and_then(|entry| u16_at(entry + 8))
+}
+
+// turn the pixels the way a viewer would turn them, as the tag is dropped with the rest of EXIF
+fn apply_orientation(image: DynamicImage, orientation: u16) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+// write to a temp file first, so a half written image is never served
+fn write_file(dir: &Path, file_name: &str, bytes: &[u8]) -> Result<(), failure::Error> {
+    let temp = dir.join(format!(".{}.tmp", file_name));
+    std::fs::write(&temp, bytes)?;
+    std::fs::rename(&temp, dir.join(file_name))?;
+    Ok(())
+}
+
+// decode and re-encode the upload, which drops EXIF(gps, camera serial...) and any other metadata,
+// a jpeg is turned upright by its EXIF orientation before that.
+// then write the original size, the thumbnail and the responsive widths smaller than the original.
+// an animated gif keeps its first frame only.
+pub(crate) fn process_image(bytes: &[u8], media_config: &MediaConfig) -> Result<StoredImage, failure::Error> {
+    if bytes.len().gt(&media_config.max_upload_size) {
+        return Err(MediaError::TooLarge(media_config.max_upload_size).into());
+    }
+    let format = detect_format(bytes, media_config)?;
+
+    // the header is checked before decoding, a tiny file can claim a huge canvas
+    let (width, height) = image::io::Reader::with_format(Cursor::new(bytes), format).into_dimensions()
+                              .map_err(|e| MediaError::InvalidImage(e.to_string()))?;
+    if width.max(height).gt(&media_config.max_dimension) {
+        return Err(MediaError::TooManyPixels(media_config.max_dimension).into());
+    }
+    let image = image::load_from_memory_with_format(bytes, format).map_err(|e| MediaError::InvalidImage(e.to_string()))?;
+    let image = match format {
+        ImageFormat::Jpeg => apply_orientation(image, exif_orientation(bytes).unwrap_or(1)),
+        _ => image,
+    };
+
+    let dir = Path::new(&media_config.dir);
+    std::fs::create_dir_all(dir)?;
+    let file_name = content_file_name(bytes, format);
+
+    let stripped = encode(&image, format)?;
+    write_file(dir, &file_name, &stripped)?;
+
+    let thumbnail = image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+    write_file(dir, &variant_file_name(&file_name, "thumb"), &encode(&thumbnail, format)?)?;
+
+    let variants: Vec<u32> = RESPONSIVE_WIDTHS.iter().copied().filter(|w| w.lt(&image.width())).collect();
+    for variant_width in variants.iter() {
+        let resized = image.resize(*variant_width, u32::max_value(), FilterType::Lanczos3);
+        write_file(dir, &variant_file_name(&file_name, &variant_width.to_string()), &encode(&resized, format)?)?;
+    }
+
+    Ok(StoredImage {
+        file_name,
+        content_type: format!("image/{}", format_name(format).unwrap_or("png")),
+        width: image.width(),
+        height: image.height(),
+        size: stripped.len(),
+        variants,
+    })
+}
+
+// everything a template needs to show or insert an image
+#[derive(Debug, Serialize)]
+pub(crate) struct MediaLinks {
+    pub(crate) id: i32,
+    pub(crate) original_name: String,
+    pub(crate) width: i32,
+    pub(crate) height: i32,
+    pub(crate) url: String,
+    pub(crate) thumbnail_url: String,
+    pub(crate) srcset: String,
+    pub(crate) markdown: String,
+}
+
+impl From<&Media> for MediaLinks {
+    fn from(media: &Media) -> Self {
+        let url = format!("{}/{}", MEDIA_URL, media.file_name);
+        let widths = media.variant_widths();
+        let srcset = widths.iter()
+                           .map(|w| format!("{}/{} {}w", MEDIA_URL, variant_file_name(&media.file_name, &w.to_string()), w))
+                           .chain(std::iter::once(format!("{} {}w", url, media.width)))
+                           .collect::<Vec<_>>()
+                           .join(", ");
+        // a post links the largest variant, the original may be far too heavy for a page
+        let post_url = widths.last().map_or_else(
+            || url.clone(),
+            |w| format!("{}/{}", MEDIA_URL, variant_file_name(&media.file_name, &w.to_string()))
+        );
+        let alt = media.original_name.rsplitn(2, '.').last().unwrap_or_default().replace(|c| c == '[' || c == ']', "");
+
+        MediaLinks {
+            id: media.id,
+            original_name: media.original_name.clone(),
+            width: media.width,
+            height: media.height,
+            thumbnail_url: format!("{}/{}", MEDIA_URL, variant_file_name(&media.file_name, "thumb")),
+            markdown: format!("![{}]({})", alt, post_url),
+            url,
+            srcset,
+        }
+    }
+}
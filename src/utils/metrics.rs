@@ -0,0 +1,164 @@
+use actix_web::{ dev::ResourceDef, HttpRequest };
+use lazy_static::lazy_static;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::media::MEDIA_URL;
+
+// upper bounds in seconds, the same as the default buckets of the prometheus clients
+const BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()], // not cumulative, summed up when exported
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(i) = BUCKETS.iter().position(|bound| seconds.le(bound)) {
+            self.buckets[i] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+
+    fn export(&self, out: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(self.buckets.iter()) {
+            cumulative += count;
+            let _ = writeln!(out, "{}_bucket{{{},le=\"{}\"}} {}", name, labels, bound, cumulative);
+        }
+        let _ = writeln!(out, "{}_bucket{{{},le=\"+Inf\"}} {}", name, labels, self.count);
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, self.count);
+    }
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    requests: BTreeMap<(String, String, u16), u64>, // (method, route, status) => count
+    latencies: BTreeMap<(String, String), Histogram>, // (method, route)
+    renders: BTreeMap<String, Histogram>, // template name
+}
+
+lazy_static! {
+    static ref REGISTRY: Mutex<Registry> = Mutex::new(Registry::default());
+}
+
+// values gathered when /metrics is scraped
+#[derive(Debug, Default)]
+pub(crate) struct Gauges {
+    pub(crate) pool_connections: u32,
+    pub(crate) pool_idle_connections: u32,
+    pub(crate) pool_max_size: u32,
+    pub(crate) posts: i64,
+    pub(crate) comments: i64,
+}
+
+// the routes with dynamic segments, as registered in main.rs with the scope prefixed.
+// keep it in sync when such a route is added, or its requests are counted as "other".
+const DYNAMIC_ROUTES: [&str; 15] = [
+    "/sitemap-{page}.xml",
+    "/admin/comments/{id}/",
+    "/admin/comments/{id}/delete/",
+    "/admin/blocklist/{id}/delete/",
+    "/admin/all_guests_messages/{id}/",
+    "/admin/all_guests_messages/{id}/reply/",
+    "/admin/verify_email/{token}/",
+    "/admin/{title}/",
+    "/subscribe/{id}/{expires}/{signature}/",
+    "/unsubscribe/{id}/{signature}/",
+    "/newsletter/confirm/{token}/",
+    "/newsletter/unsubscribe/{token}/",
+    "/page/{page_num}/",
+    "/article/{title}/",
+    "/category/{year}/",
+];
+
+lazy_static! {
+    static ref ROUTE_DEFS: Vec<ResourceDef> = DYNAMIC_ROUTES.iter().map(|pattern| ResourceDef::new(*pattern)).collect();
+}
+
+// the pattern instead of the actual path, otherwise every article would be a new series.
+// /article/hello/ => /article/{title}/
+pub(crate) fn route_label(req: &HttpRequest, status: u16) -> String {
+    let path = req.path();
+    if path.starts_with("/static/") {
+        return "/static".to_owned();
+    }
+    if path.starts_with(MEDIA_URL) && path[MEDIA_URL.len()..].starts_with('/') {
+        return MEDIA_URL.to_owned();
+    }
+    // without segments the path is one of the fixed routes, unless nothing matched
+    if req.match_info().iter().next().is_none() {
+        return if status.eq(&404) { "unmatched".to_owned() } else { path.to_owned() };
+    }
+    ROUTE_DEFS.iter()
+              .find(|route| route.is_match(path))
+              .map_or_else(|| "other".to_owned(), |route| route.pattern().to_owned())
+}
+
+// /metrics is off without a token, otherwise the scraper sends it as a bearer token
+pub(crate) fn scrape_allowed(token: &str, authorization: Option<&str>) -> bool {
+    let given = match authorization.and_then(|value| value.strip_prefix("Bearer ")) {
+        Some(given) => given.trim(),
+        None => return false,
+    };
+    !token.is_empty() && token.len().eq(&given.len())
+        && token.bytes().zip(given.bytes()).fold(0, |diff, (lhs, rhs)| diff | (lhs ^ rhs)).eq(&0)
+}
+
+pub(crate) fn record_request(method: &str, route: String, status: u16, duration: Duration) {
+    let mut registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+    *registry.requests.entry((method.to_owned(), route.clone(), status)).or_insert(0) += 1;
+    registry.latencies.entry((method.to_owned(), route)).or_default().observe(duration);
+}
+
+pub(crate) fn record_render(template: &str, duration: Duration) {
+    let mut registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+    registry.renders.entry(template.to_owned()).or_default().observe(duration);
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+// prometheus text exposition format 0.0.4
+pub(crate) fn export(gauges: &Gauges) -> String {
+    let registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+    let mut out = String::new();
+
+    out.push_str("# HELP http_requests_total Number of handled requests.\n# TYPE http_requests_total counter\n");
+    for ((method, route, status), count) in registry.requests.iter() {
+        let _ = writeln!(out, "http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}", method, escape(route), status, count);
+    }
+
+    out.push_str("# HELP http_request_duration_seconds Request latency.\n# TYPE http_request_duration_seconds histogram\n");
+    for ((method, route), histogram) in registry.latencies.iter() {
+        let labels = format!("method=\"{}\",route=\"{}\"", method, escape(route));
+        histogram.export(&mut out, "http_request_duration_seconds", &labels);
+    }
+
+    out.push_str("# HELP template_render_duration_seconds Time spent rendering tera templates.\n# TYPE template_render_duration_seconds histogram\n");
+    for (template, histogram) in registry.renders.iter() {
+        let labels = format!("template=\"{}\"", escape(template));
+        histogram.export(&mut out, "template_render_duration_seconds", &labels);
+    }
+
+    let gauges = [
+        ("db_pool_connections", "Connections opened by the pool.", i64::from(gauges.pool_connections)),
+        ("db_pool_idle_connections", "Idle connections in the pool.", i64::from(gauges.pool_idle_connections)),
+        ("db_pool_max_size", "Maximum size of the pool.", i64::from(gauges.pool_max_size)),
+        ("blog_posts", "Number of posts.", gauges.posts),
+        ("blog_comments", "Number of comments.", gauges.comments),
+    ];
+    for (name, help, value) in gauges.iter() {
+        let _ = writeln!(out, "# HELP {} {}\n# TYPE {} gauge\n{} {}", name, help, name, name, value);
+    }
+    out
+}
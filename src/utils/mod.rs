@@ -1,2 +1,16 @@
+pub(crate) mod analytics;
+pub(crate) mod blocklist;
+pub(crate) mod cache;
+pub(crate) mod config;
+pub(crate) mod http_cache;
+pub(crate) mod logging;
+pub(crate) mod mailer;
+pub(crate) mod media;
 pub(crate) mod macros;
+pub(crate) mod metrics;
+pub(crate) mod newsletter;
+pub(crate) mod notifications;
+pub(crate) mod seo;
+pub(crate) mod sitemap;
+pub(crate) mod tls;
 pub(crate) mod utils;
\ No newline at end of file
@@ -0,0 +1,164 @@
+use actix_web::web::Data;
+use chrono::{ Duration, Utc };
+use diesel::Connection;
+use serde_derive::{ Deserialize, Serialize };
+
+use crate::jobs::SEND_NEWSLETTER;
+use crate::models::job::{ JobOperation, NewJob };
+use crate::models::post::Post;
+use crate::models::subscriber::{ NewsletterIssue, Subscriber, SubscriberOperation, EVERY_POST, WEEKLY };
+use super::{ config, mailer::{ self, Mail }, notifications::normalize_email, seo, utils::DbPool };
+
+// days a confirmation link works
+pub(crate) const CONFIRM_DAYS: i64 = 7;
+pub(crate) const DIGEST_DAYS: i64 = 7;
+
+// the payload of a send_newsletter job
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct IssueJob {
+    pub(crate) issue: i32,
+}
+
+pub(crate) fn frequency_of(requested: &str) -> &'static str {
+    if requested.trim().eq_ignore_ascii_case(WEEKLY) { WEEKLY } else { EVERY_POST }
+}
+
+// the shape of an address, whether it exists is what the confirmation finds out
+pub(crate) fn is_valid_email(email: &str) -> bool {
+    let mut parts = email.rsplitn(2, '@');
+    let (domain, local) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+    email.len().le(&254) && !local.is_empty() && domain.contains('.') && !domain.starts_with('.') && !domain.ends_with('.')
+        && !email.chars().any(|c| c.is_whitespace() || c.eq(&'<') || c.eq(&'>') || c.eq(&',') || c.eq(&'"'))
+}
+
+pub(crate) fn normalize_subscriber_email(email: &str) -> Option<String> {
+    Some(normalize_email(email)).filter(|email| is_valid_email(email))
+}
+
+// the jobs run without a request, so the links come from the config
+fn configured_site_url() -> String {
+    let blog_config = config::current();
+    if blog_config.site_url.is_empty() {
+        format!("http://{}:{}", blog_config.address, blog_config.port)
+    } else {
+        blog_config.site_url.clone()
+    }
+}
+
+pub(crate) fn confirm_link(subscriber: &Subscriber, site_url: &str) -> String {
+    format!("{}/newsletter/confirm/{}/", site_url, subscriber.token)
+}
+
+pub(crate) fn unsubscribe_link(subscriber: &Subscriber, site_url: &str) -> String {
+    format!("{}/newsletter/unsubscribe/{}/", site_url, subscriber.token)
+}
+
+pub(crate) fn confirm_mail(subscriber: &Subscriber, site_url: &str) -> Result<Mail, failure::Error> {
+    let mut ctx = tera::Context::new();
+    ctx.insert("link", &confirm_link(subscriber, site_url));
+    ctx.insert("days", &CONFIRM_DAYS);
+    ctx.insert("weekly", &subscriber.frequency.eq(WEEKLY));
+    mailer::render_mail("mail/newsletter_confirm.txt", &ctx, &subscriber.email)
+}
+
+#[derive(Serialize)]
+struct IssuePost<'a> {
+    title: &'a str,
+    excerpt: String,
+    link: String,
+}
+
+// a mail of the issue to every subscriber, nothing if its posts are gone
+pub(crate) fn issue_mails(issue: &NewsletterIssue, posts: &[Post], subscribers: &[Subscriber], site_url: &str) -> Result<Vec<Mail>, failure::Error> {
+    if posts.is_empty() {
+        return Ok(Vec::new());
+    }
+    let issue_posts: Vec<IssuePost> = posts.iter().map(|post| IssuePost {
+        title: &post.title,
+        excerpt: post.excerpt_or_generated(),
+        link: seo::absolute_url(site_url, &seo::article_path(&post.title)),
+    }).collect();
+    let template = if issue.post_id.is_some() { "mail/newsletter_post.txt" } else { "mail/newsletter_digest.txt" };
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("posts", &issue_posts);
+    subscribers.iter().map(|subscriber| {
+        let link = unsubscribe_link(subscriber, site_url);
+        ctx.insert("unsubscribe", &link);
+        let mut mail = mailer::render_mail(template, &ctx, &subscriber.email)?;
+        mail.unsubscribe = Some(link);
+        Ok(mail)
+    }).collect()
+}
+
+fn queue_issue(issue: &NewsletterIssue, pool: &Data<DbPool>) -> Result<(), failure::Error> {
+    let payload = serde_json::to_string(&IssueJob { issue: issue.id })?;
+    JobOperation::enqueue(&NewJob::new(SEND_NEWSLETTER, payload, Utc::now().naive_utc()), pool).map(|_| ())
+}
+
+// called once a post is published, a post is announced once even if it's published again. true if it was queued.
+pub(crate) fn announce_post(post_id: i32, pool: &Data<DbPool>) -> Result<bool, failure::Error> {
+    match SubscriberOperation::create_post_issue(post_id, pool)? {
+        Some(issue) => queue_issue(&issue, pool).map(|_| true),
+        None => Ok(false),
+    }
+}
+
+pub(crate) fn queue_digest(pool: &Data<DbPool>) -> Result<bool, failure::Error> {
+    match SubscriberOperation::create_digest_issue(Duration::days(DIGEST_DAYS), pool)? {
+        Some(issue) => queue_issue(&issue, pool).map(|_| true),
+        None => Ok(false),
+    }
+}
+
+// the handler of send_newsletter: a mail job for each subscriber, so every mail is retried on its own.
+// an issue marked sent is skipped, so a job retried after it went through mails nobody twice.
+pub(crate) fn send_issue(issue_id: i32, pool: &Data<DbPool>) -> Result<usize, failure::Error> {
+    let issue = match SubscriberOperation::get_issue(issue_id, pool)? {
+        Some(issue) if issue.sent.is_none() => issue,
+        _ => return Ok(0),
+    };
+    let posts = SubscriberOperation::get_issue_posts(&issue, Duration::days(DIGEST_DAYS), pool)?;
+    let frequency = if issue.post_id.is_some() { EVERY_POST } else { WEEKLY };
+    let subscribers = SubscriberOperation::get_active_subscribers(frequency, pool)?;
+
+    let mails = issue_mails(&issue, &posts, &subscribers, &configured_site_url())?;
+    // the mails are queued only with the issue marked sent, so a retry neither skips nor repeats them
+    let conn = &*pool.get()?;
+    conn.transaction::<_, failure::Error, _>(|| {
+        if !SubscriberOperation::mark_sent_on(issue.id, mails.len() as i32, conn)? {
+            return Ok(0);
+        }
+        for mail in mails.iter() {
+            mailer::queue_mail_on(mail, conn)?;
+        }
+        Ok(mails.len())
+    })
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+// the admin's export, one row for each subscriber
+pub(crate) fn subscribers_csv(subscribers: &[Subscriber]) -> String {
+    let mut csv = String::from("email,status,frequency,bounces,created,confirmed\r\n");
+    for subscriber in subscribers {
+        let confirmed = subscriber.confirmed.map_or_else(String::new, |time| time.format("%Y-%m-%d %H:%M:%S").to_string());
+        let row = [
+            csv_field(&subscriber.email),
+            csv_field(&subscriber.status),
+            csv_field(&subscriber.frequency),
+            subscriber.bounces.to_string(),
+            subscriber.created.format("%Y-%m-%d %H:%M:%S").to_string(),
+            confirmed,
+        ];
+        csv.push_str(&row.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
@@ -0,0 +1,93 @@
+use actix_web::web::Data;
+use chrono::{ Duration, Utc };
+use percent_encoding::{ utf8_percent_encode, NON_ALPHANUMERIC };
+
+use crate::models::comment::NewComment;
+use crate::models::post::Post;
+use crate::models::subscription::{ CommentAudience, CommentSubscription, SubscriptionOperation };
+use super::{ mailer::{ self, Mail }, newsletter::{ is_valid_email, CONFIRM_DAYS }, seo, utils::DbPool };
+
+// the same address written another way is the same subscriber
+pub(crate) fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+fn unsubscribe_message(subscription: &CommentSubscription) -> String {
+    format!("unsubscribe:{}:{}", subscription.id, subscription.email)
+}
+
+// the signature covers the address as well, so a link stops working once its subscription is gone
+pub(crate) fn unsubscribe_link(subscription: &CommentSubscription, site_url: &str) -> String {
+    format!("{}/unsubscribe/{}/{}/", site_url, subscription.id, mailer::sign(&unsubscribe_message(subscription)))
+}
+
+pub(crate) fn is_valid_unsubscribe(subscription: &CommentSubscription, signature: &str) -> bool {
+    mailer::verify(&unsubscribe_message(subscription), signature)
+}
+
+fn subscribe_message(post_id: i32, email: &str, expires: i64) -> String {
+    format!("subscribe:{}:{}:{}", post_id, expires, email)
+}
+
+// the link which subscribes an address to the comments of a post, signed and expiring, so nothing is stored before it's followed
+pub(crate) fn subscribe_link(post_id: i32, email: &str, site_url: &str) -> String {
+    let expires = (Utc::now() + Duration::days(CONFIRM_DAYS)).timestamp();
+    format!("{}/subscribe/{}/{}/{}/?email={}", site_url, post_id, expires, mailer::sign(&subscribe_message(post_id, email, expires)),
+            utf8_percent_encode(email, NON_ALPHANUMERIC))
+}
+
+pub(crate) fn is_valid_subscribe(post_id: i32, email: &str, expires: i64, signature: &str) -> bool {
+    expires.gt(&Utc::now().timestamp()) && mailer::verify(&subscribe_message(post_id, email, expires), signature)
+}
+
+// anyone can type any address in the comment form, its owner has to confirm
+pub(crate) fn subscribe_mail(post: &Post, email: &str, site_url: &str) -> Result<Mail, failure::Error> {
+    let mut ctx = tera::Context::new();
+    ctx.insert("post_title", &post.title);
+    ctx.insert("link", &subscribe_link(post.id, email, site_url));
+    ctx.insert("days", &CONFIRM_DAYS);
+    mailer::render_mail("mail/comment_subscribe.txt", &ctx, email)
+}
+
+// a mail to the author of the post, unless it's their own comment, and one to every subscriber
+pub(crate) fn comment_mails(audience: &CommentAudience, comment: &NewComment, site_url: &str) -> Result<Vec<Mail>, failure::Error> {
+    let commenter = normalize_email(&comment.email);
+    let mut ctx = tera::Context::new();
+    ctx.insert("post_title", &audience.post.title);
+    ctx.insert("comment", comment);
+    ctx.insert("link", &seo::absolute_url(site_url, &seo::article_path(&audience.post.title)));
+
+    let mut mails = Vec::new();
+    let author_email = audience.author.as_ref().map(|author| normalize_email(&author.email)).filter(|email| !email.is_empty());
+    if let Some(author_email) = author_email.as_ref().filter(|email| email.ne(&&commenter)) {
+        mails.push(mailer::render_mail("mail/new_comment.txt", &ctx, author_email)?);
+    }
+    // the author hears about it once
+    for subscription in audience.subscribers.iter().filter(|subscription| author_email.as_ref().map_or(true, |email| email.ne(&subscription.email))) {
+        let link = unsubscribe_link(subscription, site_url);
+        ctx.insert("unsubscribe", &link);
+        let mut mail = mailer::render_mail("mail/comment_reply.txt", &ctx, &subscription.email)?;
+        mail.unsubscribe = Some(link);
+        mails.push(mail);
+    }
+    Ok(mails)
+}
+
+// queues the mails about a new comment, and a confirmation to the commenter who asked for the next ones.
+// returns how many mails were queued.
+pub(crate) fn notify_new_comment(comment: &NewComment, subscribe: bool, site_url: &str, pool: &Data<DbPool>) -> Result<usize, failure::Error> {
+    let commenter = normalize_email(&comment.email);
+    let audience = match SubscriptionOperation::comment_audience(comment.post_id, &commenter, pool)? {
+        Some(audience) => audience,
+        None => return Ok(0),
+    };
+    let mut mails = comment_mails(&audience, comment, site_url)?;
+    // the address goes into the To header, one which isn't an address gets no mail
+    if subscribe && is_valid_email(&commenter) && !SubscriptionOperation::is_subscribed(comment.post_id, &commenter, pool)? {
+        mails.push(subscribe_mail(&audience.post, &commenter, site_url)?);
+    }
+    for mail in mails.iter() {
+        mailer::queue_mail(mail, pool)?;
+    }
+    Ok(mails.len())
+}
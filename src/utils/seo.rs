@@ -0,0 +1,159 @@
+use actix_web::HttpRequest;
+use chrono::NaiveDateTime;
+use lazy_static::lazy_static;
+use percent_encoding::{ utf8_percent_encode, AsciiSet, CONTROLS };
+use regex::Regex;
+use serde_derive::Serialize;
+use serde_json::json;
+use std::collections::HashMap;
+
+use crate::models::post::Post;
+use super::config;
+
+pub(crate) const EXCERPT_LENGTH: usize = 200;
+// search engines cut the description around here
+const DESCRIPTION_LENGTH: usize = 160;
+
+lazy_static! {
+    static ref CODE_BLOCK: Regex = Regex::new(r"(?s)```.*?```").unwrap();
+    static ref IMAGE: Regex = Regex::new(r"!\[[^\]]*\]\([^)]*\)").unwrap();
+    static ref LINK: Regex = Regex::new(r"\[([^\]]*)\]\([^)]*\)").unwrap();
+    static ref HTML_TAG: Regex = Regex::new(r"<[^>]+>").unwrap();
+    static ref LINE_MARKUP: Regex = Regex::new(r"(?m)^\s*(#+|>|[-*+]|\d+\.)\s+").unwrap();
+    static ref INLINE_MARKUP: Regex = Regex::new(r"[*_`~]").unwrap();
+    static ref WHITESPACE: Regex = Regex::new(r"\s+").unwrap();
+}
+
+// the markdown as plain text, cut at a word boundary
+pub(crate) fn generate_excerpt(markdown: &str, max_chars: usize) -> String {
+    let text = CODE_BLOCK.replace_all(markdown, " ");
+    let text = IMAGE.replace_all(&text, " ");
+    let text = LINK.replace_all(&text, "$1");
+    let text = HTML_TAG.replace_all(&text, " ");
+    let text = LINE_MARKUP.replace_all(&text, "");
+    let text = INLINE_MARKUP.replace_all(&text, "");
+    let text = WHITESPACE.replace_all(&text, " ");
+    truncate(text.trim(), max_chars)
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count().le(&max_chars) {
+        return text.to_owned();
+    }
+    let cut: String = text.chars().take(max_chars).collect();
+    let cut = match cut.rfind(' ') {
+        Some(space) if space.gt(&(cut.len() / 2)) => &cut[..space],
+        _ => cut.as_str(),
+    };
+    format!("{}…", cut.trim_end_matches(|c: char| c.is_ascii_punctuation() || c.is_whitespace()))
+}
+
+// empty, a path of this site, or an absolute http(s) url
+pub(crate) fn is_valid_url(url: &str) -> bool {
+    let valid_chars = !url.chars().any(|c| c.is_whitespace() || c.eq(&'"') || c.eq(&'<') || c.eq(&'>'));
+    valid_chars && (url.is_empty() || (url.starts_with('/') && !url.starts_with("//"))
+                    || url.starts_with("http://") || url.starts_with("https://"))
+}
+
+// site_url in the config, or where this request was sent to
+pub(crate) fn site_url(req: &HttpRequest) -> String {
+    let configured = config::current().site_url.clone();
+    if !configured.is_empty() {
+        return configured;
+    }
+    let connection_info = req.connection_info();
+    format!("{}://{}", connection_info.scheme(), connection_info.host())
+}
+
+// link previews need absolute urls
+pub(crate) fn absolute_url(site_url: &str, url: &str) -> String {
+    if url.starts_with('/') {
+        format!("{}{}", site_url, url)
+    } else {
+        url.to_owned()
+    }
+}
+
+// the characters a path segment can't carry as they are, non-ascii ones are always encoded
+const PATH_SEGMENT: &AsciiSet = &CONTROLS.add(b' ').add(b'"').add(b'#').add(b'%').add(b'/').add(b'<').add(b'>')
+                                         .add(b'?').add(b'`').add(b'{').add(b'}').add(b'+');
+
+pub(crate) fn article_path(title: &str) -> String {
+    format!("/article/{}/", utf8_percent_encode(title, PATH_SEGMENT))
+}
+
+// actix decodes the path except / and +, so a title with them arrives still encoded
+pub(crate) fn article_title(segment: &str) -> String {
+    segment.replace("%2F", "/").replace("%2B", "+")
+}
+
+// the meta tags of an article: description, canonical url, opengraph/twitter card and json-ld
+#[derive(Debug, Serialize)]
+pub(crate) struct PostMeta {
+    pub(crate) description: String,
+    pub(crate) canonical_url: String,
+    pub(crate) image: Option<String>,
+    pub(crate) published: Option<String>,
+    pub(crate) modified: Option<String>,
+    pub(crate) json_ld: String,
+}
+
+fn iso8601(time: Option<NaiveDateTime>) -> Option<String> {
+    time.map(|time| format!("{}Z", time.format("%Y-%m-%dT%H:%M:%S")))
+}
+
+impl PostMeta {
+    pub(crate) fn new(post: &Post, author: &str, site_url: &str) -> Self {
+        let description = if post.meta_description.trim().is_empty() {
+            truncate(&post.excerpt_or_generated(), DESCRIPTION_LENGTH)
+        } else {
+            post.meta_description.clone()
+        };
+        let canonical_url = if post.canonical_url.is_empty() {
+            absolute_url(site_url, &article_path(&post.title))
+        } else {
+            absolute_url(site_url, &post.canonical_url)
+        };
+        let image = Some(&post.featured_image).filter(|image| !image.is_empty()).map(|image| absolute_url(site_url, image));
+        let (published, modified) = (iso8601(post.publish), iso8601(post.updated.or(post.publish)));
+
+        let mut posting = json!({
+            "@context": "https://schema.org",
+            "@type": "BlogPosting",
+            "headline": post.title,
+            "description": description,
+            "mainEntityOfPage": { "@type": "WebPage", "@id": canonical_url },
+            "author": { "@type": "Person", "name": author },
+            "keywords": post.slug,
+        });
+        if let Some(image) = &image {
+            posting["image"] = json!(image);
+        }
+        if let Some(published) = &published {
+            posting["datePublished"] = json!(published);
+        }
+        if let Some(modified) = &modified {
+            posting["dateModified"] = json!(modified);
+        }
+
+        PostMeta {
+            description,
+            canonical_url,
+            image,
+            published,
+            modified,
+            // it's put into a script tag unescaped, a </script> in the title mustn't close it
+            json_ld: posting.to_string().replace("</", "<\\/"),
+        }
+    }
+}
+
+// tera filter, {{ post | excerpt }} => the excerpt, or one generated from the body
+pub(crate) fn excerpt(value: &tera::Value, _args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+    let excerpt = value.get("excerpt").and_then(tera::Value::as_str).unwrap_or_default();
+    if !excerpt.trim().is_empty() {
+        return Ok(tera::Value::String(excerpt.to_owned()));
+    }
+    let body = value.get("body").and_then(tera::Value::as_str).unwrap_or_default();
+    Ok(tera::Value::String(generate_excerpt(body, EXCERPT_LENGTH)))
+}
@@ -0,0 +1,109 @@
+use chrono::{ Datelike, NaiveDateTime };
+use std::collections::BTreeMap;
+
+use crate::models::post::Post;
+use super::seo;
+
+// the limit of the sitemap protocol, a bigger sitemap is split and listed by a sitemap index
+pub(crate) const MAX_URLS: usize = 50_000;
+// the pages which are always there
+const STATIC_PAGES: [&str; 4] = ["/", "/all_posts/", "/about/", "/contact/"];
+
+#[derive(Debug, Clone)]
+pub(crate) struct RobotsConfig {
+    pub(crate) disallow: Vec<String>, // /admin/ is always disallowed
+    pub(crate) allow: Vec<String>,
+}
+
+impl Default for RobotsConfig {
+    fn default() -> Self {
+        RobotsConfig { disallow: vec!["/admin/".to_owned()], allow: Vec::new() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SitemapUrl {
+    pub(crate) path: String,
+    pub(crate) lastmod: Option<NaiveDateTime>,
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&apos;")
+}
+
+fn w3c_datetime(time: &NaiveDateTime) -> String {
+    format!("{}+00:00", time.format("%Y-%m-%dT%H:%M:%S"))
+}
+
+// the pages of the published posts: static pages, year archives and articles.
+// a post whose canonical url is on another site belongs to that site's sitemap.
+pub(crate) fn site_urls(posts: &[Post], site_url: &str) -> Vec<SitemapUrl> {
+    let posts: Vec<&Post> = posts.iter()
+                                 .filter(|post| post.canonical_url.is_empty() || post.canonical_url.starts_with('/')
+                                                || post.canonical_url.starts_with(site_url))
+                                 .collect();
+    let latest = posts.iter().filter_map(|post| post.updated).max();
+
+    let mut urls: Vec<SitemapUrl> = STATIC_PAGES.iter().map(|page| SitemapUrl {
+        path: page.to_string(),
+        lastmod: if page.eq(&"/") || page.eq(&"/all_posts/") { latest } else { None },
+    }).collect();
+
+    let mut years: BTreeMap<i32, Option<NaiveDateTime>> = BTreeMap::new();
+    for post in posts.iter() {
+        if let Some(created) = post.created {
+            let lastmod = years.entry(created.year()).or_insert(None);
+            *lastmod = (*lastmod).max(post.updated);
+        }
+    }
+    urls.extend(years.into_iter().map(|(year, lastmod)| SitemapUrl { path: format!("/category/{}/", year), lastmod }));
+
+    urls.extend(posts.iter().map(|post| SitemapUrl {
+        path: seo::article_path(&post.title),
+        lastmod: post.updated.or(post.publish),
+    }));
+    urls
+}
+
+pub(crate) fn urlset(site_url: &str, urls: &[SitemapUrl]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for url in urls {
+        xml.push_str(&format!("  <url><loc>{}</loc>", escape_xml(&seo::absolute_url(site_url, &url.path))));
+        if let Some(lastmod) = &url.lastmod {
+            xml.push_str(&format!("<lastmod>{}</lastmod>", w3c_datetime(lastmod)));
+        }
+        xml.push_str("</url>\n");
+    }
+    xml.push_str("</urlset>\n");
+    xml
+}
+
+// /sitemap-1.xml, /sitemap-2.xml..., each one with at most max_urls urls
+pub(crate) fn sitemap_index(site_url: &str, urls: &[SitemapUrl], max_urls: usize) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for (i, chunk) in urls.chunks(max_urls).enumerate() {
+        xml.push_str(&format!("  <sitemap><loc>{}/sitemap-{}.xml</loc>", escape_xml(site_url), i + 1));
+        if let Some(lastmod) = chunk.iter().filter_map(|url| url.lastmod).max() {
+            xml.push_str(&format!("<lastmod>{}</lastmod>", w3c_datetime(&lastmod)));
+        }
+        xml.push_str("</sitemap>\n");
+    }
+    xml.push_str("</sitemapindex>\n");
+    xml
+}
+
+// the urls of /sitemap-{page}.xml, pages start from 1
+pub(crate) fn sitemap_page(urls: &[SitemapUrl], page: usize, max_urls: usize) -> Option<&[SitemapUrl]> {
+    urls.chunks(max_urls).nth(page.checked_sub(1)?)
+}
+
+pub(crate) fn robots_txt(robots_config: &RobotsConfig, site_url: &str) -> String {
+    let mut disallow = vec!["/admin/"];
+    disallow.extend(robots_config.disallow.iter().map(String::as_str).filter(|path| path.ne(&"/admin/")));
+
+    let mut robots = String::from("User-agent: *\n");
+    robots_config.allow.iter().for_each(|path| robots.push_str(&format!("Allow: {}\n", path)));
+    disallow.iter().for_each(|path| robots.push_str(&format!("Disallow: {}\n", path)));
+    robots.push_str(&format!("\nSitemap: {}/sitemap.xml\n", site_url));
+    robots
+}
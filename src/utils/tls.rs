@@ -0,0 +1,96 @@
+use actix_web::{ web, HttpRequest, HttpResponse, http::{ header, HeaderValue } };
+use openssl::ssl::{ SslMethod, SslAcceptor, SslFiletype, SslAcceptorBuilder };
+
+// what the plain http listener does
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum PlainHttp {
+    Serve, // serve the blog
+    Redirect, // redirect everything to https
+    Off, // don't listen at all
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct TlsConfig {
+    pub(crate) enabled: bool,
+    pub(crate) port: u16,
+    pub(crate) cert: String,
+    pub(crate) key: String,
+    pub(crate) plain_http: PlainHttp,
+    pub(crate) hsts: bool,
+    pub(crate) hsts_max_age: u64,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            enabled: false,
+            port: 8443,
+            cert: "ssl_keys/crt.pem".to_owned(),
+            key: "ssl_keys/server.pem".to_owned(),
+            plain_http: PlainHttp::Serve,
+            hsts: false,
+            hsts_max_age: 31_536_000, // one year
+        }
+    }
+}
+
+impl TlsConfig {
+    pub(crate) fn hsts_header(&self) -> String {
+        format!("max-age={}; includeSubDomains", self.hsts_max_age)
+    }
+
+    // only a response over tls carries hsts, the plain listener may be serving the blog as well
+    pub(crate) fn hsts_for(&self, secure: bool) -> Option<HeaderValue> {
+        if self.enabled && self.hsts && secure {
+            HeaderValue::from_str(&self.hsts_header()).ok()
+        } else {
+            None
+        }
+    }
+}
+
+impl std::str::FromStr for PlainHttp {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "serve" => Ok(PlainHttp::Serve),
+            "redirect" => Ok(PlainHttp::Redirect),
+            "off" => Ok(PlainHttp::Off),
+            _ => Err(failure::format_err!("plain_http should be one of serve/redirect/off, but got {}", s)),
+        }
+    }
+}
+
+// http2 is negotiated by alpn on the tls listener
+pub(crate) fn load_ssl(cert: &str, key: &str) -> Result<SslAcceptorBuilder, failure::Error> {
+    let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls())?;
+    builder.set_private_key_file(key, SslFiletype::PEM)?;
+    builder.set_certificate_chain_file(cert)?;
+    Ok(builder)
+}
+
+// the only handler of the plain listener when plain_http = "redirect"
+pub(crate) async fn redirect_to_https(req: HttpRequest, tls_port: web::Data<u16>) -> HttpResponse {
+    let connection = req.connection_info();
+    let host = host_without_port(connection.host());
+    let path = req.uri().path_and_query().map_or("/", |p| p.as_str());
+
+    let location = match *tls_port.get_ref() {
+        443 => format!("https://{}{}", host, path),
+        port => format!("https://{}:{}{}", host, port, path),
+    };
+    HttpResponse::MovedPermanently().header(header::LOCATION, location).finish()
+}
+
+// example.com:8080 => example.com, [::1]:8080 => [::1]
+pub(crate) fn host_without_port(host: &str) -> &str {
+    if host.starts_with('[') {
+        host.find(']').map_or(host, |end| &host[..=end])
+    } else if host.matches(':').count().eq(&1) {
+        host.split(':').next().unwrap_or_default()
+    } else {
+        // no port, or an ipv6 address without brackets
+        host
+    }
+}
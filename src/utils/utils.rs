@@ -1,11 +1,48 @@
-use dotenv::dotenv;
+use actix_web::{ error::BlockingError, web };
 use failure;
-use diesel::{ r2d2::{ ConnectionManager, Pool }, pg::PgConnection };
+use diesel::{ prelude::*, r2d2::{ ConnectionManager, Pool } };
 use lazy_static::lazy_static;
-use openssl::ssl::{ SslMethod, SslAcceptor, SslFiletype, SslAcceptorBuilder };
-use std::{ fs::File, io::{ BufReader, prelude::* } };
+use rand::{ distributions::Alphanumeric, Rng };
 
-pub(crate) type PgPool = Pool<ConnectionManager<PgConnection>>;
+use super::config;
+
+// the storage backend is chosen at compile time, postgres by default,
+// or a single sqlite file with `--no-default-features --features sqlite`.
+#[cfg(feature = "postgres")]
+pub(crate) type DbConnection = diesel::pg::PgConnection;
+#[cfg(feature = "sqlite")]
+pub(crate) type DbConnection = diesel::sqlite::SqliteConnection;
+
+pub(crate) type DbPool = Pool<ConnectionManager<DbConnection>>;
+
+#[cfg(feature = "postgres")]
+no_arg_sql_function!(lastval, diesel::sql_types::BigInt, "The last value returned by nextval in this session");
+#[cfg(feature = "sqlite")]
+no_arg_sql_function!(last_insert_rowid, diesel::sql_types::BigInt, "The rowid of the last insert on this connection");
+
+// id of the row just inserted on this connection, because sqlite has no RETURNING clause
+pub(crate) fn last_insert_id(conn: &DbConnection) -> Result<i32, failure::Error> {
+    #[cfg(feature = "postgres")]
+    let id = diesel::select(lastval).get_result::<i64>(conn)?;
+    #[cfg(feature = "sqlite")]
+    let id = diesel::select(last_insert_rowid).get_result::<i64>(conn)?;
+    Ok(std::convert::TryFrom::try_from(id)?)
+}
+
+// sqlite needs to wait for the lock of another writer instead of failing at once,
+// and doesn't check foreign keys unless asked.
+#[cfg(feature = "sqlite")]
+#[derive(Debug)]
+struct SqlitePragmas;
+
+#[cfg(feature = "sqlite")]
+impl diesel::r2d2::CustomizeConnection<DbConnection, diesel::r2d2::Error> for SqlitePragmas {
+    fn on_acquire(&self, conn: &mut DbConnection) -> Result<(), diesel::r2d2::Error> {
+        use diesel::connection::SimpleConnection;
+        conn.batch_execute("PRAGMA busy_timeout = 5000; PRAGMA foreign_keys = ON; PRAGMA journal_mode = WAL;")
+            .map_err(diesel::r2d2::Error::QueryError)
+    }
+}
 
 #[derive(Debug)]
 pub(crate) enum Status {
@@ -24,34 +61,45 @@ impl PartialEq for Status {
 
 lazy_static! {
     pub(crate) static ref COMPILED_TEMPLATES: tera::Tera = {
-        tera::Tera::new(concat!(env!("CARGO_MANIFEST_DIR"), "/templates/**/*")).unwrap()
+        let templates = format!("{}/**/*", config::current().templates_dir);
+        let mut tera = tera::Tera::new(&templates).unwrap();
+        tera.register_function("static_url", super::http_cache::static_url);
+        tera.register_filter("excerpt", super::seo::excerpt);
+        tera
     };
 }
 
-pub(crate) fn db_pool() -> Result<PgPool, failure::Error> {
-    dotenv().ok();
-    let database_url = dotenv::var("DATABASE_URL")?;
-    let manager = ConnectionManager::<PgConnection>::new(database_url);
-    let pool = Pool::new(manager)?;
+// render a template and record the render time for /metrics
+pub(crate) fn render_template(template: &str, ctx: &tera::Context) -> tera::Result<String> {
+    let start = std::time::Instant::now();
+    let rendered = COMPILED_TEMPLATES.render(template, ctx);
+    super::metrics::record_render(template, start.elapsed());
+    rendered
+}
+
+pub(crate) fn db_pool(database_url: &str) -> Result<DbPool, failure::Error> {
+    let manager = ConnectionManager::<DbConnection>::new(database_url);
+    let builder = Pool::builder();
+    #[cfg(feature = "sqlite")]
+    let builder = builder.connection_customizer(Box::new(SqlitePragmas));
+    let pool = builder.build(manager)?;
     Ok(pool)
 }
 
-// enable http2/s
-#[allow(dead_code)]
-pub(crate) fn load_ssl() -> Result<SslAcceptorBuilder, failure::Error> {
-    let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls())?;
-    builder.set_private_key_file("ssl_keys/server.pem", SslFiletype::PEM)?;
-    builder.set_certificate_chain_file("ssl_keys/crt.pem")?;
-    Ok(builder)
-}
-
-// read project config file
-pub(crate) fn blog_config() -> Result<toml::Value, failure::Error> {
-    let config = File::open(concat!(env!("CARGO_MANIFEST_DIR"),"/actix_blog.toml"))?;
-    let mut buff = BufReader::new(config);
-    let mut contents = String::new();
-    buff.read_to_string(&mut contents)?;
-    
-    let value = contents.parse::<toml::Value>()?;
-    Ok(value)
-}
\ No newline at end of file
+// random alphanumeric string for invitation codes, email tokens and so on
+pub(crate) fn random_token(length: usize) -> String {
+    rand::thread_rng().sample_iter(&Alphanumeric).take(length).collect()
+}
+
+// run a blocking database or cpu-bound operation(like bcrypt) on the actix thread pool,
+// so that the async executor won't be blocked by postgres I/O.
+pub(crate) async fn run_blocking<F, T>(operation: F) -> Result<T, failure::Error>
+where
+    F: FnOnce() -> Result<T, failure::Error> + Send + 'static,
+    T: Send + 'static,
+{
+    web::block(operation).await.map_err(|e| match e {
+        BlockingError::Error(e) => e,
+        BlockingError::Canceled => failure::err_msg("the blocking operation was canceled"),
+    })
+}
@@ -0,0 +1,47 @@
+use actix_identity::Identity;
+use actix_web::{ web, HttpResponse };
+use chrono::{ Duration, Utc };
+use serde_derive::Deserialize;
+
+use crate::error_types::ErrorKind;
+use crate::models::analytics::AnalyticsOperation;
+use crate::utils::utils::{ render_template, DbPool };
+
+use actix_blog::login_required;
+
+const DEFAULT_DAYS: i64 = 30;
+const MAX_DAYS: i64 = 365;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct AnalyticsQuery {
+    days: Option<i64>,
+    post: Option<i32>, // the views of one article only
+}
+
+#[login_required]
+pub(crate) async fn analytics(
+    query: web::Query<AnalyticsQuery>,
+    db: web::Data<DbPool>,
+    identity: Identity
+) -> Result<HttpResponse, ErrorKind> {
+    let days = query.days.unwrap_or(DEFAULT_DAYS).max(1).min(MAX_DAYS);
+    let since = Utc::now().naive_utc().date() - Duration::days(days - 1);
+    let analytics = match AnalyticsOperation::analytics_async(since, query.post, &db).await {
+        Ok(analytics) => analytics,
+        Err(e) => return Err(ErrorKind::DbOperationError(e.to_string())),
+    };
+    // the bars are as wide as the busiest day
+    let max_views = analytics.days.iter().map(|day| day.views).max().unwrap_or_default().max(1);
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("username", &identity.identity().unwrap());
+    ctx.insert("days", &days);
+    ctx.insert("post", &query.post);
+    ctx.insert("max_views", &max_views);
+    ctx.insert("analytics", &analytics);
+    let template = render_template("admin/analytics.html", &ctx);
+    match template {
+        Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
+        Err(e) => Err(ErrorKind::TemplateError(e.to_string()))
+    }
+}
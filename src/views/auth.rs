@@ -1,14 +1,19 @@
-use actix_web::{ web, Error as HttpResponseErr, HttpResponse };
+use actix_web::{ web, Error as HttpResponseErr, HttpRequest, HttpResponse };
 use actix_identity::Identity;
 use chrono::{ NaiveDateTime, Utc };
+use diesel::Connection;
 use itertools::Itertools;
 use serde_derive::{ Deserialize, Serialize };
-use std::convert::TryFrom;
 use std::collections::HashMap;
 
-use crate::utils::utils::{ PgPool, COMPILED_TEMPLATES, Status };
-use crate::models::user::{ LoginUser, CreateUser, NewUser, PasswordChange, UserOperation };
-use crate::models::contact::ContactOperation;
+use crate::utils::utils::{ DbPool, render_template, run_blocking, Status };
+use crate::utils::{ config, mailer, media::MediaLinks, newsletter, seo };
+use crate::utils::cache::{ PAGE_CACHE, QUERY_CACHE };
+use crate::models::user::{ LoginUser, CreateUser, PasswordChange, User, UserOperation };
+use crate::models::invitation::{ self, CreateInvitation, InvitationOperation, Registration, Role };
+use crate::models::analytics::AnalyticsOperation;
+use crate::models::contact::{ ContactOperation, ContactState, NewContactReply, FOLDERS, INBOX };
+use crate::models::media::MediaOperation;
 use crate::models::comment::{ Comment, CommentOperation };
 use crate::models::post::{ NewPost, PostOperation, SubmitPost, UpdatedPost };
 use crate::error_types::ErrorKind;
@@ -20,12 +25,35 @@ pub(crate) fn redirect(url: &str) -> HttpResponse {
     HttpResponse::TemporaryRedirect().header("Location", url).finish()
 }
 
+// mails the subscribers about a post which was just published, a failure doesn't fail the request
+async fn announce_post(title: String, db: &web::Data<DbPool>) {
+    let pool = db.clone();
+    let announced = run_blocking(move || match PostOperation::get_post_by_title(&title, &pool)? {
+        Some(post) => newsletter::announce_post(post.id, &pool),
+        None => Ok(false),
+    }).await;
+    if let Err(e) = announced {
+        log::warn!("failed to announce a new post: {}", e);
+    }
+}
+
+// checked by login_required on every protected page, the account may be deactivated after the login
+pub(crate) async fn is_active_user(identity: &Identity, db: &web::Data<DbPool>) -> bool {
+    match identity.identity() {
+        Some(user_name) => match UserOperation::get_user_by_name_async(user_name, db).await {
+            Ok(Some(user)) => user.is_active,
+            _ => false,
+        },
+        None => false,
+    }
+}
+
 pub(crate) async fn async_redirect(url: &str) -> Result<HttpResponse, HttpResponseErr> {
     Ok(HttpResponse::TemporaryRedirect().header("Location", url).finish())
 }
 
 pub(crate) async fn login() -> Result<HttpResponse, ErrorKind> {
-    let template = COMPILED_TEMPLATES.render("admin/login.html", &tera::Context::new());
+    let template = render_template("admin/login.html", &tera::Context::new());
     match template {
         Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
         Err(e) => Err(ErrorKind::TemplateError(e.to_string()))
@@ -34,13 +62,18 @@ pub(crate) async fn login() -> Result<HttpResponse, ErrorKind> {
 
 pub(crate) async fn handle_login(
     login_user: web::Form<LoginUser>, 
-    db:         web::Data<PgPool>,
+    db:         web::Data<DbPool>,
     identity:   Identity
 ) -> Result<HttpResponse, HttpResponseErr> {
-    let user_found = UserOperation::get_user_by_name(&login_user.username, &db);
+    let user_found = UserOperation::get_user_by_name_async(login_user.username.clone(), &db).await;
     
     if let Ok(Some(user)) = user_found {
-        match bcrypt::verify(&login_user.password, &user.password) {
+        match UserOperation::verify_password_async(login_user.password.clone(), user.password.clone()).await {
+            Ok(true) if !user.is_active => {
+                Ok(HttpResponse::Forbidden().content_type("text/html")
+                    .body("<h1 style='text-align: center;'>This account isn't active, verify your email or ask an admin.</h1> 
+                           <h2 style='text-align: center;'><a href='.'>Go back</a></h2>"))
+            }
             Ok(true) => {
                 identity.remember(user.username);
                 Ok(redirect("/admin/dashboard/"))
@@ -61,9 +94,9 @@ pub(crate) async fn handle_login(
 new_struct!(UserExist, pub, [Debug, Clone, Serialize, Deserialize], (username=>String));
 pub(crate) async fn user_exist(
     user_exist: web::Json<UserExist>,
-    db: web::Data<PgPool>
+    db: web::Data<DbPool>
 ) -> Result<HttpResponse, HttpResponseErr> {
-    let existed_user = UserOperation::get_user_by_name(&user_exist.username, &db);
+    let existed_user = UserOperation::get_user_by_name_async(user_exist.username.clone(), &db).await;
     
     match existed_user {
         Ok(Some(_user)) => Ok(HttpResponse::Ok().json(true)),
@@ -75,9 +108,9 @@ pub(crate) async fn user_exist(
 new_struct!(EmailExist, pub, [Debug, Clone, Serialize, Deserialize], (email=>String));
 pub(crate) async fn email_exist(
     email: web::Json<EmailExist>, 
-    db: web::Data<PgPool>
+    db: web::Data<DbPool>
 ) -> Result<HttpResponse, HttpResponseErr> {
-    let existed_user = UserOperation::get_user_by_email(&email.email, &db);
+    let existed_user = UserOperation::get_user_by_email_async(email.email.clone(), &db).await;
     
     match existed_user {
         Ok(Some(_user)) => Ok(HttpResponse::Ok().json(true)),
@@ -88,25 +121,30 @@ pub(crate) async fn email_exist(
 
 #[login_required]
 pub(crate) async fn dashboard(
-    db: web::Data<PgPool>,
+    db: web::Data<DbPool>,
     identity: Identity
 ) -> Result<HttpResponse, ErrorKind> {
     if let Some(user) = identity.identity() {
-        // async way may improve the performance
-        let all_comments = CommentOperation::get_all_comments(&db); 
-        let all_contacts = ContactOperation::get_all_contacts(&db);
+        // the counts are fetched concurrently on the blocking thread pool
+        let (all_comments, inbox_counts, today_views) = futures::join!(
+            CommentOperation::get_all_comments_async(&db),
+            ContactOperation::inbox_counts_async(&db),
+            AnalyticsOperation::today_views_async(&db)
+        );
         
-        let (comments_count, messages_count) = match (all_comments, all_contacts) {
-            (Ok(comments), Ok(messages)) => (comments.len(), messages.len()),
-            _ => (0, 0)
-        };
+        let comments_count = all_comments.map(|comments| comments.len()).unwrap_or_default();
+        let (messages_count, unread_count) = inbox_counts.unwrap_or_default();
         
         let mut ctx = tera::Context::new();
         ctx.insert("username", &user);
         ctx.insert("comments_count", &comments_count);
         ctx.insert("messages_count", &messages_count);
+        ctx.insert("unread_count", &unread_count);
+        let (views_count, visitors_count) = today_views.unwrap_or_default();
+        ctx.insert("views_count", &views_count);
+        ctx.insert("visitors_count", &visitors_count);
         
-        let template = COMPILED_TEMPLATES.render("admin/dashboard.html", &ctx);
+        let template = render_template("admin/dashboard.html", &ctx);
         match template {
             Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
             Err(e) => Err(ErrorKind::TemplateError(e.to_string()))
@@ -121,8 +159,21 @@ pub(crate) async fn logout(identity: Identity) -> Result<HttpResponse, HttpRespo
     Ok(redirect("/admin/login/"))
 }
 
-pub(crate) async fn register() -> Result<HttpResponse, ErrorKind> {
-    let template = COMPILED_TEMPLATES.render("admin/register.html", &tera::Context::new());
+fn registration_closed() -> HttpResponse {
+    HttpResponse::NotFound().content_type("text/html")
+        .body("<h1 style='text-align: center;'>Registration is closed.</h1>")
+}
+
+// the invitation link can carry the code, /admin/register/?code=...
+pub(crate) async fn register(query: web::Query<HashMap<String, String>>) -> Result<HttpResponse, ErrorKind> {
+    if !config::current().allow_registration {
+        return Ok(registration_closed());
+    }
+    let mut ctx = tera::Context::new();
+    if let Some(code) = query.get("code") {
+        ctx.insert("invitation_code", code);
+    }
+    let template = render_template("admin/register.html", &ctx);
     
     match template {
         Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
@@ -131,14 +182,50 @@ pub(crate) async fn register() -> Result<HttpResponse, ErrorKind> {
 }
 
 pub(crate) async fn handle_registration(
+    req: HttpRequest,
     new_user: web::Form<CreateUser>, 
-    db: web::Data<PgPool>
+    db: web::Data<DbPool>
 ) -> Result<HttpResponse, ErrorKind> {
-    if let Ok(new_user) = NewUser::try_from(new_user) {
-        let is_inserted = UserOperation::insert_user(&new_user, &db);
-        match is_inserted {
-            Ok(Status::Success) => Ok(redirect("/admin/login/")),
-            Ok(Status::Failure) => Ok(HttpResponse::InternalServerError().into()),
+    if !config::current().allow_registration {
+        return Ok(registration_closed());
+    }
+    
+    // what was typed, to fill the form again if it's rejected
+    let mut typed = new_user.0.clone();
+    typed.password.clear();
+    if let Ok(new_user) = UserOperation::new_user_async(new_user).await {
+        let email = new_user.email.clone();
+        // the account stays inactive until the link in this mail is visited
+        let site = {
+            let connection = req.connection_info();
+            format!("{}://{}", connection.scheme(), connection.host())
+        };
+        let verification_mail = move |token: &str| {
+            let mut ctx = tera::Context::new();
+            ctx.insert("link", &format!("{}/admin/verify_email/{}/", site, token));
+            mailer::render_mail("mail/verify_email.txt", &ctx, &email)
+        };
+        let registration = InvitationOperation::register_with_invitation_async(typed.invitation_code.clone(), new_user, verification_mail, &db).await;
+        match registration {
+            Ok(Registration::Registered(_)) => {
+                Ok(HttpResponse::Ok().content_type("text/html")
+                    .body("<h1 style='text-align: center;'>Check your email to activate the account.</h1>"))
+            }
+            Ok(Registration::InvalidInvitation) => {
+                Ok(HttpResponse::Forbidden().content_type("text/html")
+                    .body("<h1 style='text-align: center;'>The invitation code is invalid or expired.</h1>
+                           <h2 style='text-align: center;'><a href='.'>Go back</a></h2>"))
+            }
+            Ok(Registration::DuplicatedUser) => {
+                let mut ctx = tera::Context::new();
+                ctx.insert("error", "The username or email is taken already.");
+                ctx.insert("form", &typed);
+                ctx.insert("invitation_code", &typed.invitation_code);
+                match render_template("admin/register.html", &ctx) {
+                    Ok(t) => Ok(HttpResponse::Conflict().content_type("text/html").body(t)),
+                    Err(e) => Err(ErrorKind::TemplateError(e.to_string())),
+                }
+            }
             Err(e) => Err(ErrorKind::DbOperationError(e.to_string())),
         }
     } else {
@@ -147,9 +234,103 @@ pub(crate) async fn handle_registration(
     }
 }
 
+pub(crate) async fn verify_email(
+    token: web::Path<String>,
+    db: web::Data<DbPool>
+) -> Result<HttpResponse, ErrorKind> {
+    match UserOperation::verify_email_async(token.into_inner(), &db).await {
+        Ok(Status::Success) => Ok(redirect("/admin/login/")),
+        Ok(Status::Failure) => {
+            Ok(HttpResponse::NotFound().content_type("text/html")
+                .body("<h1 style='text-align: center;'>The verification link is invalid or already used.</h1>"))
+        }
+        Err(e) => Err(ErrorKind::DbOperationError(e.to_string())),
+    }
+}
+
+// only superusers can see and issue invitations
+pub(crate) async fn current_superuser(user_name: String, db: &web::Data<DbPool>) -> Option<User> {
+    match UserOperation::find_user_async(user_name, db).await {
+        Ok(Some(user)) if user.is_superuser => Some(user),
+        _ => None,
+    }
+}
+
+pub(crate) fn superuser_required() -> HttpResponse {
+    HttpResponse::Forbidden().content_type("text/html")
+        .body("<h1 style='text-align: center;'>Only superusers can do this.</h1>")
+}
+
+// staff and superusers moderate the comments, authors can't
+pub(crate) async fn current_staff(user_name: String, db: &web::Data<DbPool>) -> Option<User> {
+    match UserOperation::find_user_async(user_name, db).await {
+        Ok(Some(user)) if user.is_staff || user.is_superuser => Some(user),
+        _ => None,
+    }
+}
+
+pub(crate) fn staff_required() -> HttpResponse {
+    HttpResponse::Forbidden().content_type("text/html")
+        .body("<h1 style='text-align: center;'>Only staff can do this.</h1>")
+}
+
+#[login_required]
+pub(crate) async fn invitations(
+    db: web::Data<DbPool>,
+    identity: Identity
+) -> Result<HttpResponse, ErrorKind> {
+    let user_name = identity.identity().unwrap();
+    if current_superuser(user_name.clone(), &db).await.is_none() {
+        return Ok(superuser_required());
+    }
+    
+    let mut ctx = tera::Context::new();
+    ctx.insert("username", &user_name);
+    ctx.insert("registration_open", &config::current().allow_registration);
+    match InvitationOperation::get_all_invitations_async(&db).await {
+        Ok(all_invitations) => {
+            let now = Utc::now().naive_utc();
+            let expired: Vec<i32> = all_invitations.iter().filter(|i| i.used.is_none() && !i.is_valid(&now)).map(|i| i.id).collect();
+            ctx.insert("expired", &expired);
+            ctx.insert("invitations", &all_invitations);
+        }
+        Err(e) => return Err(ErrorKind::DbOperationError(e.to_string())),
+    }
+    
+    let template = render_template("admin/invitations.html", &ctx);
+    match template {
+        Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
+        Err(e) => Err(ErrorKind::TemplateError(e.to_string()))
+    }
+}
+
+#[login_required]
+pub(crate) async fn create_invitation(
+    new_invitation: web::Form<CreateInvitation>,
+    db: web::Data<DbPool>,
+    identity: Identity
+) -> Result<HttpResponse, ErrorKind> {
+    let user_name = identity.identity().unwrap();
+    let superuser = match current_superuser(user_name, &db).await {
+        Some(superuser) => superuser,
+        None => return Ok(superuser_required()),
+    };
+    
+    let role = match new_invitation.role.parse::<Role>() {
+        Ok(role) if (1..=invitation::MAX_DAYS).contains(&new_invitation.days) => role,
+        _ => return Ok(HttpResponse::BadRequest().content_type("text/html")
+                           .body("<h1 style='text-align: center;'>Invalid role or expiry.</h1>
+                                  <h2 style='text-align: center;'><a href='.'>Go back</a></h2>")),
+    };
+    match InvitationOperation::create_invitation_async(role, new_invitation.days, superuser.id, &db).await {
+        Ok(_) => Ok(redirect("/admin/invitations/")),
+        Err(e) => Err(ErrorKind::DbOperationError(e.to_string())),
+    }
+}
+
 #[login_required]
-pub(crate) async fn reset_password(identity: Identity) -> Result<HttpResponse, ErrorKind> {
-    let template = COMPILED_TEMPLATES.render("admin/reset_password.html", &tera::Context::new());
+pub(crate) async fn reset_password(db: web::Data<DbPool>, identity: Identity) -> Result<HttpResponse, ErrorKind> {
+    let template = render_template("admin/reset_password.html", &tera::Context::new());
     
     match template {
         Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
@@ -160,15 +341,15 @@ pub(crate) async fn reset_password(identity: Identity) -> Result<HttpResponse, E
 #[login_required]
 pub(crate) async fn save_changed_password(
     reset_pwd: web::Form<PasswordChange>, 
-    db: web::Data<PgPool>,
+    db: web::Data<DbPool>,
     identity: Identity
 ) -> Result<HttpResponse, ErrorKind> {
     let user_name = identity.identity().unwrap();
     if reset_pwd.old_password.ne(&reset_pwd.new_password) {
-        let hashed_new_pwd = bcrypt::hash(&reset_pwd.new_password, bcrypt::DEFAULT_COST);
+        let hashed_new_pwd = UserOperation::hash_password_async(reset_pwd.new_password.clone()).await;
         match hashed_new_pwd {
             Ok(hashed_pwd) => {
-                let is_modified = UserOperation::modify_password(&hashed_pwd, &user_name, &db);
+                let is_modified = UserOperation::modify_password_async(hashed_pwd, user_name, &db).await;
                 if let Ok(Status::Success) = is_modified {
                     identity.forget(); // re-login
                     Ok(HttpResponse::Ok().content_type("text/html")
@@ -190,11 +371,22 @@ pub(crate) async fn save_changed_password(
 }
 
 #[login_required]
-pub(crate) async fn write_post(identity: Identity) -> Result<HttpResponse, ErrorKind> {
+pub(crate) async fn write_post(
+    db: web::Data<DbPool>,
+    identity: Identity
+) -> Result<HttpResponse, ErrorKind> {
     let author = identity.identity().unwrap();
+    // the gallery beside the editor, a click inserts the image into the markdown
+    let all_media = match MediaOperation::get_all_media_async(&db).await {
+        Ok(all_media) => all_media,
+        Err(e) => return Err(ErrorKind::DbOperationError(e.to_string())),
+    };
+    let links: Vec<MediaLinks> = all_media.iter().map(MediaLinks::from).collect();
+    
     let mut ctx = tera::Context::new();
     ctx.insert("username", &author);
-    let template = COMPILED_TEMPLATES.render("admin/write_post.html", &ctx);
+    ctx.insert("media", &links);
+    let template = render_template("admin/write_post.html", &ctx);
     
     match template {
         Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
@@ -202,18 +394,34 @@ pub(crate) async fn write_post(identity: Identity) -> Result<HttpResponse, Error
     }
 }
 
+fn invalid_post_urls(fields: &[&str]) -> HttpResponse {
+    HttpResponse::BadRequest().content_type("text/html")
+        .body(format!("<h1 style='text-align: center;'>{} should be an http(s) url or a path like /media/image.jpg.</h1>
+                       <h2 style='text-align: center;'><a href='.'>Go back</a></h2>", fields.join(" and ")))
+}
+
 #[login_required]
 pub(crate) async fn submit_post(
     new_post: web::Form<SubmitPost>, 
-    db: web::Data<PgPool>,
+    db: web::Data<DbPool>,
     identity: Identity 
 ) -> Result<HttpResponse, ErrorKind> {
+    let invalid_urls = new_post.invalid_urls();
+    if !invalid_urls.is_empty() {
+        return Ok(invalid_post_urls(&invalid_urls));
+    }
     let author = identity.identity().unwrap();
-    match UserOperation::get_id_by_username(&author, &db) {
+    match UserOperation::get_id_by_username_async(author, &db).await {
         Ok(uid) => {
             let new_post= NewPost::new(&*new_post, uid);
-            match PostOperation::insert_post(&new_post, &db) {
-                Ok(Status::Success) => Ok(redirect("/admin/dashboard/")),
+            let (title, published) = (new_post.title.clone(), new_post.status.eq("publish"));
+            match PostOperation::insert_post_async(new_post, &db).await {
+                Ok(Status::Success) => {
+                    if published {
+                        announce_post(title, &db).await;
+                    }
+                    Ok(redirect("/admin/dashboard/"))
+                }
                 _ => Ok(HttpResponse::InternalServerError().into())
             }
         }
@@ -223,11 +431,11 @@ pub(crate) async fn submit_post(
 
 #[login_required]
 pub(crate) async fn show_all_posts_by_author(
-    db: web::Data<PgPool>,
+    db: web::Data<DbPool>,
     identity: Identity
 ) -> Result<HttpResponse, ErrorKind> {
     let author = identity.identity().unwrap();
-    let user_posts = PostOperation::get_posts_by_author(&author, &db).unwrap(); // remove unwrap
+    let user_posts = PostOperation::get_posts_by_author_async(author.clone(), &db).await.unwrap(); // remove unwrap
     let created_time: Vec<Option<&NaiveDateTime>> = user_posts.iter().map(|post| post.publish.as_ref()).collect();
     
     let mut ctx = tera::Context::new();
@@ -235,7 +443,7 @@ pub(crate) async fn show_all_posts_by_author(
     ctx.insert("created_time", &created_time);
     ctx.insert("username", &author);
     
-    let template = COMPILED_TEMPLATES.render("admin/all_posts.html", &ctx);
+    let template = render_template("admin/all_posts.html", &ctx);
     match template {
         Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
         Err(e) => Err(ErrorKind::TemplateError(e.to_string()))
@@ -245,14 +453,14 @@ pub(crate) async fn show_all_posts_by_author(
 #[login_required]
 pub(crate) async fn modify_post(
     title: web::Path<String>,
-    db: web::Data<PgPool>,
+    db: web::Data<DbPool>,
     identity: Identity
 ) -> Result<HttpResponse, ErrorKind> {
     let user_name = identity.identity().unwrap();
-    if let Ok(Some(post)) = PostOperation::get_post_by_title(&title, &db) {
+    if let Ok(Some(post)) = PostOperation::get_post_by_title_async(title.into_inner(), &db).await {
         let mut ctx = tera::Context::from_serialize(post).unwrap();
         ctx.insert("username", &user_name);
-        let template = COMPILED_TEMPLATES.render("admin/modify_post.html", &ctx);
+        let template = render_template("admin/modify_post.html", &ctx);
         match template {
             Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
             Err(e) => Err(ErrorKind::TemplateError(e.to_string()))
@@ -266,17 +474,34 @@ pub(crate) async fn modify_post(
 pub(crate) async fn save_modified_post(
     title: web::Path<String>,
     modified_post: web::Form<SubmitPost>, 
-    db: web::Data<PgPool>,
+    db: web::Data<DbPool>,
     identity: Identity
 ) -> Result<HttpResponse, ErrorKind> {
+    let invalid_urls = modified_post.invalid_urls();
+    if !invalid_urls.is_empty() {
+        return Ok(invalid_post_urls(&invalid_urls));
+    }
     let updated_post = UpdatedPost {
         title: modified_post.title.to_string(), body: modified_post.body.to_string(),
         slug: modified_post.slug.to_string(), status: modified_post.status.to_string(),
         updated: Some(Utc::now().naive_utc()),
+        excerpt: modified_post.excerpt.trim().to_owned(), featured_image: modified_post.featured_image.trim().to_owned(),
+        meta_description: modified_post.meta_description.trim().to_owned(), canonical_url: modified_post.canonical_url.trim().to_owned(),
     };
+    // only a draft turning into a post is news, not every edit of a published one
+    let was_published = match PostOperation::get_post_by_title_async(title.to_string(), &db).await {
+        Ok(post) => post.map_or(false, |post| post.status.eq("publish")),
+        Err(e) => return Err(ErrorKind::DbOperationError(e.to_string())),
+    };
+    let (new_title, published) = (updated_post.title.clone(), updated_post.status.eq("publish"));
     
-    match PostOperation::update_post(&title, &updated_post, &db) {
-        Ok(Status::Success) => Ok(redirect("/admin/dashboard/")),
+    match PostOperation::update_post_async(title.into_inner(), updated_post, &db).await {
+        Ok(Status::Success) => {
+            if published && !was_published {
+                announce_post(new_title, &db).await;
+            }
+            Ok(redirect("/admin/dashboard/"))
+        }
         Ok(Status::Failure) => Ok(HttpResponse::InternalServerError().into()),
         Err(e) => Err(ErrorKind::DbOperationError(e.to_string()))
     }
@@ -284,19 +509,19 @@ pub(crate) async fn save_modified_post(
 
 #[login_required]
 pub(crate) async fn today_comments(
-    db: web::Data<PgPool>,
+    db: web::Data<DbPool>,
     identity: Identity
 ) -> Result<HttpResponse, ErrorKind> {
     let user_name = identity.identity().unwrap();
     let mut ctx = tera::Context::new();
     ctx.insert("username", &user_name);
     
-    let all_today_comments = CommentOperation::get_today_comments(&db).unwrap(); // need to remove unwrap
+    let all_today_comments = CommentOperation::get_today_comments_async(&db).await.unwrap(); // need to remove unwrap
     let mut maps: HashMap<&str, Vec<&Comment>> = HashMap::new();
     
     let ids: Vec<_> = all_today_comments.iter().map(|comment| comment.post_id).unique().collect();
     // user as_ref here duo to making sure maps has the same lifetime with these posts gotten back from database
-    let found_posts = CommentOperation::get_posts_by_comments(ids, &db);
+    let found_posts = CommentOperation::get_posts_by_comments_async(ids, &db).await;
     let _ = found_posts.as_ref().map(|posts| {
         posts.iter().for_each(|post| {
             maps.insert(
@@ -308,46 +533,192 @@ pub(crate) async fn today_comments(
     });
     
     ctx.insert("comments", &maps);
-    let template = COMPILED_TEMPLATES.render("admin/today_comments.html", &ctx);
+    let template = render_template("admin/today_comments.html", &ctx);
     match template {
         Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
         Err(e) => Err(ErrorKind::TemplateError(e.to_string()))
     }
 }
 
+const INBOX_PAGE: i64 = 20;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct InboxQuery {
+    folder: Option<String>,
+    q: Option<String>,
+    page: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct GuestMessageAction {
+    action: String, // read/unread/star/unstar/archive/unarchive
+    back: Option<String>, // the inbox page the button was on
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct GuestMessageReply {
+    body: String,
+}
+
 #[login_required]
 pub(crate) async fn all_guests_messages(
-    db: web::Data<PgPool>,
+    query: web::Query<InboxQuery>,
+    db: web::Data<DbPool>,
     identity: Identity
 ) -> Result<HttpResponse, ErrorKind> {
     let user_name = identity.identity().unwrap();
+    let query = query.into_inner();
+    let folder = query.folder.filter(|folder| FOLDERS.contains(&folder.as_str())).unwrap_or_else(|| INBOX.to_owned());
+    let key_word = query.q.map(|q| q.trim().to_owned()).filter(|q| !q.is_empty());
+    let page = query.page.unwrap_or(1);
+
+    let (inbox, counts) = futures::join!(
+        ContactOperation::get_inbox_async(folder.clone(), key_word.clone(), page, INBOX_PAGE, &db),
+        ContactOperation::inbox_counts_async(&db)
+    );
+    let inbox = inbox.map_err(|e| ErrorKind::DbOperationError(e.to_string()))?;
+    let (_, unread_count) = counts.unwrap_or_default();
+
     let mut ctx = tera::Context::new();
     ctx.insert("username", &user_name);
-    
-    let guest_msgs = ContactOperation::get_all_contacts(&db);
-    let _ = guest_msgs.map(|contacts| {
-        ctx.insert("contacts", &contacts);
-    });
+    ctx.insert("folder", &folder);
+    ctx.insert("folders", &FOLDERS);
+    ctx.insert("q", &key_word.unwrap_or_default());
+    ctx.insert("inbox", &inbox);
+    ctx.insert("unread_count", &unread_count);
+
+    let template = render_template("admin/guest_messages.html", &ctx);
+    match template {
+        Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
+        Err(e) => Err(ErrorKind::TemplateError(e.to_string()))
+    }
+}
+
+// opening a message marks it read
+#[login_required]
+pub(crate) async fn guest_message(
+    contact_id: web::Path<i32>,
+    db: web::Data<DbPool>,
+    identity: Identity
+) -> Result<HttpResponse, ErrorKind> {
+    let user_name = identity.identity().unwrap();
+    let contact_id = contact_id.into_inner();
+    let mut contact = match ContactOperation::get_contact_async(contact_id, &db).await {
+        Ok(Some(contact)) => contact,
+        Ok(None) => return Ok(HttpResponse::NotFound().content_type("text/html")
+                                  .body("<h1 style='text-align: center;'>No such message.</h1>")),
+        Err(e) => return Err(ErrorKind::DbOperationError(e.to_string())),
+    };
+    if !contact.is_read {
+        let read = ContactState { is_read: Some(true), ..ContactState::default() };
+        if let Err(e) = ContactOperation::set_state_async(contact_id, read, &db).await {
+            return Err(ErrorKind::DbOperationError(e.to_string()));
+        }
+        contact.is_read = true;
+    }
+    let replies = ContactOperation::get_replies_async(contact_id, &db).await.map_err(|e| ErrorKind::DbOperationError(e.to_string()))?;
 
-    let template = COMPILED_TEMPLATES.render("admin/guest_messages.html", &ctx);
+    let mut ctx = tera::Context::new();
+    ctx.insert("username", &user_name);
+    ctx.insert("contact", &contact);
+    ctx.insert("replies", &replies);
+    let template = render_template("admin/guest_message.html", &ctx);
     match template {
         Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
         Err(e) => Err(ErrorKind::TemplateError(e.to_string()))
     }
 }
 
+#[login_required]
+pub(crate) async fn update_guest_message(
+    contact_id: web::Path<i32>,
+    action: web::Form<GuestMessageAction>,
+    db: web::Data<DbPool>,
+    identity: Identity
+) -> Result<HttpResponse, ErrorKind> {
+    let contact_id = contact_id.into_inner();
+    let state = match action.action.parse::<ContactState>() {
+        Ok(state) => state,
+        Err(e) => return Ok(HttpResponse::BadRequest().content_type("text/html")
+                                .body(format!("<h1 style='text-align: center;'>{}.</h1>", tera::escape_html(&e.to_string())))),
+    };
+    match ContactOperation::set_state_async(contact_id, state, &db).await {
+        Ok(true) => (),
+        Ok(false) => return Ok(HttpResponse::NotFound().content_type("text/html")
+                                   .body("<h1 style='text-align: center;'>No such message.</h1>")),
+        Err(e) => return Err(ErrorKind::DbOperationError(e.to_string())),
+    }
+    // back where the button was, but never out of the inbox
+    match action.into_inner().back.filter(|back| back.starts_with("/admin/all_guests_messages/")) {
+        Some(back) => Ok(redirect(&back)),
+        None => Ok(redirect(&format!("/admin/all_guests_messages/{}/", contact_id))),
+    }
+}
+
+// the reply goes out by mail from the blog's address, and is kept under the message
+#[login_required]
+pub(crate) async fn reply_guest_message(
+    req: HttpRequest,
+    contact_id: web::Path<i32>,
+    reply: web::Form<GuestMessageReply>,
+    db: web::Data<DbPool>,
+    identity: Identity
+) -> Result<HttpResponse, ErrorKind> {
+    let user_name = identity.identity().unwrap();
+    let contact_id = contact_id.into_inner();
+    let body = reply.into_inner().body.trim().to_owned();
+    if body.is_empty() {
+        return Ok(HttpResponse::BadRequest().content_type("text/html")
+                      .body("<h1 style='text-align: center;'>The reply is empty.</h1>
+                             <h2 style='text-align: center;'><a href='.'>Go back</a></h2>"));
+    }
+    let contact = match ContactOperation::get_contact_async(contact_id, &db).await {
+        Ok(Some(contact)) => contact,
+        Ok(None) => return Ok(HttpResponse::NotFound().content_type("text/html")
+                                  .body("<h1 style='text-align: center;'>No such message.</h1>")),
+        Err(e) => return Err(ErrorKind::DbOperationError(e.to_string())),
+    };
+    let user_id = UserOperation::get_id_by_username_async(user_name.clone(), &db).await
+                                .map_err(|e| ErrorKind::DbOperationError(e.to_string()))?;
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("site", &seo::site_url(&req));
+    ctx.insert("name", &contact.tourist_name);
+    ctx.insert("body", &body);
+    ctx.insert("replier", &user_name);
+    ctx.insert("committed", &contact.committed_time.map(|time| time.format("%Y-%m-%d %H:%M").to_string()));
+    ctx.insert("quoted", &contact.message.lines().collect::<Vec<_>>());
+    let mail = mailer::render_mail("mail/contact_reply.txt", &ctx, &contact.email).map_err(|e| ErrorKind::TemplateError(e.to_string()))?;
+
+    // the mail is queued only with the reply kept
+    let new_reply = NewContactReply { contact_id, user_id, body, sent: Utc::now().naive_utc() };
+    let pool = db.clone();
+    let replied = run_blocking(move || {
+        let conn = &*pool.get()?;
+        conn.transaction::<_, failure::Error, _>(|| {
+            let reply = ContactOperation::insert_reply_on(&new_reply, conn)?;
+            mailer::queue_mail_on(&mail, conn)?;
+            Ok(reply)
+        })
+    }).await;
+    match replied {
+        Ok(_) => Ok(redirect(&format!("/admin/all_guests_messages/{}/", contact_id))),
+        Err(e) => Err(ErrorKind::DbOperationError(e.to_string())),
+    }
+}
+
 #[login_required]
 pub(crate) async fn about_self(
-    db: web::Data<PgPool>,
+    db: web::Data<DbPool>,
     identity: Identity
 ) -> Result<HttpResponse, ErrorKind> {
     let user_name = identity.identity().unwrap();
     let mut ctx = tera::Context::new();
     ctx.insert("username", &user_name);
-    match UserOperation::get_user_by_name(&user_name, &db) {
+    match UserOperation::get_user_by_name_async(user_name.clone(), &db).await {
         Ok(Some(myself)) => {
             ctx.insert("yourself", &myself);
-            let template = COMPILED_TEMPLATES.render("admin/self_info.html", &ctx);
+            let template = render_template("admin/self_info.html", &ctx);
 
             match template {
                 Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
@@ -362,6 +733,14 @@ pub(crate) async fn about_self(
     }
 }
 
+#[login_required]
+pub(crate) async fn cache_stats(db: web::Data<DbPool>, identity: Identity) -> Result<HttpResponse, ErrorKind> {
+    let mut stats = HashMap::new();
+    stats.insert("pages", PAGE_CACHE.stats());
+    stats.insert("queries", QUERY_CACHE.stats());
+    Ok(HttpResponse::Ok().json(stats))
+}
+
 pub(crate) async fn redirect_admin() -> Result<HttpResponse, HttpResponseErr> {
     async_redirect("/admin/login/").await
 }
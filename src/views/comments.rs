@@ -0,0 +1,237 @@
+use actix_identity::Identity;
+use actix_web::{ web, HttpResponse };
+use serde_derive::{ Deserialize, Serialize };
+use std::collections::HashMap;
+
+use crate::error_types::ErrorKind;
+use crate::models::blocklist::{ BlocklistOperation, EMAIL, KINDS };
+use crate::models::comment::{ Comment, CommentOperation };
+use crate::models::post::PostOperation;
+use crate::utils::blocklist::{ self, Author };
+use crate::utils::utils::{ render_template, DbPool };
+use super::auth::{ current_staff, redirect, staff_required };
+
+use actix_blog::login_required;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CommenterQuery {
+    #[serde(default)]
+    email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct EditedComment {
+    comment: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct BlockForm {
+    kind: String,
+    value: String,
+    #[serde(default)]
+    reason: String,
+    back: Option<String>,
+}
+
+#[derive(Serialize)]
+struct CommentRow<'a> {
+    comment: &'a Comment,
+    title: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct Back {
+    back: Option<String>, // the admin page the button was on
+}
+
+fn render_admin(template: &str, ctx: &tera::Context) -> Result<HttpResponse, ErrorKind> {
+    match render_template(template, ctx) {
+        Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
+        Err(e) => Err(ErrorKind::TemplateError(e.to_string()))
+    }
+}
+
+// the reason can carry what was submitted
+fn bad_request(reason: &str) -> HttpResponse {
+    HttpResponse::BadRequest().content_type("text/html")
+        .body(format!("<h1 style='text-align: center;'>{}.</h1>
+                       <h2 style='text-align: center;'><a href='javascript:history.back()'>Go back</a></h2>", tera::escape_html(reason)))
+}
+
+fn no_such_comment() -> HttpResponse {
+    HttpResponse::NotFound().content_type("text/html")
+        .body("<h1 style='text-align: center;'>No such comment.</h1>")
+}
+
+// back where the button was, but never off the admin
+fn redirect_back(back: Option<String>, fallback: &str) -> HttpResponse {
+    match back.filter(|back| back.starts_with("/admin/") && !back.starts_with("/admin//")) {
+        Some(back) => redirect(&back),
+        None => redirect(fallback),
+    }
+}
+
+#[login_required]
+pub(crate) async fn edit_comment(
+    comment_id: web::Path<i32>,
+    db: web::Data<DbPool>,
+    identity: Identity
+) -> Result<HttpResponse, ErrorKind> {
+    let user_name = identity.identity().unwrap();
+    if current_staff(user_name.clone(), &db).await.is_none() {
+        return Ok(staff_required());
+    }
+
+    let comment = match CommentOperation::get_comment_async(comment_id.into_inner(), &db).await {
+        Ok(Some(comment)) => comment,
+        Ok(None) => return Ok(no_such_comment()),
+        Err(e) => return Err(ErrorKind::DbOperationError(e.to_string())),
+    };
+    let post = PostOperation::get_post_by_id_async(comment.post_id, &db).await.map_err(|e| ErrorKind::DbOperationError(e.to_string()))?;
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("username", &user_name);
+    ctx.insert("comment", &comment);
+    ctx.insert("post_title", &post.map(|post| post.title).unwrap_or_default());
+    render_admin("admin/edit_comment.html", &ctx)
+}
+
+#[login_required]
+pub(crate) async fn save_comment(
+    comment_id: web::Path<i32>,
+    edited: web::Form<EditedComment>,
+    db: web::Data<DbPool>,
+    identity: Identity
+) -> Result<HttpResponse, ErrorKind> {
+    let user_name = identity.identity().unwrap();
+    if current_staff(user_name, &db).await.is_none() {
+        return Ok(staff_required());
+    }
+
+    let comment_id = comment_id.into_inner();
+    let text = edited.into_inner().comment.trim().to_owned();
+    if text.is_empty() {
+        return Ok(bad_request("A comment can't be empty, delete it instead"));
+    }
+    match CommentOperation::update_comment_async(comment_id, text, &db).await {
+        Ok(true) => Ok(redirect(&format!("/admin/comments/{}/", comment_id))),
+        Ok(false) => Ok(no_such_comment()),
+        Err(e) => Err(ErrorKind::DbOperationError(e.to_string())),
+    }
+}
+
+#[login_required]
+pub(crate) async fn delete_comment(
+    comment_id: web::Path<i32>,
+    back: web::Form<Back>,
+    db: web::Data<DbPool>,
+    identity: Identity
+) -> Result<HttpResponse, ErrorKind> {
+    let user_name = identity.identity().unwrap();
+    if current_staff(user_name, &db).await.is_none() {
+        return Ok(staff_required());
+    }
+
+    match CommentOperation::delete_comment_async(comment_id.into_inner(), &db).await {
+        Ok(true) => Ok(redirect_back(back.into_inner().back, "/admin/today_comments/")),
+        Ok(false) => Ok(no_such_comment()),
+        Err(e) => Err(ErrorKind::DbOperationError(e.to_string())),
+    }
+}
+
+// every comment of one commenter, and whether the email is blocked
+#[login_required]
+pub(crate) async fn commenter_comments(
+    query: web::Query<CommenterQuery>,
+    db: web::Data<DbPool>,
+    identity: Identity
+) -> Result<HttpResponse, ErrorKind> {
+    let user_name = identity.identity().unwrap();
+    if current_staff(user_name.clone(), &db).await.is_none() {
+        return Ok(staff_required());
+    }
+
+    let email = query.into_inner().email.trim().to_lowercase();
+    let (comments, entries) = futures::join!(
+        CommentOperation::get_comments_by_email_async(email.clone(), &db),
+        BlocklistOperation::get_entries_async(&db)
+    );
+    let comments = comments.map_err(|e| ErrorKind::DbOperationError(e.to_string()))?;
+    let entries = entries.map_err(|e| ErrorKind::DbOperationError(e.to_string()))?;
+    let ids: Vec<i32> = comments.iter().map(|comment| comment.post_id).collect();
+    let posts = CommentOperation::get_posts_by_comments_async(ids, &db).await.map_err(|e| ErrorKind::DbOperationError(e.to_string()))?;
+    let titles: HashMap<i32, &str> = posts.iter().map(|post| (post.id, post.title.as_str())).collect();
+    let rows: Vec<CommentRow> = comments.iter().map(|comment| CommentRow {
+        comment,
+        title: titles.get(&comment.post_id).copied().unwrap_or_default(),
+    }).collect();
+    // the page is about the email, the name patterns and the ip ranges aren't checked here
+    let email_entries: Vec<_> = entries.into_iter().filter(|entry| entry.kind.eq(EMAIL)).collect();
+    let blocked = blocklist::blocked_by(&email_entries, &Author { email: &email, name: "", address: None });
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("username", &user_name);
+    ctx.insert("email", &email);
+    ctx.insert("comments", &rows);
+    ctx.insert("blocked", &blocked);
+    render_admin("admin/commenter_comments.html", &ctx)
+}
+
+#[login_required]
+pub(crate) async fn show_blocklist(
+    db: web::Data<DbPool>,
+    identity: Identity
+) -> Result<HttpResponse, ErrorKind> {
+    let user_name = identity.identity().unwrap();
+    if current_staff(user_name.clone(), &db).await.is_none() {
+        return Ok(staff_required());
+    }
+
+    let entries = BlocklistOperation::get_entries_async(&db).await.map_err(|e| ErrorKind::DbOperationError(e.to_string()))?;
+    let mut ctx = tera::Context::new();
+    ctx.insert("username", &user_name);
+    ctx.insert("kinds", &KINDS);
+    ctx.insert("entries", &entries);
+    render_admin("admin/blocklist.html", &ctx)
+}
+
+// from the form of the blocklist, or the block buttons by a comment or a message
+#[login_required]
+pub(crate) async fn add_blocked(
+    new_entry: web::Form<BlockForm>,
+    db: web::Data<DbPool>,
+    identity: Identity
+) -> Result<HttpResponse, ErrorKind> {
+    let user_name = identity.identity().unwrap();
+    if current_staff(user_name, &db).await.is_none() {
+        return Ok(staff_required());
+    }
+
+    let new_entry = new_entry.into_inner();
+    let value = match blocklist::normalize_entry(&new_entry.kind, &new_entry.value) {
+        Ok(value) => value,
+        Err(reason) => return Ok(bad_request(&reason)),
+    };
+    match BlocklistOperation::add_async(new_entry.kind, value, new_entry.reason.trim().to_owned(), &db).await {
+        Ok(_) => Ok(redirect_back(new_entry.back, "/admin/blocklist/")),
+        Err(e) => Err(ErrorKind::DbOperationError(e.to_string())),
+    }
+}
+
+#[login_required]
+pub(crate) async fn remove_blocked(
+    entry_id: web::Path<i32>,
+    back: web::Form<Back>,
+    db: web::Data<DbPool>,
+    identity: Identity
+) -> Result<HttpResponse, ErrorKind> {
+    let user_name = identity.identity().unwrap();
+    if current_staff(user_name, &db).await.is_none() {
+        return Ok(staff_required());
+    }
+
+    match BlocklistOperation::remove_async(entry_id.into_inner(), &db).await {
+        Ok(_) => Ok(redirect_back(back.into_inner().back, "/admin/blocklist/")),
+        Err(e) => Err(ErrorKind::DbOperationError(e.to_string())),
+    }
+}
@@ -0,0 +1,60 @@
+use actix_web::{ http::header, web, HttpRequest, HttpResponse };
+use serde_json::json;
+use std::time::Duration;
+
+use crate::models::{ comment::CommentOperation, migrations, post::PostOperation };
+use crate::utils::{ config, metrics::{ self, Gauges }, utils::{ run_blocking, DbPool } };
+
+// a probe should fail fast instead of waiting for the default timeout of r2d2
+const READINESS_TIMEOUT: Duration = Duration::from_secs(2);
+
+// liveness, the process is able to answer
+pub(crate) async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().content_type("text/plain").body("ok")
+}
+
+// readiness, the database is reachable and has the migrations this binary expects
+pub(crate) async fn readyz(db: web::Data<DbPool>) -> HttpResponse {
+    let pool = db.clone();
+    let ready = run_blocking(move || {
+        let conn = pool.get_timeout(READINESS_TIMEOUT)?;
+        let status = migrations::migration_status(&conn)?;
+        if status.is_current() {
+            Ok(())
+        } else {
+            Err(failure::format_err!("pending migrations: [{}], unknown migrations: [{}]",
+                                     status.pending.join(", "), status.unknown.join(", ")))
+        }
+    }).await;
+
+    match ready {
+        Ok(()) => HttpResponse::Ok().json(json!({ "status": "ready" })),
+        Err(e) => HttpResponse::ServiceUnavailable().json(json!({ "status": "unavailable", "reason": e.to_string() })),
+    }
+}
+
+pub(crate) async fn metrics(req: HttpRequest, db: web::Data<DbPool>) -> HttpResponse {
+    // not even telling it exists without the token
+    let authorization = req.headers().get(header::AUTHORIZATION).and_then(|value| value.to_str().ok());
+    if !metrics::scrape_allowed(&config::current().metrics_token, authorization) {
+        return HttpResponse::NotFound().finish();
+    }
+
+    let state = db.state();
+    let mut gauges = Gauges {
+        pool_connections: state.connections,
+        pool_idle_connections: state.idle_connections,
+        pool_max_size: db.max_size(),
+        ..Gauges::default()
+    };
+
+    // the counts are left as 0 if the database is down, the pool gauges still tell why
+    let pool = db.clone();
+    let counts = run_blocking(move || Ok((PostOperation::count_posts(&pool)?, CommentOperation::count_comments(&pool)?))).await;
+    if let Ok((posts, comments)) = counts {
+        gauges.posts = posts;
+        gauges.comments = comments;
+    }
+
+    HttpResponse::Ok().content_type("text/plain; version=0.0.4").body(metrics::export(&gauges))
+}
@@ -0,0 +1,130 @@
+use actix_identity::Identity;
+use actix_multipart::Multipart;
+use actix_web::{ http::header, web, HttpRequest, HttpResponse };
+use futures::StreamExt;
+use serde_json::json;
+use std::collections::HashMap;
+
+use crate::error_types::ErrorKind;
+use crate::models::{ media::{ MediaOperation, NewMedia }, user::UserOperation };
+use crate::utils::{ config, media::{ self, MediaError, MediaLinks }, utils::{ render_template, run_blocking, DbPool } };
+use super::auth::redirect;
+
+use actix_blog::login_required;
+
+// the form field holding the image
+const FILE_FIELD: &str = "file";
+
+// the editor uploads with fetch and wants json back, the gallery page posts a plain form
+fn wants_json(req: &HttpRequest) -> bool {
+    req.headers().get(header::ACCEPT).and_then(|accept| accept.to_str().ok()).map_or(false, |accept| accept.contains("application/json"))
+}
+
+fn upload_failed(req: &HttpRequest, mut response: actix_web::dev::HttpResponseBuilder, reason: String) -> HttpResponse {
+    if wants_json(req) {
+        response.json(json!({ "error": reason }))
+    } else {
+        response.content_type("text/html")
+                .body(format!("<h1 style='text-align: center;'>{}.</h1>
+                               <h2 style='text-align: center;'><a href='.'>Go back</a></h2>", reason))
+    }
+}
+
+// the text fields of a multipart form, and the file in file_field
+#[derive(Debug, Default)]
+pub(crate) struct MultipartForm {
+    pub(crate) fields: HashMap<String, String>,
+    pub(crate) file: Option<(String, Vec<u8>)>, // the file name and content
+}
+
+// stops reading once a field is over the limit
+pub(crate) async fn read_multipart(mut payload: Multipart, file_field: &str, limit: usize) -> Result<MultipartForm, failure::Error> {
+    let mut form = MultipartForm::default();
+    while let Some(field) = payload.next().await {
+        let mut field = field.map_err(|e| failure::err_msg(e.to_string()))?;
+        let disposition = field.content_disposition();
+        let name = disposition.as_ref().and_then(|d| d.get_name()).unwrap_or_default().to_owned();
+        let file_name = disposition.as_ref().and_then(|d| d.get_filename()).unwrap_or_default().to_owned();
+
+        let mut bytes = Vec::new();
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk.map_err(|e| failure::err_msg(e.to_string()))?;
+            if bytes.len() + chunk.len() > limit {
+                return Err(MediaError::TooLarge(limit).into());
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        if name.eq(file_field) {
+            form.file = Some((file_name, bytes));
+        } else {
+            form.fields.insert(name, String::from_utf8_lossy(&bytes).into_owned());
+        }
+    }
+    Ok(form)
+}
+
+#[login_required]
+pub(crate) async fn upload_media(
+    req: HttpRequest,
+    payload: Multipart,
+    db: web::Data<DbPool>,
+    identity: Identity
+) -> Result<HttpResponse, ErrorKind> {
+    let media_config = config::current().media.clone();
+    let (original_name, bytes) = match read_multipart(payload, FILE_FIELD, media_config.max_upload_size).await {
+        Ok(MultipartForm { file: Some(upload), .. }) => upload,
+        Ok(_) => return Ok(upload_failed(&req, HttpResponse::BadRequest(), format!("No {} field in the upload", FILE_FIELD))),
+        Err(e) if e.downcast_ref::<MediaError>().is_some() => return Ok(upload_failed(&req, HttpResponse::PayloadTooLarge(), e.to_string())),
+        Err(e) => return Ok(upload_failed(&req, HttpResponse::BadRequest(), e.to_string())),
+    };
+    let uploader = match UserOperation::get_id_by_username_async(identity.identity().unwrap(), &db).await {
+        Ok(uid) => uid,
+        Err(e) => return Err(ErrorKind::DbOperationError(e.to_string())),
+    };
+
+    let pool = db.clone();
+    let uploaded = run_blocking(move || {
+        let format = media::detect_format(&bytes, &media_config)?;
+        // the same content is stored once, whoever uploads it again gets the existing one
+        if let Some(existing) = MediaOperation::get_media_by_file_name(&media::content_file_name(&bytes, format), &pool)? {
+            return Ok(existing);
+        }
+        let stored = media::process_image(&bytes, &media_config)?;
+        MediaOperation::insert_media(&NewMedia::new(stored, original_name, uploader), &pool)
+    }).await;
+
+    match uploaded {
+        Ok(uploaded) if wants_json(&req) => Ok(HttpResponse::Ok().json(MediaLinks::from(&uploaded))),
+        Ok(_) => Ok(redirect("/admin/media/")),
+        Err(e) => match e.downcast_ref::<MediaError>() {
+            Some(MediaError::TooLarge(_)) => Ok(upload_failed(&req, HttpResponse::PayloadTooLarge(), e.to_string())),
+            Some(MediaError::UnsupportedType(_)) => Ok(upload_failed(&req, HttpResponse::UnsupportedMediaType(), e.to_string())),
+            Some(_) => Ok(upload_failed(&req, HttpResponse::BadRequest(), e.to_string())),
+            None => Err(ErrorKind::DbOperationError(e.to_string())),
+        }
+    }
+}
+
+#[login_required]
+pub(crate) async fn gallery(
+    db: web::Data<DbPool>,
+    identity: Identity
+) -> Result<HttpResponse, ErrorKind> {
+    let all_media = match MediaOperation::get_all_media_async(&db).await {
+        Ok(all_media) => all_media,
+        Err(e) => return Err(ErrorKind::DbOperationError(e.to_string())),
+    };
+    let links: Vec<MediaLinks> = all_media.iter().map(MediaLinks::from).collect();
+    let media_config = config::current().media.clone();
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("username", &identity.identity().unwrap());
+    ctx.insert("media", &links);
+    ctx.insert("max_upload_size", &format!("{:.1} MB", media_config.max_upload_size as f64 / (1024.0 * 1024.0)));
+    ctx.insert("allowed_types", &media_config.allowed_types.join("/"));
+    let template = render_template("admin/media.html", &ctx);
+    match template {
+        Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
+        Err(e) => Err(ErrorKind::TemplateError(e.to_string()))
+    }
+}
@@ -1,2 +1,9 @@
+pub(crate) mod analytics;
 pub(crate) mod auth;
-pub(crate) mod post;
\ No newline at end of file
+pub(crate) mod comments;
+pub(crate) mod health;
+pub(crate) mod media;
+pub(crate) mod newsletter;
+pub(crate) mod post;
+pub(crate) mod sitemap;
+pub(crate) mod transfer;
\ No newline at end of file
@@ -0,0 +1,175 @@
+use actix_identity::Identity;
+use actix_web::{ http::{ header, Method }, web, HttpRequest, HttpResponse };
+use chrono::{ Duration, Utc };
+use serde_derive::Deserialize;
+
+use crate::error_types::ErrorKind;
+use crate::models::subscriber::{ Subscribe, SubscriberOperation, BOUNCED, STATUSES };
+use crate::utils::{ mailer, newsletter, seo };
+use crate::utils::utils::{ render_template, DbPool };
+use super::auth::{ current_superuser, redirect, superuser_required };
+use super::post::page_404;
+
+use actix_blog::login_required;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct SubscribersQuery {
+    status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct SubscriberAction {
+    id: i32,
+    action: String, // bounce or remove
+}
+
+fn render_newsletter(ctx: &tera::Context) -> Result<HttpResponse, ErrorKind> {
+    match render_template("newsletter.html", ctx) {
+        Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
+        Err(e) => Err(ErrorKind::TemplateError(e.to_string()))
+    }
+}
+
+// the same page whether the address is new or known, so the form doesn't tell who subscribed
+pub(crate) async fn subscribe(
+    req: HttpRequest,
+    subscription: web::Form<Subscribe>,
+    db: web::Data<DbPool>
+) -> Result<HttpResponse, ErrorKind> {
+    let mut ctx = tera::Context::new();
+    let email = match newsletter::normalize_subscriber_email(&subscription.email) {
+        Some(email) => email,
+        None => {
+            ctx.insert("state", "invalid");
+            return render_newsletter(&ctx);
+        }
+    };
+    let frequency = newsletter::frequency_of(&subscription.frequency).to_owned();
+    match SubscriberOperation::subscribe_async(email, frequency, &db).await {
+        Ok(Some(subscriber)) => {
+            let mail = newsletter::confirm_mail(&subscriber, &seo::site_url(&req)).map_err(|e| ErrorKind::TemplateError(e.to_string()))?;
+            if let Err(e) = mailer::queue_mail_async(mail, &db).await {
+                return Err(ErrorKind::DbOperationError(e.to_string()));
+            }
+        }
+        Ok(None) => (),
+        Err(e) => return Err(ErrorKind::DbOperationError(e.to_string())),
+    }
+    ctx.insert("state", "pending");
+    render_newsletter(&ctx)
+}
+
+pub(crate) async fn confirm(
+    token: web::Path<String>,
+    db: web::Data<DbPool>
+) -> Result<HttpResponse, ErrorKind> {
+    let expiry = Duration::days(newsletter::CONFIRM_DAYS);
+    let mut ctx = tera::Context::new();
+    match SubscriberOperation::confirm_async(token.into_inner(), expiry, &db).await {
+        Ok(Some(subscriber)) => {
+            ctx.insert("state", "confirmed");
+            ctx.insert("email", &subscriber.email);
+        }
+        Ok(None) => ctx.insert("state", "expired"),
+        Err(e) => return Err(ErrorKind::DbOperationError(e.to_string())),
+    }
+    render_newsletter(&ctx)
+}
+
+// like the unsubscribe of comment replies, a get asks first and a post unsubscribes
+pub(crate) async fn unsubscribe(
+    req: HttpRequest,
+    token: web::Path<String>,
+    db: web::Data<DbPool>
+) -> Result<HttpResponse, ErrorKind> {
+    let token = token.into_inner();
+    let subscriber = match SubscriberOperation::get_subscriber_by_token_async(token.clone(), &db).await {
+        Ok(Some(subscriber)) => subscriber,
+        Ok(None) => return page_404().await,
+        Err(e) => return Err(ErrorKind::DbOperationError(e.to_string())),
+    };
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("email", &subscriber.email);
+    if req.method().eq(&Method::POST) {
+        if let Err(e) = SubscriberOperation::unsubscribe_async(token, &db).await {
+            return Err(ErrorKind::DbOperationError(e.to_string()));
+        }
+        ctx.insert("state", "unsubscribed");
+    } else {
+        ctx.insert("state", "unsubscribe");
+    }
+    render_newsletter(&ctx)
+}
+
+#[login_required]
+pub(crate) async fn subscribers(
+    query: web::Query<SubscribersQuery>,
+    db: web::Data<DbPool>,
+    identity: Identity
+) -> Result<HttpResponse, ErrorKind> {
+    let user_name = identity.identity().unwrap();
+    if current_superuser(user_name.clone(), &db).await.is_none() {
+        return Ok(superuser_required());
+    }
+
+    let status = query.into_inner().status.filter(|status| STATUSES.contains(&status.as_str()));
+    let subscribers = match SubscriberOperation::get_subscribers_async(status.clone(), &db).await {
+        Ok(subscribers) => subscribers,
+        Err(e) => return Err(ErrorKind::DbOperationError(e.to_string())),
+    };
+    let mut ctx = tera::Context::new();
+    ctx.insert("username", &user_name);
+    ctx.insert("status", &status);
+    ctx.insert("statuses", &STATUSES);
+    ctx.insert("subscribers", &subscribers);
+    match render_template("admin/subscribers.html", &ctx) {
+        Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
+        Err(e) => Err(ErrorKind::TemplateError(e.to_string()))
+    }
+}
+
+#[login_required]
+pub(crate) async fn update_subscriber(
+    action: web::Form<SubscriberAction>,
+    db: web::Data<DbPool>,
+    identity: Identity
+) -> Result<HttpResponse, ErrorKind> {
+    let user_name = identity.identity().unwrap();
+    if current_superuser(user_name, &db).await.is_none() {
+        return Ok(superuser_required());
+    }
+
+    let updated = match action.action.as_str() {
+        "bounce" => SubscriberOperation::set_status_async(action.id, BOUNCED.to_owned(), &db).await,
+        "remove" => SubscriberOperation::remove_async(action.id, &db).await,
+        _ => return Ok(HttpResponse::BadRequest().content_type("text/html")
+                           .body("<h1 style='text-align: center;'>Unknown action.</h1>
+                                  <h2 style='text-align: center;'><a href='.'>Go back</a></h2>")),
+    };
+    match updated {
+        Ok(_) => Ok(redirect("/admin/subscribers/")),
+        Err(e) => Err(ErrorKind::DbOperationError(e.to_string())),
+    }
+}
+
+#[login_required]
+pub(crate) async fn export_subscribers(
+    db: web::Data<DbPool>,
+    identity: Identity
+) -> Result<HttpResponse, ErrorKind> {
+    let user_name = identity.identity().unwrap();
+    if current_superuser(user_name, &db).await.is_none() {
+        return Ok(superuser_required());
+    }
+
+    match SubscriberOperation::get_subscribers_async(None, &db).await {
+        Ok(subscribers) => {
+            let file_name = format!("subscribers-{}.csv", Utc::now().format("%Y%m%d%H%M%S"));
+            Ok(HttpResponse::Ok().content_type("text/csv; charset=utf-8")
+                                 .header(header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", file_name))
+                                 .body(newsletter::subscribers_csv(&subscribers)))
+        }
+        Err(e) => Err(ErrorKind::DbOperationError(e.to_string())),
+    }
+}
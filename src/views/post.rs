@@ -1,42 +1,148 @@
-use actix_web::{ web, Error as HttpResponseErr, HttpResponse };
+use actix_web::{ http::{ header, Method }, web, Error as HttpResponseErr, HttpRequest, HttpResponse };
 use actix_session::Session;
 use chrono::{ NaiveDateTime, Datelike };
 use itertools::Itertools;
 use serde_derive::{ Deserialize, Serialize };
+use sha2::{ Digest, Sha256 };
 
-use crate::utils::utils::{ PgPool, COMPILED_TEMPLATES };
+use crate::utils::utils::{ DbPool, render_template, run_blocking };
+use crate::utils::cache::{ self, CachedPage, PAGE_CACHE, INDEX_PAGE, ALL_POSTS_PAGE };
+use crate::utils::http_cache::{ self, conditional_html };
+use crate::utils::seo::{ self, PostMeta };
+use crate::utils::{ analytics, blocklist::{ self, Author }, newsletter, notifications };
 use crate::models::post::{ PostStatus, Post, PostOperation };
-use crate::models::comment::{ CreateComment, CommentOperation, NewComment };
+use crate::models::comment::{ Comment, CreateComment, CommentOperation, NewComment };
+use crate::models::like::{ LikeOperation, LikeState };
+use crate::models::analytics::{ AnalyticsOperation, NewPageView };
+use crate::models::blocklist::BlocklistOperation;
 use crate::models::contact::{ NewContact, CreateContact, ContactOperation };
+use crate::models::user::{ User, UserOperation };
+use crate::models::redirect::RedirectOperation;
+use crate::models::subscription::SubscriptionOperation;
 use crate::error_types::ErrorKind;
 
-const PAGE: usize = 4;
+pub(crate) const PAGE: usize = 4;
 
+// the time of the latest modification among these posts, used as Last-Modified
+pub(crate) fn last_updated<'a>(posts: impl IntoIterator<Item = &'a Post>) -> Option<NaiveDateTime> {
+    posts.into_iter().filter_map(|post| post.updated).max()
+}
+
+// the contexts of the public pages below, the static export of the site renders them as well
+
+pub(crate) fn pages_num(posts: &[Post]) -> usize {
+    if posts.len() % PAGE == 0 { posts.len() / PAGE } else { posts.len() / PAGE + 1 }
+}
 
-pub(crate) async fn about() -> Result<HttpResponse, ErrorKind> {
-    let template = COMPILED_TEMPLATES.render("about.html", &tera::Context::new());
+fn time_categories(posts: &[Post]) -> Vec<Option<i32>> {
+    posts.iter().map(|post| post.created.map(|time| time.year())).unique().collect()
+}
+
+pub(crate) fn index_context(posts: &[Post]) -> tera::Context {
+    let mut ctx = tera::Context::new();
+    ctx.insert("time_categories", &time_categories(posts));
+    if posts.len() <= PAGE {
+        ctx.insert("posts", &posts.get(0..));
+    } else {
+        ctx.insert("curr_posts", &posts.get(0..PAGE));
+        ctx.insert("posts_num", &pages_num(posts));
+    }
+    ctx
+}
+
+// pages start from 1, the last page holds the rest of the posts
+pub(crate) fn page_context(posts: &[Post], page_num: usize) -> tera::Context {
+    let mut ctx = tera::Context::new();
+    let created_time: Vec<Option<&NaiveDateTime>> = posts.iter().map(|post| post.publish.as_ref()).collect();
+    ctx.insert("created_time", &created_time);
+    ctx.insert("time_categories", &time_categories(posts));
+
+    let start = page_num.saturating_sub(1) * PAGE;
+    ctx.insert("curr_posts", &posts.get(start.min(posts.len())..(start + PAGE).min(posts.len())));
+    ctx.insert("posts_num", &pages_num(posts));
+    ctx
+}
+
+// the author in json-ld, the full name if there's one
+pub(crate) fn author_name(user: Option<User>) -> String {
+    match user {
+        Some(user) => {
+            let full_name = format!("{} {}", user.first_name, user.last_name).trim().to_owned();
+            if full_name.is_empty() { user.username } else { full_name }
+        }
+        None => String::new(),
+    }
+}
+
+// a new comment changes the article as well
+pub(crate) fn article_last_modified(post: &Post, comments: &[Comment]) -> Option<NaiveDateTime> {
+    comments.iter().filter_map(|comment| comment.committed_time).chain(post.updated).max()
+}
+
+pub(crate) fn article_context(post: &Post, author: &str, comments: Option<&[Comment]>, site_url: &str) -> tera::Context {
+    let mut ctx = tera::Context::new();
+    ctx.insert("meta", &PostMeta::new(post, author, site_url));
+    ctx.insert("post", post);
+    if let Some(comments) = comments {
+        ctx.insert("comments", comments);
+    }
+    ctx
+}
+
+// all_posts.html, of all the posts or of a year
+pub(crate) fn posts_context(posts: &[Post]) -> tera::Context {
+    let mut ctx = tera::Context::new();
+    ctx.insert("posts", posts);
+    ctx
+}
+
+pub(crate) async fn about(req: HttpRequest) -> Result<HttpResponse, ErrorKind> {
+    let template = render_template("about.html", &tera::Context::new());
     
     match template {
-        Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
+        Ok(t) => Ok(conditional_html(&req, http_cache::STATIC_PAGE, t, None)),
         Err(e) => Err(ErrorKind::TemplateError(e.to_string()))
     }
 }
 
-pub(crate) async fn contact() -> Result<HttpResponse, ErrorKind> {
-    let template = COMPILED_TEMPLATES.render("contact.html", &tera::Context::new());
+pub(crate) async fn contact(req: HttpRequest) -> Result<HttpResponse, ErrorKind> {
+    let template = render_template("contact.html", &tera::Context::new());
     
     match template {
-        Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
+        Ok(t) => Ok(conditional_html(&req, http_cache::STATIC_PAGE, t, None)),
         Err(e) => Err(ErrorKind::TemplateError(e.to_string()))
     }
 }
 
+// someone on the blocklist is told it went fine, so it isn't worth trying another way
+async fn is_blocked(author: &Author<'_>, db: &web::Data<DbPool>) -> bool {
+    match BlocklistOperation::get_entries_async(db).await {
+        Ok(entries) => match blocklist::blocked_by(&entries, author) {
+            Some(entry) => {
+                log::info!("dropped what {} <{}> wrote, blocked by {} {}", author.name, author.email, entry.kind, entry.value);
+                true
+            }
+            None => false,
+        },
+        // nobody is blocked while the blocklist can't be read
+        Err(e) => {
+            log::warn!("failed to read the blocklist: {}", e);
+            false
+        }
+    }
+}
+
 pub(crate) async fn add_contact(
+    req: HttpRequest,
     contact: web::Json<CreateContact>, 
-    db: web::Data<PgPool>
+    db: web::Data<DbPool>
 ) -> Result<HttpResponse, HttpResponseErr> {
+    let author = Author { email: &contact.email, name: &contact.tourist_name, address: blocklist::client_address(&req) };
+    if is_blocked(&author, &db).await {
+        return Ok(HttpResponse::Ok().json(true));
+    }
     let new_contact = NewContact::new(&contact);
-    if ContactOperation::insert_contact(new_contact, &db).is_ok() {
+    if ContactOperation::insert_contact_async(new_contact, &db).await.is_ok() {
         Ok(HttpResponse::Ok().json(true))
     } else {
         Ok(HttpResponse::Ok().json(false))
@@ -44,27 +150,25 @@ pub(crate) async fn add_contact(
 }
 
 pub(crate) async fn show_all_posts(
-    db: web::Data<PgPool>
+    req: HttpRequest,
+    db: web::Data<DbPool>
 ) -> Result<HttpResponse, ErrorKind> {
+    if let Some(page) = PAGE_CACHE.get(INDEX_PAGE) {
+        return Ok(conditional_html(&req, http_cache::INDEX, page.body, page.last_modified));
+    }
+
     let status = PostStatus::Published;
-    let all_posts = PostOperation::get_all_posts(status, &db);
+    let all_posts = PostOperation::get_all_posts_async(status, &db).await;
     
     match all_posts {
         Ok(posts) => {
-            let mut ctx = tera::Context::new();
-            let time_categories = posts.iter().map(|post| post.created.map(|time| time.year())).unique().collect::<Vec<Option<i32>>>();
-            ctx.insert("time_categories", &time_categories);
-            if posts.len() <= PAGE {
-                ctx.insert("posts", &posts.get(0..));
-            } else {
-                ctx.insert("curr_posts", &posts.get(0..PAGE));
-                let posts_num = if posts.len() % PAGE == 0 { posts.len () / PAGE } else { posts.len () / PAGE + 1 };
-                ctx.insert("posts_num", &posts_num);
-            }
-            
-            let template = COMPILED_TEMPLATES.render("index.html", &ctx);
+            let template = render_template("index.html", &index_context(&posts));
             match template {
-                Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
+                Ok(t) => {
+                    let last_modified = last_updated(&posts);
+                    PAGE_CACHE.insert(INDEX_PAGE, CachedPage { body: t.clone(), post_id: None, last_modified });
+                    Ok(conditional_html(&req, http_cache::INDEX, t, last_modified))
+                }
                 Err(e) => Err(ErrorKind::TemplateError(e.to_string()))
             }
         }
@@ -73,34 +177,17 @@ pub(crate) async fn show_all_posts(
 }
 
 pub(crate) async fn pagination(
+    req: HttpRequest,
     page_num: web::Path<usize>, 
-    db: web::Data<PgPool>
+    db: web::Data<DbPool>
 ) -> Result<HttpResponse, ErrorKind> {
     let status = PostStatus::Published;
     
-    match PostOperation::get_all_posts(status, &db) {
+    match PostOperation::get_all_posts_async(status, &db).await {
         Ok(posts) => {
-            let mut ctx = tera::Context::new();
-            
-            let created_time: Vec<Option<&NaiveDateTime>> = posts.iter().map(|post| post.publish.as_ref()).collect();
-            ctx.insert("created_time", &created_time);
-            
-            let time_categories = posts.iter().map(|post| post.created.map(|time| time.year())).unique().collect::<Vec<Option<i32>>>();
-            ctx.insert("time_categories", &time_categories);
-            
-            let range = if (*page_num * PAGE).lt(&posts.len()) {
-                (*page_num - 1) * PAGE..*page_num * PAGE
-            } else {
-                (*page_num - 1) * PAGE..(*page_num - 1) * PAGE + posts.len() % PAGE
-            };
-            // ctx.insert("curr_posts", &posts[range]);
-            ctx.insert("curr_posts", &posts.get(range));
-            let posts_num = if posts.len() % PAGE == 0 { posts.len () / PAGE } else { posts.len () / PAGE + 1 };
-            ctx.insert("posts_num", &posts_num);
-
-            let template = COMPILED_TEMPLATES.render("index.html", &ctx);
+            let template = render_template("index.html", &page_context(&posts, *page_num));
             match template {
-                Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
+                Ok(t) => Ok(conditional_html(&req, http_cache::INDEX, t, last_updated(&posts))),
                 Err(e) => Err(ErrorKind::TemplateError(e.to_string()))
             }
         }
@@ -108,29 +195,77 @@ pub(crate) async fn pagination(
     }
 }
 
-new_struct!(Like, pub, [Debug, Clone, Serialize, Deserialize], (likes_count=>i32));
-pub async fn user_likes(
-    like: web::Json<Like>, 
-    session: Session, 
-    db: web::Data<PgPool>
-) -> Result<HttpResponse, HttpResponseErr> {
-    let article_id = session.get::<i32>("article_id");
-    
-    if let Ok(Some(post_id)) = article_id {
-        let _ = PostOperation::update_likes((like.likes_count, post_id), &db);
-        Ok(HttpResponse::Ok().json(true))
-    } else {
-        Ok(HttpResponse::Ok().json(false))
+new_struct!(LikeTarget, pub, [Debug, Clone, Serialize, Deserialize], (post_id=>i32));
+
+// a visitor is remembered by the session, and recognized by a hash of the address and the browser after it expires.
+// the hash is all that's kept about a visitor.
+fn visitor_of(req: &HttpRequest, session: &Session) -> String {
+    if let Ok(Some(visitor)) = session.get::<String>("visitor") {
+        return visitor;
+    }
+    let address = blocklist::client_address(req).map(|address| address.to_string()).unwrap_or_default();
+    let browser = req.headers().get(header::USER_AGENT).and_then(|agent| agent.to_str().ok()).unwrap_or_default();
+    let visitor = format!("{:x}", Sha256::digest(format!("{}|{}", address, browser).as_bytes()));
+    let _ = session.set("visitor", &visitor);
+    visitor
+}
+
+fn like_response(state: Result<Option<LikeState>, failure::Error>) -> Result<HttpResponse, ErrorKind> {
+    match state {
+        Ok(Some(state)) => Ok(HttpResponse::Ok().json(state)),
+        Ok(None) => Ok(HttpResponse::NotFound().json(false)),
+        Err(e) => Err(ErrorKind::DbOperationError(e.to_string())),
     }
 }
 
+// whether the visitor likes the post, the article page is cached for everyone so it asks after loading
+pub(crate) async fn like_state(
+    req: HttpRequest,
+    target: web::Query<LikeTarget>,
+    session: Session,
+    db: web::Data<DbPool>
+) -> Result<HttpResponse, ErrorKind> {
+    let visitor = visitor_of(&req, &session);
+    like_response(LikeOperation::like_state_async(target.post_id, visitor, &db).await)
+}
+
+// a visitor likes a post once, liking again changes nothing
+pub(crate) async fn user_likes(
+    req: HttpRequest,
+    target: web::Json<LikeTarget>,
+    session: Session,
+    db: web::Data<DbPool>
+) -> Result<HttpResponse, ErrorKind> {
+    let visitor = visitor_of(&req, &session);
+    like_response(LikeOperation::like_async(target.post_id, visitor, &db).await)
+}
+
+pub(crate) async fn user_unlikes(
+    req: HttpRequest,
+    target: web::Json<LikeTarget>,
+    session: Session,
+    db: web::Data<DbPool>
+) -> Result<HttpResponse, ErrorKind> {
+    let visitor = visitor_of(&req, &session);
+    like_response(LikeOperation::unlike_async(target.post_id, visitor, &db).await)
+}
+
 new_struct!(Search, pub, [Debug, Clone, Serialize, Deserialize], (key_word=>String));
 pub(crate) async fn search(
+    req: HttpRequest,
     key_word: web::Form<Search>, 
-    db: web::Data<PgPool>
+    db: web::Data<DbPool>
 ) -> Result<HttpResponse, ErrorKind> {
+    if let Some(term) = analytics::search_term(&req, &key_word.key_word) {
+        let db = db.clone();
+        actix_rt::spawn(async move {
+            if let Err(e) = AnalyticsOperation::record_search_async(term, &db).await {
+                log::warn!("failed to record a search: {}", e);
+            }
+        });
+    }
     let status = PostStatus::Published;
-    let all_posts = PostOperation::get_all_posts(status, &db);
+    let all_posts = PostOperation::get_all_posts_async(status, &db).await;
     
     let ctx = match (all_posts, regex::Regex::new(&format!("(?i){}", &key_word.key_word))) {
         (Ok(posts), Ok(re)) => {
@@ -147,33 +282,60 @@ pub(crate) async fn search(
         }
     };
     
-    let template = COMPILED_TEMPLATES.render("search.html", &ctx);
+    let template = render_template("search.html", &ctx);
     match template {
         Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
         Err(e) => Err(ErrorKind::TemplateError(e.to_string()))
     }
 }
 
+// counted off the request, the page is served without waiting for it
+fn record_view(new_view: Option<NewPageView>, db: &web::Data<DbPool>) {
+    if let Some(new_view) = new_view {
+        let db = db.clone();
+        actix_rt::spawn(async move {
+            if let Err(e) = AnalyticsOperation::record_view_async(new_view, &db).await {
+                log::warn!("failed to record a page view: {}", e);
+            }
+        });
+    }
+}
+
 pub(crate) async fn post_detail(
+    req: HttpRequest,
     title: web::Path<String>,
     session: Session, 
-    db: web::Data<PgPool>
+    db: web::Data<DbPool>
 ) -> Result<HttpResponse, ErrorKind> {
-    let post_found = PostOperation::get_post_by_title(&title, &db);
+    let title = seo::article_title(&title);
+    let page_key = cache::article_page(&title);
+    if let Some(page) = PAGE_CACHE.get(&page_key) {
+        let _ = page.post_id.map(|post_id| session.set("article_id", &post_id));
+        record_view(page.post_id.and_then(|post_id| analytics::page_view(&req, post_id)), &db);
+        return Ok(conditional_html(&req, http_cache::ARTICLE, page.body, page.last_modified));
+    }
+
+    let post_found = PostOperation::get_post_by_title_async(title, &db).await;
     
     match post_found {
         Ok(Some(post)) => {
-            let mut ctx = tera::Context::new();
-            ctx.insert("post", &post);
-            
+            let author = author_name(UserOperation::get_user_by_id_async(post.user_id, &db).await.ok().and_then(|user| user));
             let _ = session.set("article_id", &post.id);
+            record_view(analytics::page_view(&req, post.id), &db);
             
-            let related_comments = CommentOperation::get_comments_by_post(post.id, &db);
-            let _ = related_comments.map(|comments| ctx.insert("comments", &comments));
+            let related_comments = CommentOperation::get_comments_by_post_async(post.id, &db).await.ok();
+            let last_modified = match &related_comments {
+                Some(comments) => article_last_modified(&post, comments),
+                None => post.updated,
+            };
+            let ctx = article_context(&post, &author, related_comments.as_deref(), &seo::site_url(&req));
             
-            let template = COMPILED_TEMPLATES.render("post_detail.html", &ctx);
+            let template = render_template("post_detail.html", &ctx);
             match template {
-                Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
+                Ok(t) => {
+                    PAGE_CACHE.insert(page_key, CachedPage { body: t.clone(), post_id: Some(post.id), last_modified });
+                    Ok(conditional_html(&req, http_cache::ARTICLE, t, last_modified))
+                }
                 Err(e) => Err(ErrorKind::TemplateError(e.to_string()))
             }
         }
@@ -182,18 +344,23 @@ pub(crate) async fn post_detail(
     }
 }
 
-pub(crate) async fn all_posts(db: web::Data<PgPool>) -> Result<HttpResponse, ErrorKind> {
+pub(crate) async fn all_posts(req: HttpRequest, db: web::Data<DbPool>) -> Result<HttpResponse, ErrorKind> {
+    if let Some(page) = PAGE_CACHE.get(ALL_POSTS_PAGE) {
+        return Ok(conditional_html(&req, http_cache::ALL_POSTS, page.body, page.last_modified));
+    }
+
     let status = PostStatus::Published;
-    let all_posts = PostOperation::get_all_posts(status, &db);
+    let all_posts = PostOperation::get_all_posts_async(status, &db).await;
 
     match all_posts {
         Ok(posts) => {
-            let mut ctx = tera::Context::new();
-            ctx.insert("posts", &posts);
-            
-            let template = COMPILED_TEMPLATES.render("all_posts.html", &ctx);
+            let template = render_template("all_posts.html", &posts_context(&posts));
             match template {
-                Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
+                Ok(t) => {
+                    let last_modified = last_updated(&posts);
+                    PAGE_CACHE.insert(ALL_POSTS_PAGE, CachedPage { body: t.clone(), post_id: None, last_modified });
+                    Ok(conditional_html(&req, http_cache::ALL_POSTS, t, last_modified))
+                }
                 Err(e) => Err(ErrorKind::TemplateError(e.to_string()))
             }
         }
@@ -202,19 +369,17 @@ pub(crate) async fn all_posts(db: web::Data<PgPool>) -> Result<HttpResponse, Err
 }
 
 pub(crate) async fn show_posts_by_year(
+    req: HttpRequest,
     year: web::Path<i32>,
-    db: web::Data<PgPool>
+    db: web::Data<DbPool>
 ) -> Result<HttpResponse, ErrorKind> {
-    let all_posts = PostOperation::get_posts_by_year(*year, &db);
+    let all_posts = PostOperation::get_posts_by_year_async(*year, &db).await;
 
     match all_posts {
         Ok(posts) => {
-            let mut ctx = tera::Context::new();
-            ctx.insert("posts", &posts);
-            
-            let template = COMPILED_TEMPLATES.render("all_posts.html", &ctx);
+            let template = render_template("all_posts.html", &posts_context(&posts));
             match template {
-                Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
+                Ok(t) => Ok(conditional_html(&req, http_cache::CATEGORY, t, last_updated(&posts))),
                 Err(e) => Err(ErrorKind::TemplateError(e.to_string()))
             }
         }
@@ -223,7 +388,7 @@ pub(crate) async fn show_posts_by_year(
 }
 
 pub(crate) async fn page_404() -> Result<HttpResponse, ErrorKind> {
-    let template = COMPILED_TEMPLATES.render("page_404.html", &tera::Context::new());
+    let template = render_template("page_404.html", &tera::Context::new());
             
     match template {
         Ok(t) => Ok(HttpResponse::NotFound().content_type("text/html").body(t)),
@@ -231,18 +396,119 @@ pub(crate) async fn page_404() -> Result<HttpResponse, ErrorKind> {
     }
 }
 
+// the default service: a permalink of an imported blog is redirected to its article, anything else is 404
+pub(crate) async fn not_found(req: HttpRequest, db: web::Data<DbPool>) -> Result<HttpResponse, ErrorKind> {
+    if req.method().ne(&Method::GET) && req.method().ne(&Method::HEAD) {
+        return page_404().await;
+    }
+    match RedirectOperation::get_redirect_async(req.path().to_owned(), &db).await {
+        Ok(Some(redirect)) => Ok(HttpResponse::MovedPermanently().header(header::LOCATION, redirect.new_path).finish()),
+        Ok(None) => page_404().await,
+        Err(e) => Err(ErrorKind::DbOperationError(e.to_string())),
+    }
+}
+
 pub(crate) async fn add_comment(
+    req: HttpRequest,
     comment: web::Json<CreateComment>, 
     session: Session, 
-    db: web::Data<PgPool>
+    db: web::Data<DbPool>
 ) -> Result<HttpResponse, HttpResponseErr> {
     let article_id = session.get::<i32>("article_id");
 
     if let Ok(Some(id)) = article_id {
+        // mails go to the address, it can be left out but not be something else
+        let email = notifications::normalize_email(&comment.email);
+        if !email.is_empty() && !newsletter::is_valid_email(&email) {
+            return Ok(HttpResponse::BadRequest().json(false));
+        }
+        let author = Author { email: &comment.email, name: &comment.username, address: blocklist::client_address(&req) };
+        if is_blocked(&author, &db).await {
+            return Ok(HttpResponse::Ok().json(true));
+        }
         let new_comment = NewComment::new(&comment, id);
-        let _ = CommentOperation::insert_comment(new_comment, &db);
+        if CommentOperation::insert_comment_async(new_comment, &db).await.is_ok() {
+            let (new_comment, subscribe, site_url, pool) = (NewComment::new(&comment, id), comment.notify_replies, seo::site_url(&req), db.clone());
+            // the comment is kept even if the mails about it cannot be queued
+            if let Err(e) = run_blocking(move || notifications::notify_new_comment(&new_comment, subscribe, &site_url, &pool)).await {
+                log::warn!("failed to queue the mails about a new comment: {}", e);
+            }
+        }
         Ok(HttpResponse::Ok().json(true))
     } else {
         Ok(HttpResponse::InternalServerError().into())
     }
 }
+
+fn render_unsubscribe(ctx: &tera::Context) -> Result<HttpResponse, ErrorKind> {
+    match render_template("unsubscribe.html", ctx) {
+        Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
+        Err(e) => Err(ErrorKind::TemplateError(e.to_string()))
+    }
+}
+
+new_struct!(SubscribeLink, pub, [Debug, Clone, Serialize, Deserialize], (email=>String));
+
+// the link in the confirmation mail of a commenter. like unsubscribe, a get asks first and a post subscribes
+pub(crate) async fn subscribe(
+    req: HttpRequest,
+    path: web::Path<(i32, i64, String)>,
+    link: web::Query<SubscribeLink>,
+    db: web::Data<DbPool>
+) -> Result<HttpResponse, ErrorKind> {
+    let (post_id, expires, signature) = path.into_inner();
+    let email = link.into_inner().email;
+    if !notifications::is_valid_subscribe(post_id, &email, expires, &signature) {
+        return page_404().await;
+    }
+    let post = match PostOperation::get_post_by_id_async(post_id, &db).await {
+        Ok(Some(post)) => post,
+        Ok(None) => return page_404().await,
+        Err(e) => return Err(ErrorKind::DbOperationError(e.to_string())),
+    };
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("email", &email);
+    ctx.insert("post_title", &post.title);
+    if req.method().eq(&Method::POST) {
+        if let Err(e) = SubscriptionOperation::subscribe_async(post_id, email, &db).await {
+            return Err(ErrorKind::DbOperationError(e.to_string()));
+        }
+        ctx.insert("subscribed", &true);
+    } else {
+        ctx.insert("subscribed", &false);
+    }
+    match render_template("subscribe.html", &ctx) {
+        Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
+        Err(e) => Err(ErrorKind::TemplateError(e.to_string()))
+    }
+}
+
+// the link in a reply notification. a get asks first, since mail scanners follow links,
+// a post unsubscribes, from the form or a one-click List-Unsubscribe of the mail client.
+pub(crate) async fn unsubscribe(
+    req: HttpRequest,
+    path: web::Path<(i32, String)>,
+    db: web::Data<DbPool>
+) -> Result<HttpResponse, ErrorKind> {
+    let (subscription_id, signature) = path.into_inner();
+    let subscription = match SubscriptionOperation::get_subscription_async(subscription_id, &db).await {
+        Ok(Some(subscription)) if notifications::is_valid_unsubscribe(&subscription, &signature) => subscription,
+        Ok(_) => return page_404().await,
+        Err(e) => return Err(ErrorKind::DbOperationError(e.to_string())),
+    };
+    let post = PostOperation::get_post_by_id_async(subscription.post_id, &db).await.ok().and_then(|post| post);
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("email", &subscription.email);
+    ctx.insert("post_title", &post.map(|post| post.title).unwrap_or_default());
+    if req.method().eq(&Method::POST) {
+        if let Err(e) = SubscriptionOperation::unsubscribe_async(subscription_id, &db).await {
+            return Err(ErrorKind::DbOperationError(e.to_string()));
+        }
+        ctx.insert("unsubscribed", &true);
+    } else {
+        ctx.insert("unsubscribed", &false);
+    }
+    render_unsubscribe(&ctx)
+}
@@ -0,0 +1,49 @@
+use actix_web::{ web, HttpRequest, HttpResponse };
+
+use crate::error_types::ErrorKind;
+use crate::models::post::{ PostOperation, PostStatus };
+use crate::utils::{ config, http_cache::{ self, conditional_response }, seo, sitemap::{ self, SitemapUrl, MAX_URLS } };
+use crate::utils::utils::DbPool;
+
+const XML: &str = "application/xml";
+
+async fn all_urls(req: &HttpRequest, db: &web::Data<DbPool>) -> Result<Vec<SitemapUrl>, ErrorKind> {
+    match PostOperation::get_all_posts_async(PostStatus::Published, db).await {
+        Ok(posts) => Ok(sitemap::site_urls(&posts, &seo::site_url(req))),
+        Err(e) => Err(ErrorKind::DbOperationError(e.to_string())),
+    }
+}
+
+// the urlset, or an index of /sitemap-{n}.xml once there're more urls than a sitemap can hold
+pub(crate) async fn sitemap(req: HttpRequest, db: web::Data<DbPool>) -> Result<HttpResponse, ErrorKind> {
+    let urls = all_urls(&req, &db).await?;
+    let site_url = seo::site_url(&req);
+    let last_modified = urls.iter().filter_map(|url| url.lastmod).max();
+    let xml = if urls.len().le(&MAX_URLS) {
+        sitemap::urlset(&site_url, &urls)
+    } else {
+        sitemap::sitemap_index(&site_url, &urls, MAX_URLS)
+    };
+    Ok(conditional_response(&req, http_cache::SITEMAP, XML, xml, last_modified))
+}
+
+pub(crate) async fn sitemap_page(
+    req: HttpRequest,
+    page: web::Path<usize>,
+    db: web::Data<DbPool>
+) -> Result<HttpResponse, ErrorKind> {
+    let urls = all_urls(&req, &db).await?;
+    match sitemap::sitemap_page(&urls, page.into_inner(), MAX_URLS) {
+        Some(chunk) => {
+            let last_modified = chunk.iter().filter_map(|url| url.lastmod).max();
+            let xml = sitemap::urlset(&seo::site_url(&req), chunk);
+            Ok(conditional_response(&req, http_cache::SITEMAP, XML, xml, last_modified))
+        }
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+pub(crate) async fn robots(req: HttpRequest) -> HttpResponse {
+    let robots = sitemap::robots_txt(&config::current().robots, &seo::site_url(&req));
+    conditional_response(&req, http_cache::STATIC_PAGE, "text/plain", robots, None)
+}
@@ -0,0 +1,104 @@
+use actix_identity::Identity;
+use actix_multipart::Multipart;
+use actix_web::{ http::header, web, HttpResponse };
+use chrono::Utc;
+
+use crate::error_types::ErrorKind;
+use crate::transfer::{ self, ExportOptions, ImportReport, ImportSource };
+use crate::utils::utils::{ render_template, run_blocking, DbPool };
+use super::auth::{ current_superuser, superuser_required };
+use super::media::{ read_multipart, MultipartForm };
+
+use actix_blog::login_required;
+
+// a zip of every post or a wordpress export is far below this
+const MAX_IMPORT_SIZE: usize = 20 * 1024 * 1024;
+const FILE_FIELD: &str = "file";
+
+fn render_transfer(user_name: &str, report: Option<&ImportReport>, error: Option<String>) -> Result<HttpResponse, ErrorKind> {
+    let mut ctx = tera::Context::new();
+    ctx.insert("username", user_name);
+    ctx.insert("report", &report);
+    ctx.insert("error", &error);
+    let template = render_template("admin/transfer.html", &ctx);
+    match template {
+        Ok(t) => Ok(HttpResponse::Ok().content_type("text/html").body(t)),
+        Err(e) => Err(ErrorKind::TemplateError(e.to_string()))
+    }
+}
+
+#[login_required]
+pub(crate) async fn transfer(
+    db: web::Data<DbPool>,
+    identity: Identity
+) -> Result<HttpResponse, ErrorKind> {
+    let user_name = identity.identity().unwrap();
+    if current_superuser(user_name.clone(), &db).await.is_none() {
+        return Ok(superuser_required());
+    }
+    render_transfer(&user_name, None, None)
+}
+
+#[login_required]
+pub(crate) async fn export_posts(
+    options: web::Query<ExportOptions>,
+    db: web::Data<DbPool>,
+    identity: Identity
+) -> Result<HttpResponse, ErrorKind> {
+    let user_name = identity.identity().unwrap();
+    if current_superuser(user_name.clone(), &db).await.is_none() {
+        return Ok(superuser_required());
+    }
+
+    let options = options.into_inner();
+    if let Err(e) = options.parse() {
+        return Ok(HttpResponse::BadRequest().content_type("text/html")
+                                            .body(format!("<h1 style='text-align: center;'>{}.</h1>", tera::escape_html(&e.to_string()))));
+    }
+    let pool = db.clone();
+    match run_blocking(move || transfer::export_posts(&options, &pool)).await {
+        Ok((zip, _)) => {
+            let file_name = format!("posts-{}.zip", Utc::now().format("%Y%m%d%H%M%S"));
+            Ok(HttpResponse::Ok().content_type("application/zip")
+                                 .header(header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", file_name))
+                                 .body(zip))
+        }
+        Err(e) => Err(ErrorKind::DbOperationError(e.to_string())),
+    }
+}
+
+// posts without a known author are imported as the current user's
+#[login_required]
+pub(crate) async fn import_posts(
+    payload: Multipart,
+    db: web::Data<DbPool>,
+    identity: Identity
+) -> Result<HttpResponse, ErrorKind> {
+    let user_name = identity.identity().unwrap();
+    if current_superuser(user_name.clone(), &db).await.is_none() {
+        return Ok(superuser_required());
+    }
+
+    let (fields, (file_name, bytes)) = match read_multipart(payload, FILE_FIELD, MAX_IMPORT_SIZE).await {
+        Ok(MultipartForm { fields, file: Some(file) }) => (fields, file),
+        Ok(_) => return render_transfer(&user_name, None, Some(format!("No {} field in the upload", FILE_FIELD))),
+        Err(e) => return render_transfer(&user_name, None, Some(e.to_string())),
+    };
+    // a checkbox, only sent when it's checked
+    let dry_run = fields.contains_key("dry_run");
+    let source = match fields.get("source").map_or("", String::as_str).parse::<ImportSource>() {
+        Ok(source) => source,
+        Err(e) => return render_transfer(&user_name, None, Some(e.to_string())),
+    };
+
+    let import = match run_blocking(move || transfer::read_import(source, &file_name, &bytes)).await {
+        Ok(import) => import,
+        Err(e) => return render_transfer(&user_name, None, Some(e.to_string())),
+    };
+    let pool = db.clone();
+    let author = user_name.clone();
+    match run_blocking(move || transfer::import_posts(import, Some(&author), dry_run, &pool)).await {
+        Ok(report) => render_transfer(&user_name, Some(&report), None),
+        Err(e) => Err(ErrorKind::DbOperationError(e.to_string())),
+    }
+}